@@ -0,0 +1,9 @@
+//! Fuzz the allocator with arbitrary sequences of operations.
+//!
+//! Run with `cargo +nightly fuzz run operations` from the repository root.
+//! The seed corpus in `corpus/operations` contains a few interesting sequences.
+#![no_main]
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    emballoc::fuzz_operations(data);
+});
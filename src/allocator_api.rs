@@ -0,0 +1,391 @@
+//! This module implements the unstable [`Allocator`]-trait for the allocator.
+//!
+//! The trait is only available on a nightly compiler, therefore this module is
+//! only compiled with the `allocator-api` feature.
+use crate::{GenericAllocator, RawLock};
+
+use core::alloc::{AllocError, Allocator, GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+// SAFETY: the memory blocks stay valid until they are deallocated, as the heap
+// is part of the allocator itself, which cannot be moved or dropped while it is
+// borrowed by a collection. Zero-sized allocations do not access the heap.
+unsafe impl<L: RawLock, const N: usize> Allocator for GenericAllocator<L, N> {
+    /// Allocate memory as described by the `layout`.
+    ///
+    /// The returned slice covers the whole usable memory of the block, which
+    /// might be larger than requested, as the blocks are rounded up to a
+    /// multiple of the [`GRANULARITY`] (see [`alloc_with_size()`]). Zero-sized
+    /// allocations return a dangling, but properly aligned pointer without
+    /// using the heap. Deallocating such a pointer does nothing.
+    ///
+    /// [`alloc_with_size()`]: GenericAllocator::alloc_with_size
+    /// [`GRANULARITY`]: GenericAllocator::GRANULARITY
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if layout.size() == 0 {
+            let dangling = NonNull::new(ptr::without_provenance_mut(layout.align()));
+            return dangling
+                .map(|ptr| NonNull::slice_from_raw_parts(ptr, 0))
+                .ok_or(AllocError);
+        }
+        // SAFETY: the size of the layout is non-zero
+        let (ptr, size) = unsafe { self.alloc_with_size(layout) }.ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, size))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            // SAFETY: the memory was allocated by this allocator with the same
+            // layout (safety contract of this function).
+            unsafe { self.dealloc(ptr.as_ptr(), layout) };
+        }
+    }
+
+    /// Grow the allocation at `ptr` to the `new_layout`.
+    ///
+    /// If the pointer is sufficiently aligned for the `new_layout` and the
+    /// block is followed by a large enough free block, the allocation is grown
+    /// in place. Otherwise the data is moved into a new block.
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: the safety contract is the same as for this function
+        unsafe { self.grow_with(ptr, old_layout, new_layout, false) }
+    }
+
+    /// Grow the allocation at `ptr` to the `new_layout` and zero the new memory.
+    ///
+    /// This behaves like [`grow()`](Self::grow), but all the memory after the
+    /// old size is zeroed. The preserved data is not written.
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        // SAFETY: the safety contract is the same as for this function
+        unsafe { self.grow_with(ptr, old_layout, new_layout, true) }
+    }
+
+    /// Shrink the allocation at `ptr` to the `new_layout`.
+    ///
+    /// If the pointer is sufficiently aligned for the `new_layout`, the block
+    /// is shrunk in place by splitting off the unused memory at its end as a
+    /// free block. Only if the new alignment is not fulfilled by the pointer,
+    /// the data is moved into a new block.
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if new_layout.size() == 0 {
+            // SAFETY: the memory was allocated by this allocator with the old
+            // layout (safety contract of this function).
+            unsafe { self.deallocate(ptr, old_layout) };
+            return self.allocate(new_layout);
+        }
+
+        if ptr.as_ptr() as usize % new_layout.align() == 0 {
+            let mut raw = self.raw.lock();
+            let shrunk = raw.shrink_in_place(ptr.as_ptr(), new_layout.size());
+            if shrunk.is_some() {
+                raw.forget_rounding(old_layout.size());
+                raw.count_rounding(new_layout.size());
+            }
+            drop(raw);
+            if let Some(size) = shrunk {
+                return Ok(NonNull::slice_from_raw_parts(ptr, size));
+            }
+        }
+        let memory = self.allocate(new_layout)?;
+        // SAFETY: the old block is valid for the old size, which is larger than
+        // the new size (safety contract of this function), and the new block is
+        // a fresh allocation, so they do not overlap.
+        unsafe {
+            let new = memory.cast::<u8>().as_ptr();
+            ptr::copy_nonoverlapping(ptr.as_ptr(), new, new_layout.size());
+            self.deallocate(ptr, old_layout);
+        }
+        Ok(memory)
+    }
+}
+impl<L: RawLock, const N: usize> GenericAllocator<L, N> {
+    /// Grow the allocation at `ptr` and zero the added memory, if requested.
+    ///
+    /// See [`Allocator::grow()`] for details.
+    ///
+    /// # Safety
+    /// The same requirements as for [`Allocator::grow()`] apply.
+    unsafe fn grow_with(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+        zeroed: bool,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        if old_layout.size() == 0 {
+            // a zero-sized allocation does not have a block, which could grow
+            return if zeroed {
+                self.allocate_zeroed(new_layout)
+            } else {
+                self.allocate(new_layout)
+            };
+        }
+
+        let in_place = if ptr.as_ptr() as usize % new_layout.align() == 0 {
+            self.lock_for_alloc().and_then(|mut raw| {
+                let size = raw.grow_in_place(ptr.as_ptr(), new_layout.size())?;
+                raw.forget_rounding(old_layout.size());
+                raw.count_rounding(new_layout.size());
+                Some(size)
+            })
+        } else {
+            None
+        };
+        let memory = if let Some(size) = in_place {
+            NonNull::slice_from_raw_parts(ptr, size)
+        } else {
+            let memory = self.allocate(new_layout)?;
+            // SAFETY: the old block is valid for its size (safety contract
+            // of this function) and the new block is a fresh allocation of
+            // at least that size, so they do not overlap.
+            unsafe {
+                let new = memory.cast::<u8>().as_ptr();
+                ptr::copy_nonoverlapping(ptr.as_ptr(), new, old_layout.size());
+                self.deallocate(ptr, old_layout);
+            }
+            memory
+        };
+
+        if zeroed {
+            // SAFETY: the memory after the old size is part of the block
+            unsafe {
+                let tail = memory.cast::<u8>().as_ptr().add(old_layout.size());
+                tail.write_bytes(0, memory.len() - old_layout.size());
+            }
+        }
+        Ok(memory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Allocator;
+    use core::alloc::{Allocator as _, Layout};
+
+    #[test]
+    fn allocation_reports_the_usable_size() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 5]>();
+        let memory = allocator.allocate(layout).unwrap();
+        assert_eq!(memory.len(), 8);
+        unsafe { allocator.deallocate(memory.cast(), layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+
+        // over-aligned allocations report the usable size after the padding
+        let layout = Layout::from_size_align(5, 32).unwrap();
+        let memory = allocator.allocate(layout).unwrap();
+        assert_eq!(memory.cast::<u8>().as_ptr() as usize % 32, 0);
+        assert!(memory.len() >= 8);
+        unsafe { allocator.deallocate(memory.cast(), layout) };
+    }
+
+    #[test]
+    fn grow_in_place() {
+        let allocator = Allocator::<128>::new();
+        let old_layout = Layout::new::<[u8; 8]>();
+        let ptr = allocator.allocate(old_layout).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().write_bytes(0xAA, 8) };
+
+        // only the added memory is zeroed, the old data is preserved
+        let new_layout = Layout::new::<[u8; 32]>();
+        let memory = unsafe { allocator.grow_zeroed(ptr, old_layout, new_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+        assert_eq!(memory.len(), 32);
+        let bytes = unsafe { memory.as_ref() };
+        assert_eq!(bytes[..8], [0xAA; 8]);
+        assert_eq!(bytes[8..], [0; 24]);
+        assert_eq!(allocator.stats().used_blocks, 1);
+
+        unsafe { allocator.deallocate(ptr, new_layout) };
+        assert_eq!(allocator.available_bytes(), allocator.capacity());
+    }
+
+    #[test]
+    fn grow_into_an_exactly_fitting_block() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let first = allocator.allocate(layout).unwrap().cast::<u8>();
+        let second = allocator.allocate(layout).unwrap().cast::<u8>();
+        let third = allocator.allocate(layout).unwrap().cast::<u8>();
+        unsafe { allocator.deallocate(second, layout) };
+
+        // the free block including its header is merged into the first one
+        let size = 8 + Allocator::<128>::GRANULARITY + 8;
+        let new_layout = Layout::from_size_align(size, 1).unwrap();
+        let memory = unsafe { allocator.grow(first, layout, new_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), first);
+        assert_eq!(memory.len(), size);
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (2, 1));
+
+        unsafe { allocator.deallocate(third, layout) };
+        unsafe { allocator.deallocate(first, new_layout) };
+    }
+
+    #[test]
+    fn grow_by_moving() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let ptr = allocator.allocate(layout).unwrap().cast::<u8>();
+        let next = allocator.allocate(layout).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().copy_from(b"emballoc".as_ptr(), 8) };
+
+        let new_layout = Layout::new::<[u8; 32]>();
+        let memory = unsafe { allocator.grow_zeroed(ptr, layout, new_layout) }.unwrap();
+        assert_ne!(memory.cast::<u8>(), ptr);
+        let bytes = unsafe { memory.as_ref() };
+        assert_eq!(bytes[..8], *b"emballoc");
+        assert_eq!(bytes[8..], [0; 24]);
+        assert_eq!(allocator.stats().used_blocks, 2);
+
+        unsafe { allocator.deallocate(memory.cast(), new_layout) };
+        unsafe { allocator.deallocate(next, layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    fn grow_over_aligned_allocation() {
+        let allocator = Allocator::<512>::new();
+        let layout = Layout::from_size_align(8, 32).unwrap();
+        let ptr = allocator.allocate(layout).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().copy_from(b"emballoc".as_ptr(), 8) };
+
+        // the same alignment is kept in place
+        let new_layout = Layout::from_size_align(64, 32).unwrap();
+        let memory = unsafe { allocator.grow(ptr, layout, new_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+
+        // a stricter alignment might require moving the data
+        let strict_layout = Layout::from_size_align(64, 128).unwrap();
+        let memory = unsafe { allocator.grow(ptr, new_layout, strict_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>().as_ptr() as usize % 128, 0);
+        assert_eq!(unsafe { &memory.as_ref()[..8] }, b"emballoc");
+        unsafe { allocator.deallocate(memory.cast(), strict_layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn in_place_resizing_counts_the_rounding() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(5, 1).unwrap();
+        let ptr = allocator.allocate(layout).unwrap().cast::<u8>();
+        assert_eq!(allocator.internal_fragmentation(), 3);
+
+        let grown = Layout::from_size_align(30, 1).unwrap();
+        let memory = unsafe { allocator.grow(ptr, layout, grown) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+        assert_eq!(allocator.internal_fragmentation(), 2);
+
+        let shrunk = Layout::from_size_align(9, 1).unwrap();
+        let memory = unsafe { allocator.shrink(ptr, grown, shrunk) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+        assert_eq!(allocator.internal_fragmentation(), 3);
+        unsafe { allocator.deallocate(ptr, shrunk) };
+        assert_eq!(allocator.internal_fragmentation(), 0);
+    }
+
+    #[test]
+    fn shrink_in_place() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 64]>();
+        let ptr = allocator.allocate(layout).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().copy_from(b"emballoc".as_ptr(), 8) };
+
+        // the tail is freed and merged with the following free block
+        let new_layout = Layout::new::<[u8; 7]>();
+        let memory = unsafe { allocator.shrink(ptr, layout, new_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+        assert_eq!(memory.len(), 8);
+        assert_eq!(unsafe { memory.as_ref() }, b"emballoc");
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (1, 1));
+        assert_eq!(
+            stats.free,
+            allocator.capacity() - 8 - Allocator::<128>::GRANULARITY
+        );
+
+        unsafe { allocator.deallocate(ptr, new_layout) };
+        assert_eq!(allocator.available_bytes(), allocator.capacity());
+    }
+
+    #[test]
+    fn shrink_with_stricter_alignment() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::from_size_align(64, 4).unwrap();
+        let ptr = allocator.allocate(layout).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().copy_from(b"emballoc".as_ptr(), 8) };
+
+        // the pointer is kept, if it fulfills the new alignment
+        let align = 1 << (ptr.as_ptr() as usize).trailing_zeros();
+        let new_layout = Layout::from_size_align(8, align).unwrap();
+        let memory = unsafe { allocator.shrink(ptr, layout, new_layout) }.unwrap();
+        assert_eq!(memory.cast::<u8>(), ptr);
+
+        // otherwise the data is moved
+        let strict_layout = Layout::from_size_align(8, 2 * align).unwrap();
+        let memory = unsafe { allocator.shrink(ptr, new_layout, strict_layout) }.unwrap();
+        assert_ne!(memory.cast::<u8>(), ptr);
+        assert_eq!(memory.cast::<u8>().as_ptr() as usize % (2 * align), 0);
+        assert_eq!(unsafe { &memory.as_ref()[..8] }, b"emballoc");
+        unsafe { allocator.deallocate(memory.cast(), strict_layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    fn zero_sized_allocation() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(0, 16).unwrap();
+        let memory = allocator.allocate(layout).unwrap();
+        assert_eq!(memory.len(), 0);
+        assert_eq!(memory.cast::<u8>().as_ptr() as usize % 16, 0);
+        assert_eq!(allocator.stats().used_blocks, 0);
+        unsafe { allocator.deallocate(memory.cast(), layout) };
+    }
+
+    #[test]
+    fn vec_in_the_allocator() {
+        let allocator = Allocator::<128>::new();
+        let mut vec = std::vec::Vec::with_capacity_in(5, &allocator);
+        vec.extend_from_slice(b"hello");
+        // the `Vec` of the standard library currently ignores additional memory
+        assert!(vec.capacity() >= 5);
+        assert_eq!(allocator.stats().used_blocks, 1);
+        drop(vec);
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    fn empty_vec_does_not_use_the_heap() {
+        let allocator = Allocator::<128>::new();
+        let mut vec = std::vec::Vec::<u32, _>::new_in(&allocator);
+        assert_eq!(allocator.stats().used_blocks, 0);
+
+        // the first push grows the zero-sized allocation
+        vec.push(42);
+        assert_eq!(allocator.stats().used_blocks, 1);
+        assert_eq!(vec.pop(), Some(42));
+        vec.shrink_to_fit();
+        assert_eq!(allocator.stats().used_blocks, 0);
+
+        vec.push(7);
+        assert_eq!(vec, [7]);
+        drop(vec);
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+}
@@ -0,0 +1,189 @@
+//! This module provides the [`BumpAllocator`] for heaps, which are never freed.
+use core::alloc::{GlobalAlloc, Layout};
+use core::mem::MaybeUninit;
+use core::ptr;
+
+/// A bump allocator for memory, which is allocated once and never freed.
+///
+/// Often the heap memory is only allocated during the initialization phase of a
+/// firmware (e.g. for long-lived data structures) and never freed afterwards.
+/// For such use cases the [`Allocator`](crate::Allocator) is more than needed:
+/// this allocator simply hands out the next unused bytes of its buffer. This is
+/// faster and there is no memory overhead per allocation, as no headers are
+/// stored in the heap.
+///
+/// Individual allocations cannot be freed: [`GlobalAlloc::dealloc()`] does
+/// nothing. All the memory can only be released at once via [`reset()`].
+/// ```
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::BumpAllocator<4096> = emballoc::BumpAllocator::new();
+/// ```
+///
+/// [`reset()`]: Self::reset
+pub struct BumpAllocator<const N: usize> {
+    /// The internal state of the bump allocator.
+    bump: spin::Mutex<Bump<N>>,
+}
+impl<const N: usize> BumpAllocator<N> {
+    /// Create a new [`BumpAllocator`] with exactly `N` bytes heap space.
+    ///
+    /// In contrast to the [`Allocator`](crate::Allocator), the whole heap can
+    /// be used for allocations, as there are no headers.
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn new() -> Self {
+        let bump = spin::Mutex::new(Bump {
+            memory: [MaybeUninit::uninit(); N],
+            next: 0,
+        });
+        Self { bump }
+    }
+
+    /// Query the number of bytes used by the allocations so far.
+    ///
+    /// This includes the padding between the allocations, which is necessary
+    /// to fulfill the alignment requirements.
+    pub fn used(&self) -> usize {
+        self.bump.lock().next
+    }
+
+    /// Release all the allocations at once.
+    ///
+    /// Afterwards, the whole heap is available for allocations again.
+    ///
+    /// # Safety
+    /// All the memory allocated so far is handed out again, so the caller has
+    /// to ensure, that none of the previous allocations is used afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::BumpAllocator<4096> = emballoc::BumpAllocator::new();
+    ///
+    /// let ptr = unsafe { ALLOCATOR.alloc(Layout::new::<u32>()) };
+    /// assert_eq!(ALLOCATOR.used(), 4);
+    /// unsafe { ALLOCATOR.reset() }; // `ptr` must not be used anymore
+    /// assert_eq!(ALLOCATOR.used(), 0);
+    /// ```
+    pub unsafe fn reset(&self) {
+        self.bump.lock().next = 0;
+    }
+}
+impl<const N: usize> Default for BumpAllocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// SAFETY: the memory handed out is taken from the unused part of the buffer,
+// which is never handed out again (except after a `reset()`, which has to
+// guarantee, that the previous allocations are not used anymore).
+unsafe impl<const N: usize> GlobalAlloc for BumpAllocator<N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.bump.lock().alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {
+        // individual allocations are never freed, only all at once by `reset()`
+    }
+}
+
+/// The internal state of a [`BumpAllocator`].
+struct Bump<const N: usize> {
+    /// The heap memory.
+    memory: [MaybeUninit<u8>; N],
+    /// The offset of the first unused byte of the heap memory.
+    next: usize,
+}
+impl<const N: usize> Bump<N> {
+    /// Allocate the memory for the `layout` after all previous allocations.
+    ///
+    /// The start of the allocation is aligned as requested by the `layout`. A
+    /// null pointer is returned, if the remaining memory is too small.
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let base = self.memory.as_mut_ptr().cast::<u8>();
+        let unused = base.wrapping_add(self.next);
+        let start = self.next.checked_add(unused.align_offset(layout.align()));
+        let end = start.and_then(|start| start.checked_add(layout.size()));
+        match (start, end) {
+            (Some(start), Some(end)) if end <= N => {
+                self.next = end;
+                base.wrapping_add(start)
+            }
+            _ => ptr::null_mut(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BumpAllocator;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::ptr;
+
+    #[test]
+    fn allocations_are_contiguous() {
+        let allocator = BumpAllocator::<64>::new();
+        let layout = Layout::new::<[u8; 3]>();
+        let first = unsafe { allocator.alloc(layout) };
+        let second = unsafe { allocator.alloc(layout) };
+        let third = unsafe { allocator.alloc(layout) };
+
+        // there are no headers between the allocations
+        assert_eq!(second as usize, first as usize + 3);
+        assert_eq!(third as usize, second as usize + 3);
+        assert_eq!(allocator.used(), 9);
+    }
+
+    #[test]
+    fn alignment() {
+        let allocator = BumpAllocator::<64>::new();
+        unsafe { allocator.alloc(Layout::new::<u8>()) };
+        for align in [2, 4, 8, 16] {
+            let ptr = unsafe { allocator.alloc(Layout::from_size_align(1, align).unwrap()) };
+            assert_ne!(ptr, ptr::null_mut());
+            assert_eq!(ptr as usize % align, 0);
+        }
+    }
+
+    #[test]
+    fn exhaustion() {
+        let allocator = BumpAllocator::<16>::new();
+        let layout = Layout::new::<[u8; 6]>();
+        assert_ne!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+        assert_ne!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+        assert_eq!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+
+        // the remaining memory can still be used
+        assert_ne!(
+            unsafe { allocator.alloc(Layout::new::<u8>()) },
+            ptr::null_mut()
+        );
+        assert_eq!(allocator.used(), 13);
+
+        let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+        assert_eq!(unsafe { allocator.alloc(huge) }, ptr::null_mut());
+    }
+
+    #[test]
+    fn dealloc_does_not_free() {
+        let allocator = BumpAllocator::<16>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.used(), 8);
+        assert_ne!(unsafe { allocator.alloc(layout) }, ptr);
+    }
+
+    #[test]
+    fn reset_frees_everything() {
+        let allocator = BumpAllocator::<32>::new();
+        let layout = Layout::new::<[u8; 16]>();
+        let first = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.alloc(layout) };
+        assert_eq!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+
+        unsafe { allocator.reset() };
+        assert_eq!(allocator.used(), 0);
+        assert_eq!(unsafe { allocator.alloc(layout) }, first);
+    }
+}
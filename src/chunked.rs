@@ -0,0 +1,256 @@
+//! This module provides the [`ChunkedAllocator`] for heaps growing at runtime.
+use crate::{GenericAllocator, RawLock};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::ptr;
+
+/// A heap consisting of several separately declared chunks.
+///
+/// If the heap size is not known at compile time (e.g. because it differs
+/// between the variants of a product), the heap can be declared as several
+/// chunks, i.e. separate allocators with `M` bytes of heap space each. The
+/// chunks are added at runtime via [`add_chunk()`](Self::add_chunk), up to `C`
+/// of them. An allocation is served by the first chunk (in the order of their
+/// addition), which has enough memory available. Therefore the later chunks are
+/// only used, once the earlier ones are full, so that the allocations stay
+/// close together under light load.
+///
+/// Every allocation fails, until the first chunk is added. Therefore the first
+/// chunk has to be added before the first allocation, e.g. at the start of a
+/// bare-metal program, if this is used as the global allocator.
+///
+/// The chunks are identified by their index in the order of their addition,
+/// i.e. the first chunk added has the id `0`. If an allocation has to be placed
+/// in a specific chunk (e.g. because only one of them is accessible by a DMA
+/// controller), [`alloc_in_region()`](Self::alloc_in_region) can be used.
+///
+/// A deallocation is forwarded to the chunk containing the pointer. Since the
+/// chunks are independent heaps, an allocation can never span several chunks,
+/// i.e. the largest allocation is limited by the size of a single chunk.
+///
+/// # Example
+/// ```
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use emballoc::{Allocator, ChunkedAllocator};
+///
+/// static FIRST: Allocator<1024> = Allocator::new();
+/// static SECOND: Allocator<1024> = Allocator::new();
+///
+/// static ALLOCATOR: ChunkedAllocator<emballoc::SpinLock, 1024, 2> = ChunkedAllocator::new();
+///
+/// assert!(ALLOCATOR.add_chunk(&FIRST));
+/// // e.g. only on product variants with more memory
+/// assert!(ALLOCATOR.add_chunk(&SECOND));
+/// ```
+pub struct ChunkedAllocator<L: 'static, const M: usize, const C: usize> {
+    /// The chunks added so far (in the order of their addition).
+    chunks: spin::Mutex<[Option<&'static GenericAllocator<L, M>>; C]>,
+}
+impl<L, const M: usize, const C: usize> ChunkedAllocator<L, M, C> {
+    /// Create a new [`ChunkedAllocator`] without any chunks.
+    ///
+    /// Every allocation fails, until the first chunk is added via
+    /// [`add_chunk()`](Self::add_chunk).
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn new() -> Self {
+        Self {
+            chunks: spin::Mutex::new([None; C]),
+        }
+    }
+}
+impl<L, const M: usize, const C: usize> Default for ChunkedAllocator<L, M, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<L: RawLock, const M: usize, const C: usize> ChunkedAllocator<L, M, C> {
+    /// Add the `chunk` to the heap.
+    ///
+    /// The chunk is used after all the previously added chunks. Its memory has
+    /// to be managed by this allocator exclusively from now on, i.e. the chunk
+    /// must not be used directly or added to another allocator. If there are
+    /// already `C` chunks or if the chunk was already added, `false` is
+    /// returned and the heap is unchanged.
+    pub fn add_chunk(&self, chunk: &'static GenericAllocator<L, M>) -> bool {
+        let mut chunks = self.chunks.lock();
+        if chunks.iter().flatten().any(|added| ptr::eq(*added, chunk)) {
+            return false;
+        }
+        let free = chunks.iter_mut().find(|slot| slot.is_none());
+        free.map_or(false, |slot| {
+            *slot = Some(chunk);
+            true
+        })
+    }
+
+    /// Query the number of chunks added so far.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.lock().iter().flatten().count()
+    }
+
+    /// Allocate memory in the chunk with the given id.
+    ///
+    /// The `region_id` is the index of the chunk in the order of their addition.
+    /// Only that chunk is tried: if it cannot satisfy the allocation (or if
+    /// there is no chunk with that id), a null pointer is returned, even if
+    /// another chunk has enough memory available. This allows to place
+    /// allocations according to hardware constraints. The memory is
+    /// deallocated with [`GlobalAlloc::dealloc()`] as usual.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::{Allocator, ChunkedAllocator};
+    ///
+    /// static SRAM: Allocator<1024> = Allocator::new();
+    /// static PSRAM: Allocator<1024> = Allocator::new();
+    ///
+    /// static ALLOCATOR: ChunkedAllocator<emballoc::SpinLock, 1024, 2> = ChunkedAllocator::new();
+    /// ALLOCATOR.add_chunk(&PSRAM);
+    /// ALLOCATOR.add_chunk(&SRAM);
+    ///
+    /// // the DMA buffer has to be placed in the internal SRAM
+    /// let layout = Layout::new::<[u8; 64]>();
+    /// let buffer = unsafe { ALLOCATOR.alloc_in_region(1, layout) };
+    /// assert!(SRAM.block_of(buffer).is_some());
+    /// unsafe { ALLOCATOR.dealloc(buffer, layout) };
+    /// ```
+    pub unsafe fn alloc_in_region(&self, region_id: usize, layout: Layout) -> *mut u8 {
+        match self.chunks().get(region_id) {
+            // SAFETY: the safety contract is the same as for this function.
+            Some(Some(chunk)) => unsafe { chunk.alloc_with_size(layout) }
+                .map_or(ptr::null_mut(), |(ptr, _size)| ptr.as_ptr()),
+            _ => ptr::null_mut(),
+        }
+    }
+
+    /// Obtain a copy of the chunks added so far.
+    ///
+    /// The lock of the chunks is released again, so that it is not held
+    /// during the (possibly long) operations on the chunks themselves. This is
+    /// sound, as chunks are only ever added, but never removed.
+    fn chunks(&self) -> [Option<&'static GenericAllocator<L, M>>; C] {
+        *self.chunks.lock()
+    }
+}
+impl<L: RawLock, const M: usize, const C: usize> fmt::Debug for ChunkedAllocator<L, M, C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChunkedAllocator")
+            .field("chunk_size", &M)
+            .field("chunks", &self.chunk_count())
+            .field("capacity", &C)
+            .finish()
+    }
+}
+// SAFETY: the allocations are forwarded to the allocators of the chunks, which
+// fulfill the contract of `GlobalAlloc`. A deallocation is forwarded to the
+// chunk containing the memory, which is the one, that allocated it.
+unsafe impl<L: RawLock, const M: usize, const C: usize> GlobalAlloc for ChunkedAllocator<L, M, C> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        for chunk in self.chunks().iter().flatten() {
+            // SAFETY: the safety contract is the same as for this function.
+            if let Some((ptr, _size)) = unsafe { chunk.alloc_with_size(layout) } {
+                return ptr.as_ptr();
+            }
+        }
+        ptr::null_mut()
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let owner = self.chunks().iter().flatten().copied().find(|chunk| {
+            let (start, size) = chunk.memory_region();
+            (start as usize..start as usize + size).contains(&(ptr as usize))
+        });
+        if let Some(owner) = owner {
+            // SAFETY: the memory was allocated by this chunk (the caller
+            // guarantees, that it was allocated by this allocator), therefore
+            // the safety contract is the same as for this function.
+            unsafe { owner.dealloc(ptr, layout) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkedAllocator;
+    use crate::{Allocator, SpinLock};
+    use core::alloc::{GlobalAlloc, Layout};
+
+    #[test]
+    fn later_chunks_are_used_when_earlier_ones_are_full() {
+        static FIRST: Allocator<64> = Allocator::new();
+        static SECOND: Allocator<64> = Allocator::new();
+        let allocator = ChunkedAllocator::<SpinLock, 64, 2>::new();
+        let layout = Layout::from_size_align(24, 4).unwrap();
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+
+        assert!(allocator.add_chunk(&FIRST));
+        assert!(allocator.add_chunk(&SECOND));
+        assert!(!allocator.add_chunk(&SECOND));
+        assert_eq!(allocator.chunk_count(), 2);
+
+        let a = unsafe { allocator.alloc(layout) };
+        let b = unsafe { allocator.alloc(layout) };
+        assert!(FIRST.block_of(a).is_some() && FIRST.block_of(b).is_some());
+        assert_eq!(SECOND.stats().used_blocks, 0);
+
+        // the first chunk is full, so the second one is used
+        let c = unsafe { allocator.alloc(layout) };
+        assert!(SECOND.block_of(c).is_some());
+
+        // every deallocation is forwarded to the chunk containing the memory
+        unsafe { allocator.dealloc(c, layout) };
+        assert_eq!(SECOND.stats().used_blocks, 0);
+        unsafe { allocator.dealloc(a, layout) };
+        assert_eq!(FIRST.stats().used_blocks, 1);
+
+        // the freed memory of the first chunk is preferred again
+        let d = unsafe { allocator.alloc(layout) };
+        assert!(FIRST.block_of(d).is_some());
+        let large = Layout::from_size_align(64, 4).unwrap();
+        assert!(unsafe { allocator.alloc(large) }.is_null());
+    }
+
+    #[test]
+    fn allocation_in_a_specific_region() {
+        static FIRST: Allocator<64> = Allocator::new();
+        static SECOND: Allocator<64> = Allocator::new();
+        let allocator = ChunkedAllocator::<SpinLock, 64, 3>::new();
+        let layout = Layout::from_size_align(24, 4).unwrap();
+        assert!(allocator.add_chunk(&FIRST));
+        assert!(allocator.add_chunk(&SECOND));
+
+        let a = unsafe { allocator.alloc_in_region(1, layout) };
+        let b = unsafe { allocator.alloc_in_region(1, layout) };
+        assert!(SECOND.block_of(a).is_some() && SECOND.block_of(b).is_some());
+
+        // the second region is full, the first one is not used instead
+        assert!(unsafe { allocator.alloc_in_region(1, layout) }.is_null());
+        assert_eq!(FIRST.stats().used_blocks, 0);
+        let c = unsafe { allocator.alloc_in_region(0, layout) };
+        assert!(FIRST.block_of(c).is_some());
+
+        // there are no chunks with these ids
+        assert!(unsafe { allocator.alloc_in_region(2, layout) }.is_null());
+        assert!(unsafe { allocator.alloc_in_region(3, layout) }.is_null());
+
+        unsafe { allocator.dealloc(a, layout) };
+        let d = unsafe { allocator.alloc_in_region(1, layout) };
+        assert_eq!(d, a);
+    }
+
+    #[test]
+    fn limited_number_of_chunks() {
+        static FIRST: Allocator<32> = Allocator::new();
+        static SECOND: Allocator<32> = Allocator::new();
+        let allocator = ChunkedAllocator::<SpinLock, 32, 1>::new();
+        assert!(allocator.add_chunk(&FIRST));
+        assert!(!allocator.add_chunk(&SECOND));
+        assert_eq!(allocator.chunk_count(), 1);
+    }
+}
@@ -0,0 +1,244 @@
+//! This module provides the entry point for fuzzing the allocator.
+//!
+//! The function [`fuzz_operations()`] interprets arbitrary bytes as a sequence
+//! of allocator operations, so that a fuzzer (e.g. `cargo fuzz`, see the `fuzz`
+//! directory of the repository) can search for sequences breaking the
+//! splitting and merging of the blocks.
+extern crate alloc;
+
+use crate::{Allocator, Strategy};
+use alloc::vec::Vec;
+use core::alloc::{GlobalAlloc, Layout};
+
+/// The heap size used by the fuzzing harness.
+///
+/// The heap is small, so that the heap is exhausted and fragmented quickly.
+const HEAP_SIZE: usize = 512;
+
+/// A live allocation of the model.
+struct Allocation {
+    /// The address of the allocation.
+    ptr: *mut u8,
+    /// The layout used for the allocation.
+    layout: Layout,
+    /// The byte, which the whole memory of the allocation is filled with.
+    tag: u8,
+}
+impl Allocation {
+    /// Fill the memory of the allocation with its tag.
+    fn fill(&self) {
+        // SAFETY: the allocation is live and valid for its size
+        unsafe { self.ptr.write_bytes(self.tag, self.layout.size()) };
+    }
+
+    /// Check, that the first `len` bytes of the memory still contain the tag.
+    fn check(&self, len: usize) {
+        // SAFETY: the allocation is live, valid for its size (which is at
+        // least `len`) and initialized by `fill()`.
+        let memory = unsafe { core::slice::from_raw_parts(self.ptr, len) };
+        assert!(
+            memory.iter().all(|&byte| byte == self.tag),
+            "memory of an allocation was overwritten"
+        );
+    }
+}
+
+/// The sequence of bytes interpreted as operations.
+struct Input<'data>(core::slice::Iter<'data, u8>);
+impl Input<'_> {
+    /// Take the next byte of the input.
+    fn byte(&mut self) -> Option<u8> {
+        self.0.next().copied()
+    }
+
+    /// Take the next byte of the input as a size in the range `1..=256`.
+    fn size(&mut self) -> Option<usize> {
+        self.byte().map(|byte| usize::from(byte) + 1)
+    }
+
+    /// Take the next byte of the input as a layout of the given size.
+    fn layout(&mut self, size: usize) -> Option<Layout> {
+        let align = 1 << (self.byte()? % 7);
+        Layout::from_size_align(size, align).ok()
+    }
+}
+
+/// Run the operations encoded in `data` against a fresh allocator.
+///
+/// Every operation starts with a byte selecting it, followed by its arguments:
+///
+/// | selector  | operation          | arguments                            |
+/// |-----------|--------------------|--------------------------------------|
+/// | `0 mod 5` | `alloc()`          | size - 1, alignment exponent (mod 7) |
+/// | `1 mod 5` | `alloc_from_top()` | size - 1, alignment exponent (mod 7) |
+/// | `2 mod 5` | `dealloc()`        | index of the live allocation         |
+/// | `3 mod 5` | `realloc()`        | index of the live allocation, size-1 |
+/// | `4 mod 5` | `set_strategy()`   | strategy (mod 3)                     |
+///
+/// The index of a live allocation is taken modulo the number of allocations.
+/// The sequence ends at the first incomplete operation. A model of the live
+/// allocations is kept and every allocation is filled with a tag byte.
+/// After each step, the following invariants are checked:
+/// - every returned pointer lies completely inside the heap
+/// - every returned pointer fulfills the requested alignment
+/// - no two live allocations overlap and their memory is never overwritten
+/// - the data is preserved by a reallocation
+/// - the blocks tile the whole heap and the number of used blocks matches the
+///   model
+///
+/// The function is deterministic for a given input. This function is only
+/// available with the `fuzzing` feature.
+///
+/// # Panics
+/// This function panics, if any of the invariants is violated, which the
+/// fuzzer reports as a crash.
+///
+/// # Example
+/// ```
+/// // allocate 8 bytes twice, free the first allocation and grow the second one
+/// emballoc::fuzz_operations(&[0, 7, 0, 0, 7, 0, 2, 0, 3, 0, 99]);
+/// ```
+pub fn fuzz_operations(data: &[u8]) {
+    let allocator = Allocator::<HEAP_SIZE>::new();
+    let (start, size) = allocator.memory_region();
+    let heap = start as usize..start as usize + size;
+    let mut input = Input(data.iter());
+    let mut live: Vec<Allocation> = Vec::new();
+
+    let mut tag = 0_u8;
+    while let Some(allocation) = step(&allocator, &mut input, &mut live, tag) {
+        tag = tag.wrapping_add(1);
+        if let Some(allocation) = allocation.filter(|allocation| !allocation.ptr.is_null()) {
+            let start = allocation.ptr as usize;
+            let end = start + allocation.layout.size();
+            assert!(
+                heap.contains(&start) && end <= heap.end,
+                "allocation outside of the heap"
+            );
+            assert_eq!(
+                start % allocation.layout.align(),
+                0,
+                "misaligned allocation"
+            );
+            for other in &live {
+                let other_start = other.ptr as usize;
+                let other_end = other_start + other.layout.size();
+                assert!(
+                    end <= other_start || other_end <= start,
+                    "overlapping allocations"
+                );
+            }
+            allocation.fill();
+            live.push(allocation);
+        }
+
+        let raw = allocator.raw.lock();
+        assert!(raw.is_consistent(), "the blocks do not tile the heap");
+        assert_eq!(
+            raw.stats().used_blocks,
+            live.len(),
+            "wrong number of used blocks"
+        );
+        drop(raw);
+        for allocation in &live {
+            allocation.check(allocation.layout.size());
+        }
+    }
+}
+
+/// Run the next operation of the `input`.
+///
+/// A new allocation (which might be null) is returned to be checked by the
+/// caller. The allocation is tagged with `tag`. If the input is exhausted,
+/// `None` is returned.
+#[allow(clippy::option_option)] // the outer `None` marks the end of the input
+fn step(
+    allocator: &Allocator<HEAP_SIZE>,
+    input: &mut Input,
+    live: &mut Vec<Allocation>,
+    tag: u8,
+) -> Option<Option<Allocation>> {
+    let selector = input.byte()?;
+    let allocation = match selector % 5 {
+        0 | 1 => {
+            let size = input.size()?;
+            let layout = input.layout(size)?;
+            let ptr = if selector % 5 == 0 {
+                // SAFETY: the size of the layout is non-zero
+                unsafe { allocator.alloc(layout) }
+            } else {
+                // SAFETY: the size of the layout is non-zero
+                unsafe { allocator.alloc_from_top(layout) }
+            };
+            Some(Allocation { ptr, layout, tag })
+        }
+        2 => {
+            let index = usize::from(input.byte()?);
+            if !live.is_empty() {
+                let allocation = live.swap_remove(index % live.len());
+                allocation.check(allocation.layout.size());
+                // SAFETY: the allocation is live and was made with this layout
+                // by this allocator
+                unsafe { allocator.dealloc(allocation.ptr, allocation.layout) };
+            }
+            None
+        }
+        3 => {
+            let index = usize::from(input.byte()?);
+            let new_size = input.size()?;
+            if live.is_empty() {
+                return Some(None);
+            }
+            let old = live.swap_remove(index % live.len());
+            // SAFETY: the allocation is live and was made with this layout by
+            // this allocator. The new size is non-zero.
+            let ptr = unsafe { allocator.realloc(old.ptr, old.layout, new_size) };
+            if ptr.is_null() {
+                // the old allocation is still valid
+                live.push(old);
+                return Some(None);
+            }
+            let layout = Layout::from_size_align(new_size, old.layout.align())
+                .expect("the size was accepted by `realloc()`");
+            let moved = Allocation {
+                ptr,
+                layout,
+                tag: old.tag,
+            };
+            moved.check(old.layout.size().min(new_size));
+            Some(Allocation { ptr, layout, tag })
+        }
+        _ => {
+            allocator.set_strategy(match input.byte()? % 3 {
+                0 => Strategy::BestFit,
+                1 => Strategy::FirstFit,
+                _ => Strategy::Frontier,
+            });
+            None
+        }
+    };
+    Some(allocation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzz_operations;
+
+    #[test]
+    fn seed_corpus() {
+        fuzz_operations(include_bytes!("../fuzz/corpus/operations/alloc_free"));
+        fuzz_operations(include_bytes!("../fuzz/corpus/operations/fragmentation"));
+        fuzz_operations(include_bytes!("../fuzz/corpus/operations/realloc"));
+        fuzz_operations(include_bytes!("../fuzz/corpus/operations/strategies"));
+    }
+
+    #[test]
+    fn arbitrary_input() {
+        crate::tests::return_null_on_oom();
+        fuzz_operations(&[]);
+        fuzz_operations(&[0]);
+        fuzz_operations(&[2, 3, 3, 4]);
+        let data: Vec<u8> = (0..2000_u32).map(|i| (i * 7919 % 251) as u8).collect();
+        fuzz_operations(&data);
+    }
+}
@@ -0,0 +1,99 @@
+//! This module provides the [`Handle`] to relocatable allocations.
+//!
+//! With the `relocating` feature, the heap can be compacted by moving the live
+//! allocations. Raw pointers to the moved allocations become invalid, so the
+//! allocator keeps a small table of the current offsets of the allocations made
+//! via [`Allocator::alloc_handle()`](crate::Allocator::alloc_handle). That table
+//! is updated during the compaction, so the handles stay valid.
+use crate::{GenericAllocator, RawLock};
+
+use core::num::NonZeroUsize;
+
+/// The number of handles, which can be allocated at the same time.
+pub const HANDLE_COUNT: usize = 16;
+
+/// A handle to a relocatable allocation.
+///
+/// Unlike a raw pointer, a handle stays valid when its allocation is moved by
+/// [`Allocator::compact()`](crate::Allocator::compact): it refers to an entry
+/// in the handle table of the allocator, which is updated when the allocation
+/// is moved. The current address is queried via [`as_ptr()`](Self::as_ptr).
+/// There are at most 16 handles per allocator at the same time.
+///
+/// A handle is obtained via [`Allocator::alloc_handle()`] and is released via
+/// [`Allocator::dealloc_handle()`]. It must only be used with the allocator,
+/// which created it.
+///
+/// [`Allocator::alloc_handle()`]: crate::Allocator::alloc_handle
+/// [`Allocator::dealloc_handle()`]: crate::Allocator::dealloc_handle
+#[derive(Debug, PartialEq, Eq)]
+pub struct Handle {
+    /// The index of the entry in the handle table of the allocator.
+    pub(crate) index: usize,
+}
+impl Handle {
+    /// Query the current address of the allocation.
+    ///
+    /// The address is only valid until the next compaction of the heap, so it
+    /// should be queried again after every compaction. If the handle does not
+    /// belong to the `allocator`, the result is meaningless.
+    pub fn as_ptr<L: RawLock, const N: usize>(
+        &self,
+        allocator: &GenericAllocator<L, N>,
+    ) -> *mut u8 {
+        allocator.raw.lock().handle(self.index)
+    }
+}
+
+/// The table of the offsets of the allocations referenced by handles.
+///
+/// The offsets are the offsets of the memory of the blocks (not of their
+/// headers) from the start of the heap, therefore they are never zero.
+pub struct HandleTable([Option<NonZeroUsize>; HANDLE_COUNT]);
+impl HandleTable {
+    /// Create a new, empty handle table.
+    pub const fn new() -> Self {
+        Self([None; HANDLE_COUNT])
+    }
+
+    /// Check whether all the entries of the table are in use.
+    pub fn is_full(&self) -> bool {
+        self.0.iter().all(Option::is_some)
+    }
+
+    /// Store the offset in a free entry and return the index of that entry.
+    ///
+    /// If there is no free entry, `None` is returned.
+    pub fn insert(&mut self, offset: NonZeroUsize) -> Option<usize> {
+        let index = self.0.iter().position(Option::is_none)?;
+        self.0[index] = Some(offset);
+        Some(index)
+    }
+
+    /// Query the offset stored in the entry with the given index.
+    pub fn get(&self, index: usize) -> Option<NonZeroUsize> {
+        self.0.get(index).copied().flatten()
+    }
+
+    /// Remove the offset stored in the entry with the given index.
+    pub fn remove(&mut self, index: usize) -> Option<NonZeroUsize> {
+        self.0.get_mut(index).and_then(Option::take)
+    }
+
+    /// Check, whether an entry refers to the allocation at `offset`.
+    pub fn contains(&self, offset: NonZeroUsize) -> bool {
+        self.0.contains(&Some(offset))
+    }
+
+    /// Update the entry of an allocation, which moved from `old` to `new`.
+    pub fn relocate(&mut self, old: NonZeroUsize, new: NonZeroUsize) {
+        for offset in self.0.iter_mut().filter(|offset| **offset == Some(old)) {
+            *offset = Some(new);
+        }
+    }
+
+    /// Remove all the entries.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
@@ -1,8 +1,8 @@
 //! Simple allocator for embedded systems
 //!
-//! This crate provides a single type called [`Allocator`]. This type implements
-//! the [`core::alloc::GlobalAlloc`]-trait, which is required to use the
-//! [`alloc`-crate][alloc] on `#![no_std]`-targets. The allocator provided in
+//! The core of this crate is a single type called [`Allocator`]. This type
+//! implements the [`core::alloc::GlobalAlloc`]-trait, which is required to use
+//! the [`alloc`-crate][alloc] on `#![no_std]`-targets. The allocator provided in
 //! this crate is relatively simple, but reliable: its design is simple, so that
 //! errors in the implementation are unlikely. Furthermore the crate is tested
 //! rigorously (see below).
@@ -28,8 +28,15 @@
 //! implies, that more allocations will result in less usable space in the heap.
 //! The minimal buffer size is `8`, which would allow exactly one allocation of
 //! size up to 4 at a time. Adjust the size as necessary, e.g. by doing a worst
-//! case calculation and potentially adding some backup space of 10% (for
-//! example).
+//! case calculation (see [`required_size()`]) and potentially adding some
+//! backup space of 10% (for example).
+//!
+//! The size of a single block is stored in 31 bits of its header, therefore a
+//! block holds at most `0x7FFF_FFFC` bytes (just below 2 GiB, `0x7FFF_FFF8`
+//! with the `granularity-8` feature). This limits the
+//! heap size `N` to `0x8000_0000` bytes, while larger heaps are rejected when
+//! creating the allocator. Adjacent free blocks are never merged into a block
+//! exceeding that limit, they are kept separate instead.
 //!
 //! [^note-header-size]: this value is critical for worst-case calculations and
 //! therefore part of the stability guarantees of this crate. Changing it will
@@ -50,6 +57,20 @@
 //! likely complicate the algorithm and thus is incompatible with the goal to be
 //! "simple").
 //!
+//! In strictly single-threaded contexts without interrupts using the allocator,
+//! the lock is unnecessary. For those, the [`LocalAllocator`] is provided, which
+//! works without any locking. It is `unsafe` to create, as the user has to
+//! guarantee, that there is no concurrent access.
+//!
+//! If the memory is only allocated once (e.g. during the initialization of the
+//! program) and never freed, the [`BumpAllocator`] can be used instead. It
+//! simply hands out the next unused bytes of the heap without any headers and
+//! can only release all allocations at once.
+//!
+//! If the heap size is not known at compile time, the heap can be split into
+//! several chunks, which are added at runtime to a [`ChunkedAllocator`]. The
+//! later chunks are only used, once the earlier ones are full.
+//!
 //! A general problem with non-lock-free allocators is the following: it can
 //! cause deadlocks even in single-threaded environments if there are interrupts
 //! that will _allocate memory_. The interrupt is kind of a second thread, that
@@ -58,7 +79,11 @@
 //! obtain it, since the main program is interrupted and thus cannot release the
 //! lock. Therefore it is advised to never use any allocations (or deallocations
 //! to the same extend) in an interrupt handler. Performance-wise this shouldn't
-//! be done anyway.
+//! be done anyway. If an interrupt handler really has to allocate memory, use
+//! [`Allocator::alloc_nonblocking()`], which fails instead of waiting for the
+//! lock. Alternatively, the spin lock can be replaced by a lock masking the
+//! interrupts while the heap is accessed (see [`RawLock`]). Such a lock is used
+//! via [`GenericAllocator::with_lock()`].
 //!
 //! # Advanced embedded features
 //! Note to users with things like `MPU`s, `MMU`s, etc.: your device might
@@ -68,7 +93,107 @@
 //! along with the known size `N` to protect the heap memory. To users with a
 //! fully-working MMU: it is recommended, that you use an allocator, that
 //! actually supports paging, etc. This crate might still be helpful, e.g.
-//! before setting up the MMU.
+//! before setting up the MMU. If the heap should be placed in a special memory
+//! region, the [`allocator_in_section!`] macro can be used.
+//!
+//! # Cargo features
+//! The allocator does not require any features to be enabled. There are some
+//! optional features, which are disabled by default:
+//! - `relocating`: enables `Allocator::compact()`, which moves live
+//!   allocations to defragment the heap. This is only useful for programs,
+//!   which can tolerate moving allocations (e.g. handle-based designs). The
+//!   `Handle`s of `Allocator::alloc_handle()` are updated automatically.
+//! - `zeroize-on-free`: overwrites the memory of every allocation with zeros,
+//!   when it is freed (only the links of the free list are written to it
+//!   afterwards, see below). This prevents sensitive data (e.g. keys) from
+//!   lingering in the memory, but makes every deallocation more expensive.
+//! - `panic-on-dealloc-error`: aborts the program on errors detected while
+//!   freeing memory (e.g. a double-free) instead of ignoring them. This is
+//!   intended for tests on hosted targets, which should fail loudly on such
+//!   bugs. As a global allocator must not unwind, the panic describing the
+//!   error is not unwound (and thus cannot be caught), but aborts the program
+//!   after the panic handler reported it.
+//! - `defmt`: logs failed allocations via `GlobalAlloc::alloc()` (together
+//!   with the `HeapStats` at that moment) and invalid deallocations (e.g.
+//!   double-frees) as errors via `defmt`. The diagnostic types (e.g.
+//!   `HeapStats`, `BlockInfo` and `FreeError`) implement `defmt::Format`, so
+//!   they can be logged by the application as well. The application has to
+//!   provide the global `defmt` logger (e.g. via `defmt-rtt`), otherwise it
+//!   fails to link.
+//! - `simulation`: enables `simulate()`, which runs an allocation pattern on a
+//!   throwaway heap and reports the peak usage including the fragmentation.
+//!   This helps to choose the heap size. It requires the `alloc`-crate, so it
+//!   is intended for tooling and tests on a hosted target.
+//! - `backtrace`: captures a backtrace for every allocation, which can be
+//!   inspected via `Allocator::live_allocations()`. This helps to find the
+//!   origin of leaked memory in tests. Allocations via the additional method
+//!   `Allocator::alloc_tracked()` record the source location of the call as
+//!   well. It requires the `std`-crate and Rust 1.65 or newer, so it is
+//!   intended for tests on a hosted target only.
+//! - `bounded-spin`: gives up acquiring the internal lock for an allocation
+//!   after `SPIN_LIMIT` attempts, so that the allocation fails instead of
+//!   waiting forever. This turns a deadlock into an allocation failure, which
+//!   is easier to diagnose during development. Deallocations still wait until
+//!   the lock is available. This should not be used in production, as an
+//!   allocation might fail spuriously under heavy contention.
+//! - `allocator-api`: implements the unstable `Allocator`-trait, so that the
+//!   allocator can be used for individual collections (e.g. via
+//!   `Vec::with_capacity_in()`). The allocations report the whole usable size
+//!   of their blocks. This requires a nightly compiler.
+//! - `ring-log`: records the last 16 allocations and deallocations in a small
+//!   ring buffer inside the allocator, which can be copied out via
+//!   `Allocator::recent_ops()`, e.g. after a crash. This acts like a black-box
+//!   recorder and requires a few hundred bytes of memory.
+//! - `fuzzing`: enables `fuzz_operations()`, which interprets arbitrary bytes
+//!   as a sequence of allocator operations and checks the invariants of the
+//!   heap after every step. This is the entry point for the `cargo fuzz`
+//!   targets in the `fuzz` directory and requires the `alloc`-crate.
+//! - `dump`: enables `parse_dump()`, which reconstructs the list of blocks from
+//!   a copy of the heap memory, e.g. taken from a RAM dump of a crashed device.
+//!   This is intended for post-mortem tooling and requires the `alloc`-crate.
+//! - `guard-block`: places a guard block directly behind the heap, which is
+//!   never handed out. `Allocator::verify()` detects, if it was overwritten by
+//!   writing past the end of the heap. This is a software analog of a guard
+//!   page for devices without a memory protection unit. The guard block takes
+//!   4 bytes of memory in addition to the heap.
+//! - `metrics`: counts the blocks visited while searching for blocks during
+//!   allocations and deallocations. `Allocator::avg_scan_steps()` reports the
+//!   average per operation, which is a health metric rising with the
+//!   fragmentation of the heap. The live allocations are counted as well, so
+//!   that their number can be capped via `Allocator::set_max_live_allocations()`
+//!   to surface leaks. The counting costs a bit of time on every operation.
+//! - `fill-on-alloc`: fills the memory of every allocation with the byte
+//!   `0xCD` before handing it out (like the debug heap of MSVC). Reading
+//!   uninitialized memory then yields an obvious sentinel in a debugger
+//!   instead of stale data. `GlobalAlloc::alloc_zeroed()` still returns zeroed
+//!   memory. This is the counterpart of `zeroize-on-free` for debugging and
+//!   makes every allocation more expensive.
+//! - `abort-on-oom`: aborts the program, if an allocation via
+//!   `GlobalAlloc::alloc()` fails, instead of returning a null pointer. The
+//!   panic message reported before aborting contains the requested layout and
+//!   the `HeapStats` of the heap at that moment, which is more helpful than the
+//!   message of `handle_alloc_error()`. The panic is not unwound, as the global
+//!   allocator must not unwind. A custom handler can be registered via
+//!   `set_oom_handler()` instead. The methods indicating failure otherwise
+//!   (e.g. `Allocator::alloc_with_size()` returning `None`) are not affected.
+//! - `paranoid`: checks every new allocation against the used blocks before
+//!   handing it out. If the free list is corrupted (e.g. by a write to freed
+//!   memory), it might point into a used block, which would then be handed out
+//!   twice. Such an allocation fails instead and the handler registered via
+//!   `Allocator::set_overlap_handler()` is called. The refused block is
+//!   quarantined, i.e. it stays marked as used and is never handed out again.
+//!   This walks the whole heap on every allocation, so it is intended for
+//!   safety-critical systems, which prefer a slow allocator over silently
+//!   handing out the same memory twice. In debug builds, the invariants of the
+//!   heap are additionally checked after every allocation and deallocation.
+//! - `granularity-8`: rounds every block to a multiple of 8 bytes instead of 4
+//!   and aligns the heap to 8 bytes (see `Allocator::GRANULARITY`). Every block
+//!   header is padded to 8 bytes, so that the memory of every block is aligned
+//!   to 8 bytes. Allocations with an alignment of 8 (e.g. of `u64` or of `f64`)
+//!   then never need additional memory for the alignment, at the cost of 4 more
+//!   bytes of overhead per block. All sizes of 4 bytes mentioned in this
+//!   documentation (e.g. the header size and the rounding) are 8 bytes then,
+//!   and the minimum heap size is 16 bytes.
 //!
 //! # Testing
 //! As mentioned before: an allocator is a very critical part in the overall
@@ -162,7 +287,10 @@
 //!     USED size = 8  USED size FREE size = 8
 //!     ```
 //!     Nothing special here, except that the allocator could choose between the
-//!     two blocks of 8. Here the first one was chosen (arbitrarily).
+//!     two blocks of 8. Here the first one was chosen, as it was freed most
+//!     recently (see below). Otherwise, the tie would have been broken by the
+//!     address: the block with the lowest address is chosen among equally
+//!     fitting blocks, so the placement is always deterministic.
 //! 10. The second allocation (block size 4) is freed.
 //!     ```text
 //!     xxxx 0000 0000 yyyy 0000 0000 0000 0000
@@ -201,7 +329,49 @@
 //!     Something interesting here is, that one could check for such conditions
 //!     from time to time and fix them during that scan. Doing it this way does
 //!     not come with a constant time penalty when deallocating. Furthermore it
-//!     lets the user decide, whether that feature is necessary or not.
+//!     lets the user decide, whether that feature is necessary or not. This is
+//!     what [`Allocator::coalesce_free_blocks()`] does.
+//!
+//! Scanning all the blocks for every allocation gets slow for larger heaps.
+//! Therefore the few most recently freed blocks are remembered: an allocation
+//! of exactly the size of such a block simply reuses it without scanning.
+//!
+//! Furthermore, the free blocks are linked into a doubly-linked free list, so
+//! that an allocation only visits the free blocks instead of all the blocks.
+//! The links are stored in the first 8 bytes of the memory of every free block
+//! (two `u32`s in native byte order: the offsets of the memory of the previous
+//! and the next free block, with `0` for a missing link). Therefore the list
+//! costs no memory besides the memory of the free blocks, and a block is
+//! removed from the list in constant time, when it is allocated or merged.
+//! Free blocks with less than 8 bytes of memory are not linked. They are only
+//! found by scanning all the blocks, which is done for allocations of at most
+//! 4 bytes, if there are such blocks. With the `zeroize-on-free` feature, the
+//! links are the only data left in the memory of a freed block.
+//!
+//! Allocations with an alignment larger than 4 are placed at the first suitably
+//! aligned address inside of a free block. The memory in front of that address
+//! is split off into a separate free block (unless it is too small to hold a
+//! header, in which case it becomes padding of the used block), so such an
+//! allocation does not take more memory than necessary. The remembered blocks
+//! are checked for over-aligned allocations as well: the most recently freed
+//! block, into which the allocation fits, is reused. Therefore repeated
+//! allocations with the same size and alignment (e.g. DMA buffers) yield the
+//! same pointer again. If there is no suitably aligned address with enough
+//! memory behind it, the allocation fails with a null pointer. This is always
+//! the case for alignments larger than the heap, unless the heap happens to
+//! contain an address with that alignment. Allocations from the top of the heap
+//! are placed at the last suitably aligned address instead, splitting off the
+//! memory behind the allocation in the same way.
+//!
+//! Every header is stored as a `u32` in native byte order: the upper 31 bits
+//! hold the size of the memory after the header, bit 0 is set for an occupied
+//! (i.e. used or reserved) block and cleared for a free block, and bit 1 is set
+//! in addition for a reserved block (see `Allocator::reserve_block()`). Bit 1
+//! is the lowest bit of the size, which is always zero otherwise, as the sizes
+//! are multiples of 4. With the `granularity-8` feature, the `u32` is followed
+//! by 4 bytes of padding, so that every header takes 8 bytes. This layout is
+//! stable, so that tools can parse a copy of the heap memory (see
+//! `parse_dump()` behind the `dump` feature).
 //!
 //! [alloc]: https://doc.rust-lang.org/alloc/index.html
 //! [gist_hosted-test]: https://gist.github.com/jfrimmel/61943f9879adfbe760a78efa17a0ecaa
@@ -209,14 +379,72 @@
 //! [codecov]: https://codecov.io/gh/jfrimmel/emballoc
 //! [ci-logs]: https://app.circleci.com/pipelines/github/jfrimmel/emballoc
 #![cfg_attr(not(test), no_std)]
+#![cfg_attr(feature = "allocator-api", feature(allocator_api))]
 #![warn(unsafe_op_in_unsafe_fn)]
 #![warn(clippy::undocumented_unsafe_blocks)]
+#![cfg_attr(test, allow(clippy::undocumented_unsafe_blocks))]
+
+#[cfg(feature = "backtrace")]
+extern crate std;
 
+#[cfg(feature = "allocator-api")]
+#[allow(clippy::incompatible_msrv)] // the feature requires a nightly compiler
+mod allocator_api;
+mod bump;
+mod chunked;
+#[cfg(feature = "fuzzing")]
+mod fuzzing;
+#[cfg(feature = "relocating")]
+mod handle;
+mod local;
+mod lock;
 mod raw_allocator;
-use raw_allocator::RawAllocator;
+mod reservation;
+#[cfg(feature = "ring-log")]
+mod ring_log;
+mod section;
+#[cfg(feature = "simulation")]
+mod simulation;
+mod stats;
+mod strategy;
+mod sub;
+#[cfg(all(feature = "defmt", feature = "test-logger", not(test)))]
+mod test_logger;
+mod tracking;
+pub use bump::BumpAllocator;
+pub use chunked::ChunkedAllocator;
+#[cfg(feature = "fuzzing")]
+pub use fuzzing::fuzz_operations;
+#[cfg(feature = "relocating")]
+pub use handle::Handle;
+pub use local::LocalAllocator;
+#[cfg(feature = "bounded-spin")]
+pub use lock::SPIN_LIMIT;
+use lock::{Mutex, MutexGuard};
+pub use lock::{PhasedLock, RawLock, SpinLock};
+pub use raw_allocator::FreeError;
+pub use raw_allocator::State;
+#[cfg(feature = "dump")]
+pub use raw_allocator::{parse_dump, ParseError};
+use raw_allocator::{RawAllocator, HEADER_SIZE};
+pub use reservation::Reservation;
+#[cfg(feature = "ring-log")]
+pub use ring_log::{LogEntry, Operation};
+#[cfg(feature = "simulation")]
+pub use simulation::{simulate, Op, SimResult};
+pub use stats::{BlockInfo, Blocks, CoalesciblePairs, FreeBlocks, HeapStats, RecoveryReport};
+pub use strategy::{Selector, Strategy};
+pub use sub::SubAllocator;
+#[cfg(feature = "backtrace")]
+pub use tracking::LiveAllocation;
+use tracking::Tracker;
 
 use core::alloc::{GlobalAlloc, Layout};
-use core::ptr;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+#[cfg(feature = "abort-on-oom")]
+use core::sync::atomic::{AtomicPtr, Ordering};
 
 /// The memory allocator for embedded systems.
 ///
@@ -236,16 +464,66 @@ use core::ptr;
 /// ```
 /// Also please refer to the [crate-level](crate)-documentation for
 /// recommendations on the buffer size and general usage.
-pub struct Allocator<const N: usize> {
+///
+/// The internal state is protected by a [`SpinLock`]. If another lock should be
+/// used, e.g. one masking the interrupts, refer to [`GenericAllocator`].
+pub type Allocator<const N: usize> = GenericAllocator<SpinLock, N>;
+
+/// The memory allocator for embedded systems using a custom lock of type `L`.
+///
+/// This is the type behind the [`Allocator`], which uses the default
+/// [`SpinLock`]. Any other lock implementing the [`RawLock`]-trait can be used
+/// via [`with_lock()`](Self::with_lock), e.g. the mutex of a real-time
+/// operating system or a lock masking the interrupts on single-core systems:
+/// ```
+/// # use core::sync::atomic::{AtomicBool, Ordering};
+/// # struct InterruptLock(AtomicBool);
+/// # impl InterruptLock {
+/// #     const fn new() -> Self { Self(AtomicBool::new(false)) }
+/// # }
+/// # unsafe impl emballoc::RawLock for InterruptLock {
+/// #     fn lock(&self) { assert!(self.try_lock()) }
+/// #     fn try_lock(&self) -> bool { !self.0.swap(true, Ordering::Acquire) }
+/// #     unsafe fn unlock(&self) { self.0.store(false, Ordering::Release) }
+/// # }
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::GenericAllocator<InterruptLock, 4096> =
+///     emballoc::GenericAllocator::with_lock(InterruptLock::new());
+/// ```
+/// Apart from the lock, it behaves exactly like the [`Allocator`].
+///
+/// The allocator is [`Sync`] (as required for the `#[global_allocator]`), if
+/// the lock is [`Sync`], since all accesses to the internal state are guarded
+/// by the lock. Likewise it is [`Send`], if the lock is [`Send`]. Both traits
+/// are derived automatically, so no additional bounds are necessary:
+/// ```compile_fail
+/// use core::cell::Cell;
+/// # struct CellLock(Cell<bool>);
+/// # unsafe impl emballoc::RawLock for CellLock {
+/// #     fn lock(&self) { assert!(self.try_lock()) }
+/// #     fn try_lock(&self) -> bool { !self.0.replace(true) }
+/// #     unsafe fn unlock(&self) { self.0.set(false) }
+/// # }
+///
+/// // a lock based on a `Cell` cannot be shared between threads
+/// static ALLOCATOR: emballoc::GenericAllocator<CellLock, 4096> =
+///     emballoc::GenericAllocator::with_lock(CellLock(Cell::new(false)));
+/// ```
+pub struct GenericAllocator<L, const N: usize> {
     /// The internal raw allocator.
     ///
     /// The raw allocator handles allocations of contiguous byte slices without
     /// needing to worry about alignment. The raw allocator is protected by a
-    /// `spin::Mutex` to make it usable with shared references (requirement of
-    /// [`GlobalAlloc`]).
-    raw: spin::Mutex<RawAllocator<N>>,
+    /// mutex using the lock `L` to make it usable with shared references
+    /// (requirement of [`GlobalAlloc`]).
+    raw: Mutex<L, RawAllocator<N>>,
+    /// The live allocations tracked for leak diagnosis.
+    ///
+    /// This is only used with the `backtrace` feature. Otherwise it does not
+    /// take any memory.
+    live: Tracker,
 }
-impl<const N: usize> Allocator<N> {
+impl<const N: usize> GenericAllocator<SpinLock, N> {
     /// Create a new [`Allocator`] with exactly `N` bytes heap space.
     ///
     /// Note, that the usable size is less than the heap size, since there is
@@ -277,7 +555,8 @@ impl<const N: usize> Allocator<N> {
     ///
     /// # Panics
     /// This function will panic, if the supplied buffer size, i.e. `N`, is less
-    /// than `8` or not divisible by `4`.
+    /// than `8` or not divisible by `4` (`16` and `8` with the `granularity-8`
+    /// feature).
     /// ```should_panic
     /// emballoc::Allocator::<63>::new(); // not divisible by 4
     /// ```
@@ -286,176 +565,4828 @@ impl<const N: usize> Allocator<N> {
     /// ```
     #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
     pub const fn new() -> Self {
-        let raw = spin::Mutex::new(RawAllocator::new());
-        Self { raw }
+        Self::with_lock(SpinLock::new())
     }
 
-    /// Align a given pointer to the specified alignment.
+    /// Create a new [`Allocator`], whose first `prefix_bytes` are reserved.
     ///
-    /// # Safety
-    /// This function requires `align` to be a power of two and requires the
-    /// `ptr` to point to a memory region, that is large enough, so that the
-    /// aligned pointer is still in that memory region.
-    unsafe fn align_to(ptr: *mut u8, align: usize) -> *mut u8 {
-        let addr = ptr as usize;
-        let mismatch = addr & (align - 1);
-        let offset = if mismatch == 0 { 0 } else { align - mismatch };
-        // SAFETY: "in-bound"-requirement is part of the safety-contract of this
-        // function, therefore the caller is responsible for it
-        unsafe { ptr.add(offset) }
+    /// The start of the heap is pre-marked as a used block owned by the caller,
+    /// e.g. for a fixed descriptor handed over by a bootloader, which has to
+    /// live at the start of the heap. The size is rounded up to a multiple of
+    /// the [`GRANULARITY`](Self::GRANULARITY). The memory of that block is obtained via [`reserved_prefix()`] and
+    /// is never handed out by the allocator, while the remaining heap is
+    /// available for allocations as usual. The prefix stays reserved, until it
+    /// is released via [`GlobalAlloc::dealloc()`] (with any layout fitting
+    /// into the prefix), e.g. in a later phase of the program.
+    ///
+    /// # Example
+    /// ```
+    /// use emballoc::Allocator;
+    ///
+    /// static ALLOCATOR: Allocator<4096> = Allocator::new_with_reserved_prefix(64);
+    ///
+    /// let descriptor = ALLOCATOR.reserved_prefix();
+    /// assert_eq!(ALLOCATOR.block_of(descriptor).unwrap().size, 64);
+    /// // the headers are as large as the granularity
+    /// const HEADER_SIZE: usize = Allocator::<4096>::GRANULARITY;
+    /// assert_eq!(ALLOCATOR.available_bytes(), 4096 - HEADER_SIZE - 64 - HEADER_SIZE);
+    /// ```
+    ///
+    /// # Panics
+    /// This function will panic under the same conditions as
+    /// [`new()`](Self::new) or if the prefix does not leave room for the header
+    /// of a free block, i.e. if the rounded `prefix_bytes` exceed `N - 8`
+    /// (`N - 16` with the `granularity-8` feature).
+    ///
+    /// [`reserved_prefix()`]: GenericAllocator::reserved_prefix
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn new_with_reserved_prefix(prefix_bytes: usize) -> Self {
+        let prefix = (prefix_bytes + HEADER_SIZE - 1) / HEADER_SIZE * HEADER_SIZE;
+        let raw = Mutex::new(SpinLock::new(), RawAllocator::with_prefix(prefix));
+        let live = Tracker::new();
+        Self { raw, live }
     }
-}
-// SAFETY: the safety contracts of global allocator is a bit lengthy, but in
-// short: the implementation does not panic (at least on purpose, if it would,
-// there is a bug) and it actually adheres to the layout requirements (ensured
-// by tests).
-unsafe impl<const N: usize> GlobalAlloc for Allocator<N> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let align = layout.align();
-        // the raw allocator always returns 4-byte-aligned slices, therefore
-        // smaller alignments are always fulfilled. Larger alignments are a bit
-        // more tricky, since this requires over-allocation and adjusting the
-        // pointer accordingly. The over-allocation is rather conservative and
-        // uses a worst case estimation, therefore it allocates `align` bytes
-        // more, ensuring there is enough memory.
-        let size = if align > 4 {
-            layout.size() + align
-        } else {
-            layout.size()
-        };
 
-        // allocate a memory block and return the sufficiently aligned pointer
-        // into that memory block.
-        match self.raw.lock().alloc(size) {
-            // SAFETY: `align` is a power of two as by the contract of `Layout`.
-            // Furthermore the memory slice is enlarged (see above), so that the
-            // aligned pointer will still be in the same allocation.
-            Some(memory) => unsafe { Self::align_to(ptr::addr_of_mut!(*memory).cast(), align) },
-            None => ptr::null_mut(),
-        }
+    /// Create a new [`Allocator`] with a predetermined layout of blocks.
+    ///
+    /// The heap consists of the given blocks in address order, each described
+    /// by its state and the size of its memory (excluding the 4 byte header).
+    /// This allows tests to reproduce a fragmented heap deterministically
+    /// instead of replaying a sequence of allocations. The used blocks are not
+    /// owned by anyone, so they are never freed (unless a test frees them by
+    /// a pointer into their memory). Adjacent free blocks are kept separate.
+    /// This function is only available in tests and debug builds.
+    ///
+    /// # Panics
+    /// This function panics, if a size is not a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY) or if the blocks
+    /// (including their headers) do not add up to the heap size `N`.
+    ///
+    /// # Example
+    /// ```
+    /// use emballoc::{Allocator, State};
+    ///
+    /// // the headers are as large as the granularity
+    /// const HEADER_SIZE: usize = Allocator::<64>::GRANULARITY;
+    /// let allocator = Allocator::<64>::from_layout(&[
+    ///     (State::Used, 8),
+    ///     (State::Free, 16),
+    ///     (State::Used, 64 - 3 * HEADER_SIZE - 8 - 16),
+    /// ]);
+    /// assert_eq!(allocator.largest_free_block(), 16);
+    /// ```
+    #[cfg(any(test, debug_assertions))]
+    #[must_use = "assign the allocator to a variable and use it in a test"]
+    pub fn from_layout(blocks: &[(State, usize)]) -> Self {
+        let raw = Mutex::new(SpinLock::new(), RawAllocator::from_layout(blocks));
+        let live = Tracker::new();
+        Self { raw, live }
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // alignment is irrelevant here, as `RawAllocator::free` can handle any
-        // pointer in an entry's memory, so simply forward the pointer. The
-        // `free()`-method might detect errors, but those cannot lead to panics
-        // (by contract of `GlobalAlloc`). Therefore there are two choices:
-        // 1. abort the process
-        // 2. ignore the error
-        // Since there is no process and there is no stable way to abort the
-        // program on `core` the only viable option is option #1: do nothing.
-        let _maybe_error = self.raw.lock().free(ptr.cast()).ok();
-        // errors are ignored
+    /// Create a new [`Allocator`], rejecting an invalid `N` at compile time.
+    ///
+    /// This is the same as [`new()`](Self::new), but instead of panicking on an
+    /// invalid buffer size, the compilation fails. The buffer size `N` has to
+    /// be at least `8` and divisible by `4`.
+    /// ```
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new_const_checked();
+    /// ```
+    /// Both an usage in a `static` and a usage at runtime are checked:
+    /// ```compile_fail
+    /// static ALLOCATOR: emballoc::Allocator<6> = emballoc::Allocator::new_const_checked();
+    /// ```
+    /// ```compile_fail
+    /// let allocator = emballoc::Allocator::<63>::new_const_checked();
+    /// ```
+    /// Note, that the check is done when the constant is evaluated, i.e. when
+    /// the code is built. A pure `cargo check` might not report the error for
+    /// the runtime usage.
+    ///
+    /// A bound on the type itself (e.g. `where [(); valid::<N>()]:`) would be
+    /// even nicer, but this requires the incomplete `generic_const_exprs`
+    /// feature, which is not an option for this crate. This constructor works
+    /// on the stable compiler instead.
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn new_const_checked() -> Self {
+        #[allow(clippy::let_unit_value)] // forces the evaluation of the check
+        let () = Self::VALID_SIZE;
+        Self::new()
     }
-}
 
-// include the readme in doc-tests. Credits to https://blog.guillaume-gomez.fr/articles/2020-03-07+cfg%28doctest%29+is+stable+and+you+should+use+it
-#[cfg(doctest)]
-mod extra_doctests {
-    /// Helper macro to pass a "dynamic"/included string to the `extern`-block
-    macro_rules! doc_check {
-        ($x:expr) => {
-            #[doc = $x]
-            extern "C" {}
-        };
-    }
-    // Check the code snippets in the Readme.
-    doc_check!(include_str!("../README.md"));
+    /// Compile-time check of the buffer size `N` for [`new_const_checked()`].
+    ///
+    /// [`new_const_checked()`]: Self::new_const_checked
+    const VALID_SIZE: () = {
+        #[cfg(not(feature = "granularity-8"))]
+        assert!(
+            N >= 8 && N % 4 == 0,
+            "invalid heap size: N has to be at least 8 and divisible by 4"
+        );
+        #[cfg(feature = "granularity-8")]
+        assert!(
+            N >= 16 && N % 8 == 0,
+            "invalid heap size: N has to be at least 16 and divisible by 8"
+        );
+    };
 }
+impl<L, const N: usize> GenericAllocator<L, N> {
+    /// The granularity of the block sizes in bytes.
+    ///
+    /// Every block is rounded up to a multiple of this value and the memory of
+    /// every block is aligned to it. Therefore allocations with an alignment
+    /// up to this value never need additional memory for the alignment, while
+    /// larger alignments are placed at a suitably aligned address inside of a
+    /// free block (splitting off the memory in front of it).
+    ///
+    /// The granularity is `4` by default and equal to the size of the block
+    /// headers, which is part of the stability guarantees of this crate (see
+    /// the [crate-level](crate)-documentation). With the `granularity-8`
+    /// feature, it is `8` instead, so that allocations aligned to 8 bytes (e.g.
+    /// on 64-bit targets) never need additional memory. As the memory of every
+    /// block directly follows its header, the headers are padded to 8 bytes
+    /// then, i.e. every block costs 4 more bytes.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    /// use emballoc::Allocator;
+    ///
+    /// let allocator = Allocator::<4096>::new();
+    /// let layout = Layout::from_size_align(12, Allocator::<4096>::GRANULARITY).unwrap();
+    /// let (_ptr, size) = unsafe { allocator.alloc_with_size(layout) }.unwrap();
+    /// assert_eq!(size % Allocator::<4096>::GRANULARITY, 0);
+    /// assert!(size < 12 + Allocator::<4096>::GRANULARITY);
+    /// ```
+    pub const GRANULARITY: usize = raw_allocator::GRANULARITY;
 
-#[cfg(test)]
-mod tests {
-    use crate::Allocator;
-    use core::alloc::{GlobalAlloc, Layout};
-    use core::ptr;
-
-    #[test]
-    fn alignment_of_align_to() {
-        // create buffer memory for proper indexing. One could use random
-        // integers and cast them to pointers, but this would violate the strict
-        // provenance rules and `miri` would detect that. Therefore this uses a
-        // valid and suitable aligned buffer and uses pointers into that buffer.
-        #[repr(align(16))]
-        struct Align([u8; 16]);
-        let mut just_a_buffer_to_get_a_valid_address = Align([0_u8; 16]);
-        let base: *mut u8 = ptr::addr_of_mut!(just_a_buffer_to_get_a_valid_address.0).cast();
-
-        // create some pointers to the buffer with some offsets
-        let ptr_0x10 = base;
-        let ptr_0x11 = base.wrapping_add(1);
-        let ptr_0x14 = base.wrapping_add(4);
-        let ptr_0x1c = base.wrapping_add(0xc);
-        let ptr_0x20 = base.wrapping_add(0x10);
-
-        // the actual test for the alignment of `align_to()`
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 4) }, ptr_0x14);
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x10, 4) }, ptr_0x10);
-
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 1) }, ptr_0x11);
-
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x1c, 16) }, ptr_0x20);
+    /// Create a new allocator with exactly `N` bytes heap space using `lock`.
+    ///
+    /// This is the same as [`Allocator::new()`], but uses the given `lock`
+    /// instead of the default [`SpinLock`]. The same requirements on the heap
+    /// size `N` apply.
+    ///
+    /// # Panics
+    /// This function will panic, if the supplied buffer size, i.e. `N`, is less
+    /// than `8` or not divisible by `4` (`16` and `8` with the `granularity-8`
+    /// feature).
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn with_lock(lock: L) -> Self {
+        let raw = Mutex::new(lock, RawAllocator::new());
+        let live = Tracker::new();
+        Self { raw, live }
     }
 
-    // the following tests ensure, that a pointer with the requested alignment
-    // is returned
-
-    /// Assert the given alignment of pointers.
-    macro_rules! assert_alignment {
-        ($ptr:expr, $align:expr) => {{
-            assert_eq!(($ptr as usize) % $align, 0, "Alignment not fulfilled");
-        }};
+    /// Create a new allocator in the given uninitialized `memory` using `lock`.
+    ///
+    /// This is the same as [`with_lock()`](Self::with_lock), but the allocator
+    /// is initialized in place and a reference to it is returned. Only the
+    /// bookkeeping and the first header of the heap are written, the rest of
+    /// the heap memory stays uninitialized. This allows to place the allocator
+    /// in a memory section, which is not zero-initialized at boot (e.g. via
+    /// `#[link_section]`), saving the time for clearing the heap at startup.
+    /// The same is available via [`From`] for locks implementing [`Default`].
+    ///
+    /// The memory is borrowed by the returned reference, so typically it is a
+    /// `&'static mut` reference to a `static mut`-variable. Creating such a
+    /// reference is `unsafe`: the caller has to make sure, that it is created
+    /// only once and that the variable is never accessed otherwise, since this
+    /// would alias the exclusive reference.
+    ///
+    /// # Example
+    /// ```
+    /// use core::mem::MaybeUninit;
+    /// use emballoc::Allocator;
+    ///
+    /// static mut HEAP: MaybeUninit<Allocator<4096>> = MaybeUninit::uninit();
+    ///
+    /// // SAFETY: this is the only access to `HEAP`
+    /// let memory = unsafe { &mut *core::ptr::addr_of_mut!(HEAP) };
+    /// let allocator: &'static Allocator<4096> = memory.into();
+    /// assert_eq!(allocator.available_bytes(), allocator.capacity());
+    /// ```
+    ///
+    /// # Panics
+    /// This function will panic, if the supplied buffer size, i.e. `N`, is less
+    /// than `8` or not divisible by `4` (`16` and `8` with the `granularity-8`
+    /// feature).
+    pub fn init_in(memory: &mut MaybeUninit<Self>, lock: L) -> &Self {
+        // SAFETY: the memory is valid for writes and properly aligned, as it is
+        // borrowed exclusively. Afterwards the allocator is fully initialized.
+        unsafe {
+            Self::init_at(memory.as_mut_ptr(), lock);
+            &*memory.as_ptr()
+        }
     }
 
-    #[test]
-    fn small_alignments() {
-        let allocator = Allocator::<128>::new();
-
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 2).unwrap()) };
-        assert_alignment!(ptr, 1);
+    /// Initialize an allocator in place using `lock`.
+    ///
+    /// This is equivalent to writing [`with_lock(lock)`](Self::with_lock) to
+    /// `this`, but the heap memory is left uninitialized except for the first
+    /// header. Therefore no temporary copy of the heap is created on the stack.
+    ///
+    /// # Safety
+    /// The pointer `this` must be valid for writes and properly aligned.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as [`with_lock()`].
+    ///
+    /// [`with_lock()`]: Self::with_lock
+    unsafe fn init_at(this: *mut Self, lock: L) {
+        // SAFETY: the allocator is valid for writes and properly aligned
+        // (safety contract of this function), so are its fields. The raw
+        // allocator is initialized completely by `RawAllocator::init_at()`.
+        unsafe {
+            let raw = ptr::addr_of_mut!((*this).raw);
+            Mutex::init_at(raw, lock, |raw| RawAllocator::init_at(raw));
+            ptr::addr_of_mut!((*this).live).write(Tracker::new());
+        }
+    }
 
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 4).unwrap()) };
-        assert_alignment!(ptr, 4);
+    /// Query the total number of bytes, which can be allocated.
+    ///
+    /// This is the memory available to a single allocation on an empty heap,
+    /// which is less than the heap size `N`, as every block requires a header,
+    /// which is as large as the [`GRANULARITY`](Self::GRANULARITY). Every
+    /// further allocation reduces the usable memory by another header. Refer to [`available_bytes()`](Self::available_bytes)
+    /// for the memory, that is currently available.
+    ///
+    /// # Example
+    /// ```
+    /// use emballoc::Allocator;
+    ///
+    /// let allocator = Allocator::<4096>::new();
+    /// assert_eq!(allocator.capacity(), 4096 - Allocator::<4096>::GRANULARITY);
+    /// ```
+    pub const fn capacity(&self) -> usize {
+        N - HEADER_SIZE
     }
+}
 
-    #[test]
-    fn medium_alignments() {
-        let allocator = Allocator::<128>::new();
+/// Abort the program with a panic message built from `args`.
+///
+/// The allocator must not unwind (e.g. out of `GlobalAlloc::dealloc()`), so a
+/// plain panic is not an option. Instead, the panic is started while a guard is
+/// alive, whose destructor panics again during the unwinding. This aborts the
+/// program right away, but the message of the first panic is still reported by
+/// the panic handler. With `panic = "abort"`, the first panic aborts already.
+#[cfg(any(feature = "abort-on-oom", feature = "panic-on-dealloc-error"))]
+#[cold]
+fn abort(args: fmt::Arguments) -> ! {
+    struct Abort;
+    impl Drop for Abort {
+        fn drop(&mut self) {
+            panic!("aborting, as the allocator must not unwind");
+        }
+    }
 
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 8).unwrap()) };
-        assert_alignment!(ptr, 8);
+    let _abort = Abort;
+    panic!("{}", args);
+}
 
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 32).unwrap()) };
-        assert_alignment!(ptr, 32);
-    }
+/// The handler registered via [`set_oom_handler()`] (null, if there is none).
+#[cfg(feature = "abort-on-oom")]
+static OOM_HANDLER: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
 
-    #[cfg(not(miri))] // too slow
-    #[test]
-    fn huge_alignment() {
-        // in static memory to prevent stack overflow
-        const FOUR_MEG: usize = 4 * 1024 * 1024;
+/// Register a handler for failed allocations with the `abort-on-oom` feature.
+///
+/// By default, a failed allocation via [`GlobalAlloc::alloc()`] aborts the
+/// program with the `abort-on-oom` feature. The registered `handler` is called
+/// with the requested layout and the [`HeapStats`] of the heap instead, and the
+/// allocation returns a null pointer afterwards. This allows to log the failure
+/// in a custom way or to test the code reporting it. Passing `None` restores
+/// the default behavior of aborting. Unlike the handler of
+/// [`set_dealloc_error_handler()`](GenericAllocator::set_dealloc_error_handler),
+/// this handler is shared by all allocators of the program.
+///
+/// The handler is called after the allocator was unlocked, so it may use the
+/// allocator. It must not panic though, as `alloc()` is not allowed to unwind.
+///
+/// This function is only available with the `abort-on-oom` feature.
+///
+/// # Example
+/// ```
+/// use core::sync::atomic::{AtomicUsize, Ordering};
+///
+/// static FAILURES: AtomicUsize = AtomicUsize::new(0);
+///
+/// emballoc::set_oom_handler(Some(|_layout, _stats| {
+///     FAILURES.fetch_add(1, Ordering::Relaxed);
+/// }));
+/// ```
+#[cfg(feature = "abort-on-oom")]
+pub fn set_oom_handler(handler: Option<fn(Layout, HeapStats)>) {
+    let handler = handler.map_or(ptr::null_mut(), |handler| handler as *mut ());
+    OOM_HANDLER.store(handler, Ordering::Release);
+}
 
-        static ALLOCATOR: Allocator<{ 10 * 1024 * 1024 }> = Allocator::new();
-        let ptr = unsafe { ALLOCATOR.alloc(Layout::from_size_align(4, FOUR_MEG).unwrap()) };
+/// Report, that an allocation of `layout` failed with the `abort-on-oom` feature.
+///
+/// This calls the handler registered via [`set_oom_handler()`], so that a null
+/// pointer is returned afterwards. Without a handler, the program is aborted
+/// instead of returning a null pointer, so that the heap statistics `stats`
+/// are reported: the panic message contains them. The lock of the allocator
+/// has to be released beforehand, as the handler or the panic handler might
+/// allocate memory.
+#[cfg(feature = "abort-on-oom")]
+#[cold]
+fn out_of_memory(layout: Layout, stats: HeapStats) {
+    let handler = OOM_HANDLER.load(Ordering::Acquire);
+    if handler.is_null() {
+        abort(format_args!(
+            "out of memory: failed to allocate {} bytes aligned to {} ({:?})",
+            layout.size(),
+            layout.align(),
+            stats
+        ));
+    }
+    // SAFETY: non-null pointers are only stored by `set_oom_handler()`, which
+    // converts a function pointer of this very type.
+    let handler = unsafe { core::mem::transmute::<*mut (), fn(Layout, HeapStats)>(handler) };
+    handler(layout, stats);
+}
 
-        assert_alignment!(ptr, FOUR_MEG);
+/// Compute the heap size required for allocations of the given `sizes`.
+///
+/// Every allocation occupies a block, whose size is the requested size rounded
+/// up to a multiple of the [`GRANULARITY`](GenericAllocator::GRANULARITY),
+/// plus the header of the same size. This function sums up those block sizes,
+/// so that the result is the minimal heap size `N`, with which all the
+/// allocations can be live at the same time (if they are made in a fresh
+/// allocator). The result is at least two headers, the minimal heap size.
+///
+/// This assumes an alignment of at most the granularity, as larger alignments
+/// might require additional memory. The result does
+/// not account for fragmentation either: if the allocations are freed and
+/// re-allocated in a different order, more memory might be required.
+///
+/// This function is a `const fn`, therefore it can be used to compute the heap
+/// size at compile time.
+///
+/// # Example
+/// ```
+/// use emballoc::{required_size, Allocator};
+///
+/// const HEAP_SIZE: usize = required_size(&[16, 5, 96]);
+/// static ALLOCATOR: Allocator<HEAP_SIZE> = Allocator::new();
+/// const HEADER_SIZE: usize = Allocator::<HEAP_SIZE>::GRANULARITY;
+/// assert_eq!(
+///     HEAP_SIZE,
+///     (16 + HEADER_SIZE) + (8 + HEADER_SIZE) + (96 + HEADER_SIZE)
+/// );
+/// ```
+#[must_use]
+pub const fn required_size(sizes: &[usize]) -> usize {
+    let mut total = 0_usize;
+    let mut i = 0;
+    while i < sizes.len() {
+        let rounded = sizes[i].saturating_add(HEADER_SIZE - 1) / HEADER_SIZE * HEADER_SIZE;
+        total = total.saturating_add(rounded.saturating_add(HEADER_SIZE));
+        i += 1;
+    }
+    if total < 2 * HEADER_SIZE {
+        2 * HEADER_SIZE
+    } else {
+        total
+    }
+}
+impl<L: RawLock, const N: usize> GenericAllocator<L, N> {
+    /// Query the current usage statistics of the heap.
+    ///
+    /// This walks over all blocks in the heap, so this operation takes time
+    /// linear to the number of allocations. The internal lock is held during
+    /// that time.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let stats = allocator.stats();
+    /// assert_eq!(stats.used, 0);
+    /// assert_eq!(stats.free, allocator.capacity());
+    /// ```
+    pub fn stats(&self) -> HeapStats {
+        self.raw.lock().stats()
     }
 
-    #[test]
-    fn allocation_failure() {
-        let allocator = Allocator::<128>::new();
+    /// Query the current usage statistics of the heap without blocking.
+    ///
+    /// This is like [`stats()`](Self::stats), but the internal lock is only
+    /// tried once: if it is currently held (e.g. by an allocation on another
+    /// thread), `None` is returned instead of waiting for it. Therefore this
+    /// never blocks on the allocator, which makes it suitable for telemetry,
+    /// that must not be delayed by the users of the heap.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// if let Some(stats) = allocator.try_stats() {
+    ///     assert_eq!(stats.free, allocator.capacity());
+    /// }
+    /// ```
+    pub fn try_stats(&self) -> Option<HeapStats> {
+        self.raw.try_lock().map(|raw| raw.stats())
+    }
 
-        // try an allocation, that exceeds the total memory size
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(129, 1).unwrap()) };
-        assert_eq!(ptr, ptr::null_mut());
+    /// Query the usage statistics of the heap without acquiring the lock.
+    ///
+    /// This is a last-resort tool for post-mortem diagnostics: it reads the
+    /// heap structure even if the internal lock is held, e.g. by a thread that
+    /// was halted by a debugger or by the code interrupted by a fault handler.
+    /// In such situations, [`stats()`](Self::stats) would never return, as the
+    /// lock is never released. Otherwise, prefer [`stats()`](Self::stats).
+    ///
+    /// # Safety
+    /// The heap must not be modified concurrently, i.e. the lock holder (if
+    /// any) has to be stopped, e.g. because the core is halted or because this
+    /// is called from a fault handler, which never returns. Note, that the
+    /// lock holder might have been stopped in the middle of an update, so the
+    /// heap might be inconsistent: the result might be garbage (or the walk
+    /// over the heap might even panic). If the heap is modified concurrently,
+    /// the behavior is undefined.
+    ///
+    /// # Example
+    /// ```
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// // e.g. in a fault handler:
+    /// let stats = unsafe { ALLOCATOR.snapshot_unlocked() };
+    /// assert_eq!(stats.free, ALLOCATOR.capacity());
+    /// ```
+    pub unsafe fn snapshot_unlocked(&self) -> HeapStats {
+        // SAFETY: the heap is not modified concurrently (safety contract of
+        // this function)
+        unsafe { self.raw.get_unlocked() }.stats()
     }
 
-    #[test]
-    fn allocation_failure_due_to_alignment() {
-        let allocator = Allocator::<128>::new();
+    /// Copy the most recent allocator operations into `out`.
+    ///
+    /// The allocator records the last 16 allocations and deallocations in a
+    /// ring buffer, which can be inspected e.g. after a crash to find out, what
+    /// happened right before. The most recent `out.len()` operations are copied
+    /// into `out` in chronological order, i.e. the oldest one first. The number
+    /// of copied operations is returned. Allocations via the [`LocalAllocator`]
+    /// are recorded as well.
+    ///
+    /// This function is only available with the `ring-log` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::{LogEntry, Operation};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+    /// unsafe { allocator.dealloc(ptr, Layout::new::<u32>()) };
+    ///
+    /// let mut log = [LogEntry::default(); 16];
+    /// let count = allocator.recent_ops(&mut log);
+    /// assert_eq!(count, 2);
+    /// assert_eq!(log[0].operation, Operation::Alloc);
+    /// assert_eq!(log[1].operation, Operation::Free);
+    /// ```
+    #[cfg(feature = "ring-log")]
+    pub fn recent_ops(&self, out: &mut [LogEntry]) -> usize {
+        self.raw.lock().recent_ops(out)
+    }
 
-        // try an allocation, that exceeds the total memory size
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 128).unwrap()) };
-        assert_eq!(ptr, ptr::null_mut());
+    /// Query all live allocations together with the backtraces of their
+    /// allocation.
+    ///
+    /// This is a debugging aid for finding memory leaks in tests: if there are
+    /// allocations left at the end of a test, the backtraces show, where the
+    /// leaked memory was allocated. Note, that capturing a backtrace for every
+    /// allocation is very slow.
+    ///
+    /// Allocations made via the [`LocalAllocator`] and allocations made while
+    /// the tracking itself allocates memory (if this is the global allocator)
+    /// are not tracked.
+    ///
+    /// This function is only available with the `backtrace` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let leaked = unsafe { allocator.alloc(Layout::new::<u32>()) };
+    ///
+    /// for allocation in allocator.live_allocations() {
+    ///     println!("leaked {:?}, allocated at:\n{}", allocation.ptr, allocation.backtrace);
+    /// }
+    /// ```
+    #[cfg(feature = "backtrace")]
+    pub fn live_allocations(&self) -> std::vec::Vec<LiveAllocation> {
+        self.live.snapshot()
+    }
+
+    /// Allocate memory and record the source location of the call.
+    ///
+    /// This is the same as [`GlobalAlloc::alloc()`], but the location of the
+    /// caller is recorded in addition to the backtrace and reported as
+    /// [`LiveAllocation::location`] by [`live_allocations()`]. This points leak
+    /// reports directly at the allocating line, which is easier to read than
+    /// a backtrace. The location is obtained via `#[track_caller]`, so a helper
+    /// function can pass on its own caller by being `#[track_caller]` as well.
+    /// A reallocation of the memory does not keep the location.
+    ///
+    /// This function is only available with the `backtrace` feature.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let leaked = unsafe { allocator.alloc_tracked(Layout::new::<u32>()) };
+    ///
+    /// for allocation in allocator.live_allocations() {
+    ///     let location = allocation.location.unwrap();
+    ///     println!("leaked {:?}, allocated at {}", allocation.ptr, location);
+    /// }
+    /// ```
+    ///
+    /// [`live_allocations()`]: Self::live_allocations
+    #[cfg(feature = "backtrace")]
+    #[track_caller]
+    pub unsafe fn alloc_tracked(&self, layout: Layout) -> *mut u8 {
+        let location = core::panic::Location::caller();
+        let ptr = self
+            .lock_for_alloc()
+            .map_or(ptr::null_mut(), |mut raw| Self::alloc_in(&mut raw, layout));
+        self.live.insert_at(ptr, Some(location));
+        ptr
+    }
+
+    /// Query the number of bytes wasted by enlarging blocks to their end.
+    ///
+    /// If the free memory remaining after an allocation is too small for a new
+    /// free block (see [`set_split_threshold()`](Self::set_split_threshold)),
+    /// the whole free block is allocated instead (see step 5 of the algorithm
+    /// in the [crate-level](crate)-documentation). This returns the total number
+    /// of bytes allocated this way in addition to the requested (rounded) sizes
+    /// over the lifetime of the allocator. Together with the fragmentation, this
+    /// quantifies the memory overhead of the allocator. The counter saturates
+    /// at `usize::MAX`.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::Allocator;
+    ///
+    /// const GRANULARITY: usize = Allocator::<32>::GRANULARITY;
+    /// let allocator = Allocator::<32>::new();
+    /// // the memory of the only block is one granule larger than requested
+    /// let layout = Layout::from_size_align(32 - 2 * GRANULARITY, 1).unwrap();
+    /// unsafe { allocator.alloc(layout) };
+    /// assert_eq!(allocator.wasted_tail_bytes(), GRANULARITY); // no room for another block
+    /// ```
+    pub fn wasted_tail_bytes(&self) -> usize {
+        self.raw.lock().wasted_tail_bytes()
+    }
+
+    /// Query the largest single allocation served by the heap.
+    ///
+    /// This is the largest size requested by a successful allocation since the
+    /// creation of the allocator or since the last reset via
+    /// [`reset_touched()`](Self::reset_touched). In contrast to the total
+    /// memory usage, this is the largest contiguous block ever required by the
+    /// workload, so it helps to size the heap for the worst-case contiguous
+    /// request. Failed allocations are not taken into account.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u8; 100]>()) };
+    /// unsafe { allocator.dealloc(ptr, Layout::new::<[u8; 100]>()) };
+    /// unsafe { allocator.alloc(Layout::new::<[u8; 20]>()) };
+    /// assert_eq!(allocator.max_single_alloc(), 100);
+    /// ```
+    pub fn max_single_alloc(&self) -> usize {
+        self.raw.lock().max_single_alloc()
+    }
+
+    /// Query the heap size, which would have avoided every failed allocation.
+    ///
+    /// Whenever an allocation fails, the heap size demanded at that moment is
+    /// remembered: the memory of all the live blocks and the reserved memory
+    /// (see [`reserve()`](Self::reserve)) plus the requested block, each
+    /// including its header. The largest demand since the creation of the
+    /// allocator is returned, so that the heap of the next firmware build can
+    /// be sized based on the out-of-memory situations in the field. If no
+    /// allocation has failed so far, `None` is returned. The demand is not
+    /// reset by [`reset_touched()`](Self::reset_touched).
+    ///
+    /// Note, that this assumes a heap without fragmentation, so it is a lower
+    /// bound: if the returned size is not larger than `N`, the allocation
+    /// failed due to fragmentation. Allocations rejected by the cap of
+    /// [`set_max_alloc()`](Self::set_max_alloc) are not taken into account.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::Allocator;
+    ///
+    /// const HEADER_SIZE: usize = Allocator::<128>::GRANULARITY;
+    /// let allocator = Allocator::<128>::new();
+    /// assert_eq!(allocator.recommended_min_size(), None);
+    ///
+    /// unsafe { allocator.alloc(Layout::new::<[u8; 96]>()) };
+    /// # #[cfg(not(feature = "abort-on-oom"))]
+    /// unsafe { allocator.alloc(Layout::new::<[u8; 64]>()) }; // fails
+    /// # #[cfg(not(feature = "abort-on-oom"))]
+    /// assert_eq!(
+    ///     allocator.recommended_min_size(),
+    ///     Some((96 + HEADER_SIZE) + (64 + HEADER_SIZE))
+    /// );
+    /// ```
+    pub fn recommended_min_size(&self) -> Option<usize> {
+        self.raw.lock().recommended_min_size()
+    }
+
+    /// Query the number of bytes lost to rounding up the live allocations.
+    ///
+    /// The size of every block is a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY), so the requested size of an
+    /// allocation is rounded up. This returns the total number of bytes added
+    /// this way to the live allocations, i.e. the internal fragmentation. This
+    /// is distinct from the [`wasted_tail_bytes()`](Self::wasted_tail_bytes),
+    /// which are added on top of the rounded sizes.
+    ///
+    /// The header of a block only stores the rounded size, as the header size
+    /// of 4 bytes is part of the stability guarantees of this crate. Therefore
+    /// the rounding is counted when allocating and determined from the layout
+    /// passed to [`GlobalAlloc::dealloc()`] when freeing, which relies on the
+    /// layout being the one used for the allocation (as required by the
+    /// contract of `GlobalAlloc`). Allocations, which are freed without a
+    /// layout (e.g. via [`free_at()`](Self::free_at)), are not counted at all.
+    /// Merged allocations (see [`try_merge()`](Self::try_merge)) span whole
+    /// blocks, so they are not rounded at all.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::new::<[u8; 7]>();
+    /// let ptr = unsafe { allocator.alloc(layout) };
+    /// assert_eq!(allocator.internal_fragmentation(), 1); // rounded up to 8
+    /// unsafe { allocator.dealloc(ptr, layout) };
+    /// assert_eq!(allocator.internal_fragmentation(), 0);
+    /// ```
+    pub fn internal_fragmentation(&self) -> usize {
+        self.raw.lock().internal_fragmentation()
+    }
+
+    /// Query the average number of scan steps per allocation or deallocation.
+    ///
+    /// Allocations walk the free blocks (see [`Strategy`]) and deallocations
+    /// scan the blocks for the one containing the pointer. Every visited block
+    /// is a scan step. This returns the steps of all operations so far divided
+    /// by the number of successful allocations and deallocations over the
+    /// lifetime of the allocator (the steps of failed allocations are included,
+    /// as they were spent as well). A rising average is an indicator of rising
+    /// fragmentation, which makes the operations slower. If there were no
+    /// operations yet, `0` is returned.
+    ///
+    /// This function is only available with the `metrics` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::new::<u32>();
+    /// let ptr = unsafe { allocator.alloc(layout) };
+    /// unsafe { allocator.dealloc(ptr, layout) };
+    /// // the allocation visits the only free block and the deallocation finds
+    /// // the block of the pointer immediately
+    /// assert_eq!(allocator.avg_scan_steps(), 1.0);
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn avg_scan_steps(&self) -> f32 {
+        self.raw.lock().avg_scan_steps()
+    }
+
+    /// Query the number of bytes currently available for allocations.
+    ///
+    /// This is the total memory of all free blocks, i.e. [`HeapStats::free`].
+    /// Due to the fragmentation, this memory might be split across several
+    /// free blocks, so a single allocation of that size might still fail: the
+    /// [largest free block] is the limit for a single allocation. Furthermore,
+    /// every allocation requires a header (as large as the
+    /// [`GRANULARITY`](Self::GRANULARITY)), so allocating multiple blocks uses
+    /// more memory than the sum of their sizes.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// assert_eq!(allocator.available_bytes(), allocator.capacity());
+    ///
+    /// unsafe { allocator.alloc(Layout::new::<[u32; 4]>()) };
+    /// let header = emballoc::Allocator::<4096>::GRANULARITY;
+    /// assert_eq!(allocator.available_bytes(), allocator.capacity() - 16 - header);
+    /// ```
+    ///
+    /// [largest free block]: Self::largest_free_block
+    pub fn available_bytes(&self) -> usize {
+        self.stats().free
+    }
+
+    /// Merge all adjacent free memory blocks.
+    ///
+    /// A deallocation only merges the freed block with the free block after it
+    /// (if any), but not with a free block in front of it (see the [crate-level
+    /// documentation](crate#implementation) for details). Therefore there
+    /// might be multiple adjacent free blocks, which cannot serve an allocation
+    /// larger than each of them. This function walks over all blocks once and
+    /// merges all adjacent free blocks, so it is a good idea to call it from
+    /// time to time (e.g. during an idle phase) or after an allocation failed.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::new::<u32>();
+    /// let first = unsafe { allocator.alloc(layout) };
+    /// let second = unsafe { allocator.alloc(layout) };
+    /// unsafe { allocator.dealloc(first, layout) };
+    /// unsafe { allocator.dealloc(second, layout) };
+    /// assert_eq!(allocator.free_block_count(), 2);
+    ///
+    /// allocator.coalesce_free_blocks();
+    /// assert_eq!(allocator.free_block_count(), 1);
+    /// ```
+    pub fn coalesce_free_blocks(&self) {
+        self.raw.lock().coalesce_free_blocks();
+    }
+
+    /// Try to recover from a corrupted heap.
+    ///
+    /// A bug like a buffer overflow might overwrite the header of the following
+    /// block, which breaks the chain of blocks and usually leads to a crash on
+    /// a later allocation. This function rescans the heap from its start and
+    /// checks every header: as soon as a header is invalid (its block would
+    /// exceed the heap or has a size, which is not a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY)), the
+    /// following headers cannot be found anymore. Therefore the memory from
+    /// that header up to the end of the heap is quarantined: it is marked as a
+    /// single used block, so that it is never handed out again. The blocks in
+    /// front of it stay usable, so the program can continue with less memory
+    /// instead of crashing. This is intended for contexts, where leaking memory
+    /// is preferable to a crash. If the heap is intact, nothing is changed.
+    ///
+    /// Note, that this is a best-effort recovery: a corrupted header, which
+    /// still looks valid (e.g. a used block marked as free), cannot be
+    /// detected. Live allocations in the quarantined memory are not affected,
+    /// but freeing them does not return their memory to the heap.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let report = allocator.try_recover();
+    /// assert_eq!(report.corrupted_at, None);
+    /// assert_eq!(report.quarantined, 0);
+    /// ```
+    pub fn try_recover(&self) -> RecoveryReport {
+        self.raw.lock().recover()
+    }
+
+    /// Check the heap for corruption.
+    ///
+    /// All the headers are checked to form a valid chain of blocks covering
+    /// the whole heap, just like [`try_recover()`](Self::try_recover) does,
+    /// but nothing is changed. With the `guard-block` feature, the guard block
+    /// directly behind the heap is checked as well: it is never handed out, so
+    /// it is only overwritten by writing past the end of the heap (e.g. when
+    /// overrunning the last allocation). This is a software analog of a guard
+    /// page for devices without a memory protection unit. If any corruption is
+    /// detected, `false` is returned. A corrupted guard block stays corrupted,
+    /// as the overrun is a bug of the program, which cannot be repaired.
+    ///
+    /// This walks over all the blocks of the heap, so it should be called
+    /// periodically (e.g. from an idle task) rather than on every allocation.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// assert!(allocator.verify());
+    /// ```
+    pub fn verify(&self) -> bool {
+        self.raw.lock().verify()
+    }
+
+    /// Count the free memory blocks.
+    ///
+    /// The free memory of the heap is split into separate free blocks between
+    /// the allocations. A rising number of free blocks is an early indicator of
+    /// fragmentation, even if the [largest free block] is still large enough.
+    /// This is equal to [`HeapStats::free_blocks`], but cheaper to obtain than
+    /// the full [`stats()`](Self::stats).
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// assert_eq!(allocator.free_block_count(), 1);
+    /// ```
+    ///
+    /// [largest free block]: Self::largest_free_block
+    pub fn free_block_count(&self) -> usize {
+        self.raw.lock().free_block_count()
+    }
+
+    /// Query the number of bytes currently spent on the block headers.
+    ///
+    /// Every block (used or free) starts with a header, which is not usable for
+    /// allocations. The headers are as large as the
+    /// [`GRANULARITY`](Self::GRANULARITY), so this is the granularity times the
+    /// number of blocks. The overhead grows with the number of allocations and with the
+    /// fragmentation of the free memory, as every additional block costs
+    /// another header.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// const HEADER_SIZE: usize = emballoc::Allocator::<4096>::GRANULARITY;
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// assert_eq!(allocator.overhead_bytes(), HEADER_SIZE);
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+    /// // the allocation and the remaining free block
+    /// assert_eq!(allocator.overhead_bytes(), 2 * HEADER_SIZE);
+    /// ```
+    pub fn overhead_bytes(&self) -> usize {
+        self.raw.lock().overhead_bytes()
+    }
+
+    /// Iterate over the free blocks of the heap.
+    ///
+    /// The iterator yields the offset (of the header, see [`BlockInfo::offset`])
+    /// and the size of every free block in address order, while the used
+    /// blocks are skipped. This is useful to visualize the fragmentation of the
+    /// heap, e.g. as a bar chart of the free fragments.
+    ///
+    /// The allocator is locked as long as the iterator exists, therefore there
+    /// must not be any allocations or deallocations with this allocator while
+    /// iterating, as this would deadlock.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// let layout = Layout::new::<[u8; 8]>();
+    /// let first = unsafe { allocator.alloc(layout) };
+    /// let _second = unsafe { allocator.alloc(layout) };
+    /// unsafe { allocator.dealloc(first, layout) };
+    ///
+    /// const HEADER_SIZE: usize = emballoc::Allocator::<64>::GRANULARITY;
+    /// let free: Vec<_> = allocator.free_blocks().collect();
+    /// assert_eq!(
+    ///     free,
+    ///     [(0, 8), (2 * (HEADER_SIZE + 8), 64 - 3 * HEADER_SIZE - 16)]
+    /// );
+    /// ```
+    pub fn free_blocks(&self) -> FreeBlocks<'_, L, N> {
+        FreeBlocks::new(self.raw.lock())
+    }
+
+    /// Iterate over the free blocks, which could be merged.
+    ///
+    /// Freeing a block only merges it with the following free block, so runs of
+    /// adjacent free blocks might remain (see
+    /// [`coalesce_free_blocks()`](Self::coalesce_free_blocks)). The iterator
+    /// yields the offset of the first block of every such run (of at least two
+    /// blocks) and the size of the block, which merging the run would result
+    /// in, in address order. This allows to estimate, whether a coalescing pass
+    /// is worth running, e.g. by comparing the largest merged size with
+    /// [`largest_free_block()`](Self::largest_free_block). The heap is not
+    /// modified.
+    ///
+    /// The allocator is locked as long as the iterator exists, therefore there
+    /// must not be any allocations or deallocations with this allocator while
+    /// iterating, as this would deadlock.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// let layout = Layout::new::<[u8; 8]>();
+    /// let first = unsafe { allocator.alloc(layout) };
+    /// let second = unsafe { allocator.alloc(layout) };
+    /// let _third = unsafe { allocator.alloc(layout) };
+    /// unsafe { allocator.dealloc(first, layout) };
+    /// unsafe { allocator.dealloc(second, layout) };
+    ///
+    /// let header = emballoc::Allocator::<64>::GRANULARITY;
+    /// let runs: Vec<_> = allocator.coalescible_pairs().collect();
+    /// assert_eq!(runs, [(0, 8 + header + 8)]);
+    /// ```
+    pub fn coalescible_pairs(&self) -> CoalesciblePairs<'_, L, N> {
+        CoalesciblePairs::new(self.raw.lock())
+    }
+
+    /// Iterate over all the blocks of the heap.
+    ///
+    /// The metadata of every block (used or free) is yielded in address order,
+    /// like [`block_of()`](Self::block_of) reports it. This is useful to check
+    /// the layout of the heap in tests, e.g. against the layout passed to
+    /// [`Allocator::from_layout()`].
+    ///
+    /// The allocator is locked as long as the iterator exists, therefore there
+    /// must not be any allocations or deallocations with this allocator while
+    /// iterating, as this would deadlock.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::State;
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// unsafe { allocator.alloc(Layout::new::<[u8; 8]>()) };
+    ///
+    /// const HEADER_SIZE: usize = emballoc::Allocator::<64>::GRANULARITY;
+    /// let blocks: Vec<_> = allocator
+    ///     .iter_blocks()
+    ///     .map(|block| (block.offset, block.size, block.state))
+    ///     .collect();
+    /// assert_eq!(
+    ///     blocks,
+    ///     [
+    ///         (0, 8, State::Used),
+    ///         (HEADER_SIZE + 8, 64 - 2 * HEADER_SIZE - 8, State::Free)
+    ///     ]
+    /// );
+    /// ```
+    pub fn iter_blocks(&self) -> Blocks<'_, L, N> {
+        Blocks::new(self.raw.lock())
+    }
+
+    /// Query the size of the largest free memory block.
+    ///
+    /// This is the largest allocation size, that could currently succeed, and
+    /// is equal to [`HeapStats::largest_free`]. It is cheaper to obtain than the
+    /// full [`stats()`](Self::stats) though, as only this single number is
+    /// tracked while walking over the blocks.
+    ///
+    /// Note, that an allocation with an alignment larger than the
+    /// [`GRANULARITY`](Self::GRANULARITY) is placed at the first suitably
+    /// aligned address inside of the block, so up to `align - GRANULARITY`
+    /// bytes in front of it (depending on the address of the block) cannot be
+    /// used for it. Memory reserved via [`reserve()`](Self::reserve) is
+    /// included in this number.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// assert_eq!(allocator.largest_free_block(), allocator.capacity());
+    /// ```
+    pub fn largest_free_block(&self) -> usize {
+        self.raw.lock().largest_free_block()
+    }
+
+    /// Query the largest size, which can currently be allocated with `align`.
+    ///
+    /// In contrast to [`largest_free_block()`](Self::largest_free_block), this
+    /// accounts for alignments larger than `4`: such an allocation is placed at
+    /// the first suitably aligned address in a free block, so the memory in
+    /// front of that address cannot be used for it. Furthermore the memory
+    /// reserved via [`reserve()`](Self::reserve) is excluded. An allocation of
+    /// the returned size with the given alignment succeeds (as long as the heap
+    /// is not modified in the meantime), while a larger one fails. If `align`
+    /// is not a power of two, `0` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let capacity = allocator.capacity();
+    /// assert_eq!(allocator.largest_alloc_for_align(4), capacity);
+    /// // the first aligned address is less than 64 bytes into the heap memory
+    /// let largest = allocator.largest_alloc_for_align(64);
+    /// assert!(capacity - 64 < largest && largest <= capacity);
+    /// ```
+    pub fn largest_alloc_for_align(&self, align: usize) -> usize {
+        if !align.is_power_of_two() {
+            return 0;
+        }
+        self.raw.lock().largest_aligned_allocation(align)
+    }
+
+    /// Check, whether an allocation could grow in place.
+    ///
+    /// This returns `true`, if the memory from `ptr` up to `ptr + new_size`
+    /// fits into the block containing `ptr` and the free block following it,
+    /// so that the allocation could be extended without moving the data. This
+    /// allows higher-level containers to choose between growing in place and a
+    /// fresh allocation. If `ptr` does not point into a live allocation of this
+    /// allocator, `false` is returned.
+    ///
+    /// This is a read-only operation: neither the heap is modified nor is any
+    /// memory reserved for the allocation, so a subsequent allocation might
+    /// occupy the memory following the block.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+    /// // the remaining heap is free and follows the allocation
+    /// assert!(allocator.can_grow_in_place(ptr, allocator.capacity()));
+    /// assert!(!allocator.can_grow_in_place(ptr, allocator.capacity() + 1));
+    /// ```
+    pub fn can_grow_in_place(&self, ptr: *mut u8, new_size: usize) -> bool {
+        self.raw.lock().can_grow_in_place(ptr, new_size)
+    }
+
+    /// Query the metadata of the block containing `ptr`.
+    ///
+    /// This searches the block, whose memory contains the given pointer, and
+    /// returns its offset in the heap, its size and its state. The pointer may
+    /// point anywhere into the memory of the block, e.g. to a field of an
+    /// allocated struct. If the pointer is not inside of any block (e.g. if it
+    /// was not allocated by this allocator), `None` is returned. This is useful
+    /// for debugging, e.g. to validate own bookkeeping.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::State;
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u8; 6]>()) };
+    ///
+    /// let info = allocator.block_of(ptr).unwrap();
+    /// assert_eq!(info.size, 8);
+    /// assert_eq!(info.state, State::Used);
+    /// assert_eq!(allocator.block_of(core::ptr::null()), None);
+    /// ```
+    pub fn block_of(&self, ptr: *const u8) -> Option<BlockInfo> {
+        self.raw.lock().block_of(ptr)
+    }
+
+    /// Check whether two pointers point into the same block.
+    ///
+    /// This is `true`, if both `a` and `b` point anywhere into the memory of a
+    /// single block, e.g. to two fields of the same allocated struct. If they
+    /// point into different blocks or if `a` is not inside of the heap at all,
+    /// `false` is returned. This is cheaper than comparing the results of two
+    /// calls to [`block_of()`](Self::block_of), as the heap is scanned once.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::new::<[u8; 8]>();
+    /// let first = unsafe { allocator.alloc(layout) };
+    /// let second = unsafe { allocator.alloc(layout) };
+    ///
+    /// assert!(allocator.same_block(first, first.wrapping_add(7)));
+    /// assert!(!allocator.same_block(first, second));
+    /// ```
+    pub fn same_block(&self, a: *const u8, b: *const u8) -> bool {
+        self.raw.lock().same_block(a, b)
+    }
+
+    /// Merge two allocations, which are adjacent in the heap, into one.
+    ///
+    /// If the blocks of `a` and `b` are both used and directly follow each
+    /// other (in any order), they are combined into a single used block. The
+    /// one of `a` and `b` with the lower address is returned, which is the
+    /// start of the merged allocation: it spans up to the end of the block of
+    /// the other allocation. The 4 bytes of the block header between both
+    /// allocations become part of the memory, so the data of both allocations
+    /// is kept in place (i.e. it is not contiguous).
+    ///
+    /// The layouts of the allocations `a` and `b` are required to stop
+    /// counting their rounding (see
+    /// [`internal_fragmentation()`](Self::internal_fragmentation)). The merged
+    /// allocation is freed once via the returned pointer with a layout of its
+    /// whole size, i.e. from the returned pointer up to the end of the block.
+    ///
+    /// If the blocks are not adjacent (or if either pointer does not point
+    /// into a used block), nothing is changed and `None` is returned. The same
+    /// applies to blocks, which cannot be merged for other reasons, e.g. a
+    /// block referenced by a handle with the `relocating` feature.
+    ///
+    /// # Safety
+    /// Both `a` and `b` have to be allocations of this allocator owned by the
+    /// caller, which were allocated with `a_layout` and `b_layout`. If the
+    /// allocations are merged, the other pointer must not be freed anymore.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::new::<[u8; 8]>();
+    /// let first = unsafe { allocator.alloc(layout) };
+    /// let second = unsafe { allocator.alloc(layout) };
+    ///
+    /// let merged = unsafe { allocator.try_merge(second, layout, first, layout) }.unwrap();
+    /// assert_eq!(merged, first);
+    /// // the header of the second block is part of the merged block
+    /// const SIZE: usize = 8 + emballoc::Allocator::<4096>::GRANULARITY + 8;
+    /// assert_eq!(allocator.block_of(merged).unwrap().size, SIZE);
+    /// unsafe { allocator.dealloc(merged, Layout::new::<[u8; SIZE]>()) };
+    /// ```
+    pub unsafe fn try_merge(
+        &self,
+        a: *mut u8,
+        a_layout: Layout,
+        b: *mut u8,
+        b_layout: Layout,
+    ) -> Option<*mut u8> {
+        let mut raw = self.raw.lock();
+        let merged = raw.try_merge(a, b)?;
+        // the merged allocation spans the whole block, so it is not rounded
+        raw.forget_rounding(a_layout.size());
+        raw.forget_rounding(b_layout.size());
+        drop(raw);
+        let (merged, other) = if merged == a { (a, b) } else { (b, a) };
+        self.live.remove(other);
+        Some(merged)
+    }
+
+    /// Query the memory region of the heap.
+    ///
+    /// This returns the start address and the size (which is always `N`) of the
+    /// buffer backing the heap. All allocations are served from that memory
+    /// region. This is useful e.g. for configuring a memory protection unit
+    /// (MPU) to protect the heap memory (see the [crate-level](crate)
+    /// documentation). The start address is always aligned to the
+    /// [`GRANULARITY`](Self::GRANULARITY).
+    ///
+    /// Note, that the address is only meaningful, as long as the allocator is
+    /// not moved, which is the case for allocators in a `static` variable.
+    ///
+    /// # Example
+    /// ```
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let (start, size) = ALLOCATOR.memory_region();
+    /// assert_eq!(size, 4096);
+    /// // configure the MPU to protect `start..start + size`
+    /// ```
+    pub fn memory_region(&self) -> (*const u8, usize) {
+        (self.raw.lock().as_ptr(), N)
+    }
+
+    /// Obtain the memory of the reserved prefix of the heap.
+    ///
+    /// This is the memory of the first block, which is the prefix reserved by
+    /// [`Allocator::new_with_reserved_prefix()`]. The address is the start of
+    /// the heap plus the header of the block, i.e. it is fixed as long as the
+    /// allocator is not moved, which is the case for allocators in a `static`
+    /// variable. For an allocator created otherwise, the first block is just
+    /// the block of the first allocation.
+    pub fn reserved_prefix(&self) -> *mut u8 {
+        self.offset_to_ptr(HEADER_SIZE)
+    }
+
+    /// Convert an offset into the heap to the corresponding address.
+    ///
+    /// This is the counterpart to [`ptr_to_offset()`](Self::ptr_to_offset)
+    /// and is useful to correlate offsets (e.g. in a dump of the heap or in
+    /// [`BlockInfo::offset`]) with the addresses seen by the program. If the
+    /// offset is not inside the heap (i.e. not less than `N`), a null pointer
+    /// is returned.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let ptr = allocator.offset_to_ptr(16);
+    /// assert_eq!(allocator.ptr_to_offset(ptr), Some(16));
+    /// assert!(allocator.offset_to_ptr(4096).is_null());
+    /// ```
+    pub fn offset_to_ptr(&self, offset: usize) -> *mut u8 {
+        let (start, size) = self.memory_region();
+        if offset < size {
+            start.wrapping_add(offset) as *mut u8
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    /// Convert an address inside the heap to the offset from its start.
+    ///
+    /// This is the counterpart to [`offset_to_ptr()`](Self::offset_to_ptr). If
+    /// the pointer does not point into the heap memory, `None` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+    /// // the first allocation is directly after the first header
+    /// let header = emballoc::Allocator::<4096>::GRANULARITY;
+    /// assert_eq!(allocator.ptr_to_offset(ptr), Some(header));
+    /// assert_eq!(allocator.ptr_to_offset(core::ptr::null()), None);
+    /// ```
+    pub fn ptr_to_offset(&self, ptr: *const u8) -> Option<usize> {
+        let (start, size) = self.memory_region();
+        (ptr as usize)
+            .checked_sub(start as usize)
+            .filter(|&offset| offset < size)
+    }
+
+    /// Determine the offset an allocation would occupy without performing it.
+    ///
+    /// The same search as for [`GlobalAlloc::alloc()`] is run with the current
+    /// [`Strategy`] (including the recently freed blocks, the reserved memory
+    /// and the caps), but it stops before splitting a block, i.e. the heap is
+    /// not modified at all. The offset of the memory from the start of the heap
+    /// is returned, which an allocation with the `layout` would return right
+    /// now (see [`ptr_to_offset()`](Self::ptr_to_offset)). If the allocation
+    /// would fail, `None` is returned. This allows to plan the placement of
+    /// allocations and to check placement decisions in tests.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let layout = Layout::from_size_align(64, 32).unwrap();
+    /// let offset = allocator.probe_alloc(layout).unwrap();
+    /// let ptr = unsafe { allocator.alloc(layout) };
+    /// assert_eq!(allocator.ptr_to_offset(ptr), Some(offset));
+    /// assert_eq!(allocator.probe_alloc(Layout::new::<[u8; 4096]>()), None);
+    /// ```
+    pub fn probe_alloc(&self, layout: Layout) -> Option<usize> {
+        self.raw.lock().probe(layout.size(), layout.align())
+    }
+
+    /// Write to the whole free heap memory once.
+    ///
+    /// On some targets the heap memory is not physically backed until it is
+    /// written to for the first time. Calling this function during the startup
+    /// faults in the whole buffer deterministically, so that there are no
+    /// latency spikes later on.
+    ///
+    /// This does not change the logical state of the heap: only the memory of
+    /// free blocks is overwritten with zeros (except for the links of the free
+    /// list), so existing allocations are left untouched. If called on a fresh
+    /// allocator, the whole buffer is written.
+    ///
+    /// # Example
+    /// ```
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    /// ALLOCATOR.prefault();
+    /// assert_eq!(ALLOCATOR.stats().free, ALLOCATOR.capacity());
+    /// ```
+    pub fn prefault(&self) {
+        self.raw.lock().zero_free_memory();
+    }
+
+    /// Free all allocations at once and zero the memory used so far.
+    ///
+    /// The allocator keeps track of the highest address ever handed out or
+    /// written by the allocator itself, e.g. for the links of the free list
+    /// (the "high-water mark"). This function overwrites the heap memory up to
+    /// that mark with zeros and resets the heap to a single free block, just
+    /// like a freshly created allocator. The memory after the mark was never used and
+    /// is therefore not written. This is much faster than zeroing the whole
+    /// heap, if a large heap was only used lightly, e.g. to wipe the memory of
+    /// a completed phase of the program. Settings like the [`reserve()`]d
+    /// memory or the [`Strategy`] are kept.
+    ///
+    /// # Safety
+    /// All the memory allocated so far is handed out again, so the caller has
+    /// to ensure, that none of the previous allocations is used afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let ptr = unsafe { ALLOCATOR.alloc(Layout::new::<u32>()) };
+    /// unsafe { ALLOCATOR.reset_touched() }; // `ptr` must not be used anymore
+    /// assert_eq!(ALLOCATOR.stats().used_blocks, 0);
+    /// ```
+    ///
+    /// [`reserve()`]: Self::reserve
+    pub unsafe fn reset_touched(&self) {
+        self.raw.lock().reset_touched();
+        self.live.clear();
+    }
+
+    /// Free all allocations at once and fill the whole heap memory with `byte`.
+    ///
+    /// The heap is reset to a single free block like with
+    /// [`reset_touched()`](Self::reset_touched), but the whole heap memory is
+    /// filled with `byte` before the header of that block is written. This is
+    /// meant for deterministic tests against the raw memory: every byte, which
+    /// differs from the pattern afterwards, was written by the allocator or by
+    /// the program. Note, that the allocator writes the block headers and the
+    /// links of the free list, which are stored in the first 8 bytes of every
+    /// free block, so they remain in the memory of a block allocated from it.
+    /// With the `fill-on-alloc` feature, the whole memory of every allocation
+    /// is overwritten with its fill pattern instead. This function is only
+    /// available in tests and debug builds.
+    ///
+    /// # Safety
+    /// All the memory allocated so far is handed out again, so the caller has
+    /// to ensure, that none of the previous allocations is used afterwards.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// unsafe { ALLOCATOR.clear_to_pattern(0xA5) };
+    /// let ptr = unsafe { ALLOCATOR.alloc(Layout::new::<[u8; 16]>()) };
+    /// // the allocator does not initialize the allocated memory (except for
+    /// // the free-list links in the first 8 bytes)
+    /// # #[cfg(not(feature = "fill-on-alloc"))]
+    /// assert_eq!(unsafe { ptr.add(15).read() }, 0xA5);
+    /// ```
+    #[cfg(any(test, debug_assertions))]
+    pub unsafe fn clear_to_pattern(&self, byte: u8) {
+        self.raw.lock().clear_to_pattern(byte);
+        self.live.clear();
+    }
+
+    /// Allocate memory without ever blocking on the internal lock.
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], except if the internal lock
+    /// is currently held (e.g. by the code interrupted by an interrupt handler
+    /// calling this function). In that case, a null pointer is returned
+    /// immediately instead of waiting for the lock, which would deadlock on a
+    /// single-core system.
+    ///
+    /// Therefore the caller must handle spurious allocation failures: a null
+    /// pointer does not necessarily mean, that the heap is exhausted. Retrying
+    /// later (e.g. on the next interrupt) might succeed.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// // e.g. in an interrupt handler
+    /// let ptr = unsafe { ALLOCATOR.alloc_nonblocking(Layout::new::<u32>()) };
+    /// if ptr.is_null() {
+    ///     // heap exhausted or lock contended: handle the failure gracefully
+    /// }
+    /// ```
+    pub unsafe fn alloc_nonblocking(&self, layout: Layout) -> *mut u8 {
+        let ptr = self
+            .raw
+            .try_lock()
+            .map_or(ptr::null_mut(), |mut raw| Self::alloc_in(&mut raw, layout));
+        self.live.insert(ptr);
+        ptr
+    }
+
+    /// Allocate memory and report the actually usable size.
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but additionally returns the
+    /// number of bytes, which can be used starting at the returned pointer. As
+    /// the memory blocks are rounded up to a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY) (and might be
+    /// enlarged for alignment or by avoiding tiny free blocks), this is at
+    /// least `layout.size()`, but might be larger. This allows e.g. a `Vec`-like
+    /// type to use the whole block instead of just the requested size. If the
+    /// allocation fails, `None` is returned.
+    ///
+    /// The memory can be deallocated with [`GlobalAlloc::dealloc()`] using the
+    /// original `layout`.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let (ptr, size) = unsafe { allocator.alloc_with_size(Layout::new::<[u8; 5]>()) }.unwrap();
+    /// assert_eq!(size, 8);
+    /// ```
+    pub unsafe fn alloc_with_size(&self, layout: Layout) -> Option<(NonNull<u8>, usize)> {
+        let allocation = self
+            .lock_for_alloc()
+            .and_then(|mut raw| Self::alloc_in_with_size(&mut raw, layout));
+        if let Some((ptr, _)) = allocation {
+            self.live.insert(ptr.as_ptr());
+        }
+        allocation
+    }
+
+    /// Allocate memory and report its offset into the heap.
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but additionally returns the
+    /// offset of the memory from the start of the heap (the same as
+    /// [`ptr_to_offset()`](Self::ptr_to_offset) would return). The memory can
+    /// be deallocated via [`free_at()`](Self::free_at) using only that offset,
+    /// which avoids scanning the heap for the block containing a pointer. It
+    /// can be deallocated with [`GlobalAlloc::dealloc()`] as well.
+    ///
+    /// Only alignments up to the [`GRANULARITY`](Self::GRANULARITY) are
+    /// supported, so that the memory starts directly behind the header of its
+    /// block. If the alignment is larger or
+    /// if the allocation fails, `None` is returned.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// let (ptr, offset) = unsafe { allocator.alloc_with_offset(Layout::new::<u32>()) }.unwrap();
+    /// assert_eq!(allocator.ptr_to_offset(ptr.as_ptr()), Some(offset));
+    /// unsafe { allocator.free_at(offset) }.unwrap();
+    /// ```
+    pub unsafe fn alloc_with_offset(&self, layout: Layout) -> Option<(NonNull<u8>, usize)> {
+        if layout.align() > Self::GRANULARITY {
+            return None;
+        }
+        let mut raw = self.lock_for_alloc()?;
+        let base = raw.as_ptr() as usize;
+        let memory = raw.alloc(layout.size())?;
+        let ptr = NonNull::new(ptr::addr_of_mut!(*memory).cast::<u8>())?;
+        // the allocation is freed without its layout, see `free_at()`
+        raw.forget_rounding(layout.size());
+        drop(raw);
+        self.live.insert(ptr.as_ptr());
+        Some((ptr, ptr.as_ptr() as usize - base))
+    }
+
+    /// Deallocate the memory at the given `offset` into the heap.
+    ///
+    /// This is the counterpart to [`alloc_with_offset()`]. In contrast to
+    /// [`GlobalAlloc::dealloc()`], the heap is not scanned for the block
+    /// containing the memory, as the header of the block is read directly in
+    /// front of the memory. Therefore this takes constant time (plus merging
+    /// the block with the following free block) regardless of the number of
+    /// blocks in the heap.
+    ///
+    /// # Safety
+    /// The `offset` has to be returned by [`alloc_with_offset()`] of this
+    /// allocator and the memory must not have been deallocated already. Any
+    /// other offset might be mistaken for a header of a block, which corrupts
+    /// the heap. Only the obvious errors below are detected.
+    ///
+    /// # Errors
+    /// An offset, which is not a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY) or not inside of the heap, is rejected
+    /// with [`FreeError::AllocationNotFound`]. If the block is already
+    /// free, [`FreeError::DoubleFreeDetected`] is returned, and if it is a
+    /// pending reservation, [`FreeError::ReservedBlock`]. The error handler
+    /// (see [`set_dealloc_error_handler()`](Self::set_dealloc_error_handler))
+    /// is not called, as the error is returned to the caller instead.
+    ///
+    /// [`alloc_with_offset()`]: Self::alloc_with_offset
+    pub unsafe fn free_at(&self, offset: usize) -> Result<(), FreeError> {
+        let mut raw = self.raw.lock();
+        let ptr = raw.as_ptr().wrapping_add(offset) as *mut u8;
+        // SAFETY: the caller guarantees, that the offset is the start of the
+        // memory of a block (safety contract of this function).
+        unsafe { raw.free_at(offset) }?;
+        drop(raw);
+        self.live.remove(ptr);
+        Ok(())
+    }
+
+    /// Allocate multiple blocks of the same `layout` at once.
+    ///
+    /// This performs up to `count` allocations (but at most `out.len()` ones)
+    /// while holding the internal lock only once, which is cheaper than
+    /// separate calls to [`GlobalAlloc::alloc()`], e.g. when allocating a pool
+    /// of equally sized buffers. The pointers are written to the start of `out`
+    /// and the number of successful allocations is returned. The allocation
+    /// stops at the first failure, the pointers allocated before stay valid
+    /// and have to be freed as usual. The remaining entries of `out` are not
+    /// modified.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let mut buffers = [core::ptr::null_mut(); 8];
+    /// let count = unsafe { ALLOCATOR.alloc_batch(8, Layout::new::<[u8; 64]>(), &mut buffers) };
+    /// assert_eq!(count, 8);
+    /// ```
+    pub unsafe fn alloc_batch(&self, count: usize, layout: Layout, out: &mut [*mut u8]) -> usize {
+        let count = count.min(out.len());
+        let allocated = self.lock_for_alloc().map_or(0, |mut raw| {
+            let allocations = out.iter_mut().take(count).map(|slot| {
+                *slot = Self::alloc_in(&mut raw, layout);
+                *slot
+            });
+            allocations.take_while(|ptr| !ptr.is_null()).count()
+        });
+        for &ptr in &out[..allocated] {
+            self.live.insert(ptr);
+        }
+        allocated
+    }
+
+    /// Allocate a header followed by an array of `count` elements.
+    ///
+    /// This is the layout of a structure with a trailing flexible array, e.g. a
+    /// message header followed by its payload. The combined layout is computed
+    /// from the `header` and the `elem` layouts: the array starts after the
+    /// header at the next offset aligned to the alignment of the elements and
+    /// the elements are padded to their alignment (like in a slice). The whole
+    /// block is aligned to the larger one of both alignments. The pointer to
+    /// the block, the combined layout and the offset of the array relative to
+    /// the pointer are returned. The memory is deallocated with
+    /// [`GlobalAlloc::dealloc()`] using the returned layout.
+    ///
+    /// If the combined layout is empty or too large (i.e. its size overflows
+    /// `isize::MAX`) or if the allocation fails, `None` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// // a `u16` length followed by 10 `u64` values
+    /// let (ptr, layout, offset) = ALLOCATOR
+    ///     .alloc_flex(Layout::new::<u16>(), Layout::new::<u64>(), 10)
+    ///     .unwrap();
+    /// assert_eq!(offset, 8);
+    /// assert_eq!(layout.size(), 8 + 10 * 8);
+    /// let values = unsafe { ptr.as_ptr().add(offset) }.cast::<u64>();
+    /// unsafe { values.write(42) };
+    /// unsafe { ALLOCATOR.dealloc(ptr.as_ptr(), layout) };
+    /// ```
+    pub fn alloc_flex(
+        &self,
+        header: Layout,
+        elem: Layout,
+        count: usize,
+    ) -> Option<(NonNull<u8>, Layout, usize)> {
+        let array_size = elem.pad_to_align().size().checked_mul(count)?;
+        let array = Layout::from_size_align(array_size, elem.align()).ok()?;
+        let (layout, offset) = header.extend(array).ok()?;
+        if layout.size() == 0 {
+            return None;
+        }
+        // SAFETY: the size of the layout is non-zero (checked above)
+        let (ptr, _size) = unsafe { self.alloc_with_size(layout) }?;
+        Some((ptr, layout, offset))
+    }
+
+    /// Deallocate multiple blocks at once.
+    ///
+    /// This frees the memory of all the pointers in `ptrs` while holding the
+    /// internal lock only once, which is cheaper than separate calls to
+    /// [`GlobalAlloc::dealloc()`], e.g. when tearing down a subsystem. Freeing
+    /// a block only merges it with the following free block, so afterwards all
+    /// adjacent free blocks are merged (see
+    /// [`coalesce_free_blocks()`](Self::coalesce_free_blocks)). Therefore
+    /// neighboring blocks freed in the same batch end up as a single free
+    /// block regardless of their order in `ptrs`.
+    ///
+    /// The number of pointers, which were freed successfully, is returned.
+    /// Invalid pointers (e.g. a pointer outside of the heap or one, which was
+    /// freed already) are skipped, the error handler (see
+    /// [`set_dealloc_error_handler()`](Self::set_dealloc_error_handler)) is not
+    /// called. As the layouts of the allocations are unknown, their rounding
+    /// is still counted by
+    /// [`internal_fragmentation()`](Self::internal_fragmentation).
+    ///
+    /// # Safety
+    /// Every valid pointer has to be allocated by this allocator and must not
+    /// be used afterwards, just like for [`GlobalAlloc::dealloc()`].
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let mut buffers = [core::ptr::null_mut(); 8];
+    /// let count = unsafe { ALLOCATOR.alloc_batch(8, Layout::new::<[u8; 64]>(), &mut buffers) };
+    /// assert_eq!(unsafe { ALLOCATOR.free_batch(&buffers[..count]) }, count);
+    /// assert_eq!(ALLOCATOR.stats().free_blocks, 1);
+    /// ```
+    pub unsafe fn free_batch(&self, ptrs: &[*mut u8]) -> usize {
+        let mut raw = self.raw.lock();
+        let freed = ptrs.iter().filter(|&&ptr| raw.free(ptr).is_ok()).count();
+        if freed > 0 {
+            raw.coalesce_free_blocks();
+        }
+        drop(raw);
+        for &ptr in ptrs {
+            self.live.remove(ptr);
+        }
+        freed
+    }
+
+    /// Reserve memory for allocations in emergency situations.
+    ///
+    /// This carves out a memory region at the end of the heap, which is large
+    /// enough for an allocation of `bytes` bytes. Regular allocations never use
+    /// that region, so it is guaranteed, that a subsequent allocation of up to
+    /// `bytes` bytes via [`alloc_reserved()`](Self::alloc_reserved) succeeds,
+    /// even if the rest of the heap is exhausted. This is useful e.g. for a
+    /// fault handler, which has to allocate a small error report.
+    ///
+    /// Note, that the reserved size has to account for over-aligned layouts:
+    /// such an allocation starts at the first suitably aligned address of the
+    /// reserved memory, which may be up to `align - GRANULARITY` bytes behind
+    /// its start.
+    ///
+    /// A reservation is only possible, if the memory at the end of the heap is
+    /// currently free. If it is not, `false` is returned and the previous
+    /// reservation (if any) is kept. Calling this function again replaces the
+    /// previous reservation. A reservation of `0` removes the reservation.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<64> = emballoc::Allocator::new();
+    /// assert!(ALLOCATOR.reserve(16));
+    ///
+    /// let layout = Layout::new::<[u32; 4]>();
+    /// # #[cfg(not(feature = "abort-on-oom"))]
+    /// while !unsafe { ALLOCATOR.alloc(layout) }.is_null() {} // exhaust the heap
+    /// assert!(!unsafe { ALLOCATOR.alloc_reserved(layout) }.is_null());
+    /// ```
+    pub fn reserve(&self, bytes: usize) -> bool {
+        self.raw.lock().reserve(bytes)
+    }
+
+    /// Allocate memory, which may use the memory reserved via [`reserve()`].
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but this allocation might
+    /// use the memory reserved for emergencies. It should be used on critical
+    /// paths only, which must not fail.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size. The memory is released with the
+    /// usual [`GlobalAlloc::dealloc()`].
+    ///
+    /// [`reserve()`]: Self::reserve
+    pub unsafe fn alloc_reserved(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.lock_for_alloc().map_or(ptr::null_mut(), |mut raw| {
+            Self::alloc_aligned(layout, |size, align| {
+                raw.alloc_aligned_reserved(size, align)
+            })
+        });
+        self.live.insert(ptr);
+        ptr
+    }
+
+    /// Compact the heap by moving live allocations.
+    ///
+    /// Over time, the heap might get fragmented: there is enough free memory in
+    /// total, but no single free block is large enough for an allocation. This
+    /// function moves all allocations towards the start of the heap (keeping
+    /// their order), so that afterwards all the free memory forms a single
+    /// contiguous block at the end of the heap.
+    ///
+    /// Every moved allocation is reported to the `relocate` callback, which is
+    /// called with the old address, the new address and the size of the moved
+    /// memory. Note, that the reported size is the size of the memory block,
+    /// which is the requested size rounded up to a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY). The
+    /// callback has to update all the references to the moved allocation, e.g.
+    /// in a handle table. Allocations, which are not moved, are not reported.
+    /// The [`Handle`]s obtained via [`alloc_handle()`](Self::alloc_handle) are
+    /// updated automatically, so they do not require any work in the callback.
+    ///
+    /// This function is only available with the `relocating` feature.
+    ///
+    /// # Safety
+    /// Moving memory behind the back of its owner is inherently dangerous, so
+    /// the caller must ensure the following:
+    /// - all live allocations must have been made with an alignment of at most
+    ///   `4`, as the allocations are moved by multiples of `4` bytes only.
+    /// - the moved memory must not be accessed via the old address afterwards.
+    ///   Every pointer or reference to a moved allocation must be updated
+    ///   accordingly (e.g. in the callback). In particular, there must be no
+    ///   Rust references to any allocation while this function runs.
+    /// - the memory must be valid to be moved bitwise, i.e. it must not contain
+    ///   self-referential data.
+    ///
+    /// The callback is run while the allocator is locked, so it must not
+    /// allocate or deallocate memory with this allocator (which would
+    /// deadlock).
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<64> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<u32>();
+    /// let first = unsafe { ALLOCATOR.alloc(layout) };
+    /// let mut second = unsafe { ALLOCATOR.alloc(layout) };
+    /// unsafe { ALLOCATOR.dealloc(first, layout) };
+    ///
+    /// unsafe {
+    ///     ALLOCATOR.compact(|old, new, _size| {
+    ///         if old == second {
+    ///             second = new;
+    ///         }
+    ///     })
+    /// };
+    /// assert_eq!(second, first);
+    /// ```
+    #[cfg(feature = "relocating")]
+    pub unsafe fn compact(&self, mut relocate: impl FnMut(*mut u8, *mut u8, usize)) {
+        self.raw.lock().compact(|old, new, size| {
+            self.live.relocate(old, new);
+            relocate(old, new, size);
+        });
+    }
+
+    /// Allocate relocatable memory referenced by a [`Handle`].
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but instead of a pointer a
+    /// handle is returned. The handle refers to an entry in a small table inside
+    /// the allocator, which is updated by [`compact()`](Self::compact) when the
+    /// allocation is moved. Therefore the current address can always be queried
+    /// via [`Handle::as_ptr()`] without any bookkeeping in the callback of the
+    /// compaction. The memory is released via
+    /// [`dealloc_handle()`](Self::dealloc_handle).
+    ///
+    /// If the allocation fails, if all the 16 handles are in use or if the
+    /// alignment of the `layout` is larger than the
+    /// [`GRANULARITY`](Self::GRANULARITY) (which would not survive a
+    /// compaction), `None` is returned.
+    ///
+    /// This function is only available with the `relocating` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<64> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<u32>();
+    /// let first = unsafe { ALLOCATOR.alloc(layout) };
+    /// let handle = ALLOCATOR.alloc_handle(layout).unwrap();
+    /// unsafe { handle.as_ptr(&ALLOCATOR).cast::<u32>().write(42) };
+    ///
+    /// unsafe { ALLOCATOR.dealloc(first, layout) };
+    /// unsafe { ALLOCATOR.compact(|_old, _new, _size| {}) };
+    /// assert_eq!(handle.as_ptr(&ALLOCATOR), first);
+    /// assert_eq!(unsafe { handle.as_ptr(&ALLOCATOR).cast::<u32>().read() }, 42);
+    /// ALLOCATOR.dealloc_handle(handle);
+    /// ```
+    #[cfg(feature = "relocating")]
+    pub fn alloc_handle(&self, layout: Layout) -> Option<Handle> {
+        if layout.align() > Self::GRANULARITY {
+            return None;
+        }
+        let mut raw = self.lock_for_alloc()?;
+        let (index, ptr) = raw.alloc_handle(layout.size())?;
+        // the allocation is freed without its layout, see `dealloc_handle()`
+        raw.forget_rounding(layout.size());
+        drop(raw);
+        self.live.insert(ptr);
+        Some(Handle { index })
+    }
+
+    /// Deallocate the memory referenced by a [`Handle`].
+    ///
+    /// The handle has to be obtained via [`alloc_handle()`](Self::alloc_handle)
+    /// of this allocator. Afterwards, its entry in the handle table is available
+    /// for a new handle.
+    ///
+    /// This function is only available with the `relocating` feature.
+    #[cfg(feature = "relocating")]
+    #[allow(clippy::needless_pass_by_value)] // the handle is used up
+    pub fn dealloc_handle(&self, handle: Handle) {
+        let mut raw = self.raw.lock();
+        let ptr = raw.take_handle(handle.index);
+        let result = Self::dealloc_in(&mut raw, ptr, None);
+        drop(raw);
+        self.live.remove(ptr);
+        Self::finish_dealloc(result);
+    }
+
+    /// Reserve a block, which is committed or aborted later on.
+    ///
+    /// This allocates memory for the `layout` like [`GlobalAlloc::alloc()`],
+    /// but returns a pending [`Reservation`]. Its memory can be filled before
+    /// the reservation is finalized: [`commit()`](Self::commit) turns it into
+    /// an ordinary allocation, while [`abort()`](Self::abort) returns the
+    /// memory to the heap. This allows to compute into the memory first and
+    /// to make it visible (e.g. to a consumer) only afterwards.
+    ///
+    /// Until then, the block is in the state [`State::Reserved`]: it is neither
+    /// free nor used, so [`stats()`](Self::stats) reports it separately (see
+    /// [`HeapStats::reserved_blocks`]), as do the other diagnostics like
+    /// [`iter_blocks()`](Self::iter_blocks). If the allocation fails or the
+    /// size of the `layout` is zero, `None` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<u32>();
+    /// let reservation = ALLOCATOR.reserve_block(layout).unwrap();
+    /// unsafe { reservation.as_ptr().cast::<u32>().write(42) };
+    /// assert_eq!(ALLOCATOR.stats().reserved_blocks, 1);
+    ///
+    /// let ptr = ALLOCATOR.commit(reservation);
+    /// assert_eq!(unsafe { ptr.cast::<u32>().read() }, 42);
+    /// assert_eq!(ALLOCATOR.stats().used_blocks, 1);
+    /// unsafe { ALLOCATOR.dealloc(ptr, layout) };
+    /// ```
+    pub fn reserve_block(&self, layout: Layout) -> Option<Reservation> {
+        if layout.size() == 0 {
+            return None;
+        }
+        let ptr = self.lock_for_alloc().and_then(|mut raw| {
+            let (ptr, _size) = Self::alloc_in_with_size(&mut raw, layout)?;
+            raw.set_state(ptr.as_ptr(), State::Used, State::Reserved);
+            Some(ptr)
+        })?;
+        self.live.insert(ptr.as_ptr());
+        Some(Reservation { ptr, layout })
+    }
+
+    /// Commit a [`Reservation`] and obtain its memory.
+    ///
+    /// The block is marked as used, so afterwards the memory is an ordinary
+    /// allocation, which has to be freed via [`GlobalAlloc::dealloc()`] with
+    /// the layout of the reservation. The reservation has to be obtained via
+    /// [`reserve_block()`] of this allocator.
+    ///
+    /// [`reserve_block()`]: Self::reserve_block
+    #[allow(clippy::needless_pass_by_value)] // the reservation is used up
+    #[must_use = "the committed memory has to be freed"]
+    pub fn commit(&self, reservation: Reservation) -> *mut u8 {
+        let ptr = reservation.as_ptr();
+        let committed = self.raw.lock().set_state(ptr, State::Reserved, State::Used);
+        debug_assert!(committed, "reservation of another allocator");
+        ptr
+    }
+
+    /// Abort a [`Reservation`] and return its memory to the heap.
+    ///
+    /// The reserved block is freed without being committed. The reservation
+    /// has to be obtained via [`reserve_block()`] of this allocator. Note, that
+    /// a pending reservation cannot be freed via [`GlobalAlloc::dealloc()`]
+    /// (see [`FreeError::ReservedBlock`]).
+    ///
+    /// [`reserve_block()`]: Self::reserve_block
+    #[allow(clippy::needless_pass_by_value)] // the reservation is used up
+    pub fn abort(&self, reservation: Reservation) {
+        let ptr = reservation.as_ptr();
+        let result = {
+            let mut raw = self.raw.lock();
+            // only used blocks can be freed, so the block is marked as used
+            // under the same lock before freeing it
+            let reserved = raw.set_state(ptr, State::Reserved, State::Used);
+            debug_assert!(reserved, "reservation of another allocator");
+            Self::dealloc_in(&mut raw, ptr, Some(reservation.layout()))
+        };
+        self.live.remove(ptr);
+        Self::finish_dealloc(result);
+    }
+
+    /// Allocate memory at a fixed offset into the heap.
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but the returned memory
+    /// starts exactly at the given `offset` from the start of the heap, i.e. at
+    /// [`offset_to_ptr(offset)`](Self::offset_to_ptr). This allows to pin a
+    /// buffer to a specific address, e.g. for a peripheral requiring its buffer
+    /// at a fixed location. The memory at the offset (including the 4 bytes
+    /// before it, which hold the header of the block) has to be free. The free
+    /// block containing it is split on both sides as necessary. The memory is
+    /// released with the usual [`GlobalAlloc::dealloc()`].
+    ///
+    /// If that memory is not free, if the offset is not a multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY) or if
+    /// the address does not fulfill the alignment of the `layout`, a null
+    /// pointer is returned. Like [`alloc()`](GlobalAlloc::alloc), the memory
+    /// reserved via [`reserve()`](Self::reserve) is never used.
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<[u8; 256]>();
+    /// let buffer = unsafe { ALLOCATOR.alloc_at(1024, layout) };
+    /// assert_eq!(buffer, ALLOCATOR.offset_to_ptr(1024));
+    /// // the memory is in use now
+    /// assert!(unsafe { ALLOCATOR.alloc_at(1024, layout) }.is_null());
+    /// ```
+    pub unsafe fn alloc_at(&self, offset: usize, layout: Layout) -> *mut u8 {
+        let ptr = self.lock_for_alloc().map_or(ptr::null_mut(), |mut raw| {
+            let address = raw.as_ptr() as usize + offset;
+            if address % layout.align() != 0 {
+                return ptr::null_mut();
+            }
+            raw.alloc_at(offset, layout.size())
+                .map_or(ptr::null_mut(), |memory| memory.as_mut_ptr().cast())
+        });
+        self.live.insert(ptr);
+        ptr
+    }
+
+    /// Allocate memory at the end of the heap.
+    ///
+    /// This behaves like [`GlobalAlloc::alloc()`], but places the allocation
+    /// at the highest possible address instead of the best fitting free block.
+    /// The idea is to allocate long-lived objects (e.g. during the startup)
+    /// from the end of the heap, while short-lived objects are allocated from
+    /// the start of the heap as usual. This way, the frequent allocations and
+    /// deallocations of short-lived objects do not fragment the memory between
+    /// the long-lived objects. The memory is released with the usual
+    /// [`GlobalAlloc::dealloc()`].
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<[u32; 8]>();
+    /// let long_lived = unsafe { ALLOCATOR.alloc_from_top(layout) };
+    /// let short_lived = unsafe { ALLOCATOR.alloc(layout) };
+    /// assert!(short_lived < long_lived);
+    /// ```
+    pub unsafe fn alloc_from_top(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.lock_for_alloc().map_or(ptr::null_mut(), |mut raw| {
+            Self::alloc_aligned(layout, |size, align| {
+                raw.alloc_aligned_from_top(size, align)
+            })
+        });
+        self.live.insert(ptr);
+        ptr
+    }
+
+    /// Allocate memory, which does not cross a `boundary`.
+    ///
+    /// Some hardware (e.g. DMA controllers) requires buffers, which do not
+    /// straddle a certain boundary (e.g. a 1K page), i.e. the first and the
+    /// last byte of the allocation have to be in the same `boundary`-sized and
+    /// -aligned region. This is a requirement distinct from the alignment: a
+    /// buffer of 32 bytes with an alignment of 4 might still cross such a
+    /// boundary.
+    ///
+    /// This is achieved by aligning the allocation to the next power of two of
+    /// its size (or the alignment of the `layout`, if that is larger). Such an
+    /// allocation can never cross a larger power of two. This over-allocates
+    /// like any over-aligned allocation, but it is simple and cannot fail due
+    /// to an unfortunate placement. The memory is released with the usual
+    /// [`GlobalAlloc::dealloc()`] using the original `layout`.
+    ///
+    /// A null pointer is returned, if the allocation fails, if `boundary` is
+    /// not a power of two or if the size of the `layout` exceeds `boundary`
+    /// (as such an allocation always crosses a boundary).
+    ///
+    /// # Safety
+    /// The same requirements as for [`GlobalAlloc::alloc()`] apply, i.e. the
+    /// `layout` must have a non-zero size.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let descriptor = Layout::new::<[u32; 12]>();
+    /// let ptr = unsafe { ALLOCATOR.alloc_no_cross(descriptor, 1024) } as usize;
+    /// assert_eq!(ptr / 1024, (ptr + descriptor.size() - 1) / 1024);
+    /// ```
+    pub unsafe fn alloc_no_cross(&self, layout: Layout, boundary: usize) -> *mut u8 {
+        if !boundary.is_power_of_two() || layout.size() > boundary {
+            return ptr::null_mut();
+        }
+        let align = layout.size().next_power_of_two().max(layout.align());
+        Layout::from_size_align(layout.size(), align).map_or(ptr::null_mut(), |layout| {
+            // SAFETY: the size is non-zero (safety contract of this function)
+            unsafe { self.alloc(layout) }
+        })
+    }
+
+    /// Reallocate memory with a different alignment.
+    ///
+    /// [`GlobalAlloc::realloc()`] keeps the alignment of the original layout.
+    /// This function instead moves the allocation at `ptr` into a new block of
+    /// `new_size` bytes, which is aligned to `new_align`. The contents of the
+    /// old block are copied (up to the smaller of the old and new size) and
+    /// the old block is freed afterwards. Re-aligning in place is generally
+    /// not possible, so the returned pointer usually differs from `ptr`.
+    ///
+    /// If the new block cannot be allocated (or if `new_size` and `new_align`
+    /// do not form a valid [`Layout`]), a null pointer is returned and the old
+    /// block is left untouched, just like with `realloc()`.
+    ///
+    /// # Safety
+    /// The caller has to ensure the following:
+    /// - `ptr` is currently allocated by this allocator with `old_layout`
+    /// - `new_size` is greater than zero
+    ///
+    /// If this function returns a non-null pointer, the memory at `ptr` must
+    /// not be used anymore.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    ///
+    /// let layout = Layout::new::<[u8; 16]>();
+    /// let ptr = unsafe { ALLOCATOR.alloc(layout) };
+    /// let ptr = unsafe { ALLOCATOR.realloc_aligned(ptr, layout, 64, 32) };
+    /// assert_eq!(ptr as usize % 32, 0);
+    /// ```
+    pub unsafe fn realloc_aligned(
+        &self,
+        ptr: *mut u8,
+        old_layout: Layout,
+        new_size: usize,
+        new_align: usize,
+    ) -> *mut u8 {
+        let new_layout = match Layout::from_size_align(new_size, new_align) {
+            Ok(layout) => layout,
+            Err(_) => return ptr::null_mut(),
+        };
+
+        let mut raw = match self.lock_for_alloc() {
+            Some(raw) => raw,
+            None => return ptr::null_mut(),
+        };
+        let new_ptr = Self::alloc_in(&mut raw, new_layout);
+        if !new_ptr.is_null() {
+            // SAFETY: both pointers are valid for the copied size (the caller
+            // guarantees, that `ptr` is allocated with `old_layout`) and the new
+            // block is a fresh allocation, so they cannot overlap.
+            unsafe { ptr::copy_nonoverlapping(ptr, new_ptr, old_layout.size().min(new_size)) };
+            let result = Self::dealloc_in(&mut raw, ptr, Some(old_layout));
+            drop(raw);
+            self.live.remove(ptr);
+            self.live.insert(new_ptr);
+            Self::finish_dealloc(result);
+        }
+        new_ptr
+    }
+
+    /// Carve a [`SubAllocator`] with `M` bytes of heap space out of this heap.
+    ///
+    /// The sub-allocator manages its part of the heap independently, so that
+    /// a subsystem using it can neither use more than `M` bytes nor starve the
+    /// other subsystems. See [`SubAllocator`] for details. The `name` is only
+    /// used for diagnostic purposes. The sub-allocator uses a default-created
+    /// lock of the same type as this allocator.
+    ///
+    /// The sub-allocator occupies a block of this heap containing its heap of
+    /// `M` bytes and a bit of bookkeeping. If there is not enough memory
+    /// available for it, `None` is returned.
+    ///
+    /// # Panics
+    /// This function panics, if `M` is less than `8` or not divisible by `4`
+    /// (`16` and `8` with the `granularity-8` feature).
+    pub fn sub_allocator<const M: usize>(
+        &self,
+        name: &'static str,
+    ) -> Option<SubAllocator<'_, L, N, M>>
+    where
+        L: Default,
+    {
+        SubAllocator::new(self, name, L::default())
+    }
+
+    /// Register a handler for errors detected during deallocation.
+    ///
+    /// Deallocating memory might fail, e.g. if a pointer is freed twice or if
+    /// the pointer was not allocated by this allocator. Those errors indicate a
+    /// bug in the program, but [`GlobalAlloc::dealloc()`] cannot report them.
+    /// Therefore they are silently ignored by default. The registered `handler`
+    /// is called with the detected error instead, so that the error can be
+    /// logged or counted, while `dealloc()` still returns normally afterwards.
+    /// Passing `None` restores the default behavior of ignoring the errors.
+    /// With the `panic-on-dealloc-error` feature, the handler is called before
+    /// aborting.
+    ///
+    /// The handler is called while the allocator is locked, so it must not
+    /// allocate or deallocate memory with this allocator (which would deadlock).
+    /// Furthermore it must not panic, as `dealloc()` is not allowed to unwind.
+    ///
+    /// # Example
+    /// ```
+    /// use core::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    /// static ERRORS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// ALLOCATOR.set_dealloc_error_handler(Some(|_error| {
+    ///     ERRORS.fetch_add(1, Ordering::Relaxed);
+    /// }));
+    /// ```
+    pub fn set_dealloc_error_handler(&self, handler: Option<fn(FreeError)>) {
+        self.raw.lock().set_dealloc_error_handler(handler);
+    }
+
+    /// Register a handler for allocations refused due to a corrupted heap.
+    ///
+    /// With the `paranoid` feature, every new allocation is checked against
+    /// the used blocks. If the free list is corrupted (e.g. by a write to freed
+    /// memory), the chosen block might overlap a used block. The allocation
+    /// fails in that case and the `handler` is called with the refused block.
+    /// That block is quarantined on purpose: it stays marked as used, as its
+    /// memory overlaps a live allocation, so it can neither be handed out nor
+    /// be returned to the free list. Its memory is leaked therefore. Passing
+    /// `None` removes the handler.
+    ///
+    /// The handler is called while the allocator is locked, so it must not
+    /// allocate or deallocate memory with this allocator (which would deadlock).
+    /// Furthermore it must not panic, as `alloc()` is not allowed to unwind.
+    /// This method is only available with the `paranoid` feature.
+    #[cfg(feature = "paranoid")]
+    pub fn set_overlap_handler(&self, handler: Option<fn(BlockInfo)>) {
+        self.raw.lock().set_overlap_handler(handler);
+    }
+
+    /// Set the strategy for choosing a free block for an allocation.
+    ///
+    /// The strategy can be changed at any time, e.g. depending on the current
+    /// phase of the program. It only affects subsequent allocations. The
+    /// strategy is stored along with the heap and protected by the same lock,
+    /// so changing it is thread-safe. The default is [`Strategy::BestFit`].
+    ///
+    /// # Example
+    /// ```
+    /// use emballoc::Strategy;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    /// ALLOCATOR.set_strategy(Strategy::FirstFit);
+    /// assert_eq!(ALLOCATOR.strategy(), Strategy::FirstFit);
+    /// ```
+    pub fn set_strategy(&self, strategy: Strategy) {
+        self.raw.lock().set_strategy(strategy);
+    }
+
+    /// Query the current strategy for choosing a free block.
+    ///
+    /// See [`set_strategy()`](Self::set_strategy) for details.
+    pub fn strategy(&self) -> Strategy {
+        self.raw.lock().strategy()
+    }
+
+    /// Set the minimum size of free blocks created by splitting larger blocks.
+    ///
+    /// An allocation is usually served from a larger free block, which is then
+    /// split into the allocated block and a new free block holding the rest of
+    /// the memory. If that rest is tiny, the new free block is unlikely to be
+    /// usable for any allocation, but it slows down the search for free blocks
+    /// and fragments the heap. Therefore the whole free block is allocated, if
+    /// the new free block would have less than `threshold` bytes of memory.
+    /// This slightly over-allocates in favor of less fragmentation.
+    ///
+    /// The default threshold is `4`, i.e. only free blocks without any memory
+    /// are avoided. A threshold of `0` always splits the blocks.
+    ///
+    /// # Example
+    /// ```
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    /// ALLOCATOR.set_split_threshold(16);
+    /// ```
+    pub fn set_split_threshold(&self, threshold: usize) {
+        self.raw.lock().set_split_threshold(threshold);
+    }
+
+    /// Set the maximum size of a single allocation in bytes.
+    ///
+    /// Every allocation requesting more than `cap` bytes from the heap fails
+    /// immediately (e.g. a null pointer is returned) without scanning the heap.
+    /// This is a cheap safety valve against absurd requests, e.g. caused by a
+    /// corrupted length field, which would otherwise only fail after scanning
+    /// the heap or even succeed and exhaust it. Only the requested size counts
+    /// towards the cap: over-aligned allocations are placed at an aligned
+    /// address inside of a free block and the padding in front of that address
+    /// is not part of the request.
+    ///
+    /// By default, there is no cap (i.e. it is `usize::MAX`). The cap applies
+    /// to future allocations only, existing allocations are not affected.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// allocator.set_max_alloc(256);
+    /// # #[cfg(not(feature = "abort-on-oom"))]
+    /// assert!(unsafe { allocator.alloc(Layout::new::<[u8; 257]>()) }.is_null());
+    /// assert!(!unsafe { allocator.alloc(Layout::new::<[u8; 256]>()) }.is_null());
+    /// ```
+    pub fn set_max_alloc(&self, cap: usize) {
+        self.raw.lock().set_max_alloc(cap);
+    }
+
+    /// Set the maximum number of simultaneously live allocations.
+    ///
+    /// While there are `cap` live allocations, every further allocation fails
+    /// (e.g. a null pointer is returned) even if there is enough memory
+    /// available. Freeing an allocation allows a new one again. This turns a
+    /// leak of many small allocations (e.g. leaked handles) into allocation
+    /// failures during testing instead of a slow growth of the memory usage.
+    /// With the `abort-on-oom` feature, such a failure aborts like any other
+    /// failed allocation. Note, that [`GlobalAlloc::realloc()`] allocates the
+    /// new block before freeing the old one, so it fails at the cap as well.
+    ///
+    /// By default, there is no cap (i.e. it is `usize::MAX`). Lowering the cap
+    /// below the current number of live allocations does not affect them, but
+    /// new allocations fail until enough of them are freed. This requires the
+    /// `metrics` feature, which counts the live allocations.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    /// allocator.set_max_live_allocations(1);
+    /// let layout = Layout::new::<u32>();
+    /// let ptr = unsafe { allocator.alloc(layout) };
+    /// # #[cfg(not(feature = "abort-on-oom"))]
+    /// assert!(unsafe { allocator.alloc(layout) }.is_null());
+    /// unsafe { allocator.dealloc(ptr, layout) };
+    /// assert!(!unsafe { allocator.alloc(layout) }.is_null());
+    /// ```
+    #[cfg(feature = "metrics")]
+    pub fn set_max_live_allocations(&self, cap: usize) {
+        self.raw.lock().set_max_live_allocations(cap);
+    }
+
+    /// Acquire the lock of the raw allocator for an allocation.
+    ///
+    /// With the `bounded-spin` feature, this gives up after a limited number
+    /// of attempts (see `SPIN_LIMIT`) and returns `None`, so that the
+    /// allocation fails. Otherwise this waits until the lock is available.
+    fn lock_for_alloc(&self) -> Option<MutexGuard<'_, L, RawAllocator<N>>> {
+        if cfg!(feature = "bounded-spin") {
+            self.raw.lock_bounded(lock::SPIN_LIMIT)
+        } else {
+            Some(self.raw.lock())
+        }
+    }
+
+    /// Allocate memory for the `layout` using the (locked) raw allocator.
+    ///
+    /// The raw allocator places the allocation at a suitably aligned address
+    /// inside of a free block (see `RawAllocator::alloc_aligned()`), so that a
+    /// large alignment does not require over-allocating. A null pointer is
+    /// returned, if the allocation failed.
+    fn alloc_in(raw: &mut RawAllocator<N>, layout: Layout) -> *mut u8 {
+        Self::alloc_in_with_size(raw, layout).map_or(ptr::null_mut(), |(ptr, _size)| ptr.as_ptr())
+    }
+
+    /// Allocate memory for the `layout` and report the usable size.
+    ///
+    /// This is the same as [`alloc_in()`](Self::alloc_in), but additionally
+    /// returns the number of bytes usable from the returned pointer up to the
+    /// end of the allocated block. `None` is returned, if the allocation
+    /// failed.
+    fn alloc_in_with_size(
+        raw: &mut RawAllocator<N>,
+        layout: Layout,
+    ) -> Option<(NonNull<u8>, usize)> {
+        Self::alloc_aligned_with_size(layout, |size, align| raw.alloc_aligned(size, align))
+    }
+
+    /// Resize the allocation at `ptr` without touching its block, if possible.
+    ///
+    /// The block often has room for the new size already, e.g. when growing
+    /// from 13 to 15 bytes, as both sizes are rounded up to 16 bytes. Then the
+    /// allocation is kept (only the counted rounding is updated) and `true` is
+    /// returned. This applies to any growth, which fits into the block, and to
+    /// shrinking, which does not reduce the rounded size. Otherwise (i.e. if
+    /// the allocation has to move or if shrinking would free memory) nothing is
+    /// changed and `false` is returned.
+    fn resize_in_block(
+        raw: &mut RawAllocator<N>,
+        ptr: *mut u8,
+        old_size: usize,
+        new_size: usize,
+    ) -> bool {
+        let rounded = |size: usize| (size + HEADER_SIZE - 1) / HEADER_SIZE;
+        let fits = raw
+            .usable_size(ptr)
+            .map_or(false, |usable| new_size <= usable);
+        // the old size fits into the block as well, so it does not overflow
+        if fits && (new_size >= old_size || rounded(new_size) == rounded(old_size)) {
+            raw.forget_rounding(old_size);
+            raw.count_rounding(new_size);
+            return true;
+        }
+        false
+    }
+
+    /// Obtain the dangling pointer returned when reallocating to zero bytes.
+    ///
+    /// The pointer is non-null and aligned to the alignment of the `layout`,
+    /// but it does not point into the heap.
+    fn dangling(layout: Layout) -> *mut u8 {
+        layout.align() as *mut u8
+    }
+
+    /// Check, whether `ptr` is the dangling pointer of an empty `layout`.
+    ///
+    /// See [`dangling()`](Self::dangling) for details.
+    fn is_dangling(ptr: *mut u8, layout: Layout) -> bool {
+        layout.size() == 0 && ptr == Self::dangling(layout)
+    }
+
+    /// Deallocate the memory at `ptr` using the (locked) raw allocator.
+    ///
+    /// If the `layout` of the allocation is known, the rounding of its size is
+    /// not counted anymore (see
+    /// [`internal_fragmentation()`](Self::internal_fragmentation)).
+    ///
+    /// The error is returned, so that it can be passed to
+    /// [`finish_dealloc()`](Self::finish_dealloc) once the lock is released.
+    fn dealloc_in(
+        raw: &mut RawAllocator<N>,
+        ptr: *mut u8,
+        layout: Option<Layout>,
+    ) -> Result<(), FreeError> {
+        // alignment is irrelevant here, as `RawAllocator::free` can handle any
+        // pointer in an entry's memory, so simply forward the pointer. The
+        // `free()`-method might detect errors, but those cannot lead to panics
+        // (by contract of `GlobalAlloc`). Therefore there are two choices:
+        // 1. abort the process
+        // 2. ignore the error
+        // Since there is no process and there is no stable way to abort the
+        // program on `core` the default is option #2: do nothing. The user may
+        // register a handler to get notified about the error though, and the
+        // `defmt` feature logs it. Test builds may opt into aborting instead
+        // (see `finish_dealloc()`), so that the bug is not missed.
+        let result = raw.free(ptr.cast());
+        match result {
+            Ok(()) => {
+                if let Some(layout) = layout {
+                    raw.forget_rounding(layout.size());
+                }
+            }
+            Err(error) => {
+                #[cfg(feature = "defmt")]
+                defmt::error!(
+                    "invalid deallocation of {=usize:#x}: {}",
+                    ptr as usize,
+                    error
+                );
+                if let Some(handler) = raw.dealloc_error_handler() {
+                    handler(error);
+                }
+            }
+        }
+        result
+    }
+
+    /// Finish a deallocation with the `result` of [`dealloc_in()`](Self::dealloc_in).
+    ///
+    /// Errors were reported already, so they are ignored, unless the
+    /// `panic-on-dealloc-error` feature is enabled: then the program is aborted
+    /// with a panic message describing the error. The lock of the allocator has
+    /// to be released beforehand, as the panic handler might allocate memory.
+    #[cfg_attr(not(feature = "panic-on-dealloc-error"), allow(unused_variables))]
+    fn finish_dealloc(result: Result<(), FreeError>) {
+        #[cfg(feature = "panic-on-dealloc-error")]
+        if let Err(error) = result {
+            abort(format_args!("invalid deallocation: {}", error));
+        }
+    }
+
+    /// Allocate memory for the `layout` using the given raw allocation.
+    ///
+    /// The `raw_alloc` closure is called with the size and the alignment of the
+    /// `layout`. It has to return memory containing an address aligned to the
+    /// alignment, which is followed by at least the size in bytes, like
+    /// [`RawAllocator::alloc_aligned()`]. That address is returned. A null
+    /// pointer is returned, if the allocation failed.
+    fn alloc_aligned<'memory>(
+        layout: Layout,
+        raw_alloc: impl FnOnce(usize, usize) -> Option<&'memory mut [MaybeUninit<u8>]>,
+    ) -> *mut u8 {
+        Self::alloc_aligned_with_size(layout, raw_alloc)
+            .map_or(ptr::null_mut(), |(ptr, _size)| ptr.as_ptr())
+    }
+
+    /// Allocate memory for the `layout` and report the usable size.
+    ///
+    /// This is the same as [`alloc_aligned()`](Self::alloc_aligned), but
+    /// additionally returns the number of bytes usable from the returned
+    /// pointer up to the end of the allocated block. `None` is returned, if the
+    /// allocation failed.
+    fn alloc_aligned_with_size<'memory>(
+        layout: Layout,
+        raw_alloc: impl FnOnce(usize, usize) -> Option<&'memory mut [MaybeUninit<u8>]>,
+    ) -> Option<(NonNull<u8>, usize)> {
+        let align = layout.align();
+        // the raw allocator only uses the memory actually needed for the
+        // alignment, depending on the address of the chosen free block, so
+        // there is no need to over-allocate `align` bytes up front
+        let memory = raw_alloc(layout.size(), align)?;
+        let block_size = memory.len();
+        let start = ptr::addr_of_mut!(*memory).cast::<u8>();
+        // the raw allocator always returns memory aligned to the granularity,
+        // so there is nothing to adjust for small alignments
+        let ptr = if align <= Self::GRANULARITY {
+            start
+        } else {
+            // SAFETY: `align` is a power of two as by the contract of `Layout`.
+            // The memory contains an aligned address followed by the requested
+            // size (guarantee of the raw allocation).
+            unsafe { Self::align_to(start, align) }
+        };
+        let padding = ptr as usize - start as usize;
+        NonNull::new(ptr).map(|ptr| (ptr, block_size - padding))
+    }
+
+    /// Align a given pointer to the specified alignment.
+    ///
+    /// The callers skip this for alignments of at most 4, which the memory of
+    /// the raw allocator already fulfills.
+    ///
+    /// # Safety
+    /// This function requires `align` to be a power of two and requires the
+    /// `ptr` to point to a memory region, that is large enough, so that the
+    /// aligned pointer is still in that memory region.
+    unsafe fn align_to(ptr: *mut u8, align: usize) -> *mut u8 {
+        let addr = ptr as usize;
+        let mismatch = addr & (align - 1);
+        let offset = if mismatch == 0 { 0 } else { align - mismatch };
+        // SAFETY: "in-bound"-requirement is part of the safety-contract of this
+        // function, therefore the caller is responsible for it
+        unsafe { ptr.add(offset) }
+    }
+}
+#[allow(clippy::missing_fields_in_debug)] // the tracked allocations are no summary
+impl<const N: usize> GenericAllocator<PhasedLock, N> {
+    /// Enable the locking of this allocator.
+    ///
+    /// The allocator keeps its heap, so all the live allocations stay valid.
+    /// Afterwards the allocator may be used from multiple execution contexts.
+    /// This has to be called while no iterator over the blocks of the heap
+    /// (e.g. of [`free_blocks()`](Self::free_blocks)) is alive. See
+    /// [`PhasedLock`] for details.
+    pub fn enable_locking(&self) {
+        self.raw.raw_lock().enable();
+    }
+
+    /// Disable the locking of this allocator.
+    ///
+    /// This is the reverse of [`enable_locking()`](Self::enable_locking): the
+    /// allocator keeps its heap, but does not use any atomic read-modify-write
+    /// operation anymore.
+    ///
+    /// # Safety
+    /// The caller has to guarantee, that the allocator is never used from
+    /// multiple execution contexts at the same time afterwards, until the
+    /// locking is enabled again. No iterator over the blocks of the heap (e.g.
+    /// of [`free_blocks()`](Self::free_blocks)) may be alive during this call.
+    pub unsafe fn disable_locking(&self) {
+        // SAFETY: the lock is not held and there are no concurrent accesses
+        // (safety contract of this function).
+        unsafe { self.raw.raw_lock().disable() };
+    }
+}
+#[allow(clippy::missing_fields_in_debug)] // the live allocations are omitted
+impl<L: RawLock, const N: usize> fmt::Debug for GenericAllocator<L, N> {
+    /// Show a brief summary of the heap usage.
+    ///
+    /// This never blocks: if the internal lock is currently held, a
+    /// placeholder is shown instead of the usage statistics.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = f.debug_struct("Allocator");
+        debug.field("size", &N);
+        let stats = self.raw.try_lock().map(|raw| raw.stats());
+        if let Some(stats) = stats {
+            debug
+                .field("used", &stats.used)
+                .field("free", &stats.free)
+                .field("used_blocks", &stats.used_blocks)
+                .field("free_blocks", &stats.free_blocks);
+        } else {
+            debug.field("state", &format_args!("<locked>"));
+        }
+        debug.finish()
+    }
+}
+impl<L: Default, const N: usize> Default for GenericAllocator<L, N> {
+    fn default() -> Self {
+        Self::with_lock(L::default())
+    }
+}
+impl<'memory, L: Default, const N: usize> From<&'memory mut MaybeUninit<GenericAllocator<L, N>>>
+    for &'memory GenericAllocator<L, N>
+{
+    /// Create a new allocator in the given uninitialized memory.
+    ///
+    /// See [`GenericAllocator::init_in()`] for details.
+    fn from(memory: &'memory mut MaybeUninit<GenericAllocator<L, N>>) -> Self {
+        GenericAllocator::init_in(memory, L::default())
+    }
+}
+// SAFETY: the safety contracts of global allocator is a bit lengthy, but in
+// short: the implementation does not unwind (the features reporting errors via
+// a panic abort the program instead, see `abort()`) and it actually adheres to
+// the layout requirements (ensured by tests).
+unsafe impl<L: RawLock, const N: usize> GlobalAlloc for GenericAllocator<L, N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.lock_for_alloc().map_or(ptr::null_mut(), |mut raw| {
+            let ptr = Self::alloc_in(&mut raw, layout);
+            #[cfg(any(feature = "defmt", feature = "abort-on-oom"))]
+            if ptr.is_null() {
+                let stats = raw.stats();
+                drop(raw);
+                #[cfg(feature = "defmt")]
+                defmt::error!(
+                    "out of memory: failed to allocate {=usize} bytes aligned to {=usize} ({})",
+                    layout.size(),
+                    layout.align(),
+                    stats
+                );
+                #[cfg(feature = "abort-on-oom")]
+                out_of_memory(layout, stats);
+            }
+            ptr
+        });
+        self.live.insert(ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if Self::is_dangling(ptr, layout) {
+            return;
+        }
+        let result = Self::dealloc_in(&mut self.raw.lock(), ptr, Some(layout));
+        self.live.remove(ptr);
+        Self::finish_dealloc(result);
+    }
+
+    /// Shrink or grow the memory at `ptr` to `new_size` bytes.
+    ///
+    /// The memory is moved to a new block with the alignment of the `layout`,
+    /// just like the default implementation does. Shrinking to zero bytes is
+    /// handled explicitly: the block is freed and a dangling, but non-null and
+    /// suitably aligned pointer is returned instead of allocating an empty
+    /// block. Such a pointer can be passed to this function again (together
+    /// with the empty layout) in order to grow it, while deallocating it does
+    /// nothing. Therefore containers shrinking their memory to zero bytes do
+    /// not leak a block.
+    ///
+    /// If the block of the allocation is large enough for the new size already
+    /// (e.g. as both sizes are rounded up to the same multiple of the
+    /// [`GRANULARITY`](Self::GRANULARITY)), the same
+    /// pointer is returned without copying any memory.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if new_size == 0 {
+            // SAFETY: the safety contract is the same as for this function.
+            unsafe { self.dealloc(ptr, layout) };
+            return Self::dangling(layout);
+        }
+        if Self::is_dangling(ptr, layout) {
+            // there is neither memory to copy nor a block to free
+            // SAFETY: the size is non-zero and the alignment is the one of a
+            // valid layout. The caller guarantees, that the size does not
+            // overflow, when rounded up to the alignment.
+            return unsafe {
+                self.alloc(Layout::from_size_align_unchecked(new_size, layout.align()))
+            };
+        }
+        if Self::resize_in_block(&mut self.raw.lock(), ptr, layout.size(), new_size) {
+            return ptr;
+        }
+        // SAFETY: the caller guarantees, that `ptr` is allocated with `layout`
+        // and `new_size` is non-zero as checked above.
+        unsafe { self.realloc_aligned(ptr, layout, new_size, layout.align()) }
+    }
+}
+
+// include the readme in doc-tests. Credits to https://blog.guillaume-gomez.fr/articles/2020-03-07+cfg%28doctest%29+is+stable+and+you+should+use+it
+#[cfg(doctest)]
+mod extra_doctests {
+    /// Helper macro to pass a "dynamic"/included string to the `extern`-block
+    macro_rules! doc_check {
+        ($x:expr) => {
+            #[doc = $x]
+            extern "C" {}
+        };
+    }
+    // Check the code snippets in the Readme.
+    doc_check!(include_str!("../README.md"));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raw_allocator::HEADER_SIZE;
+    use crate::{Allocator, FreeError, State};
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::mem::MaybeUninit;
+    use core::ptr;
+    #[cfg(feature = "abort-on-oom")]
+    use std::cell::RefCell;
+
+    #[cfg(feature = "abort-on-oom")]
+    std::thread_local! {
+        static OUT_OF_MEMORY: RefCell<Vec<(Layout, crate::HeapStats)>> = RefCell::new(Vec::new());
+    }
+
+    /// Let failed allocations via `GlobalAlloc::alloc()` return a null pointer.
+    ///
+    /// With the `abort-on-oom` feature, failed allocations abort the program
+    /// by default. Tests provoking them on purpose call this first in order to
+    /// register a handler, which records the failures of the current thread.
+    /// All tests register the same handler, so they do not interfere.
+    pub(crate) fn return_null_on_oom() {
+        #[cfg(feature = "abort-on-oom")]
+        crate::set_oom_handler(Some(|layout, stats| {
+            OUT_OF_MEMORY.with(|failures| failures.borrow_mut().push((layout, stats)));
+        }));
+    }
+
+    /// Query the failed allocations of the current thread reported so far.
+    #[cfg(feature = "abort-on-oom")]
+    fn out_of_memory() -> Vec<(Layout, crate::HeapStats)> {
+        OUT_OF_MEMORY.with(|failures| failures.borrow().clone())
+    }
+
+    #[test]
+    fn alignment_of_align_to() {
+        // create buffer memory for proper indexing. One could use random
+        // integers and cast them to pointers, but this would violate the strict
+        // provenance rules and `miri` would detect that. Therefore this uses a
+        // valid and suitable aligned buffer and uses pointers into that buffer.
+        #[repr(align(16))]
+        struct Align([u8; 16]);
+        let mut just_a_buffer_to_get_a_valid_address = Align([0_u8; 16]);
+        let base: *mut u8 = ptr::addr_of_mut!(just_a_buffer_to_get_a_valid_address.0).cast();
+
+        // create some pointers to the buffer with some offsets
+        let ptr_0x10 = base;
+        let ptr_0x11 = base.wrapping_add(1);
+        let ptr_0x14 = base.wrapping_add(4);
+        let ptr_0x1c = base.wrapping_add(0xc);
+        let ptr_0x20 = base.wrapping_add(0x10);
+
+        // the actual test for the alignment of `align_to()`
+        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 4) }, ptr_0x14);
+        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x10, 4) }, ptr_0x10);
+
+        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 1) }, ptr_0x11);
+
+        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x1c, 16) }, ptr_0x20);
+    }
+
+    // the following tests ensure, that a pointer with the requested alignment
+    // is returned
+
+    /// Assert the given alignment of pointers.
+    macro_rules! assert_alignment {
+        ($ptr:expr, $align:expr) => {{
+            assert_eq!(($ptr as usize) % $align, 0, "Alignment not fulfilled");
+        }};
+    }
+
+    #[test]
+    #[allow(clippy::modulo_one)] // alignment of 1 is tested on purpose
+    fn small_alignments() {
+        let allocator = Allocator::<128>::new();
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 2).unwrap()) };
+        assert_alignment!(ptr, 1);
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 4).unwrap()) };
+        assert_alignment!(ptr, 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn small_alignments_return_the_block_memory() {
+        // the memory of the blocks is returned unchanged for alignments of 1
+        // and 2, both from the bottom and from the top of the heap
+        let allocator = Allocator::<128>::new();
+        let (start, _) = allocator.memory_region();
+        for align in [1, 2] {
+            let layout = Layout::from_size_align(5, align).unwrap();
+            let bottom = unsafe { allocator.alloc(layout) };
+            let top = unsafe { allocator.alloc_from_top(layout) };
+            for ptr in [bottom, top] {
+                let block = allocator.block_of(ptr).unwrap();
+                assert_eq!(ptr as usize - start as usize, block.offset + 4);
+                assert_eq!(block.size, 8);
+            }
+        }
+    }
+
+    #[test]
+    fn medium_alignments() {
+        let allocator = Allocator::<128>::new();
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 8).unwrap()) };
+        assert_alignment!(ptr, 8);
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 32).unwrap()) };
+        assert_alignment!(ptr, 32);
+    }
+
+    #[cfg(not(miri))] // too slow
+    #[test]
+    fn huge_alignment() {
+        // in static memory to prevent stack overflow
+        const FOUR_MEG: usize = 4 * 1024 * 1024;
+
+        static ALLOCATOR: Allocator<{ 10 * 1024 * 1024 }> = Allocator::new();
+        let ptr = unsafe { ALLOCATOR.alloc(Layout::from_size_align(4, FOUR_MEG).unwrap()) };
+
+        assert_alignment!(ptr, FOUR_MEG);
+    }
+
+    #[test]
+    fn reuse_of_recently_freed_over_aligned_blocks() {
+        let allocator = Allocator::<2048>::new();
+        let dma = Layout::from_size_align(64, 512).unwrap();
+        let separator = Layout::new::<u32>();
+
+        let first = unsafe { allocator.alloc(dma) };
+        unsafe { allocator.alloc(separator) };
+        let second = unsafe { allocator.alloc(dma) };
+        unsafe { allocator.alloc(separator) };
+        unsafe { allocator.dealloc(first, dma) };
+        unsafe { allocator.dealloc(second, dma) };
+
+        // both blocks fit, but the most recently freed one is reused, which
+        // is not the first one (as chosen by a scan).
+        let ptr = unsafe { allocator.alloc(dma) };
+        assert_eq!(ptr, second);
+        assert_alignment!(ptr, 512);
+        let ptr = unsafe { allocator.alloc(dma) };
+        assert_eq!(ptr, first);
+        assert_alignment!(ptr, 512);
+
+        // a different layout, which fits into the block, reuses the block too
+        unsafe { allocator.dealloc(ptr, dma) };
+        let layout = Layout::from_size_align(32, 64).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_alignment!(ptr, 64);
+        assert_eq!(allocator.block_of(ptr), allocator.block_of(first));
+    }
+
+    #[test]
+    fn allocation_failure() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+
+        // try an allocation, that exceeds the total memory size
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(129, 1).unwrap()) };
+        assert_eq!(ptr, ptr::null_mut());
+    }
+
+    #[test]
+    fn allocation_failure_near_overflow() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+
+        // the largest possible layouts, where the over-allocation for the
+        // alignment results in sizes near the end of the address space
+        let max = isize::MAX as usize;
+        for align in [1, 4, 8, 128, 1 << 20] {
+            let layout = Layout::from_size_align(max - (align - 1), align).unwrap();
+            let ptr = unsafe { allocator.alloc(layout) };
+            assert_eq!(ptr, ptr::null_mut());
+        }
+        assert_eq!(allocator.stats().free_blocks, 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn allocation_of_the_whole_heap() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        for size in [128 - 3, 128] {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            assert_eq!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+        }
+
+        let layout = Layout::from_size_align(128 - 4, 1).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_ne!(ptr, ptr::null_mut());
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (1, 0));
+        assert_eq!(stats.used, 128 - 4);
+
+        unsafe { allocator.dealloc(ptr, layout) };
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (0, 1));
+        assert_eq!(stats.free, 128 - 4);
+    }
+
+    #[test]
+    fn initialization_in_uninitialized_memory() {
+        // the memory is filled with garbage to check, that it is initialized
+        let mut memory = MaybeUninit::<Allocator<128>>::uninit();
+        unsafe { memory.as_mut_ptr().write_bytes(0xAB, 1) };
+        let allocator: &Allocator<128> = (&mut memory).into();
+        assert_eq!(allocator.stats(), Allocator::<128>::new().stats());
+
+        let layout = Layout::new::<[u32; 4]>();
+        let first = unsafe { allocator.alloc(layout) };
+        let second = unsafe { allocator.alloc(layout) };
+        assert!(!first.is_null() && !second.is_null());
+        assert_eq!(allocator.stats().used_blocks, 2);
+        unsafe { allocator.dealloc(second, layout) };
+        unsafe { allocator.dealloc(first, layout) };
+        assert_eq!(allocator.available_bytes(), 128 - HEADER_SIZE);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn largest_allocation_for_an_alignment() {
+        return_null_on_oom();
+        let allocator = Allocator::<512>::new();
+        let layout = Layout::new::<[u8; 100]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.alloc(Layout::new::<u32>()) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.largest_free_block(), 512 - 100 - 4 - 4 - 4 - 4);
+
+        let largest = allocator.largest_free_block();
+        assert_eq!(allocator.largest_alloc_for_align(1), largest);
+        assert_eq!(allocator.largest_alloc_for_align(4), largest);
+        let aligned = allocator.largest_alloc_for_align(64);
+        assert!(largest - 60 <= aligned && aligned <= largest);
+        assert_eq!(allocator.largest_alloc_for_align(3), 0);
+        assert!(allocator.largest_alloc_for_align(1024) <= largest);
+
+        for align in [4, 64] {
+            let size = allocator.largest_alloc_for_align(align);
+            let too_large = Layout::from_size_align(size + 1, align).unwrap();
+            assert!(unsafe { allocator.alloc(too_large) }.is_null());
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = unsafe { allocator.alloc(layout) };
+            assert!(!ptr.is_null());
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+    }
+
+    #[test]
+    fn allocation_at_an_offset() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u32; 4]>();
+        let ptr = unsafe { allocator.alloc_at(64, layout) };
+        assert_eq!(ptr, allocator.offset_to_ptr(64));
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (1, 2));
+        assert!(unsafe { allocator.alloc_at(72, layout) }.is_null());
+
+        // the address has to fulfill the alignment
+        let aligned = Layout::from_size_align(16, 32).unwrap();
+        let offset = (0..64)
+            .step_by(4)
+            .find(|offset| allocator.offset_to_ptr(128 + offset) as usize % 32 != 0)
+            .unwrap();
+        assert!(unsafe { allocator.alloc_at(128 + offset, aligned) }.is_null());
+
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn low_alignment_does_not_over_allocate() {
+        // alignments up to 4 are fulfilled by every block, so the memory used
+        // is exactly the rounded size plus the header
+        for align in [1, 2, 4] {
+            for size in [1000, 1001, 1003] {
+                let allocator = Allocator::<2048>::new();
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { allocator.alloc(layout) };
+                assert!(!ptr.is_null());
+
+                let rounded = (size + 3) / 4 * 4;
+                let stats = allocator.stats();
+                assert_eq!(stats.used, rounded);
+                assert_eq!(stats.free, 2048 - 4 - (rounded + 4));
+                assert_eq!(allocator.block_of(ptr).unwrap().offset, 0);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn aligned_allocation_does_not_over_allocate() {
+        let allocator = Allocator::<2048>::new();
+        let layout = Layout::from_size_align(64, 128).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_alignment!(ptr, 128);
+
+        // the former strategy requested `64 + 128` bytes from the heap, while
+        // now only the memory in front of the aligned address (if any) is
+        // split off into a free block. Only a gap smaller than a header plus
+        // the split threshold is kept as padding.
+        let over_allocated = 64 + 128;
+        let used = allocator.stats().used;
+        assert!(used < 64 + 8);
+        assert!(over_allocated - used >= 120);
+        let info = allocator.block_of(ptr).unwrap();
+        let (start, _) = allocator.memory_region();
+        assert!(ptr as usize - (start as usize + info.offset + 4) < 8);
+        assert_eq!(
+            allocator.available_bytes() + used,
+            2048 - 4 * (1 + allocator.free_block_count())
+        );
+
+        // allocations of the same layout are packed without gaps in between
+        let second = unsafe { allocator.alloc(layout) };
+        assert_eq!(second as usize - ptr as usize, 128);
+        let third = unsafe { allocator.alloc(layout) };
+        assert_eq!(third as usize - second as usize, 128);
+    }
+
+    #[test]
+    fn alignments_as_large_as_the_heap() {
+        return_null_on_oom();
+        const N: usize = 256;
+        for align in [N / 2, N, 2 * N] {
+            for size in [4, N / 2, N - 4] {
+                let allocator = Allocator::<N>::new();
+                let (start, _) = allocator.memory_region();
+                // the first aligned address after the first header
+                let memory = start as usize + 4;
+                let aligned = (memory + align - 1) / align * align;
+                let fits = aligned + size <= start as usize + N;
+
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { allocator.alloc(layout) };
+                assert_eq!(ptr.is_null(), !fits, "align {}, size {}", align, size);
+                if !ptr.is_null() {
+                    assert_eq!(ptr as usize, aligned);
+                    unsafe { allocator.dealloc(ptr, layout) };
+                }
+                assert_eq!(allocator.stats().used_blocks, 0);
+                assert_eq!(allocator.largest_alloc_for_align(align) >= size, fits);
+
+                // the last aligned address, behind which the allocation fits,
+                // is used at the end of the heap
+                let end = start as usize + N;
+                let top = (end - size) / align * align;
+                let ptr = unsafe { allocator.alloc_from_top(layout) };
+                assert_eq!(
+                    ptr.is_null(),
+                    top < memory,
+                    "align {}, size {}",
+                    align,
+                    size
+                );
+                if !ptr.is_null() {
+                    assert_eq!(ptr as usize, top);
+                }
+            }
+        }
+
+        // there is always an address aligned to half of the heap size
+        let allocator = Allocator::<N>::new();
+        let layout = Layout::from_size_align(4, N / 2).unwrap();
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn no_over_allocation_for_already_aligned_blocks() {
+        // a used block filling the given number of bytes needs at least 4 bytes
+        // of memory besides its header, so the gap is enlarged if necessary
+        let filler = |bytes: usize| {
+            let bytes = if bytes == 0 || bytes >= 8 {
+                bytes
+            } else {
+                bytes + 32
+            };
+            (bytes > 0).then(|| Layout::from_size_align(bytes - 4, 4).unwrap())
+        };
+        let layout = Layout::from_size_align(32, 32).unwrap();
+
+        // the free memory at the bottom of the heap starts aligned
+        let allocator = Allocator::<256>::new();
+        let (start, _) = allocator.memory_region();
+        let gap = filler((32 - (start as usize + 4) % 32) % 32);
+        if let Some(gap) = gap {
+            assert!(!unsafe { allocator.alloc(gap) }.is_null());
+        }
+        let aligned = start as usize + 4 + gap.map_or(0, |gap| gap.size() + 4);
+        let ptr = unsafe { allocator.alloc_reserved(layout) };
+        assert_eq!(ptr as usize, aligned);
+        assert_eq!(allocator.block_of(ptr).unwrap().size, 32);
+
+        // the free memory at the top of the heap ends aligned
+        let allocator = Allocator::<256>::new();
+        let (start, size) = allocator.memory_region();
+        let end = start as usize + size;
+        let gap = filler(end % 32);
+        if let Some(gap) = gap {
+            assert!(!unsafe { allocator.alloc_from_top(gap) }.is_null());
+        }
+        let aligned = end - gap.map_or(0, |gap| gap.size() + 4) - 32;
+        let ptr = unsafe { allocator.alloc_from_top(layout) };
+        assert_eq!(ptr as usize, aligned);
+        assert_eq!(allocator.block_of(ptr).unwrap().size, 32);
+    }
+
+    #[test]
+    fn allocation_failure_due_to_alignment() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        let (start, size) = allocator.memory_region();
+
+        // the allocation fits, if there is an aligned address in the heap
+        // memory (after the first header) with enough memory behind it
+        let memory = start as usize + 4;
+        let aligned = (memory + 127) / 128 * 128;
+        let fits = aligned + 8 <= start as usize + size;
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 128).unwrap()) };
+        assert_eq!(ptr.is_null(), !fits);
+
+        // there is never an address aligned to 256 with 128 bytes behind it
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(128, 256).unwrap()) };
+        assert_eq!(ptr, ptr::null_mut());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn stats() {
+        let allocator = Allocator::<128>::new();
+        assert_eq!(allocator.stats().used_blocks, 0);
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(9, 4).unwrap()) };
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.used_blocks), (12, 1));
+        assert_eq!((stats.free, stats.free_blocks), (128 - 12 - 8, 1));
+
+        unsafe { allocator.dealloc(ptr, Layout::from_size_align(9, 4).unwrap()) };
+        assert_eq!(allocator.stats().used, 0);
+    }
+
+    #[test]
+    fn snapshot_without_locking() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 12]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(unsafe { allocator.snapshot_unlocked() }, allocator.stats());
+
+        // the snapshot works even if the lock is held (by a "halted" thread)
+        let expected = allocator.stats();
+        let guard = allocator.raw.lock();
+        assert_eq!(unsafe { allocator.snapshot_unlocked() }, expected);
+        drop(guard);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn allocation_from_both_ends() {
+        let allocator = Allocator::<128>::new();
+        let (start, _) = allocator.memory_region();
+        let layout = Layout::new::<u64>();
+
+        let top = unsafe { allocator.alloc_from_top(layout) };
+        let bottom = unsafe { allocator.alloc(layout) };
+        assert_alignment!(top, 8);
+        assert_alignment!(bottom, 8);
+        assert!(top as usize + 8 <= start as usize + 128);
+        assert!(top as usize >= start as usize + 128 - 16 - 4);
+        assert!(bottom < top);
+        unsafe { top.cast::<u64>().write(1) };
+        unsafe { bottom.cast::<u64>().write(2) };
+
+        unsafe { allocator.dealloc(top, layout) };
+        unsafe { allocator.dealloc(bottom, layout) };
+        let stats = allocator.stats();
+        assert_eq!(stats.used_blocks, 0);
+        assert_eq!(stats.free + 4 * stats.free_blocks, 128);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn capacity() {
+        assert_eq!(Allocator::<8>::new().capacity(), 8 - 4);
+        assert_eq!(Allocator::<128>::new().capacity(), 128 - 4);
+        assert_eq!(Allocator::<4096>::new().capacity(), 4096 - 4);
+
+        // a single allocation of that size is possible
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(allocator.capacity(), 4).unwrap();
+        assert_ne!(unsafe { allocator.alloc(layout) }, ptr::null_mut());
+        assert_eq!(allocator.available_bytes(), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn required_size() {
+        /// Allocate all `sizes` in a heap of size `N`, which has to be full.
+        fn fill<const N: usize>(sizes: &[usize]) {
+            let allocator = Allocator::<N>::new();
+            for &size in sizes {
+                let layout = Layout::from_size_align(size, 1).unwrap();
+                assert!(!unsafe { allocator.alloc(layout) }.is_null());
+            }
+            assert_eq!(allocator.available_bytes(), 0);
+        }
+
+        const SMALL: [usize; 3] = [1, 2, 3];
+        const MIXED: [usize; 4] = [16, 5, 100, 7];
+        const EXACT: [usize; 2] = [8, 64];
+        assert_eq!(super::required_size(&SMALL), 3 * 8);
+        assert_eq!(super::required_size(&MIXED), 20 + 12 + 104 + 12);
+        assert_eq!(super::required_size(&EXACT), 12 + 68);
+        fill::<{ super::required_size(&SMALL) }>(&SMALL);
+        fill::<{ super::required_size(&MIXED) }>(&MIXED);
+        fill::<{ super::required_size(&EXACT) }>(&EXACT);
+
+        // the result is at least the minimal heap size and saturates
+        assert_eq!(super::required_size(&[]), 8);
+        assert_eq!(super::required_size(&[0]), 8);
+        assert_eq!(super::required_size(&[usize::MAX]), usize::MAX);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn recovery_after_buffer_overflow() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let first = unsafe { allocator.alloc(layout) };
+        let overflowing = unsafe { allocator.alloc(layout) };
+        let _overwritten = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(first, layout) };
+
+        // writing 12 bytes destroys the header of the following block
+        unsafe { overflowing.write_bytes(0xFF, 12) };
+        let report = allocator.try_recover();
+        assert_eq!(report.corrupted_at, Some(24));
+        assert_eq!(report.quarantined, 128 - 24);
+
+        // the heap is walkable again and the memory in front is still usable
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (2, 1));
+        assert_eq!(stats.used, 8 + 100);
+        assert_eq!(unsafe { allocator.alloc(layout) }, first);
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn max_alloc() {
+        return_null_on_oom();
+        let allocator = Allocator::<1024>::new();
+        allocator.set_max_alloc(100);
+        let layout = |size| Layout::from_size_align(size, 4).unwrap();
+
+        assert!(unsafe { allocator.alloc(layout(101)) }.is_null());
+        assert!(unsafe { allocator.alloc(layout(1 << 20)) }.is_null());
+        let ptr = unsafe { allocator.alloc(layout(100)) };
+        assert!(!ptr.is_null());
+
+        // growing beyond the cap fails as well, while shrinking is fine
+        assert!(unsafe { allocator.realloc(ptr, layout(100), 101) }.is_null());
+        let ptr = unsafe { allocator.realloc(ptr, layout(100), 50) };
+        assert!(!ptr.is_null());
+        assert_eq!(allocator.stats().used_blocks, 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn available_bytes() {
+        let allocator = Allocator::<128>::new();
+        assert_eq!(allocator.available_bytes(), 124);
+
+        let layout = Layout::new::<[u8; 12]>();
+        let ptrs = [(); 4].map(|_| unsafe { allocator.alloc(layout) });
+        assert_eq!(allocator.available_bytes(), 124 - 4 * 16);
+        unsafe { allocator.dealloc(ptrs[0], layout) };
+        unsafe { allocator.dealloc(ptrs[2], layout) };
+        // the memory is available, but fragmented (the headers of the freed
+        // blocks are still in use)
+        assert_eq!(allocator.available_bytes(), 124 - 2 * 16 - 2 * 4);
+        assert!(allocator.largest_free_block() < allocator.available_bytes());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn allocation_with_size() {
+        let allocator = Allocator::<512>::new();
+        for (size, align) in [(1, 1), (4, 4), (5, 2), (13, 8), (30, 64), (3, 128)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let (ptr, granted) = unsafe { allocator.alloc_with_size(layout) }.unwrap();
+            let ptr = ptr.as_ptr();
+            assert!(granted >= size);
+            assert_alignment!(ptr, align);
+
+            // the granted size extends exactly to the end of the block
+            let info = allocator.block_of(ptr).unwrap();
+            let block_end = allocator.offset_to_ptr(info.offset + 4 + info.size);
+            assert_eq!(ptr as usize + granted, block_end as usize);
+            assert_eq!(
+                allocator.block_of(ptr.wrapping_add(granted - 1)),
+                Some(info)
+            );
+            unsafe { ptr.write_bytes(0xAA, granted) };
+        }
+        let layout = Layout::new::<[u8; 512]>();
+        assert_eq!(unsafe { allocator.alloc_with_size(layout) }, None);
+    }
+
+    #[test]
+    fn deallocation_by_offset() {
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let (first, a) = unsafe { allocator.alloc_with_offset(layout) }.unwrap();
+        let (_, b) = unsafe { allocator.alloc_with_offset(layout) }.unwrap();
+        assert_eq!((a, b), (HEADER_SIZE, 2 * HEADER_SIZE + 8));
+        assert_eq!(allocator.ptr_to_offset(first.as_ptr()), Some(a));
+        let aligned = Layout::from_size_align(8, 2 * Allocator::<64>::GRANULARITY).unwrap();
+        assert_eq!(unsafe { allocator.alloc_with_offset(aligned) }, None);
+
+        // the freed blocks are merged with the following free block
+        unsafe { allocator.free_at(b) }.unwrap();
+        assert_eq!(allocator.free_block_count(), 1);
+        assert_eq!(allocator.largest_free_block(), 64 - 2 * HEADER_SIZE - 8);
+        unsafe { allocator.free_at(a) }.unwrap();
+        assert_eq!(allocator.largest_free_block(), 64 - HEADER_SIZE);
+
+        // offsets, which cannot be the memory of a block, are rejected
+        for offset in [0, 6, 68, usize::MAX] {
+            let result = unsafe { allocator.free_at(offset) };
+            assert_eq!(result, Err(FreeError::AllocationNotFound));
+        }
+        let result = unsafe { allocator.free_at(a) };
+        assert_eq!(result, Err(FreeError::DoubleFreeDetected));
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn reset_of_touched_memory() {
+        let allocator = Allocator::<4096>::new();
+        allocator.prefault(); // make the whole memory readable
+        let (start, _) = allocator.memory_region();
+
+        let layout = Layout::new::<[u8; 24]>();
+        let ptrs = [(); 3].map(|_| unsafe { allocator.alloc(layout) });
+        for ptr in ptrs {
+            unsafe { ptr.write_bytes(0xAA, layout.size()) };
+        }
+        unsafe { allocator.dealloc(ptrs[1], layout) };
+
+        unsafe { allocator.reset_touched() };
+        let used = unsafe { core::slice::from_raw_parts(start.add(4), 3 * (4 + 24)) };
+        assert!(used.iter().all(|&byte| byte == 0));
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (0, 1));
+        assert_eq!(stats.free, 4096 - 4);
+        assert_eq!(unsafe { allocator.alloc(layout) }, ptrs[0]);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn clear_to_pattern() {
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::from_size_align(8, 4).unwrap();
+        let old = unsafe { allocator.alloc(layout) };
+        unsafe { old.write_bytes(0xAA, layout.size()) };
+
+        unsafe { allocator.clear_to_pattern(0xFF) };
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(ptr, old);
+        let (start, size) = allocator.memory_region();
+        let memory = unsafe { core::slice::from_raw_parts(start, size) };
+        let written: Vec<_> = (0..size).filter(|&i| memory[i] != 0xFF).collect();
+        // only the headers and the free-list links were written: the links of
+        // the initial free block are still part of the allocated memory
+        assert_eq!(written, (0..24).collect::<Vec<_>>());
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free_blocks), (1, 1));
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn realloc_to_zero_frees_the_block() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(allocator.stats().used_blocks, 1);
+
+        let empty = unsafe { allocator.realloc(ptr, layout, 0) };
+        assert!(!empty.is_null());
+        assert_alignment!(empty, 8);
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.free), (0, 128 - 4));
+
+        // the dangling pointer can grow again or be deallocated
+        let empty_layout = Layout::from_size_align(0, 8).unwrap();
+        let ptr = unsafe { allocator.realloc(empty, empty_layout, 4) };
+        assert!(allocator.block_of(ptr).is_some());
+        assert_alignment!(ptr, 8);
+        unsafe { allocator.dealloc(empty, empty_layout) };
+        assert_eq!(allocator.stats().used_blocks, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "fill-on-alloc")]
+    fn fill_on_alloc() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 24]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        let memory = unsafe { core::slice::from_raw_parts_mut(ptr, layout.size()) };
+        assert!(memory.iter().all(|&byte| byte == 0xCD));
+
+        // reused memory is filled again, unless zeroed memory is requested
+        memory.fill(0xAA);
+        unsafe { allocator.dealloc(ptr, layout) };
+        let zeroed = unsafe { allocator.alloc_zeroed(layout) };
+        assert_eq!(zeroed, ptr);
+        let memory = unsafe { core::slice::from_raw_parts_mut(zeroed, layout.size()) };
+        assert!(memory.iter().all(|&byte| byte == 0));
+        memory.fill(0xAA);
+        unsafe { allocator.dealloc(zeroed, layout) };
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(ptr, zeroed);
+        let memory = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+        assert!(memory.iter().all(|&byte| byte == 0xCD));
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn merge_adjacent_allocations() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 7]>();
+        let ptrs = [(); 5].map(|_| unsafe { allocator.alloc(layout) });
+        let merge = |a, b| unsafe { allocator.try_merge(a, layout, b, layout) };
+        assert_eq!(allocator.internal_fragmentation(), 5);
+
+        // adjacent in order
+        assert_eq!(merge(ptrs[0], ptrs[1]), Some(ptrs[0]));
+        let block = allocator.block_of(ptrs[1]).unwrap();
+        assert_eq!(
+            (block.offset, block.size, block.state),
+            (0, 20, State::Used)
+        );
+        assert_eq!(allocator.internal_fragmentation(), 3);
+
+        // adjacent in reverse order
+        assert_eq!(merge(ptrs[3], ptrs[2]), Some(ptrs[2]));
+        assert!(allocator.same_block(ptrs[2], ptrs[3]));
+        assert_eq!(allocator.stats().used_blocks, 3);
+        assert_eq!(allocator.internal_fragmentation(), 1);
+
+        // neither blocks with another block in between nor the same block nor
+        // free blocks are merged
+        assert_eq!(merge(ptrs[0], ptrs[4]), None);
+        assert_eq!(merge(ptrs[0], ptrs[1]), None);
+        unsafe { allocator.dealloc(ptrs[4], layout) };
+        assert_eq!(merge(ptrs[2], ptrs[4]), None);
+        assert_eq!(allocator.stats().used_blocks, 2);
+        assert_eq!(allocator.internal_fragmentation(), 0);
+
+        // a merged allocation is freed at once
+        unsafe { allocator.dealloc(ptrs[0], Layout::new::<[u8; 20]>()) };
+        unsafe { allocator.dealloc(ptrs[2], Layout::new::<[u8; 20]>()) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+        assert_eq!(allocator.internal_fragmentation(), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn internal_fragmentation() {
+        let allocator = Allocator::<256>::new();
+        let ptrs = [5, 6, 8, 13, 3].map(|size| {
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            (unsafe { allocator.alloc(layout) }, layout)
+        });
+        assert_eq!(allocator.internal_fragmentation(), 3 + 2 + 3 + 1);
+
+        unsafe { allocator.dealloc(ptrs[1].0, ptrs[1].1) };
+        unsafe { allocator.dealloc(ptrs[2].0, ptrs[2].1) };
+        assert_eq!(allocator.internal_fragmentation(), 3 + 3 + 1);
+
+        // reallocations move the rounding to the new size
+        let layout = Layout::from_size_align(13, 1).unwrap();
+        let ptr = unsafe { allocator.realloc(ptrs[3].0, layout, 30) };
+        assert_eq!(allocator.internal_fragmentation(), 3 + 2 + 1);
+        unsafe { allocator.dealloc(ptr, Layout::from_size_align(30, 1).unwrap()) };
+        assert_eq!(allocator.internal_fragmentation(), 3 + 1);
+
+        unsafe { allocator.reset_touched() };
+        assert_eq!(allocator.internal_fragmentation(), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn from_layout() {
+        let layout = [
+            (State::Used, 8),
+            (State::Free, 4),
+            (State::Used, 12),
+            (State::Free, 16),
+            (State::Free, 0),
+            (State::Used, 4),
+            (State::Free, 56),
+        ];
+        let allocator = Allocator::<128>::from_layout(&layout);
+        let blocks: Vec<_> = allocator
+            .iter_blocks()
+            .map(|block| (block.state, block.size))
+            .collect();
+        assert_eq!(blocks, layout);
+        assert_eq!(
+            allocator
+                .iter_blocks()
+                .map(|block| block.offset)
+                .collect::<Vec<_>>(),
+            [0, 12, 20, 36, 56, 60, 68]
+        );
+        assert!(allocator.verify());
+
+        // the allocations use the predetermined free blocks
+        let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+        assert_eq!(allocator.block_of(ptr).unwrap().offset, 12);
+        let ptr = unsafe { allocator.alloc(Layout::new::<[u8; 16]>()) };
+        assert_eq!(allocator.block_of(ptr).unwrap().offset, 36);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn coalescible_pairs() {
+        let allocator = Allocator::<128>::from_layout(&[
+            (State::Free, 8),
+            (State::Free, 4),
+            (State::Used, 8),
+            (State::Free, 12),
+            (State::Used, 4),
+            (State::Free, 0),
+            (State::Free, 16),
+            (State::Free, 8),
+            (State::Used, 4),
+            (State::Free, 24),
+        ]);
+        let runs: Vec<_> = allocator.coalescible_pairs().collect();
+        assert_eq!(runs, [(0, 8 + 4 + 4), (56, 16 + 8 + 2 * 4)]);
+
+        // the runs are merged exactly like reported
+        allocator.coalesce_free_blocks();
+        let free: Vec<_> = allocator.free_blocks().collect();
+        assert_eq!(free, [(0, 16), (32, 12), (56, 32), (100, 24)]);
+        assert_eq!(allocator.coalescible_pairs().count(), 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn probe_alloc() {
+        return_null_on_oom();
+        use crate::Strategy;
+
+        fn highest_address(blocks: &mut dyn Iterator<Item = (usize, usize)>) -> Option<usize> {
+            blocks.map(|(offset, _size)| offset).max()
+        }
+
+        let strategies = [
+            Strategy::BestFit,
+            Strategy::FirstFit,
+            Strategy::Frontier,
+            Strategy::Custom(highest_address),
+        ];
+        let layouts = [
+            (4, 4),
+            (20, 4),
+            (8, 8),
+            (24, 16),
+            (40, 64),
+            (12, 4),
+            (64, 32),
+        ];
+        for strategy in strategies {
+            let allocator = Allocator::<1024>::from_layout(&[
+                (State::Used, 8),
+                (State::Free, 48),
+                (State::Used, 4),
+                (State::Free, 20),
+                (State::Used, 12),
+                (State::Free, 100),
+                (State::Used, 4),
+                (State::Free, 796),
+            ]);
+            allocator.set_strategy(strategy);
+            for (size, align) in layouts {
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let blocks: Vec<_> = allocator.iter_blocks().collect();
+                let offset = allocator.probe_alloc(layout);
+                assert_eq!(allocator.iter_blocks().collect::<Vec<_>>(), blocks);
+
+                // the allocation lands exactly at the predicted offset
+                let ptr = unsafe { allocator.alloc(layout) };
+                assert_eq!(allocator.ptr_to_offset(ptr), offset);
+                assert!(offset.is_some());
+            }
+        }
+
+        // a recently freed block is predicted to be reused
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u8; 16]>();
+        let ptrs = [(); 4].map(|_| unsafe { allocator.alloc(layout) });
+        unsafe { allocator.dealloc(ptrs[2], layout) };
+        unsafe { allocator.dealloc(ptrs[0], layout) };
+        assert_eq!(
+            allocator.probe_alloc(layout),
+            allocator.ptr_to_offset(ptrs[0])
+        );
+        assert_eq!(unsafe { allocator.alloc(layout) }, ptrs[0]);
+        assert_eq!(
+            allocator.probe_alloc(layout),
+            allocator.ptr_to_offset(ptrs[2])
+        );
+        assert_eq!(unsafe { allocator.alloc(layout) }, ptrs[2]);
+
+        // the reserved memory is not used and failures are predicted as well
+        let allocator = Allocator::<256>::new();
+        assert!(allocator.reserve(200));
+        assert_eq!(allocator.probe_alloc(Layout::new::<[u8; 64]>()), None);
+        assert!(unsafe { allocator.alloc(Layout::new::<[u8; 64]>()) }.is_null());
+        assert_eq!(allocator.probe_alloc(Layout::new::<[u8; 40]>()), Some(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid block layout")]
+    fn from_layout_not_filling_the_heap() {
+        let _ = Allocator::<64>::from_layout(&[(State::Used, 8), (State::Free, 12)]);
+    }
+
+    #[test]
+    fn max_single_alloc() {
+        return_null_on_oom();
+        let allocator = Allocator::<256>::new();
+        assert_eq!(allocator.max_single_alloc(), 0);
+        let ptrs = [(12, 4), (57, 1), (20, 16), (8, 8)].map(|(size, align)| {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            (unsafe { allocator.alloc(layout) }, layout)
+        });
+        assert_eq!(allocator.max_single_alloc(), 57);
+
+        // freeing and failed allocations do not change the maximum
+        for (ptr, layout) in ptrs {
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert!(unsafe { allocator.alloc(Layout::new::<[u8; 512]>()) }.is_null());
+        assert_eq!(allocator.max_single_alloc(), 57);
+
+        unsafe { allocator.reset_touched() };
+        assert_eq!(allocator.max_single_alloc(), 0);
+    }
+
+    #[test]
+    fn batch_allocation() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::from_size_align(12, 8).unwrap();
+        let mut out = [ptr::null_mut(); 6];
+
+        // the whole batch fits, but is limited by the output slice
+        let count = unsafe { allocator.alloc_batch(4, layout, &mut out) };
+        assert_eq!(count, 4);
+        assert!(out[4..].iter().all(|ptr| ptr.is_null()));
+        let count = unsafe { allocator.alloc_batch(8, layout, &mut out[4..]) };
+        assert_eq!(count, 2);
+        for (i, &ptr) in out.iter().enumerate() {
+            assert_alignment!(ptr, 8);
+            assert!(out[..i].iter().all(|&other| other != ptr));
+        }
+        assert_eq!(allocator.stats().used_blocks, 6);
+
+        // only some allocations of the batch fit
+        let mut out = [ptr::null_mut(); 16];
+        let count = unsafe { allocator.alloc_batch(16, layout, &mut out) };
+        assert!(0 < count && count < 16);
+        assert!(out[count..].iter().all(|ptr| ptr.is_null()));
+        for &ptr in &out[..count] {
+            assert_alignment!(ptr, 8);
+            unsafe { ptr.write_bytes(0xAA, layout.size()) };
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert_eq!(allocator.stats().used_blocks, 6);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn recommended_min_size() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        let sizes = [40, 20, 100];
+        let [first, second, large] = sizes.map(|size| Layout::from_size_align(size, 4).unwrap());
+        unsafe { allocator.alloc(first) };
+        let ptr = unsafe { allocator.alloc(second) };
+        assert_eq!(allocator.recommended_min_size(), None);
+
+        // the failed request plus the live blocks (including the headers)
+        assert!(unsafe { allocator.alloc(large) }.is_null());
+        assert_eq!(allocator.recommended_min_size(), Some(44 + 24 + 104));
+        let recommended = allocator.recommended_min_size().unwrap();
+        assert_eq!(recommended, crate::required_size(&sizes));
+
+        // smaller demands and capped allocations do not lower the peak
+        unsafe { allocator.dealloc(ptr, second) };
+        assert!(unsafe { allocator.alloc(large) }.is_null());
+        allocator.set_max_alloc(64);
+        assert!(unsafe { allocator.alloc(Layout::new::<[u8; 200]>()) }.is_null());
+        assert_eq!(allocator.recommended_min_size(), Some(recommended));
+
+        // the recommended heap size would have sufficed
+        let allocator = Allocator::<{ 44 + 24 + 104 }>::new();
+        for layout in [first, second, large] {
+            assert!(!unsafe { allocator.alloc(layout) }.is_null());
+        }
+        assert_eq!(allocator.recommended_min_size(), None);
+    }
+
+    #[test]
+    fn flexible_array_allocation() {
+        let allocator = Allocator::<1024>::new();
+        for header_align in [1, 2, 4, 8, 16] {
+            for (elem_size, elem_align) in [(1, 1), (3, 1), (2, 2), (4, 4), (12, 8), (32, 32)] {
+                for count in [0, 1, 5] {
+                    let header = Layout::from_size_align(3, header_align).unwrap();
+                    let elem = Layout::from_size_align(elem_size, elem_align).unwrap();
+                    let (ptr, layout, offset) = allocator.alloc_flex(header, elem, count).unwrap();
+                    let ptr = ptr.as_ptr();
+                    assert_alignment!(ptr, header_align);
+                    assert_alignment!(ptr.wrapping_add(offset), elem_align);
+                    assert!(offset >= header.size());
+
+                    // every element (padded to its alignment) fits into the block
+                    let stride = (elem_size + elem_align - 1) / elem_align * elem_align;
+                    assert!(offset + count * stride <= layout.size());
+                    unsafe { ptr.write_bytes(0xAA, layout.size()) };
+                    assert!(allocator.verify());
+                    unsafe { allocator.dealloc(ptr, layout) };
+                }
+            }
+        }
+        assert_eq!(allocator.stats().used_blocks, 0);
+
+        // empty and oversized layouts are rejected
+        let empty = Layout::new::<()>();
+        assert!(allocator.alloc_flex(empty, empty, 3).is_none());
+        assert!(allocator
+            .alloc_flex(empty, Layout::new::<u32>(), 0)
+            .is_none());
+        assert!(allocator
+            .alloc_flex(Layout::new::<u8>(), Layout::new::<u64>(), usize::MAX / 4)
+            .is_none());
+        assert!(allocator
+            .alloc_flex(Layout::new::<u8>(), Layout::new::<u64>(), 1000)
+            .is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn max_live_allocations() {
+        return_null_on_oom();
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        allocator.set_max_live_allocations(3);
+        let ptrs = [(); 3].map(|_| unsafe { allocator.alloc(layout) });
+        assert!(ptrs.iter().all(|ptr| !ptr.is_null()));
+
+        // the fourth allocation fails, although there is memory available
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        assert!(unsafe { allocator.alloc_with_size(layout) }.is_none());
+        assert!(allocator.available_bytes() > 100);
+        assert_eq!(allocator.recommended_min_size(), None);
+
+        // moving an allocation requires a new one temporarily
+        let moved = unsafe { allocator.realloc(ptrs[2], layout, 16) };
+        assert!(moved.is_null());
+
+        // freeing (or merging) an allocation allows a new one
+        unsafe { allocator.dealloc(ptrs[0], layout) };
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        unsafe { allocator.try_merge(ptr, layout, ptrs[1], layout) }.unwrap();
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+
+        allocator.set_max_live_allocations(usize::MAX);
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+        assert_eq!(allocator.stats().used_blocks, 4);
+    }
+
+    #[test]
+    fn realloc_within_the_rounded_size() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(13, 1).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { ptr.write_bytes(0xAA, 13) };
+        let stats = allocator.stats();
+
+        // growing and shrinking within the same multiple of 4 keeps the block
+        let grown = unsafe { allocator.realloc(ptr, layout, 15) };
+        assert_eq!(grown, ptr);
+        let layout = Layout::from_size_align(15, 1).unwrap();
+        let grown = unsafe { allocator.realloc(ptr, layout, 16) };
+        assert_eq!(grown, ptr);
+        let layout = Layout::from_size_align(16, 1).unwrap();
+        let shrunk = unsafe { allocator.realloc(ptr, layout, 13) };
+        assert_eq!(shrunk, ptr);
+        assert_eq!(allocator.stats(), stats);
+        assert_eq!(allocator.internal_fragmentation(), 3);
+
+        // otherwise, the memory is moved
+        let layout = Layout::from_size_align(13, 1).unwrap();
+        let moved = unsafe { allocator.realloc(ptr, layout, 17) };
+        assert_ne!(moved, ptr);
+        assert_eq!(allocator.stats().total_allocations, 2);
+        assert_eq!(
+            unsafe { core::slice::from_raw_parts(moved, 13) },
+            [0xAA; 13]
+        );
+        let layout = Layout::from_size_align(17, 1).unwrap();
+        let shrunk = unsafe { allocator.realloc(moved, layout, 9) };
+        assert_ne!(shrunk, moved);
+        assert_eq!(allocator.stats().used_blocks, 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn batch_deallocation() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u8; 20]>();
+        let mut ptrs = [ptr::null_mut(); 6];
+        let count = unsafe { allocator.alloc_batch(6, layout, &mut ptrs) };
+        assert_eq!(count, 6);
+
+        // the adjacent blocks are merged, although they are freed front to back
+        let freed = unsafe { allocator.free_batch(&ptrs[1..4]) };
+        assert_eq!(freed, 3);
+        let block = allocator.block_of(ptrs[1]).unwrap();
+        assert_eq!((block.size, block.state), (3 * 20 + 2 * 4, State::Free));
+        assert_eq!(allocator.stats().free_blocks, 2);
+        assert_eq!(allocator.stats().used_blocks, 3);
+
+        // invalid pointers are skipped
+        let mut outside = 0_u8;
+        let batch = [ptrs[1], ptrs[5], ptr::addr_of_mut!(outside), ptrs[0]];
+        let freed = unsafe { allocator.free_batch(&batch) };
+        assert_eq!(freed, 2);
+        assert_eq!(allocator.stats().used_blocks, 1);
+        assert!(allocator.verify());
+        assert_eq!(unsafe { allocator.free_batch(&[]) }, 0);
+    }
+
+    #[test]
+    fn granularity() {
+        const GRANULARITY: usize = Allocator::<128>::GRANULARITY;
+        #[cfg(not(feature = "granularity-8"))]
+        assert_eq!(GRANULARITY, 4);
+        #[cfg(feature = "granularity-8")]
+        assert_eq!(GRANULARITY, 8);
+
+        // allocations aligned to the granularity never waste memory for the
+        // alignment, only for rounding up the size
+        let allocator = Allocator::<128>::new();
+        for size in [1, 4, 7, 8] {
+            let layout = Layout::from_size_align(size, GRANULARITY).unwrap();
+            let (ptr, granted) = unsafe { allocator.alloc_with_size(layout) }.unwrap();
+            assert_alignment!(ptr.as_ptr(), GRANULARITY);
+            assert_eq!(
+                granted,
+                (size + GRANULARITY - 1) / GRANULARITY * GRANULARITY
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "granularity-8")]
+    fn eight_byte_alignment_does_not_over_allocate() {
+        let allocator = Allocator::<256>::new();
+        let (start, _) = allocator.memory_region();
+        assert_alignment!(start, 8);
+
+        // the blocks are placed right behind each other without any padding
+        // for the alignment, even for sizes, which are not multiples of 8
+        let mut offset = 0;
+        for (size, rounded) in [(8, 8), (12, 16), (24, 24), (1, 8)] {
+            let layout = Layout::from_size_align(size, 8).unwrap();
+            let (ptr, granted) = unsafe { allocator.alloc_with_size(layout) }.unwrap();
+            assert_alignment!(ptr.as_ptr(), 8);
+            assert_eq!(granted, rounded);
+            let info = allocator.block_of(ptr.as_ptr()).unwrap();
+            assert_eq!((info.offset, info.size), (offset, rounded));
+            offset += 8 + rounded;
+        }
+        assert_eq!(allocator.free_block_count(), 1);
+
+        // the same holds for the types with an alignment of 8
+        let ptr = unsafe { allocator.alloc(Layout::new::<u64>()) };
+        assert_alignment!(ptr, 8);
+        let info = allocator.block_of(ptr).unwrap();
+        assert_eq!((info.offset, info.size), (offset, 8));
+        let ptr = unsafe { allocator.alloc(Layout::new::<[u64; 3]>()) };
+        let info = allocator.block_of(ptr).unwrap();
+        assert_eq!((info.offset, info.size), (offset + 16, 24));
+
+        let stats = allocator.stats();
+        assert_eq!(stats.used, 8 + 16 + 24 + 8 + 8 + 24);
+        assert_eq!(stats.used + stats.free + 7 * 8, 256);
+    }
+
+    #[test]
+    #[cfg(feature = "backtrace")]
+    fn backtraces_of_live_allocations() {
+        use std::backtrace::BacktraceStatus;
+
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u8; 16]>();
+        let freed = unsafe { allocator.alloc(layout) };
+        let leaked = unsafe { allocator.alloc_from_top(layout) };
+        unsafe { allocator.dealloc(freed, layout) };
+
+        let live = allocator.live_allocations();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].ptr, leaked);
+        assert_eq!(live[0].backtrace.status(), BacktraceStatus::Captured);
+        assert!(!live[0].backtrace.to_string().is_empty());
+
+        let moved = unsafe { allocator.realloc_aligned(leaked, layout, 32, 16) };
+        let live = allocator.live_allocations();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].ptr, moved);
+
+        unsafe { allocator.dealloc(moved, layout) };
+        assert!(allocator.live_allocations().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "backtrace")]
+    fn locations_of_live_allocations() {
+        fn leak(allocator: &Allocator<256>) -> (*mut u8, u32) {
+            let layout = Layout::new::<[u8; 16]>();
+            (unsafe { allocator.alloc_tracked(layout) }, line!())
+        }
+
+        let allocator = Allocator::<256>::new();
+        let untracked = unsafe { allocator.alloc(Layout::new::<u32>()) };
+        let (leaked, line) = leak(&allocator);
+
+        let live = allocator.live_allocations();
+        assert_eq!(live.len(), 2);
+        assert_eq!((live[0].ptr, live[0].location), (untracked, None));
+        assert_eq!(live[1].ptr, leaked);
+        let location = live[1].location.unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+    }
+
+    #[test]
+    fn allocation_without_crossing_a_boundary() {
+        let layout = Layout::new::<[u8; 40]>();
+        let crosses = |ptr: *mut u8, boundary| {
+            ptr as usize / boundary != (ptr as usize + layout.size() - 1) / boundary
+        };
+
+        let mut naive_crossings = 0;
+        for filler in (4..=64).step_by(4) {
+            let allocator = Allocator::<512>::new();
+            unsafe { allocator.alloc(Layout::from_size_align(filler, 4).unwrap()) };
+            let naive = unsafe { allocator.alloc(layout) };
+            if crosses(naive, 64) {
+                naive_crossings += 1;
+            }
+            unsafe { allocator.dealloc(naive, layout) };
+
+            for boundary in [64, 128, 256] {
+                let ptr = unsafe { allocator.alloc_no_cross(layout, boundary) };
+                assert!(!ptr.is_null());
+                assert!(!crosses(ptr, boundary));
+                unsafe { ptr.write_bytes(0xAA, layout.size()) };
+                unsafe { allocator.dealloc(ptr, layout) };
+            }
+        }
+        // the naive placement would have crossed the boundary in some cases
+        assert!(naive_crossings > 0);
+
+        let allocator = Allocator::<512>::new();
+        for boundary in [0, 3, 48, 32] {
+            let ptr = unsafe { allocator.alloc_no_cross(layout, boundary) };
+            assert_eq!(ptr, ptr::null_mut());
+        }
+        let ptr = unsafe { allocator.alloc_no_cross(Layout::new::<u8>(), 1) };
+        assert!(!ptr.is_null());
+    }
+
+    #[test]
+    #[cfg(feature = "bounded-spin")]
+    fn bounded_spinning_on_the_lock() {
+        static ALLOCATOR: Allocator<128> = Allocator::new();
+        let layout = Layout::new::<u32>();
+
+        // the allocation of another thread fails, while the lock is held
+        let guard = ALLOCATOR.raw.lock();
+        let allocation = std::thread::spawn(move || unsafe { ALLOCATOR.alloc(layout) } as usize);
+        assert_eq!(allocation.join().unwrap(), 0);
+        drop(guard);
+
+        let ptr = unsafe { ALLOCATOR.alloc(layout) };
+        assert!(!ptr.is_null());
+        unsafe { ALLOCATOR.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn stats_without_blocking() {
+        static ALLOCATOR: Allocator<128> = Allocator::new();
+        assert_eq!(ALLOCATOR.try_stats(), Some(ALLOCATOR.stats()));
+
+        // another thread gives up immediately, while the lock is held
+        let guard = ALLOCATOR.raw.lock();
+        let stats = std::thread::spawn(|| ALLOCATOR.try_stats());
+        assert_eq!(stats.join().unwrap(), None);
+        drop(guard);
+
+        let stats = std::thread::spawn(|| ALLOCATOR.try_stats());
+        assert_eq!(stats.join().unwrap().unwrap().free, 128 - 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    #[cfg(feature = "ring-log")]
+    fn log_of_recent_operations() {
+        return_null_on_oom();
+        use crate::{LogEntry, Operation};
+
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 12]>();
+        let first = unsafe { allocator.alloc(layout) };
+        let second = unsafe { allocator.alloc(Layout::new::<[u8; 5]>()) };
+        unsafe { allocator.dealloc(first, layout) };
+        let huge = unsafe { allocator.alloc(Layout::new::<[u8; 256]>()) };
+        assert!(huge.is_null());
+
+        let mut log = [LogEntry::default(); 8];
+        assert_eq!(allocator.recent_ops(&mut log), 4);
+        let entries = log.map(|entry| (entry.operation, entry.size, entry.offset));
+        assert_eq!(entries[0], (Operation::Alloc, 12, Some(0)));
+        assert_eq!(entries[1], (Operation::Alloc, 5, Some(16)));
+        assert_eq!(entries[2], (Operation::Free, 12, Some(0)));
+        assert_eq!(entries[3], (Operation::Alloc, 256, None));
+        assert_eq!(allocator.block_of(second).unwrap().offset, 16);
+    }
+
+    #[test]
+    fn strategy() {
+        use crate::Strategy;
+
+        let allocator = Allocator::<256>::new();
+        assert_eq!(allocator.strategy(), Strategy::BestFit);
+
+        // create a large free block in front of a small free block
+        let large = Layout::new::<[u8; 64]>();
+        let small = Layout::new::<[u8; 16]>();
+        let separator = Layout::new::<u32>();
+        let large_ptr = unsafe { allocator.alloc(large) };
+        unsafe { allocator.alloc(separator) };
+        let small_ptr = unsafe { allocator.alloc(small) };
+        unsafe { allocator.alloc(separator) };
+        unsafe { allocator.dealloc(large_ptr, large) };
+        unsafe { allocator.dealloc(small_ptr, small) };
+
+        let layout = Layout::new::<[u8; 8]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(ptr, small_ptr);
+        unsafe { allocator.dealloc(ptr, layout) };
+
+        allocator.set_strategy(Strategy::FirstFit);
+        assert_eq!(allocator.strategy(), Strategy::FirstFit);
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(ptr, large_ptr);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn custom_strategy() {
+        return_null_on_oom();
+        use crate::Strategy;
+
+        fn highest_address(blocks: &mut dyn Iterator<Item = (usize, usize)>) -> Option<usize> {
+            blocks.map(|(offset, _size)| offset).max()
+        }
+        fn invalid(_blocks: &mut dyn Iterator<Item = (usize, usize)>) -> Option<usize> {
+            Some(2)
+        }
+
+        let allocator = Allocator::<256>::new();
+        allocator.set_strategy(Strategy::Custom(highest_address));
+        assert_eq!(allocator.strategy(), Strategy::Custom(highest_address));
+        assert_ne!(allocator.strategy(), Strategy::Custom(invalid));
+        assert_ne!(allocator.strategy(), Strategy::BestFit);
+        assert!(format!("{:?}", allocator.strategy()).starts_with("Custom(0x"));
+
+        // the blocks are placed one after the other, as the remaining free
+        // block at the end of the heap has the highest address
+        let layout = Layout::new::<[u8; 16]>();
+        let ptrs = [(); 4].map(|_| unsafe { allocator.alloc(layout) });
+        assert!(ptrs
+            .windows(2)
+            .all(|pair| pair[1] == pair[0].wrapping_add(20)));
+        unsafe { allocator.dealloc(ptrs[0], layout) };
+        unsafe { allocator.dealloc(ptrs[2], layout) };
+
+        // the holes are skipped in favor of the last free block
+        let (start, _) = allocator.memory_region();
+        let small = Layout::new::<[u8; 8]>();
+        let ptr = unsafe { allocator.alloc(small) };
+        assert_eq!(allocator.block_of(ptr).unwrap().offset, 4 * 20);
+        // over-aligned allocations are placed by the selector as well (the
+        // holes are too small, so that the recently freed blocks are not used)
+        let aligned = Layout::from_size_align(24, 16).unwrap();
+        let ptr = unsafe { allocator.alloc(aligned) };
+        assert!(ptr as usize - start as usize > 4 * 20 + 12);
+        assert_eq!(ptr as usize % 16, 0);
+
+        // offsets, which were not offered, are rejected
+        allocator.set_strategy(Strategy::Custom(invalid));
+        assert!(unsafe { allocator.alloc(small) }.is_null());
+        assert!(unsafe { allocator.alloc(aligned) }.is_null());
+        assert_eq!(allocator.stats().used_blocks, 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn free_block_count() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 12]>();
+        let ptrs = [(); 6].map(|_| unsafe { allocator.alloc(layout) });
+        assert_eq!(allocator.free_block_count(), 1);
+
+        for &ptr in ptrs.iter().step_by(2) {
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert_eq!(allocator.free_block_count(), 4);
+        assert_eq!(allocator.free_block_count(), allocator.stats().free_blocks);
+
+        // freeing the blocks in front of free blocks merges them, so there are
+        // no additional free blocks
+        unsafe { allocator.dealloc(ptrs[3], layout) };
+        unsafe { allocator.dealloc(ptrs[5], layout) };
+        assert_eq!(allocator.free_block_count(), 4);
+        unsafe { allocator.dealloc(ptrs[1], layout) };
+        assert_eq!(allocator.free_block_count(), 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn overhead_bytes() {
+        let allocator = Allocator::<128>::new();
+        let blocks = || {
+            let stats = allocator.stats();
+            stats.used_blocks + stats.free_blocks
+        };
+        assert_eq!(allocator.overhead_bytes(), 4);
+
+        let layout = Layout::new::<[u8; 12]>();
+        let ptrs = [(); 6].map(|_| unsafe { allocator.alloc(layout) });
+        assert_eq!(allocator.overhead_bytes(), 4 * 7);
+        assert_eq!(allocator.overhead_bytes(), 4 * blocks());
+
+        // freeing every other block does not merge any blocks
+        for &ptr in ptrs.iter().step_by(2) {
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert_eq!(allocator.overhead_bytes(), 4 * 7);
+        // merging blocks removes their headers
+        unsafe { allocator.dealloc(ptrs[5], layout) };
+        assert_eq!(allocator.overhead_bytes(), 4 * 6);
+        assert_eq!(allocator.overhead_bytes(), 4 * blocks());
+
+        // the usable memory and the headers make up the whole heap
+        let stats = allocator.stats();
+        assert_eq!(stats.used + stats.free + allocator.overhead_bytes(), 128);
+    }
+
+    #[test]
+    #[cfg(not(feature = "panic-on-dealloc-error"))]
+    fn dealloc_error_handler() {
+        use crate::FreeError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DOUBLE_FREES: AtomicUsize = AtomicUsize::new(0);
+        static NOT_FOUND: AtomicUsize = AtomicUsize::new(0);
+        fn handler(error: FreeError) {
+            match error {
+                FreeError::DoubleFreeDetected => DOUBLE_FREES.fetch_add(1, Ordering::SeqCst),
+                FreeError::AllocationNotFound => NOT_FOUND.fetch_add(1, Ordering::SeqCst),
+                FreeError::ReservedBlock => unreachable!("no reservation was freed"),
+            };
+        }
+
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<u32>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        unsafe { allocator.dealloc(ptr, layout) }; // ignored without handler
+
+        allocator.set_dealloc_error_handler(Some(handler));
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(DOUBLE_FREES.load(Ordering::SeqCst), 0);
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(DOUBLE_FREES.load(Ordering::SeqCst), 1);
+        let mut outside = 0_u32;
+        unsafe { allocator.dealloc(ptr::addr_of_mut!(outside).cast(), layout) };
+        assert_eq!(NOT_FOUND.load(Ordering::SeqCst), 1);
+
+        // the allocator is still usable afterwards
+        allocator.set_dealloc_error_handler(None);
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(DOUBLE_FREES.load(Ordering::SeqCst), 1);
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    /// A `defmt` logger counting the frames logged by the current thread.
+    ///
+    /// The frames themselves are encoded and refer to strings in the binary,
+    /// so they are not decoded. Tests of the logging only check, whether a
+    /// frame was logged.
+    #[cfg(feature = "defmt")]
+    mod defmt_log {
+        use std::cell::Cell;
+
+        std::thread_local! {
+            static FRAMES: Cell<usize> = Cell::new(0);
+        }
+
+        /// Query the number of frames logged by the current thread so far.
+        pub fn frames() -> usize {
+            FRAMES.with(Cell::get)
+        }
+
+        #[defmt::global_logger]
+        struct Logger;
+        // SAFETY: the logger only counts the frames per thread, therefore the
+        // frames of concurrent threads cannot interfere.
+        unsafe impl defmt::Logger for Logger {
+            fn acquire() {}
+            unsafe fn flush() {}
+            unsafe fn release() {
+                FRAMES.with(|frames| frames.set(frames.get() + 1));
+            }
+            unsafe fn write(_bytes: &[u8]) {}
+        }
+
+        defmt::timestamp!("{=u8}", 0);
+    }
+
+    #[test]
+    #[cfg(feature = "defmt")]
+    fn defmt_logging() {
+        return_null_on_oom();
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::new::<[u8; 32]>();
+        let logged = defmt_log::frames();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(defmt_log::frames(), logged);
+
+        // a failed allocation is logged
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        assert_eq!(defmt_log::frames(), logged + 1);
+
+        // an invalid deallocation is logged
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(defmt_log::frames(), logged + 1);
+        #[cfg(not(feature = "panic-on-dealloc-error"))]
+        {
+            unsafe { allocator.dealloc(ptr, layout) };
+            assert_eq!(defmt_log::frames(), logged + 2);
+        }
+
+        // the diagnostic types can be logged
+        let logged = defmt_log::frames();
+        let block = allocator
+            .block_of(unsafe { allocator.alloc(layout) })
+            .unwrap();
+        defmt::println!(
+            "{} {} {}",
+            allocator.stats(),
+            block,
+            crate::FreeError::DoubleFreeDetected
+        );
+        assert_eq!(defmt_log::frames(), logged + 1);
+    }
+
+    #[test]
+    #[cfg(feature = "abort-on-oom")]
+    fn oom_handler() {
+        use crate::LocalAllocator;
+
+        return_null_on_oom();
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::new::<[u8; 32]>();
+        // tests running on the same thread before might have failed already
+        let reported = out_of_memory().len();
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+        assert_eq!(out_of_memory().len(), reported);
+
+        // the handler gets the layout and the statistics of the failure
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        assert_eq!(out_of_memory()[reported..], [(layout, allocator.stats())]);
+
+        // failures indicated otherwise are not reported
+        assert!(unsafe { allocator.alloc_with_size(layout) }.is_none());
+        assert!(allocator.reserve_block(layout).is_none());
+        assert_eq!(out_of_memory().len(), reported + 1);
+
+        // the unlocked allocator reports its failures as well
+        let local = unsafe { LocalAllocator::<32>::new() };
+        assert!(unsafe { local.alloc(layout) }.is_null());
+        assert_eq!(out_of_memory()[reported + 1], (layout, local.stats()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn largest_free_block() {
+        let allocator = Allocator::<128>::new();
+        assert_eq!(allocator.largest_free_block(), 124);
+
+        let layout = Layout::new::<[u8; 28]>();
+        let ptrs = [(); 4].map(|_| unsafe { allocator.alloc(layout) });
+        assert_eq!(allocator.largest_free_block(), 0);
+
+        unsafe { allocator.dealloc(ptrs[0], layout) };
+        unsafe { allocator.dealloc(ptrs[2], layout) };
+        assert_eq!(allocator.largest_free_block(), 28);
+        assert_eq!(
+            allocator.largest_free_block(),
+            allocator.stats().largest_free
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn prefault() {
+        let allocator = Allocator::<128>::new();
+        allocator.prefault();
+        let stats = allocator.stats();
+        assert_eq!((stats.free, stats.free_blocks), (128 - 4, 1));
+        assert_eq!(stats.used_blocks, 0);
+    }
+
+    #[test]
+    fn nonblocking_allocation() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<u64>();
+
+        let ptr = unsafe { allocator.alloc_nonblocking(layout) };
+        assert_ne!(ptr, ptr::null_mut());
+        assert_alignment!(ptr, 8);
+
+        // simulate an interrupted allocation, which holds the lock. A blocking
+        // allocation would deadlock here.
+        let guard = allocator.raw.lock();
+        let ptr = unsafe { allocator.alloc_nonblocking(layout) };
+        assert_eq!(ptr, ptr::null_mut());
+        drop(guard);
+
+        let ptr = unsafe { allocator.alloc_nonblocking(layout) };
+        assert_ne!(ptr, ptr::null_mut());
+        assert_eq!(allocator.stats().used_blocks, 2);
+    }
+
+    #[test]
+    fn reserved_allocation() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        assert!(allocator.reserve(16));
+
+        // exhaust the regular heap memory
+        let layout = Layout::new::<u32>();
+        while !unsafe { allocator.alloc(layout) }.is_null() {}
+        assert_eq!(
+            unsafe { allocator.alloc_nonblocking(layout) },
+            ptr::null_mut()
+        );
+
+        // the critical allocation can still be satisfied
+        let layout = Layout::new::<[u32; 4]>();
+        let ptr = unsafe { allocator.alloc_reserved(layout) };
+        assert_ne!(ptr, ptr::null_mut());
+        assert_eq!(unsafe { allocator.alloc_reserved(layout) }, ptr::null_mut());
+
+        // the reserve is restored after deallocation
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_ne!(unsafe { allocator.alloc_reserved(layout) }, ptr::null_mut());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn debug_representation() {
+        let allocator = Allocator::<128>::new();
+        unsafe { allocator.alloc(Layout::new::<[u32; 2]>()) };
+        assert_eq!(
+            format!("{:?}", allocator),
+            "Allocator { size: 128, used: 8, free: 112, used_blocks: 1, free_blocks: 1 }"
+        );
+
+        // formatting must not deadlock, if the lock is held
+        let _guard = allocator.raw.lock();
+        assert_eq!(
+            format!("{:?}", allocator),
+            "Allocator { size: 128, state: <locked> }"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    #[cfg(feature = "relocating")]
+    fn compaction() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u32; 4]>();
+
+        // fragment the heap by freeing every second allocation
+        let ptrs = [(); 6].map(|_| unsafe { allocator.alloc(layout) });
+        let mut live = Vec::new();
+        for (i, &ptr) in (0..).zip(&ptrs) {
+            if i % 2 == 0 {
+                unsafe { allocator.dealloc(ptr, layout) };
+            } else {
+                unsafe { ptr.write_bytes(i, layout.size()) };
+                live.push((ptr, i));
+            }
+        }
+        let big = Layout::new::<[u32; 12]>();
+        assert_eq!(unsafe { allocator.alloc(big) }, ptr::null_mut());
+        assert_eq!(allocator.stats().free_blocks, 4);
+
+        let mut moves = Vec::new();
+        unsafe {
+            allocator.compact(|old, new, size| {
+                moves.push((old, new, size));
+                for (ptr, _) in &mut live {
+                    if *ptr == old {
+                        *ptr = new;
+                    }
+                }
+            });
+        }
+        assert_eq!(moves.len(), 3);
+        assert!(moves
+            .iter()
+            .all(|&(old, new, size)| new < old && size == 16));
+
+        // all the free memory is contiguous now and the data moved along
+        let stats = allocator.stats();
+        assert_eq!(stats.free_blocks, 1);
+        assert_eq!(stats.largest_free, stats.free);
+        for &(ptr, value) in &live {
+            let memory = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+            assert!(memory.iter().all(|&byte| byte == value));
+        }
+        assert_ne!(unsafe { allocator.alloc(big) }, ptr::null_mut());
+    }
+
+    #[test]
+    fn committed_reservation() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        assert_eq!(allocator.reserve_block(Layout::new::<()>()), None);
+
+        let reservation = allocator.reserve_block(layout).unwrap();
+        assert_eq!(reservation.layout(), layout);
+        unsafe { reservation.as_ptr().cast::<[u8; 8]>().write(*b"emballoc") };
+
+        // the committed memory is an ordinary allocation with the same content
+        let expected = reservation.as_ptr();
+        let ptr = allocator.commit(reservation);
+        assert_eq!(ptr, expected);
+        assert_eq!(unsafe { ptr.cast::<[u8; 8]>().read() }, *b"emballoc");
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.reserved_blocks), (1, 0));
+        assert_eq!(allocator.block_of(ptr).unwrap().state, State::Used);
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "panic-on-dealloc-error"))]
+    fn reservations_cannot_be_freed() {
+        use crate::FreeError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static RESERVED: AtomicUsize = AtomicUsize::new(0);
+        fn handler(error: FreeError) {
+            assert_eq!(error, FreeError::ReservedBlock);
+            RESERVED.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let allocator = Allocator::<128>::new();
+        allocator.set_dealloc_error_handler(Some(handler));
+        let layout = Layout::new::<[u8; 8]>();
+        let reservation = allocator.reserve_block(layout).unwrap();
+        let ptr = reservation.as_ptr();
+
+        // a pending reservation is neither freed directly nor in a batch
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(RESERVED.load(Ordering::SeqCst), 1);
+        assert_eq!(unsafe { allocator.free_batch(&[ptr]) }, 0);
+        assert_eq!(allocator.stats().reserved_blocks, 1);
+
+        // it can still be committed and freed afterwards
+        let ptr = allocator.commit(reservation);
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.stats().used_blocks, 0);
+        assert_eq!(RESERVED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn aborted_reservation() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 100]>();
+        let reservation = allocator.reserve_block(layout).unwrap();
+        assert_eq!(allocator.reserve_block(layout), None);
+
+        // the memory is free again and can be reserved once more
+        allocator.abort(reservation);
+        assert_eq!(allocator.stats().reserved_blocks, 0);
+        assert_eq!(allocator.available_bytes(), allocator.capacity());
+        allocator.abort(allocator.reserve_block(layout).unwrap());
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn reserved_blocks_are_neither_free_nor_used() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let used = unsafe { allocator.alloc(layout) };
+        let reservation = allocator.reserve_block(layout).unwrap();
+
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.used_blocks), (8, 1));
+        assert_eq!((stats.reserved, stats.reserved_blocks), (8, 1));
+        assert_eq!((stats.free, stats.free_blocks), (128 - 3 * 4 - 16, 1));
+        let block = allocator.block_of(reservation.as_ptr()).unwrap();
+        assert_eq!(
+            (block.offset, block.size, block.state),
+            (12, 8, State::Reserved)
+        );
+        let states: Vec<_> = allocator.iter_blocks().map(|block| block.state).collect();
+        assert_eq!(states, [State::Used, State::Reserved, State::Free]);
+
+        // the reserved block is not handed out again and cannot be merged
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!allocator.same_block(ptr, reservation.as_ptr()));
+        assert!(
+            unsafe { allocator.try_merge(used, layout, reservation.as_ptr(), layout) }.is_none()
+        );
+
+        // committing turns it into an ordinary used block
+        let committed = allocator.commit(reservation);
+        let stats = allocator.stats();
+        assert_eq!((stats.used_blocks, stats.reserved_blocks), (3, 0));
+        unsafe { allocator.dealloc(ptr, layout) };
+        unsafe { allocator.dealloc(committed, layout) };
+        unsafe { allocator.dealloc(used, layout) };
+        assert_eq!(allocator.available_bytes(), allocator.capacity());
+    }
+
+    #[test]
+    #[cfg(feature = "relocating")]
+    fn handles_survive_compaction() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u32; 4]>();
+
+        // fragment the heap, so that both handles are moved
+        let first = unsafe { allocator.alloc(layout) };
+        let a = allocator.alloc_handle(layout).unwrap();
+        let second = unsafe { allocator.alloc(layout) };
+        let b = allocator.alloc_handle(layout).unwrap();
+        unsafe {
+            a.as_ptr(&allocator).write_bytes(0xAA, layout.size());
+            b.as_ptr(&allocator).write_bytes(0xBB, layout.size());
+            allocator.dealloc(second, layout);
+            allocator.dealloc(first, layout);
+        }
+        let (old_a, old_b) = (a.as_ptr(&allocator), b.as_ptr(&allocator));
+
+        unsafe { allocator.compact(|_old, _new, _size| {}) };
+        assert_eq!(a.as_ptr(&allocator), first);
+        assert_eq!(b.as_ptr(&allocator), old_a);
+        assert_ne!(b.as_ptr(&allocator), old_b);
+        for (handle, value) in [(&a, 0xAA), (&b, 0xBB)] {
+            let ptr = handle.as_ptr(&allocator);
+            let memory = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+            assert!(memory.iter().all(|&byte| byte == value));
+        }
+
+        allocator.dealloc_handle(a);
+        allocator.dealloc_handle(b);
+        assert_eq!(allocator.stats().used_blocks, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    #[cfg(feature = "relocating")]
+    fn handle_table_is_limited() {
+        let allocator = Allocator::<512>::new();
+        let layout = Layout::new::<u32>();
+        assert_eq!(
+            allocator.alloc_handle(Layout::new::<u64>().align_to(8).unwrap()),
+            None
+        );
+
+        let handles: Vec<_> = (0..16)
+            .map(|_| allocator.alloc_handle(layout).unwrap())
+            .collect();
+        assert_eq!(allocator.alloc_handle(layout), None);
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+
+        // a released entry of the table is reused
+        let mut handles = handles.into_iter();
+        allocator.dealloc_handle(handles.next().unwrap());
+        let handle = allocator.alloc_handle(layout).unwrap();
+        assert_eq!(handle.index, 0);
+    }
+
+    #[test]
+    fn realloc_with_different_alignment() {
+        let allocator = Allocator::<512>::new();
+        let mut layout = Layout::new::<[u8; 10]>();
+        let mut ptr = unsafe { allocator.alloc(layout) };
+        unsafe {
+            ptr.cast::<[u8; 10]>()
+                .write([1, 2, 3, 4, 5, 6, 7, 8, 9, 10])
+        };
+
+        // grow the block while increasing the alignment
+        for (size, align) in [(20, 8), (40, 32), (60, 64)] {
+            ptr = unsafe { allocator.realloc_aligned(ptr, layout, size, align) };
+            layout = Layout::from_size_align(size, align).unwrap();
+            assert_ne!(ptr, ptr::null_mut());
+            assert_alignment!(ptr, align);
+            let data = unsafe { ptr.cast::<[u8; 10]>().read() };
+            assert_eq!(data, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+            assert_eq!(allocator.stats().used_blocks, 1);
+        }
+    }
+
+    #[test]
+    fn realloc_with_different_alignment_failure() {
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::new::<[u8; 4]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { ptr.cast::<[u8; 4]>().write([1, 2, 3, 4]) };
+
+        // the new block does not fit or the alignment is invalid
+        assert_eq!(
+            unsafe { allocator.realloc_aligned(ptr, layout, 64, 8) },
+            ptr::null_mut()
+        );
+        assert_eq!(
+            unsafe { allocator.realloc_aligned(ptr, layout, 8, 3) },
+            ptr::null_mut()
+        );
+        assert_eq!(unsafe { ptr.cast::<[u8; 4]>().read() }, [1, 2, 3, 4]);
+        assert_eq!(allocator.stats().used_blocks, 1);
+
+        // shrinking copies only the new size
+        let ptr = unsafe { allocator.realloc_aligned(ptr, layout, 2, 2) };
+        assert_eq!(unsafe { ptr.cast::<[u8; 2]>().read() }, [1, 2]);
+        assert_eq!(allocator.stats().used_blocks, 1);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn block_of() {
+        let allocator = Allocator::<128>::new();
+        let (start, _) = allocator.memory_region();
+        let layout = Layout::from_size_align(10, 16).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+
+        // the over-aligned pointer is inside the block, which might contain a
+        // small padding in front of it
+        let info = allocator.block_of(ptr).unwrap();
+        assert_eq!(info.state, State::Used);
+        let memory = start.wrapping_add(info.offset + 4);
+        assert!(
+            memory as usize <= ptr as usize && ptr as usize + 10 <= memory as usize + info.size
+        );
+        assert!(info.size < 12 + 8);
+        assert_eq!(allocator.block_of(ptr.wrapping_add(9)), Some(info));
+
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.block_of(ptr).unwrap().state, State::Free);
+
+        // pointers outside of the heap
+        let outside = 0_u32;
+        assert_eq!(allocator.block_of(ptr::addr_of!(outside).cast()), None);
+        assert_eq!(allocator.block_of(start.wrapping_add(128)), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn free_blocks() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<[u8; 8]>();
+        let ptrs = [(); 5].map(|_| unsafe { allocator.alloc(layout) });
+        assert_eq!(allocator.free_blocks().collect::<Vec<_>>(), [(60, 64)]);
+
+        // fragment the heap by freeing the first, the third and the fourth block
+        for &ptr in [ptrs[3], ptrs[2], ptrs[0]].iter() {
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        let free: Vec<_> = allocator.free_blocks().collect();
+        assert_eq!(free, [(0, 8), (24, 20), (60, 64)]);
+
+        // a full heap has no free blocks at all
+        let rest = unsafe { allocator.alloc(Layout::new::<[u8; 64]>()) };
+        assert!(!rest.is_null());
+        unsafe { allocator.alloc(Layout::new::<[u8; 20]>()) };
+        unsafe { allocator.alloc(layout) };
+        assert_eq!(allocator.free_blocks().next(), None);
+    }
+
+    #[test]
+    fn same_block() {
+        let allocator = Allocator::<128>::new();
+        let (start, _) = allocator.memory_region();
+        let layout = Layout::new::<[u8; 8]>();
+        let first = unsafe { allocator.alloc(layout) };
+        let second = unsafe { allocator.alloc(layout) };
+
+        // pointers into the same block
+        assert!(allocator.same_block(first, first));
+        assert!(allocator.same_block(first.wrapping_add(7), first.wrapping_add(2)));
+
+        // pointers into different blocks
+        assert!(!allocator.same_block(first, second));
+        assert!(!allocator.same_block(first, first.wrapping_add(8)));
+        assert!(!allocator.same_block(second, first.wrapping_add(7)));
+
+        // pointers outside of the heap
+        assert!(!allocator.same_block(start.wrapping_add(128), first));
+        assert!(!allocator.same_block(first, start.wrapping_add(128)));
+        assert!(!allocator.same_block(ptr::null(), ptr::null()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn memory_region() {
+        let allocator = Allocator::<128>::new();
+        let (start, size) = allocator.memory_region();
+        assert_eq!(size, 128);
+        assert_alignment!(start, 4);
+
+        // the allocations are inside that region
+        let ptr = unsafe { allocator.alloc(Layout::new::<[u8; 124]>()) };
+        assert_eq!(ptr as usize, start as usize + 4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn reserved_prefix() {
+        return_null_on_oom();
+        let allocator = Allocator::<128>::new_with_reserved_prefix(30);
+        let prefix = allocator.reserved_prefix();
+        let (start, _) = allocator.memory_region();
+        assert_eq!(prefix as usize, start as usize + 4);
+        unsafe { prefix.write_bytes(0xAB, 32) };
+        let info = allocator.block_of(prefix).unwrap();
+        assert_eq!((info.offset, info.size, info.state), (0, 32, State::Used));
+
+        // the rest of the heap is allocated as usual, but never the prefix
+        let layout = Layout::new::<[u8; 8]>();
+        let ptrs: Vec<_> = core::iter::from_fn(|| {
+            let ptr = unsafe { allocator.alloc(layout) };
+            (!ptr.is_null()).then(|| ptr)
+        })
+        .collect();
+        assert_eq!(ptrs.len(), (128 - 36) / 12);
+        assert!(ptrs.iter().all(|&ptr| ptr as usize >= prefix as usize + 36));
+        for ptr in ptrs {
+            unsafe { ptr.write_bytes(0, 8) };
+            unsafe { allocator.dealloc(ptr, layout) };
+        }
+        assert_eq!(allocator.reserved_prefix(), prefix);
+        let prefix_memory = unsafe { core::slice::from_raw_parts(prefix, 32) };
+        assert!(prefix_memory.iter().all(|&byte| byte == 0xAB));
+        assert_eq!(allocator.stats().used_blocks, 1);
+
+        // the prefix can be released in a later phase
+        unsafe { allocator.dealloc(prefix, Layout::new::<[u8; 32]>()) };
+        allocator.coalesce_free_blocks();
+        assert_eq!(allocator.largest_free_block(), 128 - 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "prefix too large for the buffer")]
+    fn reserved_prefix_without_room_for_a_free_block() {
+        let _ = Allocator::<16>::new_with_reserved_prefix(9);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn conversion_between_offsets_and_pointers() {
+        let allocator = Allocator::<128>::new();
+        let (start, _) = allocator.memory_region();
+        for offset in [0, 1, 4, 63, 127] {
+            let ptr = allocator.offset_to_ptr(offset);
+            assert_eq!(ptr as usize, start as usize + offset);
+            assert_eq!(allocator.ptr_to_offset(ptr), Some(offset));
+        }
+
+        // the offsets of the blocks match the allocated pointers
+        unsafe { allocator.alloc(Layout::new::<u32>()) };
+        let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+        let info = allocator.block_of(ptr).unwrap();
+        assert_eq!(allocator.offset_to_ptr(info.offset + 4), ptr);
+
+        // out-of-range offsets and pointers
+        assert_eq!(allocator.offset_to_ptr(128), ptr::null_mut());
+        assert_eq!(allocator.offset_to_ptr(usize::MAX), ptr::null_mut());
+        assert_eq!(allocator.ptr_to_offset(start.wrapping_add(128)), None);
+        assert_eq!(allocator.ptr_to_offset(start.wrapping_sub(1)), None);
+        assert_eq!(allocator.ptr_to_offset(ptr::null()), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn fragmentation_stress() {
+        return_null_on_oom();
+        // this is mainly intended to run under `miri` in order to detect any
+        // undefined behavior when splitting and merging blocks. Therefore the
+        // heap is small in that case.
+        const HEAP_SIZE: usize = if cfg!(miri) { 256 } else { 4096 };
+        let allocator = Allocator::<HEAP_SIZE>::new();
+
+        for size in [1, 4, 7, 12] {
+            // fill the heap completely
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            let mut ptrs = Vec::new();
+            loop {
+                let ptr = unsafe { allocator.alloc(layout) };
+                if ptr.is_null() {
+                    break;
+                }
+                unsafe { ptr.write_bytes(0xAA, size) };
+                ptrs.push(ptr);
+            }
+            assert_eq!(allocator.free_block_count(), 0);
+
+            // maximize the fragmentation by freeing every second block
+            for &ptr in ptrs.iter().step_by(2) {
+                unsafe { allocator.dealloc(ptr, layout) };
+            }
+            assert_eq!(allocator.free_block_count(), (ptrs.len() + 1) / 2);
+
+            // free the rest, which only merges with the following blocks
+            for &ptr in ptrs.iter().skip(1).step_by(2) {
+                unsafe { allocator.dealloc(ptr, layout) };
+            }
+            assert!(allocator.free_block_count() > 1);
+
+            // the heap consists of a single free block afterwards
+            allocator.coalesce_free_blocks();
+            let stats = allocator.stats();
+            assert_eq!((stats.used_blocks, stats.free_blocks), (0, 1));
+            assert_eq!(stats.free, HEAP_SIZE - 4);
+        }
     }
 
     #[test]
@@ -0,0 +1,136 @@
+//! This module provides the [`LocalAllocator`], an allocator without locking.
+use crate::raw_allocator::RawAllocator;
+use crate::{Allocator, HeapStats};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::cell::UnsafeCell;
+
+/// A memory allocator for strictly single-threaded environments.
+///
+/// This type behaves exactly like an [`Allocator`], but does not use any lock
+/// to protect its internal state. This saves the atomic operations on every
+/// allocation and deallocation and rules out any deadlock, but it is only
+/// sound, if the allocator is never accessed concurrently.
+///
+/// __Be very careful__: using this type as the global allocator is _undefined
+/// behavior_ in the presence of multiple threads or interrupt handlers, which
+/// might allocate or deallocate memory, since those could interrupt an ongoing
+/// allocation and corrupt the heap. This is the reason, why constructing this
+/// allocator is `unsafe`. If in doubt, use the [`Allocator`] instead. If the
+/// program becomes multi-threaded later on, use a
+/// [`GenericAllocator`](crate::GenericAllocator) with a
+/// [`PhasedLock`](crate::PhasedLock), whose locking can be enabled in place.
+/// ```
+/// #[global_allocator]
+/// // SAFETY: the program is single-threaded and does not allocate memory in
+/// // interrupt handlers.
+/// static ALLOCATOR: emballoc::LocalAllocator<4096> = unsafe { emballoc::LocalAllocator::new() };
+/// ```
+pub struct LocalAllocator<const N: usize> {
+    /// The internal raw allocator.
+    ///
+    /// This is not protected by any lock. The absence of concurrent accesses
+    /// is guaranteed by the caller of [`LocalAllocator::new()`].
+    raw: UnsafeCell<RawAllocator<N>>,
+}
+// SAFETY: the type is only `Sync` to be usable in a `static` (required for the
+// `#[global_allocator]`-attribute). The creator of the allocator guarantees,
+// that there is no concurrent access (safety contract of `new()`), therefore
+// the inner state is never accessed from multiple threads at the same time.
+unsafe impl<const N: usize> Sync for LocalAllocator<N> {}
+impl<const N: usize> LocalAllocator<N> {
+    /// Create a new [`LocalAllocator`] with exactly `N` bytes heap space.
+    ///
+    /// Please refer to [`Allocator::new()`] for details on the heap size.
+    ///
+    /// # Safety
+    /// The caller has to guarantee, that the allocator is never used from
+    /// multiple execution contexts at the same time. This includes threads as
+    /// well as interrupt handlers (or signal handlers on hosted systems), which
+    /// might allocate or deallocate memory.
+    ///
+    /// # Panics
+    /// This function will panic, if the supplied buffer size, i.e. `N`, is less
+    /// than `8` or not divisible by `4` (`16` and `8` with the `granularity-8`
+    /// feature).
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const unsafe fn new() -> Self {
+        let raw = UnsafeCell::new(RawAllocator::new());
+        Self { raw }
+    }
+
+    /// Query the current usage statistics of the heap.
+    ///
+    /// See [`Allocator::stats()`] for details.
+    pub fn stats(&self) -> HeapStats {
+        self.raw().stats()
+    }
+
+    /// Obtain the raw allocator.
+    #[allow(clippy::mut_from_ref)] // exclusive access is guaranteed by `new()`
+    fn raw(&self) -> &mut RawAllocator<N> {
+        // SAFETY: the creator of this allocator guaranteed, that there are no
+        // concurrent accesses (see `new()`) and this function is only used for
+        // the duration of a single operation, which does not call back into
+        // the allocator. Therefore this is the only reference at a time.
+        unsafe { &mut *self.raw.get() }
+    }
+}
+// SAFETY: the same implementation as for `Allocator` is used, just without the
+// lock, which is not necessary due to the safety contract of `new()`.
+unsafe impl<const N: usize> GlobalAlloc for LocalAllocator<N> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = Allocator::alloc_in(self.raw(), layout);
+        #[cfg(feature = "abort-on-oom")]
+        if ptr.is_null() {
+            crate::out_of_memory(layout, self.stats());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let result = Allocator::dealloc_in(self.raw(), ptr, Some(layout));
+        Allocator::<N>::finish_dealloc(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalAllocator;
+    use crate::Allocator;
+    use core::alloc::{GlobalAlloc, Layout};
+
+    #[test]
+    fn behaves_like_the_locked_allocator() {
+        let local = unsafe { LocalAllocator::<256>::new() };
+        let locked = Allocator::<256>::new();
+
+        let layouts = [
+            Layout::new::<u32>(),
+            Layout::new::<[u8; 13]>(),
+            Layout::new::<u64>(),
+            // over-aligned allocations are not included, as their placement
+            // depends on the address of the heap
+            Layout::new::<[u32; 6]>(),
+            Layout::new::<[u16; 7]>(),
+        ];
+        let local_ptrs = layouts.map(|layout| unsafe { local.alloc(layout) });
+        let locked_ptrs = layouts.map(|layout| unsafe { locked.alloc(layout) });
+        assert_eq!(local.stats(), locked.stats());
+
+        for i in [1, 3, 0] {
+            unsafe { local.dealloc(local_ptrs[i], layouts[i]) };
+            unsafe { locked.dealloc(locked_ptrs[i], layouts[i]) };
+            assert_eq!(local.stats(), locked.stats());
+        }
+
+        // the placement of the allocations is the same relative to the heap
+        let local_ptr = unsafe { local.alloc(layouts[0]) };
+        let locked_ptr = unsafe { locked.alloc(layouts[0]) };
+        assert_eq!(
+            local_ptrs[4] as usize - local_ptr as usize,
+            locked_ptrs[4] as usize - locked_ptr as usize
+        );
+        assert_eq!(local.stats(), locked.stats());
+    }
+}
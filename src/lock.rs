@@ -0,0 +1,501 @@
+//! This module provides the [`RawLock`] abstraction over the allocator lock.
+//!
+//! The state of an allocator has to be protected against concurrent access, as
+//! the allocator is shared between all threads (and interrupt handlers). The
+//! [`RawLock`] trait allows to plug in a custom locking primitive, e.g. the
+//! mutex of a real-time operating system, while [`SpinLock`] is the default.
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// The maximum number of attempts to acquire the lock for an allocation.
+///
+/// With the `bounded-spin` feature, an allocation gives up and fails (e.g. a
+/// null pointer is returned) after that many failed attempts to acquire the
+/// lock, instead of waiting forever. This turns a deadlock (e.g. an allocation
+/// in an interrupt handler, which interrupted an allocation) into an allocation
+/// failure, which is easier to diagnose. Deallocations still wait until the
+/// lock is available, as they cannot fail.
+pub const SPIN_LIMIT: usize = 1_000_000;
+
+/// A lock protecting the internal state of a [`GenericAllocator`].
+///
+/// This abstracts over the locking primitive used by the allocator. By default
+/// the [`SpinLock`] is used, which busy-waits until the lock is available. On
+/// a real-time operating system, one might want to use a mutex yielding to the
+/// scheduler instead. On a single-core system, a lock masking the interrupts
+/// (a so-called critical section) is another option. Such a lock can be used
+/// via [`GenericAllocator::with_lock()`].
+///
+/// # Safety
+/// The implementation has to guarantee mutual exclusion: after a call to
+/// [`lock()`](Self::lock) returned or [`try_lock()`](Self::try_lock) returned
+/// `true`, no other call to one of those functions may succeed until
+/// [`unlock()`](Self::unlock) is called.
+///
+/// # Example
+/// A lock for a single-threaded program, which asserts the absence of
+/// concurrent accesses instead of waiting:
+/// ```
+/// use core::sync::atomic::{AtomicBool, Ordering};
+/// use emballoc::{GenericAllocator, RawLock};
+///
+/// struct AssertingLock(AtomicBool);
+/// // SAFETY: the lock is only acquired, if it was not acquired before
+/// unsafe impl RawLock for AssertingLock {
+///     fn lock(&self) {
+///         assert!(self.try_lock(), "concurrent access");
+///     }
+///
+///     fn try_lock(&self) -> bool {
+///         !self.0.swap(true, Ordering::Acquire)
+///     }
+///
+///     unsafe fn unlock(&self) {
+///         self.0.store(false, Ordering::Release);
+///     }
+/// }
+///
+/// static ALLOCATOR: GenericAllocator<AssertingLock, 4096> =
+///     GenericAllocator::with_lock(AssertingLock(AtomicBool::new(false)));
+/// ```
+///
+/// [`GenericAllocator`]: crate::GenericAllocator
+/// [`GenericAllocator::with_lock()`]: crate::GenericAllocator::with_lock
+pub unsafe trait RawLock {
+    /// Acquire the lock, waiting until it is available.
+    fn lock(&self);
+
+    /// Try to acquire the lock without waiting.
+    ///
+    /// If the lock was acquired, `true` is returned.
+    fn try_lock(&self) -> bool;
+
+    /// Release the lock.
+    ///
+    /// # Safety
+    /// This must only be called, if the lock was acquired before (via
+    /// [`lock()`](Self::lock) or a successful [`try_lock()`](Self::try_lock))
+    /// by the current execution context.
+    unsafe fn unlock(&self);
+}
+
+/// A simple spin lock, which busy-waits until the lock is available.
+///
+/// This is the default lock of the [`Allocator`](crate::Allocator). It is
+/// based on the popular [`spin`] crate.
+pub struct SpinLock(spin::Mutex<()>);
+impl SpinLock {
+    /// Create a new, unlocked spin lock.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(spin::Mutex::new(()))
+    }
+}
+impl Default for SpinLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// SAFETY: the mutual exclusion is guaranteed by the `spin::Mutex`. The guards
+// are forgotten while the lock is held and the lock is released explicitly.
+unsafe impl RawLock for SpinLock {
+    fn lock(&self) {
+        core::mem::forget(self.0.lock());
+    }
+
+    fn try_lock(&self) -> bool {
+        self.0.try_lock().map(core::mem::forget).is_some()
+    }
+
+    unsafe fn unlock(&self) {
+        // SAFETY: the lock is held by the caller (safety contract of this
+        // function), whose guard was forgotten.
+        unsafe { self.0.force_unlock() };
+    }
+}
+
+/// A lock, which can be enabled and disabled at runtime.
+///
+/// While the lock is enabled, it behaves exactly like the [`SpinLock`]. While
+/// it is disabled, acquiring it succeeds immediately without any atomic
+/// read-modify-write operation, like the
+/// [`LocalAllocator`](crate::LocalAllocator) does. The mode is switched in
+/// place, so an allocator using this lock keeps its heap and all the live
+/// allocations stay valid. This allows a phased startup: the heap is used
+/// without locking during the single-threaded initialization and the locking
+/// is enabled before other threads (or interrupt handlers) are started.
+///
+/// # Example
+/// ```
+/// use core::alloc::{GlobalAlloc, Layout};
+/// use emballoc::{GenericAllocator, PhasedLock};
+///
+/// // SAFETY: the allocator is only used by the main thread, until the locking
+/// // is enabled.
+/// static ALLOCATOR: GenericAllocator<PhasedLock, 4096> =
+///     GenericAllocator::with_lock(unsafe { PhasedLock::disabled() });
+///
+/// let layout = Layout::new::<u32>();
+/// let ptr = unsafe { ALLOCATOR.alloc(layout) };
+/// ALLOCATOR.enable_locking();
+/// let thread = std::thread::spawn(move || unsafe { ALLOCATOR.alloc(layout) }.is_null());
+/// assert!(!thread.join().unwrap());
+/// unsafe { ALLOCATOR.dealloc(ptr, layout) };
+/// assert_eq!(ALLOCATOR.stats().used_blocks, 1);
+/// ```
+pub struct PhasedLock {
+    /// Whether the lock is used at all.
+    enabled: AtomicBool,
+    /// The lock used while it is enabled.
+    lock: SpinLock,
+}
+impl PhasedLock {
+    /// Create a new, unlocked and enabled lock.
+    #[must_use]
+    pub const fn enabled() -> Self {
+        let enabled = AtomicBool::new(true);
+        let lock = SpinLock::new();
+        Self { enabled, lock }
+    }
+
+    /// Create a new, unlocked and disabled lock.
+    ///
+    /// # Safety
+    /// The caller has to guarantee, that the lock is never used from multiple
+    /// execution contexts at the same time, until it is enabled via
+    /// [`enable()`](Self::enable). This includes threads as well as interrupt
+    /// handlers, which might allocate or deallocate memory.
+    #[must_use]
+    pub const unsafe fn disabled() -> Self {
+        let enabled = AtomicBool::new(false);
+        let lock = SpinLock::new();
+        Self { enabled, lock }
+    }
+
+    /// Query, whether the lock is currently enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    /// Enable the lock.
+    ///
+    /// Afterwards the lock guarantees mutual exclusion, so it may be used from
+    /// multiple execution contexts. This has to be called while the lock is not
+    /// held (e.g. not while iterating over the blocks of an allocator), as it
+    /// was not acquired while it was disabled.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Disable the lock.
+    ///
+    /// # Safety
+    /// The lock must not be held and the caller has to guarantee, that it is
+    /// never used from multiple execution contexts at the same time afterwards
+    /// (see [`disabled()`](Self::disabled)), until it is enabled again.
+    pub unsafe fn disable(&self) {
+        self.enabled.store(false, Ordering::Release);
+    }
+}
+impl Default for PhasedLock {
+    fn default() -> Self {
+        Self::enabled()
+    }
+}
+// SAFETY: while enabled, the mutual exclusion is guaranteed by the `SpinLock`.
+// While disabled, the creator of the lock guarantees, that there are no
+// concurrent accesses (safety contract of `disabled()` and `disable()`). The
+// mode is only switched while the lock is not held.
+unsafe impl RawLock for PhasedLock {
+    fn lock(&self) {
+        if self.is_enabled() {
+            self.lock.lock();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        !self.is_enabled() || self.lock.try_lock()
+    }
+
+    unsafe fn unlock(&self) {
+        if self.is_enabled() {
+            // SAFETY: the lock was enabled when it was acquired (as the mode is
+            // not switched while the lock is held), so the spin lock is held
+            // by the caller (safety contract of this function).
+            unsafe { self.lock.unlock() };
+        }
+    }
+}
+
+/// A mutex protecting a value of type `T` with a [`RawLock`].
+pub struct Mutex<L, T> {
+    /// The lock protecting the data.
+    lock: L,
+    /// The protected data, which is only accessed while the lock is held.
+    data: UnsafeCell<T>,
+}
+// SAFETY: the lock guarantees exclusive access to the data, so sharing the
+// mutex is sound, if the data may be sent to the other thread and the lock
+// itself may be shared.
+unsafe impl<L: Sync, T: Send> Sync for Mutex<L, T> {}
+impl<L, T> Mutex<L, T> {
+    /// Create a new mutex protecting the `data` with the given `lock`.
+    pub const fn new(lock: L, data: T) -> Self {
+        let data = UnsafeCell::new(data);
+        Self { lock, data }
+    }
+
+    /// Initialize a mutex in place using the given `lock`.
+    ///
+    /// The data is initialized by `init`, which receives a pointer to the
+    /// uninitialized data. This avoids a temporary copy of large data.
+    ///
+    /// # Safety
+    /// The pointer `this` must be valid for writes and properly aligned. The
+    /// function `init` has to initialize the data completely.
+    pub unsafe fn init_at(this: *mut Self, lock: L, init: impl FnOnce(*mut T)) {
+        // SAFETY: the mutex is valid for writes and properly aligned (safety
+        // contract of this function), so are its fields.
+        unsafe {
+            ptr::addr_of_mut!((*this).lock).write(lock);
+            init(UnsafeCell::raw_get(ptr::addr_of_mut!((*this).data)));
+        }
+    }
+
+    /// Access the lock itself, e.g. in order to configure it.
+    pub const fn raw_lock(&self) -> &L {
+        &self.lock
+    }
+
+    /// Access the data without acquiring the lock.
+    ///
+    /// # Safety
+    /// The data must not be modified while the returned reference is alive,
+    /// e.g. because the lock holder is halted.
+    pub unsafe fn get_unlocked(&self) -> &T {
+        // SAFETY: there are no modifications of the data (safety contract of
+        // this function), so a shared reference is fine.
+        unsafe { &*self.data.get() }
+    }
+}
+impl<L: RawLock, T> Mutex<L, T> {
+    /// Acquire the lock, waiting until it is available.
+    pub fn lock(&self) -> MutexGuard<'_, L, T> {
+        self.lock.lock();
+        MutexGuard { mutex: self }
+    }
+
+    /// Try to acquire the lock without waiting.
+    ///
+    /// If the lock is currently held, `None` is returned.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, L, T>> {
+        if self.lock.try_lock() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    /// Try to acquire the lock at most `attempts` times.
+    ///
+    /// If the lock could not be acquired, `None` is returned.
+    pub fn lock_bounded(&self, attempts: usize) -> Option<MutexGuard<'_, L, T>> {
+        for _ in 0..attempts {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            core::hint::spin_loop();
+        }
+        None
+    }
+}
+
+/// The access to the data of a locked [`Mutex`].
+///
+/// The lock is released, when the guard is dropped.
+pub struct MutexGuard<'mutex, L: RawLock, T> {
+    /// The locked mutex.
+    mutex: &'mutex Mutex<L, T>,
+}
+impl<L: RawLock, T> Deref for MutexGuard<'_, L, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the lock is held as long as the guard exists, so there are
+        // no other references to the data.
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+impl<L: RawLock, T> DerefMut for MutexGuard<'_, L, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: the lock is held as long as the guard exists, so there are
+        // no other references to the data.
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+impl<L: RawLock, T> Drop for MutexGuard<'_, L, T> {
+    fn drop(&mut self) {
+        // SAFETY: the lock was acquired when creating the guard
+        unsafe { self.mutex.lock.unlock() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PhasedLock, RawLock, SpinLock};
+    use crate::GenericAllocator;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// A lock counting the acquisitions and releases.
+    struct CountingLock {
+        held: AtomicBool,
+        acquired: AtomicUsize,
+        released: AtomicUsize,
+    }
+    impl CountingLock {
+        const fn new() -> Self {
+            Self {
+                held: AtomicBool::new(false),
+                acquired: AtomicUsize::new(0),
+                released: AtomicUsize::new(0),
+            }
+        }
+
+        fn counts(&self) -> (usize, usize) {
+            let acquired = self.acquired.load(Ordering::SeqCst);
+            (acquired, self.released.load(Ordering::SeqCst))
+        }
+    }
+    unsafe impl RawLock for CountingLock {
+        fn lock(&self) {
+            assert!(self.try_lock(), "the lock is not re-entrant");
+        }
+
+        fn try_lock(&self) -> bool {
+            let acquired = !self.held.swap(true, Ordering::SeqCst);
+            if acquired {
+                self.acquired.fetch_add(1, Ordering::SeqCst);
+            }
+            acquired
+        }
+
+        unsafe fn unlock(&self) {
+            assert!(self.held.swap(false, Ordering::SeqCst));
+            self.released.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn allocations_use_the_lock() {
+        let allocator = GenericAllocator::<_, 128>::with_lock(CountingLock::new());
+        let counts = || allocator.raw.lock.counts();
+        assert_eq!(counts(), (0, 0));
+
+        let layout = Layout::new::<u32>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert_eq!(counts(), (1, 1));
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(counts(), (2, 2));
+        assert_eq!(allocator.stats().used_blocks, 0);
+        assert_eq!(counts(), (3, 3));
+    }
+
+    #[test]
+    fn nonblocking_allocation_uses_try_lock() {
+        let allocator = GenericAllocator::<_, 128>::with_lock(CountingLock::new());
+        let layout = Layout::new::<u32>();
+
+        let guard = allocator.raw.lock();
+        assert!(unsafe { allocator.alloc_nonblocking(layout) }.is_null());
+        drop(guard);
+        assert_eq!(allocator.raw.lock.counts(), (1, 1));
+
+        assert!(!unsafe { allocator.alloc_nonblocking(layout) }.is_null());
+        assert_eq!(allocator.raw.lock.counts(), (2, 2));
+    }
+
+    #[test]
+    fn allocator_is_send_and_sync() {
+        fn assert_send_and_sync<T: Send + Sync>() {}
+        assert_send_and_sync::<crate::Allocator<64>>();
+        assert_send_and_sync::<GenericAllocator<CountingLock, 64>>();
+    }
+
+    #[test]
+    fn spin_lock() {
+        let lock = SpinLock::new();
+        lock.lock();
+        assert!(!lock.try_lock());
+        unsafe { lock.unlock() };
+        assert!(lock.try_lock());
+        unsafe { lock.unlock() };
+    }
+
+    #[test]
+    fn phased_lock() {
+        let lock = unsafe { PhasedLock::disabled() };
+        assert!(!lock.is_enabled());
+        lock.lock();
+        assert!(lock.try_lock(), "a disabled lock is always available");
+        unsafe { lock.unlock() };
+        unsafe { lock.unlock() };
+
+        lock.enable();
+        assert!(lock.is_enabled());
+        lock.lock();
+        assert!(!lock.try_lock());
+        unsafe { lock.unlock() };
+        assert!(lock.try_lock());
+        unsafe { lock.unlock() };
+
+        unsafe { lock.disable() };
+        lock.lock();
+        assert!(lock.try_lock());
+        unsafe { lock.unlock() };
+        unsafe { lock.unlock() };
+    }
+
+    #[test]
+    fn switching_the_lock_keeps_the_allocations() {
+        static ALLOCATOR: GenericAllocator<PhasedLock, 256> =
+            GenericAllocator::with_lock(unsafe { PhasedLock::disabled() });
+        let layout = Layout::new::<[u32; 4]>();
+        let ptrs = [(); 3].map(|_| unsafe { ALLOCATOR.alloc(layout) });
+        unsafe { ptrs[1].write(42) };
+        let stats = ALLOCATOR.stats();
+
+        // the allocations are unaffected by enabling the lock
+        ALLOCATOR.enable_locking();
+        assert_eq!(ALLOCATOR.stats(), stats);
+        assert_eq!(unsafe { ptrs[1].read() }, 42);
+        unsafe { ALLOCATOR.dealloc(ptrs[0], layout) };
+        let ptr = unsafe { ALLOCATOR.alloc(layout) };
+        assert_eq!(ptr, ptrs[0]);
+        let threads = [(); 4].map(|_| {
+            std::thread::spawn(move || {
+                for _ in 0..100 {
+                    let ptr = unsafe { ALLOCATOR.alloc(layout) };
+                    assert!(!ptr.is_null());
+                    unsafe { ALLOCATOR.dealloc(ptr, layout) };
+                }
+            })
+        });
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(ALLOCATOR.stats().used_blocks, 3);
+
+        // ... as well as by disabling it again
+        unsafe { ALLOCATOR.disable_locking() };
+        unsafe { ALLOCATOR.dealloc(ptrs[1], layout) };
+        unsafe { ALLOCATOR.dealloc(ptrs[2], layout) };
+        assert_eq!(ALLOCATOR.stats().used_blocks, 1);
+        let ptr = unsafe { ALLOCATOR.alloc(Layout::new::<[u32; 8]>()) };
+        assert!(!ptr.is_null());
+        assert_eq!(ALLOCATOR.stats().used_blocks, 2);
+    }
+}
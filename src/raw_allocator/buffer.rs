@@ -6,32 +6,132 @@
 use super::entry::{Entry, State};
 
 use core::mem::{self, MaybeUninit};
+#[cfg(feature = "guard-block")]
+use core::ptr;
+
+/// The granularity of the block sizes and of the alignment of the buffer.
+///
+/// Every block size is a multiple of the granularity and the buffer is aligned
+/// to it, so that the memory of every block is aligned to the granularity as
+/// well. It is `4` by default and `8` with the `granularity-8` feature.
+pub const GRANULARITY: usize = if cfg!(feature = "granularity-8") {
+    8
+} else {
+    4
+};
 
 /// The size of a single block header.
-pub const HEADER_SIZE: usize = mem::size_of::<Entry>();
+///
+/// A header consists of an [`Entry`], which is padded to the [`GRANULARITY`],
+/// so that the memory behind the header keeps the alignment of the header.
+/// Only the first [`ENTRY_SIZE`] bytes of a header are meaningful.
+pub const HEADER_SIZE: usize = GRANULARITY;
+
+/// The size of the [`Entry`] at the start of every block header.
+const ENTRY_SIZE: usize = mem::size_of::<Entry>();
+
+/// The size of the free-list links stored in the memory of a free block.
+///
+/// Free blocks with less memory are too small to store the links.
+pub const LINKS_SIZE: usize = 2 * mem::size_of::<u32>();
 
 /// An offset into the [`Buffer`], that is validated and known to be safe.
 ///
 /// See [`EntryIter`] for details on the idea and necessity of this type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ValidatedOffset(usize);
+impl ValidatedOffset {
+    /// The offset of the first entry, which always exists.
+    pub const FIRST: Self = Self(0);
+
+    /// The offset of the free block behind a used prefix of `prefix` bytes.
+    ///
+    /// This is only valid for a buffer created via [`Buffer::with_prefix()`].
+    pub const fn behind_prefix(prefix: usize) -> Self {
+        Self(HEADER_SIZE + prefix)
+    }
+
+    /// Query the raw offset into the buffer.
+    pub const fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// One of the two free-list links stored in the memory of a free block.
+///
+/// See [`Buffer::link()`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    /// The link to the previous block in the free list.
+    Previous,
+    /// The link to the next block in the free list.
+    Next,
+}
+impl Link {
+    /// Query the offset of the link relative to the header of the block.
+    const fn position(self) -> usize {
+        match self {
+            Self::Previous => HEADER_SIZE,
+            Self::Next => HEADER_SIZE + mem::size_of::<u32>(),
+        }
+    }
+}
+
+/// The value of the guard block behind the heap.
+///
+/// This is the header of a used block without any memory, i.e. a block, which
+/// is never handed out. An overrun past the end of the heap overwrites it.
+#[cfg(feature = "guard-block")]
+const GUARD: [u8; HEADER_SIZE] = {
+    let entry = Entry::used(0).as_raw();
+    let mut guard = [0; HEADER_SIZE];
+    let mut i = 0;
+    while i < ENTRY_SIZE {
+        guard[i] = entry[i];
+        i += 1;
+    }
+    guard
+};
+
+/// Assert, that the buffer size `n` is a multiple of the [`GRANULARITY`].
+///
+/// The message of a panic in a constant function has to be a literal, so there
+/// is one assertion per granularity.
+pub const fn assert_granular_size(n: usize) {
+    #[cfg(not(feature = "granularity-8"))]
+    assert!(n % GRANULARITY == 0, "memory size has to be divisible by 4");
+    #[cfg(feature = "granularity-8")]
+    assert!(n % GRANULARITY == 0, "memory size has to be divisible by 8");
+}
 
 /// The buffer memory backing the heap.
-#[repr(align(4))]
-pub struct Buffer<const N: usize>([MaybeUninit<u8>; N]);
+///
+/// With the `guard-block` feature, the memory is followed by a guard block
+/// (see [`is_guard_intact()`](Self::is_guard_intact)). The buffer is aligned to
+/// the [`GRANULARITY`].
+#[cfg_attr(not(feature = "granularity-8"), repr(C, align(4)))]
+#[cfg_attr(feature = "granularity-8", repr(C, align(8)))]
+pub struct Buffer<const N: usize> {
+    /// The memory of the heap.
+    memory: [MaybeUninit<u8>; N],
+    /// The guard block directly behind the memory.
+    #[cfg(feature = "guard-block")]
+    guard: [u8; HEADER_SIZE],
+}
 impl<const N: usize> Buffer<N> {
     /// Create a new buffer.
     ///
     /// This buffer will be uninitialized except for the first few bytes, which
     /// contain the first header. This header is a free [`Entry`] with the size
-    /// of the remaining buffer.
+    /// of the remaining buffer. If that block is large enough, its free-list
+    /// links are written as well (both are empty).
     ///
     /// # Panics
-    /// This function panics if the buffer is less than 4 bytes in size, i.e. if
-    /// `N < 4`.
+    /// This function panics if the buffer is too small for a header, i.e. if
+    /// `N < HEADER_SIZE`, or if it is not a multiple of the [`GRANULARITY`].
     pub const fn new() -> Self {
-        assert!(N >= HEADER_SIZE, "buffer too small, use N >= 4");
-        assert!(N % HEADER_SIZE == 0, "memory size has to be divisible by 4");
+        assert!(N >= HEADER_SIZE, "buffer too small for a header");
+        assert_granular_size(N);
         let remaining_size = N - HEADER_SIZE;
         let initial_entry = Entry::free(remaining_size).as_raw();
 
@@ -41,7 +141,169 @@ impl<const N: usize> Buffer<N> {
         buffer[1] = MaybeUninit::new(initial_entry[1]);
         buffer[2] = MaybeUninit::new(initial_entry[2]);
         buffer[3] = MaybeUninit::new(initial_entry[3]);
-        Self(buffer)
+        if remaining_size >= LINKS_SIZE {
+            let mut i = HEADER_SIZE;
+            while i < HEADER_SIZE + LINKS_SIZE {
+                buffer[i] = MaybeUninit::new(0);
+                i += 1;
+            }
+        }
+        Self {
+            memory: buffer,
+            #[cfg(feature = "guard-block")]
+            guard: GUARD,
+        }
+    }
+
+    /// Create a new buffer, whose first block of `prefix` bytes is used.
+    ///
+    /// The used block is followed by a free block with the remaining memory,
+    /// whose free-list links are written, if it is large enough. The memory of
+    /// the used block stays uninitialized.
+    ///
+    /// # Panics
+    /// This function panics if `prefix` is not a multiple of the [`GRANULARITY`]
+    /// or if there is no room for the header of the free block behind the
+    /// prefix.
+    pub const fn with_prefix(prefix: usize) -> Self {
+        assert_granular_size(N);
+        assert!(
+            prefix % GRANULARITY == 0,
+            "prefix has to be a multiple of the granularity"
+        );
+        assert!(
+            N >= 2 * HEADER_SIZE && prefix <= N - 2 * HEADER_SIZE,
+            "prefix too large for the buffer"
+        );
+        let used = Entry::used(prefix).as_raw();
+        let free_offset = ValidatedOffset::behind_prefix(prefix).0;
+        let free_size = N - free_offset - HEADER_SIZE;
+        let free = Entry::free(free_size).as_raw();
+
+        let mut buffer = [MaybeUninit::uninit(); N];
+        let mut i = 0;
+        while i < ENTRY_SIZE {
+            buffer[i] = MaybeUninit::new(used[i]);
+            buffer[free_offset + i] = MaybeUninit::new(free[i]);
+            i += 1;
+        }
+        if free_size >= LINKS_SIZE {
+            let mut i = free_offset + HEADER_SIZE;
+            while i < free_offset + HEADER_SIZE + LINKS_SIZE {
+                buffer[i] = MaybeUninit::new(0);
+                i += 1;
+            }
+        }
+        Self {
+            memory: buffer,
+            #[cfg(feature = "guard-block")]
+            guard: GUARD,
+        }
+    }
+
+    /// Initialize a buffer in place.
+    ///
+    /// This is equivalent to writing [`Buffer::new()`] to `this`, but only the
+    /// first header (and its free-list links) is written, as well as the guard
+    /// block (if any). The remaining memory stays uninitialized, so that no
+    /// temporary buffer is created on the stack.
+    ///
+    /// # Safety
+    /// The pointer `this` must be valid for writes and properly aligned.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as [`Buffer::new()`].
+    pub unsafe fn init_at(this: *mut Self) {
+        assert!(N >= HEADER_SIZE, "buffer too small for a header");
+        assert_granular_size(N);
+        let initial_entry = Entry::free(N - HEADER_SIZE).as_raw();
+        // SAFETY: the buffer is valid for writes and properly aligned (safety
+        // contract of this function), so writing the first entry is fine.
+        unsafe { this.cast::<[u8; ENTRY_SIZE]>().write(initial_entry) };
+        if N - HEADER_SIZE >= LINKS_SIZE {
+            // SAFETY: the buffer is valid for writes (safety contract of this
+            // function) and the links are inside of the buffer, as the first
+            // block is large enough to hold them.
+            unsafe {
+                this.cast::<u8>()
+                    .add(HEADER_SIZE)
+                    .write_bytes(0, LINKS_SIZE);
+            };
+        }
+        // SAFETY: the buffer is valid for writes and properly aligned (safety
+        // contract of this function), so is its guard block.
+        #[cfg(feature = "guard-block")]
+        unsafe {
+            ptr::addr_of_mut!((*this).guard).write(GUARD);
+        }
+    }
+
+    /// Reset the buffer to its initial state, filling the first `len` bytes.
+    ///
+    /// Afterwards, the buffer consists of a single free [`Entry`] again, just
+    /// like a freshly created one. Only the first `len` bytes are overwritten
+    /// with `byte` (before writing the first header and its empty free-list
+    /// links), the remaining memory is not touched. The caller has to ensure,
+    /// that `len <= N`.
+    pub fn reset(&mut self, len: usize, byte: u8) {
+        self.memory[..len].fill(MaybeUninit::new(byte));
+        self.at_mut(0).write(Entry::free(N - HEADER_SIZE));
+        if N - HEADER_SIZE >= LINKS_SIZE {
+            self.set_link(ValidatedOffset::FIRST, Link::Previous, None);
+            self.set_link(ValidatedOffset::FIRST, Link::Next, None);
+        }
+    }
+
+    /// Replace the blocks of the buffer with the given `(state, size)` pairs.
+    ///
+    /// The headers are written one after another starting at offset `0`, the
+    /// memory of the blocks is not touched (so the free-list links have to be
+    /// written afterwards). The caller has to ensure, that the sizes are
+    /// multiples of 4 and that the blocks fill the buffer exactly.
+    #[cfg(any(test, debug_assertions))]
+    pub fn set_layout(&mut self, blocks: &[(State, usize)]) {
+        let mut offset = 0;
+        for &(state, size) in blocks {
+            self.at_mut(offset).write(Entry::new(state, size));
+            offset += HEADER_SIZE + size;
+        }
+        debug_assert_eq!(offset, N);
+    }
+
+    /// Obtain a pointer to the start of the buffer.
+    pub const fn as_ptr(&self) -> *const u8 {
+        self.memory.as_ptr().cast()
+    }
+
+    /// Check, whether the guard block behind the memory is still intact.
+    ///
+    /// The guard block is never handed out, so its header is only changed by
+    /// writing past the end of the heap, e.g. by overrunning the last block.
+    #[cfg(feature = "guard-block")]
+    pub fn is_guard_intact(&self) -> bool {
+        self.guard == GUARD
+    }
+
+    /// Validate the offset of a header, which was not found by walking the
+    /// entries (e.g. an offset supplied by the user).
+    ///
+    /// The offset has to be a multiple of 4 and the block described by the
+    /// header at that offset has to end inside of the buffer. Whether there is
+    /// a header at that offset at all, cannot be checked without walking the
+    /// entries, so the result is only as trustworthy as the offset itself.
+    ///
+    /// # Safety
+    /// If the offset is inside of the buffer, the 4 bytes at that offset have
+    /// to be initialized (which is the case, if they are a header).
+    pub unsafe fn header_at(&self, offset: usize) -> Option<ValidatedOffset> {
+        if offset % HEADER_SIZE != 0 || offset >= N {
+            return None;
+        }
+        // SAFETY: the offset is aligned and the header is inside of the buffer,
+        // as the offset is less than `N`, which is a multiple of 4 (checked
+        // above). The caller guarantees, that the header is initialized.
+        let entry = unsafe { *self.at(offset).assume_init_ref() };
+        (entry.size() <= N - offset - HEADER_SIZE).then(|| ValidatedOffset(offset))
     }
 
     /// Obtain a reference to an [`Entry`] inside of the buffer.
@@ -62,7 +324,7 @@ impl<const N: usize> Buffer<N> {
     /// plus the 4 bytes after it would read past the end of the buffer.
     fn at(&self, offset: usize) -> &MaybeUninit<Entry> {
         assert!(offset % mem::align_of::<Entry>() == 0);
-        assert!(offset + HEADER_SIZE <= self.0.len());
+        assert!(offset + HEADER_SIZE <= self.memory.len());
 
         // SAFETY: this operation is unsafe for multiple reasons: the alignment
         // has to be satisfied and the entry read must be in bound of the buffer
@@ -75,7 +337,7 @@ impl<const N: usize> Buffer<N> {
         //    offset is a multiple of the alignment of `Entry`. This makes sure,
         //    that we are aligned within the buffer. Another important aspect is
         //    that the buffer itself is aligned. This is achieved using a
-        //    `#[repr(align(4))]`-attribute on the buffer itself. Therefore the
+        //    `#[repr(align)]`-attribute on the buffer itself. Therefore the
         //    alignment safety requirement is fulfilled as well.
         //
         // Note, that the memory, that is pointed to, might not contain a valid
@@ -83,7 +345,7 @@ impl<const N: usize> Buffer<N> {
         // version of an `Entry`. Therefore the caller has to ensure, that the
         // thing written or read is valid.
         unsafe {
-            let memory = &self.0[offset..offset + 4];
+            let memory = &self.memory[offset..offset + 4];
             let memory = memory.as_ptr();
             #[allow(clippy::cast_ptr_alignment)] // alignment is asserted above
             &*(memory
@@ -101,11 +363,11 @@ impl<const N: usize> Buffer<N> {
     /// plus the 4 bytes after it would read past the end of the buffer.
     fn at_mut(&mut self, offset: usize) -> &mut MaybeUninit<Entry> {
         assert!(offset % mem::align_of::<Entry>() == 0);
-        assert!(offset + HEADER_SIZE <= self.0.len());
+        assert!(offset + HEADER_SIZE <= self.memory.len());
 
         // SAFETY: same as `at()`
         unsafe {
-            let memory = &mut self.0[offset..offset + 4];
+            let memory = &mut self.memory[offset..offset + 4];
             let memory = memory.as_mut_ptr();
             #[allow(clippy::cast_ptr_alignment)] // alignment is asserted above
             &mut *(memory
@@ -115,10 +377,18 @@ impl<const N: usize> Buffer<N> {
     }
 
     /// Iterate over all entries and obtain the [`ValidatedOffset`]s.
-    pub const fn entries(&self) -> EntryIter<N> {
+    pub const fn entries(&self) -> EntryIter<'_, N> {
         EntryIter::new(self)
     }
 
+    /// Iterate over all entries starting at the entry at `offset`.
+    pub const fn entries_from(&self, offset: ValidatedOffset) -> EntryIter<'_, N> {
+        EntryIter {
+            buffer: self,
+            offset: offset.0,
+        }
+    }
+
     /// Request the memory of an entry at a [`ValidatedOffset`].
     ///
     /// This operation is safe, since the offset is validated. It returns the
@@ -127,7 +397,20 @@ impl<const N: usize> Buffer<N> {
         let size = self[offset].size();
 
         let offset = offset.0 + HEADER_SIZE;
-        &self.0[offset..offset + size]
+        &self.memory[offset..offset + size]
+    }
+
+    /// Check, whether `ptr` points into the memory of the entry at `offset`.
+    ///
+    /// The pointer may point anywhere into the memory of the block, not
+    /// necessarily to its start.
+    pub fn memory_contains(&self, offset: ValidatedOffset, ptr: *const u8) -> bool {
+        let memory = self.memory_of(offset);
+        let ptr = ptr.cast();
+        let start = memory.as_ptr();
+        let end = start.wrapping_add(memory.len());
+
+        start <= ptr && ptr < end
     }
 
     /// Request the mutable memory of an entry at a [`ValidatedOffset`].
@@ -138,7 +421,58 @@ impl<const N: usize> Buffer<N> {
         let size = self[offset].size();
 
         let offset = offset.0 + HEADER_SIZE;
-        &mut self.0[offset..offset + size]
+        &mut self.memory[offset..offset + size]
+    }
+
+    /// Read a free-list link stored in the memory of the free block at `offset`.
+    ///
+    /// The links are stored as `u32`s in native byte order in the first 8
+    /// bytes of the memory of the block: first the link to the previous block,
+    /// then the link to the next block. A link holds the offset of the memory
+    /// (not of the header) of the linked block, so that `0` marks a missing
+    /// link. A link, which cannot refer to a block large enough to store links
+    /// itself, can only be the result of a corruption and is treated as missing
+    /// as well, so that the offsets stay inside of the buffer.
+    ///
+    /// # Panics
+    /// This function panics, if the block is too small to store the links.
+    pub fn link(&self, offset: ValidatedOffset, link: Link) -> Option<ValidatedOffset> {
+        assert!(self[offset].size() >= LINKS_SIZE);
+        let start = offset.0 + link.position();
+        let mut raw = [0; 4];
+        for (byte, memory) in raw.iter_mut().zip(&self.memory[start..start + 4]) {
+            // SAFETY: the links of a free block are written, before the block
+            // is linked into the free list, so only initialized memory is read.
+            *byte = unsafe { memory.assume_init() };
+        }
+        let memory = u32::from_ne_bytes(raw) as usize;
+        let header = memory.checked_sub(HEADER_SIZE)?;
+        (header % HEADER_SIZE == 0 && header + HEADER_SIZE + LINKS_SIZE <= N)
+            .then(|| ValidatedOffset(header))
+    }
+
+    /// Write a free-list link into the memory of the free block at `offset`.
+    ///
+    /// See [`link()`](Self::link) for the format of the links.
+    ///
+    /// # Panics
+    /// This function panics, if the block is too small to store the links.
+    pub fn set_link(
+        &mut self,
+        offset: ValidatedOffset,
+        link: Link,
+        target: Option<ValidatedOffset>,
+    ) {
+        assert!(self[offset].size() >= LINKS_SIZE);
+        let start = offset.0 + link.position();
+        #[allow(clippy::cast_possible_truncation)] // offsets are limited to 31 bits
+        let raw = target.map_or(0, |target| (target.0 + HEADER_SIZE) as u32);
+        for (memory, byte) in self.memory[start..start + 4]
+            .iter_mut()
+            .zip(raw.to_ne_bytes())
+        {
+            *memory = MaybeUninit::new(byte);
+        }
     }
 
     /// Query the following free entry, if there is such an entry.
@@ -147,16 +481,49 @@ impl<const N: usize> Buffer<N> {
     /// obtain the entry after it. If there is no entry after it (because the
     /// given one is the last in the buffer) or if the entry following it is a
     /// used one, then `None` is returned.
-    pub fn following_free_entry(&mut self, offset: ValidatedOffset) -> Option<Entry> {
-        let iter_starting_at_offset = EntryIter {
+    pub fn following_free_entry(&self, offset: ValidatedOffset) -> Option<Entry> {
+        self.following_entry(offset)
+            .map(|offset| self[offset])
+            .filter(|entry| entry.state() == State::Free)
+    }
+
+    /// Query the entry following the given one, if there is such an entry.
+    ///
+    /// This is useful to walk over the entries while modifying the buffer, as
+    /// the [`EntryIter`] borrows the buffer for the whole iteration.
+    pub fn following_entry(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        let mut iter_starting_at_offset = EntryIter {
             buffer: self,
             offset: offset.0,
         };
+        iter_starting_at_offset.nth(1)
+    }
 
-        iter_starting_at_offset
-            .map(|offset| self[offset])
-            .nth(1)
-            .filter(|entry| entry.state() == State::Free)
+    /// Find the first invalid header and quarantine the memory starting there.
+    ///
+    /// The headers are walked from the start of the buffer without relying on
+    /// the buffer invariant: a header is invalid, if its size is not a multiple
+    /// of 4 or if its block exceeds the buffer. As the following headers cannot
+    /// be found anymore, the memory from the invalid header up to the end of
+    /// the buffer is turned into a single used entry, so that it is never
+    /// handed out again. The offset of the invalid header is returned, or
+    /// `None`, if all headers are valid (the buffer is not modified then).
+    pub fn quarantine_invalid(&mut self) -> Option<usize> {
+        let mut offset = 0;
+        while offset < N {
+            // SAFETY: the header at `offset` was written, as it follows valid
+            // headers only (starting with the first header, which always
+            // exists). Every bit pattern is an `Entry`.
+            let size = unsafe { self.at(offset).assume_init_ref() }.size();
+            let end = offset + HEADER_SIZE + size;
+            if size % HEADER_SIZE != 0 || end > N {
+                self.at_mut(offset)
+                    .write(Entry::used(N - offset - HEADER_SIZE));
+                return Some(offset);
+            }
+            offset = end;
+        }
+        None
     }
 
     /// Mark the given `Entry` as used and try to split it up.
@@ -177,6 +544,81 @@ impl<const N: usize> Buffer<N> {
                 .write(Entry::free(remaining_size));
         }
     }
+
+    /// Mark the memory at the end of the given free `Entry` as used.
+    ///
+    /// This function places a used `Entry` with `size` bytes of memory inside
+    /// the free entry at the given offset, so that the used memory ends at the
+    /// offset `end`. The memory before the used entry (if any) stays free as
+    /// well as the memory after `end` (if any). Both of them get their own
+    /// headers. The offset of the used entry is returned.
+    ///
+    /// The caller has to ensure, that the used entry fits into the free entry
+    /// and that all sizes and offsets are multiples of 4.
+    pub fn mark_as_used_before(
+        &mut self,
+        offset: ValidatedOffset,
+        end: usize,
+        size: usize,
+    ) -> ValidatedOffset {
+        let block_end = offset.0 + HEADER_SIZE + self[offset].size();
+        let start = end - size - HEADER_SIZE;
+        debug_assert!(offset.0 <= start && end <= block_end);
+
+        if let Some(remaining_size) = (block_end - end).checked_sub(HEADER_SIZE) {
+            self.at_mut(end).write(Entry::free(remaining_size));
+        }
+        if start == offset.0 {
+            self[offset] = Entry::used(size);
+            return offset;
+        }
+        self[offset] = Entry::free(start - offset.0 - HEADER_SIZE);
+        self.at_mut(start).write(Entry::used(size));
+        ValidatedOffset(start)
+    }
+
+    /// Move all used entries to the start of the buffer.
+    ///
+    /// The used entries are moved towards the start of the buffer (keeping
+    /// their order), so that all the free memory is combined into a single free
+    /// entry at the end of the buffer. Every moved entry is reported to the
+    /// `relocate` callback, which gets the old and the new address of the
+    /// memory of the entry as well as its size.
+    ///
+    /// Note, that all entries are moved by a multiple of the [`GRANULARITY`],
+    /// so only an alignment up to the granularity is preserved.
+    #[cfg(feature = "relocating")]
+    pub fn compact(&mut self, mut relocate: impl FnMut(*mut u8, *mut u8, usize)) {
+        let mut free_start = 0;
+        let mut current = self.entries().next();
+        while let Some(offset) = current {
+            // the following entry has to be determined before moving, since the
+            // move might overwrite the current header. The following header is
+            // never overwritten, as entries are only moved towards the start.
+            current = self.following_entry(offset);
+            let entry = self[offset];
+            if entry.state() == State::Free {
+                continue;
+            }
+
+            let length = HEADER_SIZE + entry.size();
+            if offset.0 != free_start {
+                self.memory
+                    .copy_within(offset.0..offset.0 + length, free_start);
+                let base = self.memory.as_mut_ptr().cast::<u8>();
+                relocate(
+                    base.wrapping_add(offset.0 + HEADER_SIZE),
+                    base.wrapping_add(free_start + HEADER_SIZE),
+                    entry.size(),
+                );
+            }
+            free_start += length;
+        }
+        if free_start < N {
+            self.at_mut(free_start)
+                .write(Entry::free(N - free_start - HEADER_SIZE));
+        }
+    }
 }
 impl<const N: usize> core::ops::Index<ValidatedOffset> for Buffer<N> {
     type Output = Entry;
@@ -229,7 +671,8 @@ impl<'buffer, const N: usize> Iterator for EntryIter<'buffer, N> {
     type Item = ValidatedOffset;
 
     fn next(&mut self) -> Option<Self::Item> {
-        (self.offset + HEADER_SIZE < N).then(|| {
+        // note, that a zero-sized entry at the very end of the buffer is valid
+        (self.offset + HEADER_SIZE <= N).then(|| {
             let offset = self.offset;
             // SAFETY: the buffer invariant (valid entries) have to be upheld
             let entry = unsafe { self.buffer.at(offset).assume_init_ref() };
@@ -241,7 +684,8 @@ impl<'buffer, const N: usize> Iterator for EntryIter<'buffer, N> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Buffer, Entry, ValidatedOffset, HEADER_SIZE};
+    use super::{Buffer, Entry, ValidatedOffset, GRANULARITY, HEADER_SIZE};
+    use core::mem;
 
     #[test]
     fn validated_offset_debug() {
@@ -261,6 +705,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
     fn empty_allocator() {
         let buffer = Buffer::<32>::new();
         let expected = Entry::free(32 - 4);
@@ -270,9 +715,34 @@ mod tests {
 
     #[test]
     fn header_size() {
-        // the codebase assumes, that the header size is `4`, so make sure that
-        // assumption holds.
+        // the codebase assumes, that the header size is `4` (or `8` if the
+        // entries are padded to the granularity), so make sure that assumption
+        // holds.
+        #[cfg(not(feature = "granularity-8"))]
         assert_eq!(HEADER_SIZE, 4);
+        #[cfg(feature = "granularity-8")]
+        assert_eq!(HEADER_SIZE, 8);
+        assert_eq!(HEADER_SIZE, GRANULARITY);
+        assert_eq!(mem::align_of::<Buffer<32>>(), GRANULARITY);
+    }
+
+    #[test]
+    #[cfg(feature = "granularity-8")]
+    fn padded_headers() {
+        let mut buffer = Buffer::<32>::new();
+        let expected = Entry::free(32 - 8);
+        let actual = unsafe { buffer.at(0).assume_init() };
+        assert_eq!(expected, actual);
+
+        // the split keeps the following header aligned to 8 bytes
+        buffer.mark_as_used(ValidatedOffset(0), 8);
+        assert_eq!(unsafe { buffer.at(0).assume_init() }, Entry::used(8));
+        assert_eq!(unsafe { buffer.at(16).assume_init() }, Entry::free(8));
+        assert_eq!(
+            buffer.following_entry(ValidatedOffset(0)),
+            Some(ValidatedOffset(16))
+        );
+        assert_eq!(buffer.following_entry(ValidatedOffset(16)), None);
     }
 
     #[test]
@@ -284,6 +754,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
     #[should_panic(expected = "memory size has to be divisible by 4")]
     fn invalid_buffer_size() {
         // the buffer size is not really an issue here, but the code is easier
@@ -293,6 +764,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
     fn entry_iter() {
         let buffer = Buffer::<32>::new();
         let mut iter = buffer.entries();
@@ -308,6 +780,15 @@ mod tests {
         assert_eq!(iter.next(), Some(ValidatedOffset(8)));
         assert_eq!(iter.next(), Some(ValidatedOffset(16)));
         assert_eq!(iter.next(), None);
+
+        // a zero-sized entry at the end of the buffer must be yielded as well
+        let mut buffer = Buffer::<16>::new();
+        buffer.at_mut(0).write(Entry::used(8));
+        buffer.at_mut(12).write(Entry::free(0));
+        let mut iter = buffer.entries();
+        assert_eq!(iter.next(), Some(ValidatedOffset(0)));
+        assert_eq!(iter.next(), Some(ValidatedOffset(12)));
+        assert_eq!(iter.next(), None);
     }
 
     #[test]
@@ -349,6 +830,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
     fn following_free_entry() {
         let mut buffer = Buffer::<24>::new();
         buffer.at_mut(0).write(Entry::used(4));
@@ -367,13 +849,28 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn following_entry() {
+        let mut buffer = Buffer::<24>::new();
+        buffer.at_mut(0).write(Entry::used(4));
+        buffer.at_mut(8).write(Entry::free(12));
+
+        assert_eq!(
+            buffer.following_entry(ValidatedOffset(0)),
+            Some(ValidatedOffset(8))
+        );
+        assert_eq!(buffer.following_entry(ValidatedOffset(8)), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
     fn memory_of() {
         use core::ptr;
 
         let mut buffer = Buffer::<20>::new();
         buffer.at_mut(0).write(Entry::used(4));
 
-        let expected = &buffer.0[4..8];
+        let expected = &buffer.memory[4..8];
         let actual = buffer.memory_of(ValidatedOffset(0));
         assert_eq!(ptr::addr_of!(expected[0]), ptr::addr_of!(actual[0]));
     }
@@ -394,6 +891,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "granularity-8"))]
     fn mark_used_with_split() {
         let mut buffer = Buffer::<32>::new();
         buffer.at_mut(0).write(Entry::used(4));
@@ -0,0 +1,120 @@
+//! This module provides the [`Cache`] of recently freed blocks.
+//!
+//! Firmware often allocates and frees objects of the same size over and over
+//! again (e.g. message buffers). Remembering the blocks freed last allows to
+//! serve such allocations without scanning the whole heap. The same applies to
+//! allocations with a large alignment (e.g. DMA buffers): a recently freed block
+//! is reused, if the aligned allocation fits into it.
+use super::buffer::{Buffer, ValidatedOffset};
+use super::entry::{Entry, State};
+
+/// The number of recently freed blocks remembered by the [`Cache`].
+pub const SLOTS: usize = 4;
+
+/// A tiny cache of the offsets of recently freed blocks.
+///
+/// The cache stores [`ValidatedOffset`]s, which must stay valid as long as
+/// they are stored. The offsets stay valid, as long as the block is not merged
+/// into another one. Therefore every operation merging blocks has to call
+/// [`forget()`](Self::forget) for the memory of the resulting block.
+///
+/// The blocks referenced by the cache might be reused by ordinary allocations
+/// in the meantime. Therefore the state and size of the block is checked
+/// again, before a cached block is handed out.
+pub struct Cache {
+    /// The cached offsets of the blocks (if any).
+    slots: [Option<ValidatedOffset>; SLOTS],
+    /// The index of the slot, which is overwritten next.
+    next: usize,
+}
+impl Cache {
+    /// Create a new, empty cache.
+    pub const fn new() -> Self {
+        Self {
+            slots: [None; SLOTS],
+            next: 0,
+        }
+    }
+
+    /// Remember a just freed block.
+    ///
+    /// If the cache is full, the oldest entry is replaced.
+    pub fn insert(&mut self, offset: ValidatedOffset) {
+        if self.slots.contains(&Some(offset)) {
+            return;
+        }
+        self.slots[self.next] = Some(offset);
+        self.next = (self.next + 1) % SLOTS;
+    }
+
+    /// Find a cached free block with a payload size of exactly `size` bytes.
+    ///
+    /// The most recently freed matching block is returned, but it is kept in
+    /// the cache. If there is no such block, `None` is returned.
+    pub fn find<const N: usize>(&self, buffer: &Buffer<N>, size: usize) -> Option<ValidatedOffset> {
+        self.find_if(buffer, |_offset, entry| entry.size() == size)
+    }
+
+    /// Find a cached free block, which is accepted by `fits`.
+    ///
+    /// This is the same as [`take_if()`](Self::take_if), but the block is kept
+    /// in the cache.
+    pub fn find_if<const N: usize>(
+        &self,
+        buffer: &Buffer<N>,
+        fits: impl Fn(ValidatedOffset, Entry) -> bool,
+    ) -> Option<ValidatedOffset> {
+        self.position(buffer, fits)
+            .and_then(|index| self.slots[index])
+    }
+
+    /// Take a cached free block, which is accepted by `fits`.
+    ///
+    /// The most recently freed block, which is still free and for which `fits`
+    /// returns `true`, is removed from the cache and returned. If there is no
+    /// such block, `None` is returned.
+    pub fn take_if<const N: usize>(
+        &mut self,
+        buffer: &Buffer<N>,
+        fits: impl Fn(ValidatedOffset, Entry) -> bool,
+    ) -> Option<ValidatedOffset> {
+        let index = self.position(buffer, fits)?;
+        self.slots[index].take()
+    }
+
+    /// Determine the slot of the most recently freed block accepted by `fits`.
+    fn position<const N: usize>(
+        &self,
+        buffer: &Buffer<N>,
+        fits: impl Fn(ValidatedOffset, Entry) -> bool,
+    ) -> Option<usize> {
+        (1..=SLOTS)
+            .map(|age| (self.next + SLOTS - age) % SLOTS)
+            .find(|&index| {
+                self.slots[index].map_or(false, |offset| {
+                    let entry = buffer[offset];
+                    entry.state() == State::Free && fits(offset, entry)
+                })
+            })
+    }
+
+    /// Forget all cached blocks.
+    ///
+    /// This has to be called, whenever the blocks in the heap are rearranged.
+    pub fn clear(&mut self) {
+        self.slots = [None; SLOTS];
+    }
+
+    /// Forget all cached blocks with an offset in the range `start..end`.
+    ///
+    /// This has to be called, whenever the memory in that range is merged into
+    /// a single block, since the headers of the cached blocks are no longer
+    /// valid afterwards.
+    pub fn forget(&mut self, start: usize, end: usize) {
+        for slot in &mut self.slots {
+            if slot.map_or(false, |offset| (start..end).contains(&offset.get())) {
+                *slot = None;
+            }
+        }
+    }
+}
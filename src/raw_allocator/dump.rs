@@ -0,0 +1,223 @@
+//! This module provides the [`parse_dump()`] for analyzing heap snapshots.
+//!
+//! Post-mortem tooling often only has a copy of the RAM of a device. The heap
+//! is a plain sequence of blocks, each starting with a header, so the
+//! block list can be reconstructed from a copy of the heap memory alone. The
+//! headers are decoded with the same code as used by the allocator itself.
+extern crate alloc;
+
+use super::buffer::{GRANULARITY, HEADER_SIZE};
+use super::entry::Entry;
+use crate::BlockInfo;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// An error detected when parsing a dump of the heap memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseError {
+    /// The length of the dump is zero or not a multiple of the granularity.
+    InvalidLength,
+    /// The size in the header at the given offset is not a multiple of the
+    /// granularity.
+    InvalidSize {
+        /// The offset of the invalid header from the start of the dump.
+        offset: usize,
+    },
+    /// The block with the header at the given offset exceeds the dump.
+    BlockOutOfBounds {
+        /// The offset of the invalid header from the start of the dump.
+        offset: usize,
+    },
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(
+                f,
+                "dump length is not a positive multiple of {}",
+                GRANULARITY
+            ),
+            Self::InvalidSize { offset } => write!(
+                f,
+                "block size at offset {} is not a multiple of {}",
+                offset, GRANULARITY
+            ),
+            Self::BlockOutOfBounds { offset } => {
+                write!(f, "block at offset {} exceeds the dump", offset)
+            }
+        }
+    }
+}
+
+/// Reconstruct the blocks of a heap from a copy of its memory.
+///
+/// The `dump` has to be a copy of the whole heap memory, i.e. of the `N` bytes
+/// starting at the address returned by
+/// [`Allocator::memory_region()`](crate::Allocator::memory_region). The heap is
+/// a sequence of blocks without any gaps, where every block consists of a
+/// header of 4 bytes followed by the memory of the block. The header is a
+/// `u32` in the native byte order of the device (so the dump has to be parsed
+/// on a host with the same byte order): bits 1 to 31 contain the size of the
+/// memory after the header, which is always a multiple of 4, bit 0 is set for
+/// an occupied (used or reserved) block and cleared for a free block, and bit 1
+/// (the lowest bit of the size) is set in addition for a reserved block. The
+/// first header is at offset 0 and every following header directly follows the
+/// memory of the previous block, up to the end of the heap. With the
+/// `granularity-8` feature, every header is padded to 8 bytes (the `u32` is
+/// followed by 4 bytes of padding) and the sizes are multiples of 8.
+///
+/// The blocks are returned in address order with the same metadata as reported
+/// by [`Allocator::block_of()`](crate::Allocator::block_of). If the dump does
+/// not follow the format, an error describing the first violation is returned.
+/// This function is only available with the `dump` feature.
+///
+/// # Example
+/// ```
+/// use emballoc::{parse_dump, Allocator, State};
+///
+/// // the `u32` of a header is padded to the header size, if necessary
+/// const HEADER_SIZE: usize = Allocator::<64>::GRANULARITY;
+///
+/// // a used block of 8 bytes followed by a free block of 8 bytes
+/// let mut dump = Vec::new();
+/// dump.extend_from_slice(&(8_u32 << 1 | 1).to_ne_bytes());
+/// dump.resize(HEADER_SIZE + 8, 0);
+/// dump.extend_from_slice(&(8_u32 << 1).to_ne_bytes());
+/// dump.resize(2 * HEADER_SIZE + 16, 0);
+///
+/// let blocks = parse_dump(&dump).unwrap();
+/// assert_eq!(blocks.len(), 2);
+/// assert_eq!((blocks[0].offset, blocks[0].size, blocks[0].state), (0, 8, State::Used));
+/// assert_eq!(
+///     (blocks[1].offset, blocks[1].size, blocks[1].state),
+///     (HEADER_SIZE + 8, 8, State::Free)
+/// );
+/// ```
+///
+/// # Errors
+/// An error is returned, if the length of the dump is invalid, if a header
+/// contains an invalid size or if a block does not end inside of the dump.
+pub fn parse_dump(dump: &[u8]) -> Result<Vec<BlockInfo>, ParseError> {
+    if dump.is_empty() || dump.len() % GRANULARITY != 0 {
+        return Err(ParseError::InvalidLength);
+    }
+
+    let mut blocks = Vec::new();
+    let mut offset = 0;
+    while offset < dump.len() {
+        let mut raw = [0; 4];
+        raw.copy_from_slice(&dump[offset..offset + 4]);
+        let entry = Entry::from_raw(raw);
+        let size = entry.size();
+        if size % GRANULARITY != 0 {
+            return Err(ParseError::InvalidSize { offset });
+        }
+        let end = offset + HEADER_SIZE + size;
+        if end > dump.len() {
+            return Err(ParseError::BlockOutOfBounds { offset });
+        }
+        blocks.push(BlockInfo {
+            offset,
+            size,
+            state: entry.state(),
+        });
+        offset = end;
+    }
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_dump, ParseError};
+    #[cfg(not(feature = "granularity-8"))]
+    use crate::Allocator;
+    use crate::State;
+    #[cfg(not(feature = "granularity-8"))]
+    use core::alloc::{GlobalAlloc, Layout};
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn roundtrip_of_a_live_allocator() {
+        let allocator = Allocator::<128>::new();
+        let ptrs = [8, 16, 4, 12].map(|size| {
+            let layout = Layout::from_size_align(size, 4).unwrap();
+            let ptr = unsafe { allocator.alloc(layout) };
+            unsafe { ptr.write_bytes(0xAA, size) };
+            (ptr, layout)
+        });
+        unsafe { allocator.dealloc(ptrs[1].0, ptrs[1].1) };
+        // the free memory is uninitialized otherwise
+        allocator.prefault();
+
+        let (start, size) = allocator.memory_region();
+        let dump = unsafe { core::slice::from_raw_parts(start, size) }.to_vec();
+        let blocks = parse_dump(&dump).unwrap();
+        let summary: Vec<_> = blocks
+            .iter()
+            .map(|block| (block.offset, block.size, block.state))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, 8, State::Used),
+                (12, 16, State::Free),
+                (32, 4, State::Used),
+                (40, 12, State::Used),
+                (56, 68, State::Free),
+            ]
+        );
+        for (ptr, _) in ptrs {
+            if let Some(info) = allocator.block_of(ptr) {
+                assert!(blocks.contains(&info));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn invalid_dumps() {
+        assert_eq!(parse_dump(&[]), Err(ParseError::InvalidLength));
+        assert_eq!(parse_dump(&[0; 6]), Err(ParseError::InvalidLength));
+
+        let header = |raw: u32| raw.to_ne_bytes();
+        let mut dump = header(0).to_vec();
+        dump.extend_from_slice(&header(2 << 1));
+        assert_eq!(
+            parse_dump(&dump),
+            Err(ParseError::InvalidSize { offset: 4 })
+        );
+
+        let mut dump = header(8 << 1 | 1).to_vec();
+        dump.extend_from_slice(&[0; 4]);
+        assert_eq!(
+            parse_dump(&dump),
+            Err(ParseError::BlockOutOfBounds { offset: 0 })
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "granularity-8")]
+    fn padded_headers() {
+        // every header is padded to 8 bytes, the padding is ignored
+        let header = |raw: u32| raw.to_ne_bytes();
+        let mut dump = header(8 << 1 | 1).to_vec();
+        dump.extend_from_slice(&[0xAA; 4 + 8]);
+        dump.extend_from_slice(&header(16 << 1));
+        dump.extend_from_slice(&[0xAA; 4 + 16]);
+        let summary: Vec<_> = parse_dump(&dump)
+            .unwrap()
+            .iter()
+            .map(|block| (block.offset, block.size, block.state))
+            .collect();
+        assert_eq!(summary, [(0, 8, State::Used), (16, 16, State::Free)]);
+
+        assert_eq!(parse_dump(&[0; 12]), Err(ParseError::InvalidLength));
+        let mut dump = header(4 << 1 | 1).to_vec();
+        dump.extend_from_slice(&[0; 4 + 8]);
+        assert_eq!(
+            parse_dump(&dump),
+            Err(ParseError::InvalidSize { offset: 0 })
+        );
+    }
+}
@@ -1,33 +1,44 @@
 //! This module exposes the ubiquitous [`Entry`] type and its helper [`State`].
+use super::buffer::GRANULARITY;
 #[cfg(test)]
 use core::fmt::{self, Debug, Formatter};
 
-/// Helper for showing, whether a block is used or freed.
+/// Helper for showing, whether a block is used, reserved or freed.
 ///
 /// This primarily exists to be able to match on the block state and to work
 /// ergonomically with it.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum State {
-    /// The entry is marked as "used".
-    ///
-    /// The memory after the header is assumed to contain used data and must not
-    /// be re-used for other allocations.
-    Free,
     /// The entry is marked as "free".
     ///
     /// The memory after the header is assumed to be free and thus can be used
     /// for a new allocation.
+    Free,
+    /// The entry is marked as "used".
+    ///
+    /// The memory after the header is assumed to contain used data and must not
+    /// be re-used for other allocations.
     Used,
+    /// The entry is marked as "reserved".
+    ///
+    /// The block is occupied like a used block, but it is pending: it was
+    /// reserved via [`Allocator::reserve_block()`] and is neither committed nor
+    /// aborted yet.
+    ///
+    /// [`Allocator::reserve_block()`]: crate::Allocator::reserve_block
+    Reserved,
 }
 
 /// An (un)allocated block.
 ///
-/// Such a block is either used or free and contains a block size. It is a
-/// compact version of the following `enum`:
+/// Such a block is either used, reserved or free and contains a block size. It
+/// is a compact version of the following `enum`:
 /// ```
 /// # use core::mem;
 /// enum NaiveEntry {
 ///     Used(u32),
+///     Reserved(u32),
 ///     Free(u32),
 /// }
 ///
@@ -38,10 +49,29 @@ pub enum State {
 /// It is used as a header for blocks of allocated or unallocated memory inside
 /// the heap buffer. Entries are written directly into the buffer, therefore
 /// their layout is important.
+///
+/// The size is stored in the upper 31 bits. Bit 0 marks occupied blocks, i.e.
+/// used or reserved ones, and bit 1 marks reserved blocks in addition. Bit 1 is
+/// the lowest bit of the size, which is free to use, as every block size is a
+/// multiple of the [`GRANULARITY`] and thus even.
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Entry(u32);
 impl Entry {
+    /// The largest size of a block, which can be stored in an [`Entry`].
+    ///
+    /// The size is stored in the upper 31 bits of the header and every block
+    /// size is a multiple of the [`GRANULARITY`], so this is the largest such
+    /// multiple fitting into 31 bits.
+    pub const MAX_SIZE: usize = 0x8000_0000 - GRANULARITY;
+
+    /// The bit of the header, which is set for used and reserved blocks.
+    const OCCUPIED: u32 = 0b01;
+    /// The bit of the header, which is set for reserved blocks in addition.
+    ///
+    /// This is the lowest bit of the size, which is always zero otherwise.
+    const RESERVED: u32 = 0b10;
+
     /// Create a new free [`Entry`] with the given size.
     ///
     /// Note, that the `size` is the number of bytes of the payload, i.e. the
@@ -59,17 +89,42 @@ impl Entry {
     /// size after the `Entry` itself. This is the same value as returned by
     /// [`size()`](Entry::size()).
     pub const fn used(size: usize) -> Self {
-        assert!(size <= 0x7FFF_FFFF);
+        assert!(size <= 0x7FFF_FFFF && size % 2 == 0);
         #[allow(clippy::cast_possible_truncation)] // asserted above
-        Self((size << 1 | 1) as _)
+        Self((size << 1) as u32 | Self::OCCUPIED)
+    }
+
+    /// Create a new reserved [`Entry`] with the given size.
+    ///
+    /// Note, that the `size` is the number of bytes of the payload, i.e. the
+    /// size after the `Entry` itself. This is the same value as returned by
+    /// [`size()`](Entry::size()).
+    pub const fn reserved(size: usize) -> Self {
+        assert!(size <= 0x7FFF_FFFF && size % 2 == 0);
+        #[allow(clippy::cast_possible_truncation)] // asserted above
+        Self((size << 1) as u32 | Self::OCCUPIED | Self::RESERVED)
+    }
+
+    /// Create a new [`Entry`] with the given state and size.
+    ///
+    /// See [`free()`](Self::free), [`used()`](Self::used) and
+    /// [`reserved()`](Self::reserved) for details.
+    pub const fn new(state: State, size: usize) -> Self {
+        match state {
+            State::Free => Self::free(size),
+            State::Used => Self::used(size),
+            State::Reserved => Self::reserved(size),
+        }
     }
 
     /// Query the allocation state of this block.
     pub const fn state(self) -> State {
-        if self.0 & 1 == 0 {
+        if self.0 & Self::OCCUPIED == 0 {
             State::Free
-        } else {
+        } else if self.0 & Self::RESERVED == 0 {
             State::Used
+        } else {
+            State::Reserved
         }
     }
 
@@ -78,7 +133,12 @@ impl Entry {
     /// This is the size of the usable memory, i.e. the header size is not
     /// included.
     pub const fn size(self) -> usize {
-        let size = self.0 >> 1;
+        let flags = if self.0 & Self::OCCUPIED == 0 {
+            0
+        } else {
+            Self::OCCUPIED | Self::RESERVED
+        };
+        let size = (self.0 & !flags) >> 1;
         size as _
     }
 
@@ -86,6 +146,14 @@ impl Entry {
     pub const fn as_raw(self) -> [u8; 4] {
         self.0.to_ne_bytes()
     }
+
+    /// Create an entry from its raw bytes in native endian order.
+    ///
+    /// This is the counterpart of [`as_raw()`](Self::as_raw).
+    #[cfg(feature = "dump")]
+    pub const fn from_raw(raw: [u8; 4]) -> Self {
+        Self(u32::from_ne_bytes(raw))
+    }
 }
 #[cfg(test)]
 impl Debug for Entry {
@@ -104,57 +172,93 @@ mod tests {
     #[test]
     fn equality() {
         assert_eq!(Entry::used(4), Entry::used(4));
-        assert_ne!(Entry::used(4), Entry::used(5));
+        assert_ne!(Entry::used(4), Entry::used(8));
 
         assert_eq!(Entry::free(4), Entry::free(4));
-        assert_ne!(Entry::free(4), Entry::free(5));
+        assert_ne!(Entry::free(4), Entry::free(8));
 
         assert_ne!(Entry::used(4), Entry::free(4));
-        assert_ne!(Entry::used(4), Entry::free(5));
+        assert_ne!(Entry::used(4), Entry::free(8));
 
         // now same with cloning
         assert_eq!(Entry::used(4).clone(), Entry::used(4));
-        assert_ne!(Entry::used(4).clone(), Entry::used(5));
+        assert_ne!(Entry::used(4).clone(), Entry::used(8));
 
         assert_eq!(Entry::free(4).clone(), Entry::free(4));
-        assert_ne!(Entry::free(4).clone(), Entry::free(5));
+        assert_ne!(Entry::free(4).clone(), Entry::free(8));
 
         assert_ne!(Entry::used(4).clone(), Entry::free(4));
-        assert_ne!(Entry::used(4).clone(), Entry::free(5));
+        assert_ne!(Entry::used(4).clone(), Entry::free(8));
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)] // grouped as `<size>_<state>`
     fn entry_bitpacking_state() {
-        assert_eq!(Entry::free(5).state(), State::Free);
-        assert_eq!(Entry::used(5).state(), State::Used);
+        assert_eq!(Entry::free(8).state(), State::Free);
+        assert_eq!(Entry::used(8).state(), State::Used);
+        assert_eq!(Entry::reserved(8).state(), State::Reserved);
 
-        assert_eq!(Entry(0b00_0).state(), State::Free);
-        assert_eq!(Entry(0b00_1).state(), State::Used);
+        assert_eq!(Entry(0b000_0).state(), State::Free);
+        assert_eq!(Entry(0b000_1).state(), State::Used);
+        assert_eq!(Entry(0b001_1).state(), State::Reserved);
 
-        assert_eq!(Entry(0b10_0).state(), State::Free);
-        assert_eq!(Entry(0b10_1).state(), State::Used);
-        assert_eq!(Entry(0b11_1).state(), State::Used);
-        assert_eq!(Entry(0b11_0).state(), State::Free);
+        assert_eq!(Entry(0b100_0).state(), State::Free);
+        assert_eq!(Entry(0b100_1).state(), State::Used);
+        assert_eq!(Entry(0b101_1).state(), State::Reserved);
+        assert_eq!(Entry(0b111_1).state(), State::Reserved);
+        assert_eq!(Entry(0b110_0).state(), State::Free);
 
         // now the same with cloning
-        assert_eq!(Entry::free(5).state().clone(), State::Free);
-        assert_eq!(Entry::used(5).state().clone(), State::Used);
+        assert_eq!(Entry::free(8).state().clone(), State::Free);
+        assert_eq!(Entry::used(8).state().clone(), State::Used);
+        assert_eq!(Entry::reserved(8).state().clone(), State::Reserved);
 
-        assert_eq!(Entry(0b00_0).state().clone(), State::Free);
-        assert_eq!(Entry(0b00_1).state().clone(), State::Used);
+        assert_eq!(Entry(0b000_0).state().clone(), State::Free);
+        assert_eq!(Entry(0b000_1).state().clone(), State::Used);
+        assert_eq!(Entry(0b001_1).state().clone(), State::Reserved);
 
-        assert_eq!(Entry(0b10_0).state().clone(), State::Free);
-        assert_eq!(Entry(0b10_1).state().clone(), State::Used);
-        assert_eq!(Entry(0b11_1).state().clone(), State::Used);
-        assert_eq!(Entry(0b11_0).state().clone(), State::Free);
+        assert_eq!(Entry(0b100_0).state().clone(), State::Free);
+        assert_eq!(Entry(0b100_1).state().clone(), State::Used);
+        assert_eq!(Entry(0b101_1).state().clone(), State::Reserved);
+        assert_eq!(Entry(0b111_1).state().clone(), State::Reserved);
+        assert_eq!(Entry(0b110_0).state().clone(), State::Free);
     }
 
     #[test]
+    #[allow(clippy::unusual_byte_groupings)] // grouped as `<size>_<state>`
     fn entry_bitpacking_size() {
-        assert_eq!(Entry(0b1_1).size(), 1);
         assert_eq!(Entry(0b1_0).size(), 1);
+        assert_eq!(Entry(0b1_1).size(), 0);
+        assert_eq!(Entry(0b10_1).size(), 2);
+        assert_eq!(Entry(0b11_1).size(), 2);
         assert_eq!(Entry(123 << 1).size(), 123);
-        assert_eq!(Entry(123 << 1 | 1).size(), 123);
+        assert_eq!(Entry(124 << 1 | 1).size(), 124);
+        assert_eq!(Entry(124 << 1 | 0b11).size(), 124);
+    }
+
+    #[test]
+    fn reserved_entries() {
+        assert_ne!(Entry::reserved(8), Entry::used(8));
+        assert_ne!(Entry::reserved(8), Entry::free(8));
+        assert_eq!(Entry::reserved(8).size(), 8);
+        assert_eq!(Entry::reserved(Entry::MAX_SIZE).size(), Entry::MAX_SIZE);
+        for state in [State::Free, State::Used, State::Reserved] {
+            assert_eq!(Entry::new(state, 12).state(), state);
+            assert_eq!(Entry::new(state, 12).size(), 12);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "dump")]
+    fn raw_roundtrip() {
+        for entry in [
+            Entry::free(0),
+            Entry::used(8),
+            Entry::reserved(4),
+            Entry::free(1 << 20),
+        ] {
+            assert_eq!(Entry::from_raw(entry.as_raw()), entry);
+        }
     }
 
     #[test]
@@ -172,6 +276,13 @@ mod tests {
         Entry::used((1 << 31) - 4);
     }
 
+    #[test]
+    fn maximum_size() {
+        assert_eq!(Entry::free(Entry::MAX_SIZE).size(), Entry::MAX_SIZE);
+        assert_eq!(Entry::used(Entry::MAX_SIZE).size(), Entry::MAX_SIZE);
+        assert_eq!(Entry::used(Entry::MAX_SIZE).state(), State::Used);
+    }
+
     #[test]
     #[should_panic]
     fn huge_free_block() {
@@ -184,15 +295,25 @@ mod tests {
         Entry::used(1 << 31); // panic here
     }
 
+    #[test]
+    #[should_panic]
+    fn odd_used_block() {
+        Entry::used(5); // panic here, as the lowest bit marks reserved blocks
+    }
+
     #[test]
     fn debug_representation() {
         assert_eq!(
-            format!("{:?}", Entry::used(123)),
-            "Entry { state: Used, size: 123 }"
+            format!("{:?}", Entry::used(124)),
+            "Entry { state: Used, size: 124 }"
         );
         assert_eq!(
             format!("{:?}", Entry::free(456)),
             "Entry { state: Free, size: 456 }"
         );
+        assert_eq!(
+            format!("{:?}", Entry::reserved(8)),
+            "Entry { state: Reserved, size: 8 }"
+        );
     }
 }
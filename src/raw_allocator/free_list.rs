@@ -0,0 +1,280 @@
+//! This module provides the [`FreeList`] linking the free blocks of the heap.
+//!
+//! Scanning all the entries for a free block visits the used blocks as well,
+//! which are the majority on a large heap with many live allocations. Therefore
+//! the free blocks are linked into a doubly-linked list, which is walked
+//! instead. The links are stored in the memory of the free blocks, which is not
+//! used otherwise, so the list costs no memory in the used blocks. As the list
+//! is doubly-linked, a block is removed from it in constant time, when it is
+//! allocated or merged into another block.
+use super::buffer::{Buffer, EntryIter, Link, ValidatedOffset, LINKS_SIZE};
+use super::entry::State;
+
+/// The list of the free blocks of a [`Buffer`].
+///
+/// Every free block with at least [`LINKS_SIZE`] bytes of memory is part of the
+/// list, while smaller free blocks (with `0` or `4` bytes) cannot store the
+/// links and are only counted. The order of the list is unspecified, so the
+/// callers have to order the blocks themselves, e.g. by their offsets.
+///
+/// The list has to be kept up to date by every operation creating, removing or
+/// resizing a free block: a block is [`remove()`](Self::remove)d, before its
+/// header is changed, and [`insert()`](Self::insert)ed afterwards.
+pub struct FreeList {
+    /// The first block of the list (if any).
+    head: Option<ValidatedOffset>,
+    /// The number of free blocks, which are too small to be linked.
+    unlinked: usize,
+    /// Whether [`blocks()`](Self::blocks) always scans all the entries.
+    ///
+    /// This allows to compare the allocator against the scanning one in tests.
+    #[cfg(test)]
+    pub scan: bool,
+}
+impl FreeList {
+    /// Create the free list of a freshly created [`Buffer`].
+    ///
+    /// Such a buffer consists of a single free block with empty links.
+    pub const fn new<const N: usize>() -> Self {
+        Self::with_block(ValidatedOffset::FIRST, N - super::HEADER_SIZE)
+    }
+
+    /// Create the free list of a buffer with a single free block.
+    ///
+    /// The block at `offset` has `size` bytes of memory and empty links (if it
+    /// is large enough to store them). All the other blocks have to be used.
+    pub const fn with_block(offset: ValidatedOffset, size: usize) -> Self {
+        let (head, unlinked) = if size >= LINKS_SIZE {
+            (Some(offset), 0)
+        } else {
+            (None, 1)
+        };
+        Self {
+            head,
+            unlinked,
+            #[cfg(test)]
+            scan: false,
+        }
+    }
+
+    /// Insert the free block at `offset` into the list.
+    ///
+    /// The block is linked at the front of the list, if it is large enough to
+    /// store the links, which is reported by returning `true`. Otherwise it is
+    /// only counted.
+    pub fn insert<const N: usize>(
+        &mut self,
+        buffer: &mut Buffer<N>,
+        offset: ValidatedOffset,
+    ) -> bool {
+        debug_assert_eq!(buffer[offset].state(), State::Free);
+        if buffer[offset].size() < LINKS_SIZE {
+            self.unlinked += 1;
+            return false;
+        }
+        buffer.set_link(offset, Link::Previous, None);
+        buffer.set_link(offset, Link::Next, self.head);
+        if let Some(head) = self.head {
+            buffer.set_link(head, Link::Previous, Some(offset));
+        }
+        self.head = Some(offset);
+        true
+    }
+
+    /// Remove the free block at `offset` from the list.
+    ///
+    /// The block has to be part of the list, i.e. it has to be free and its
+    /// header must not have been changed since its insertion.
+    pub fn remove<const N: usize>(&mut self, buffer: &mut Buffer<N>, offset: ValidatedOffset) {
+        debug_assert_eq!(buffer[offset].state(), State::Free);
+        if buffer[offset].size() < LINKS_SIZE {
+            self.unlinked -= 1;
+            return;
+        }
+        let previous = buffer.link(offset, Link::Previous);
+        let next = buffer.link(offset, Link::Next);
+        match previous {
+            Some(previous) => buffer.set_link(previous, Link::Next, next),
+            None => self.head = next,
+        }
+        if let Some(next) = next {
+            buffer.set_link(next, Link::Previous, previous);
+        }
+    }
+
+    /// Rebuild the list from the free blocks of the `buffer`.
+    ///
+    /// This is necessary after rearranging the blocks (or after the links
+    /// might have been corrupted), as the old links are not valid anymore.
+    pub fn rebuild<const N: usize>(&mut self, buffer: &mut Buffer<N>) {
+        self.head = None;
+        self.unlinked = 0;
+        let mut current = buffer.entries().next();
+        while let Some(offset) = current {
+            if buffer[offset].state() == State::Free {
+                self.insert(buffer, offset);
+            }
+            current = buffer.following_entry(offset);
+        }
+    }
+
+    /// Iterate over the free blocks, optionally including the unlinked ones.
+    ///
+    /// Usually only the linked blocks are visited by following the links. If
+    /// `unlinked` is set and there are unlinked free blocks, all the entries
+    /// are scanned instead, so that those blocks are visited as well. In that
+    /// case, the used blocks are yielded too, so the caller has to check the
+    /// state of the blocks.
+    pub const fn blocks<'buffer, const N: usize>(
+        &self,
+        buffer: &'buffer Buffer<N>,
+        unlinked: bool,
+    ) -> FreeBlocks<'buffer, N> {
+        let scan = unlinked && self.unlinked > 0;
+        #[cfg(test)]
+        let scan = scan || self.scan;
+        if scan {
+            FreeBlocks::Scanned(buffer.entries())
+        } else {
+            FreeBlocks::Linked(buffer, self.head)
+        }
+    }
+
+    /// Check, that the list contains exactly the free blocks of the `buffer`.
+    ///
+    /// Every free block large enough for the links has to be reachable by
+    /// following the links from the start of the list exactly once, and the
+    /// links to the previous blocks have to match. The number of the other
+    /// free blocks has to match the counter. The entries of the buffer have to
+    /// be consistent.
+    pub fn is_consistent<const N: usize>(&self, buffer: &Buffer<N>) -> bool {
+        let (mut linked, mut unlinked) = (0, 0);
+        for offset in buffer.entries() {
+            let entry = buffer[offset];
+            match entry.state() {
+                State::Free if entry.size() >= LINKS_SIZE => linked += 1,
+                State::Free => unlinked += 1,
+                State::Used | State::Reserved => {}
+            }
+        }
+
+        let mut previous = None;
+        let mut current = self.head;
+        for _ in 0..linked {
+            let offset = match current {
+                Some(offset) => offset,
+                None => return false,
+            };
+            let entry = buffer[offset];
+            if entry.state() != State::Free
+                || entry.size() < LINKS_SIZE
+                || buffer.link(offset, Link::Previous) != previous
+            {
+                return false;
+            }
+            previous = current;
+            current = buffer.link(offset, Link::Next);
+        }
+        current.is_none() && unlinked == self.unlinked
+    }
+}
+
+/// An iterator over the free blocks of a [`Buffer`].
+///
+/// See [`FreeList::blocks()`] for details. Every visited entry is yielded, so
+/// that the caller can count the steps taken.
+pub enum FreeBlocks<'buffer, const N: usize> {
+    /// The linked blocks are visited by following the links.
+    Linked(&'buffer Buffer<N>, Option<ValidatedOffset>),
+    /// All the entries are scanned, including the used ones.
+    Scanned(EntryIter<'buffer, N>),
+}
+impl<const N: usize> Iterator for FreeBlocks<'_, N> {
+    type Item = ValidatedOffset;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Linked(buffer, current) => {
+                let offset = (*current)?;
+                *current = buffer.link(offset, Link::Next);
+                Some(offset)
+            }
+            Self::Scanned(entries) => entries.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FreeList;
+    use crate::raw_allocator::buffer::{Buffer, ValidatedOffset};
+    #[cfg(not(feature = "granularity-8"))]
+    use crate::raw_allocator::entry::Entry;
+
+    #[test]
+    fn fresh_buffer() {
+        let buffer = Buffer::<32>::new();
+        let list = FreeList::new::<32>();
+        assert!(list.is_consistent(&buffer));
+        assert_eq!(
+            list.blocks(&buffer, false).collect::<Vec<_>>(),
+            [ValidatedOffset::FIRST]
+        );
+
+        // the only block is too small to store the links
+        let buffer = Buffer::<8>::new();
+        let list = FreeList::new::<8>();
+        assert!(list.is_consistent(&buffer));
+        assert_eq!(list.blocks(&buffer, false).count(), 0);
+        assert_eq!(list.blocks(&buffer, true).count(), 1);
+    }
+
+    #[test]
+    // every block is large enough for the links with a granularity of 8 bytes
+    #[cfg(not(feature = "granularity-8"))]
+    fn insert_and_remove() {
+        let mut buffer = Buffer::<64>::new();
+        let mut list = FreeList::new::<64>();
+        let first = ValidatedOffset::FIRST;
+        list.remove(&mut buffer, first);
+        buffer.mark_as_used(first, 8);
+        let second = buffer.following_entry(first).unwrap();
+        buffer.mark_as_used(second, 4);
+        let third = buffer.following_entry(second).unwrap();
+        buffer.mark_as_used(third, 8);
+        let fourth = buffer.following_entry(third).unwrap();
+        list.insert(&mut buffer, fourth);
+        assert!(list.is_consistent(&buffer));
+
+        // the blocks are inserted at the front of the list
+        buffer[first] = Entry::free(8);
+        buffer[second] = Entry::free(4);
+        buffer[third] = Entry::free(8);
+        for offset in [first, second, third] {
+            list.insert(&mut buffer, offset);
+        }
+        assert!(list.is_consistent(&buffer));
+        assert_eq!(
+            list.blocks(&buffer, false).collect::<Vec<_>>(),
+            [third, first, fourth]
+        );
+        // the block too small for the links is only found by scanning
+        assert_eq!(list.blocks(&buffer, true).count(), 4);
+
+        // blocks are removed from the middle and both ends of the list
+        list.remove(&mut buffer, first);
+        assert_eq!(
+            list.blocks(&buffer, false).collect::<Vec<_>>(),
+            [third, fourth]
+        );
+        list.remove(&mut buffer, fourth);
+        list.remove(&mut buffer, third);
+        list.remove(&mut buffer, second);
+        assert_eq!(list.blocks(&buffer, true).count(), 0);
+        buffer[first] = Entry::used(8);
+        buffer[second] = Entry::used(4);
+        buffer[third] = Entry::used(8);
+        buffer[fourth] = Entry::used(buffer[fourth].size());
+        assert!(list.is_consistent(&buffer));
+    }
+}
@@ -3,15 +3,45 @@
 //! A "raw allocator" is one, that simply gets request for a specific memory
 //! size but does not need to worry about alignment.
 mod buffer;
+mod cache;
+#[cfg(feature = "dump")]
+mod dump;
 mod entry;
+mod free_list;
+#[cfg(test)]
+mod property_tests;
 
-use buffer::HEADER_SIZE;
-use entry::{Entry, State};
+#[cfg(feature = "relocating")]
+use crate::handle::HandleTable;
+#[cfg(feature = "ring-log")]
+use crate::ring_log::{LogEntry, Operation, RingLog};
+use crate::{BlockInfo, HeapStats, RecoveryReport, Strategy};
+pub use buffer::ValidatedOffset;
+use buffer::LINKS_SIZE;
+pub use buffer::{GRANULARITY, HEADER_SIZE};
+#[cfg(feature = "dump")]
+pub use dump::{parse_dump, ParseError};
+use entry::Entry;
+pub use entry::State;
+use free_list::FreeList;
 
+use core::cmp::Reverse;
+use core::fmt;
 use core::mem::MaybeUninit;
+#[cfg(feature = "relocating")]
+use core::num::NonZeroUsize;
+use core::ptr;
 
-/// An error occurred when calling `free()`.
+/// An error detected when deallocating memory.
+///
+/// Errors during deallocation cannot be reported to the caller of
+/// [`GlobalAlloc::dealloc()`](core::alloc::GlobalAlloc::dealloc), therefore
+/// they are ignored by default. An error handler can be registered with
+/// [`Allocator::set_dealloc_error_handler()`](crate::Allocator::set_dealloc_error_handler)
+/// to get notified about them.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum FreeError {
     /// There is a double-free detected. An already freed-up-block is freed up
     /// again.
@@ -19,8 +49,42 @@ pub enum FreeError {
     /// An invalid pointer was freed up (either a pointer outside of the heap
     /// memory or a pointer to a header).
     AllocationNotFound,
+    /// A pending reservation was freed. Reservations have to be committed or
+    /// aborted instead (see
+    /// [`Allocator::reserve_block()`](crate::Allocator::reserve_block)).
+    ReservedBlock,
+}
+impl fmt::Display for FreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::DoubleFreeDetected => "double free detected",
+            Self::AllocationNotFound => "freed pointer was not allocated by this allocator",
+            Self::ReservedBlock => "freed block is a pending reservation",
+        })
+    }
+}
+
+/// The default minimum size of a free block remaining after splitting a block.
+///
+/// This avoids free blocks without any memory, which cannot be used for any
+/// allocation but slow down the scans.
+pub const DEFAULT_SPLIT_THRESHOLD: usize = HEADER_SIZE;
+
+/// Assert, that a heap of `n` bytes can hold at least a single allocation.
+///
+/// This requires two headers: the header of the block and the header of the
+/// free block, which is split off.
+const fn assert_min_size(n: usize) {
+    assert!(
+        n >= 2 * HEADER_SIZE,
+        "too small heap memory: minimum size is two headers"
+    );
 }
 
+/// The byte written to the memory of every allocation with `fill-on-alloc`.
+#[cfg(feature = "fill-on-alloc")]
+pub const FILL_PATTERN: u8 = 0xCD;
+
 /// A raw memory allocator for contiguous slices of bytes without any alignment.
 ///
 /// This allocator is an intermediate one, which does not need to handle the
@@ -31,306 +95,3517 @@ pub enum FreeError {
 pub struct RawAllocator<const N: usize> {
     /// The internal buffer abstracting over the raw bytes of the heap.
     buffer: buffer::Buffer<N>,
+    /// The recently freed blocks, which are reused for allocations of the same
+    /// size without scanning the heap.
+    recently_freed: cache::Cache,
+    /// The list of the free blocks, which is walked instead of all entries.
+    ///
+    /// Every change of a free block has to update this list, see [`FreeList`].
+    free_list: FreeList,
+    /// The number of bytes at the end of the heap, which are reserved for
+    /// [`alloc_reserved()`](Self::alloc_reserved).
+    reserved: usize,
+    /// The function to call for errors detected while freeing memory.
+    dealloc_error_handler: Option<fn(FreeError)>,
+    /// The function to call for allocations refused due to an overlap.
+    ///
+    /// See [`set_overlap_handler()`](Self::set_overlap_handler) for details.
+    #[cfg(feature = "paranoid")]
+    overlap_handler: Option<fn(BlockInfo)>,
+    /// The minimum size of the free block remaining after splitting a block.
+    ///
+    /// See [`set_split_threshold()`](Self::set_split_threshold) for details.
+    split_threshold: usize,
+    /// The strategy for choosing a free block when scanning the heap.
+    strategy: Strategy,
+    /// The maximum size of a single allocation.
+    ///
+    /// See [`set_max_alloc()`](Self::set_max_alloc) for details.
+    max_alloc: usize,
+    /// The number of further allocations allowed by the maximum number of live
+    /// allocations.
+    ///
+    /// This is the cap minus the used blocks: it is decremented by every
+    /// allocation and incremented by every free (or merge of two allocations).
+    /// See [`set_max_live_allocations()`](Self::set_max_live_allocations) for
+    /// details. A heap holds at most `2^29` blocks, so `u32::MAX` means
+    /// "unlimited", as the budget cannot be used up.
+    #[cfg(feature = "metrics")]
+    allocation_budget: u32,
+    /// The free block, into which the allocations advance with the strategy
+    /// [`Strategy::Frontier`].
+    ///
+    /// Like the offsets in the cache of the recently freed blocks, this has to
+    /// be updated, whenever the block is merged into another one.
+    frontier: Option<ValidatedOffset>,
+    /// The offset after the last byte, which might have been written since the
+    /// creation or the last [`reset_touched()`](Self::reset_touched).
+    touched: usize,
+    /// The offset of the used block quarantined by [`recover()`](Self::recover).
+    ///
+    /// That block is never freed, shrunk or grown, as it might contain the
+    /// memory of several allocations.
+    quarantine: Option<usize>,
+    /// The log of the most recent operations.
+    #[cfg(feature = "ring-log")]
+    log: RingLog,
+    /// The offsets of the allocations referenced by handles.
+    #[cfg(feature = "relocating")]
+    handles: HandleTable,
+    /// The number of successful allocations (saturating at `usize::MAX`).
+    total_allocations: usize,
+    /// The number of successful deallocations (saturating at `usize::MAX`).
+    total_frees: usize,
+    /// The number of bytes allocated in addition to the requested sizes, as
+    /// the remainders were too small to split (saturating at `usize::MAX`).
+    wasted_tail_bytes: usize,
+    /// The largest size of a single successful allocation since the creation
+    /// or the last reset of the heap.
+    max_single_alloc: usize,
+    /// The number of bytes, by which the requested sizes of the live
+    /// allocations were rounded up, see
+    /// [`internal_fragmentation()`](Self::internal_fragmentation).
+    rounding: usize,
+    /// The largest heap size demanded by a failed allocation (or `0`), see
+    /// [`recommended_min_size()`](Self::recommended_min_size).
+    peak_demand: usize,
+    /// The number of entries inspected while searching for a block
+    /// (saturating at `usize::MAX`).
+    ///
+    /// This counts the free blocks visited by the allocations as well as the
+    /// entries scanned by the deallocations, see
+    /// [`avg_scan_steps()`](Self::avg_scan_steps).
+    #[cfg(any(test, feature = "metrics"))]
+    scan_steps: usize,
 }
 impl<const N: usize> RawAllocator<N> {
     /// Create a new [`RawAllocator`] with a given heap size.
     ///
     /// # Panics
-    /// This function panics if the buffer size is less than `8` (the minimum
-    /// useful allocation heap) or if it is not divisible by 4.
+    /// This function panics if the buffer size is less than two headers (the
+    /// minimum useful allocation heap) or if it is not a multiple of the
+    /// [`GRANULARITY`].
     pub const fn new() -> Self {
-        assert!(N >= 8, "too small heap memory: minimum size is 8");
-        assert!(N % 4 == 0, "memory size has to be divisible by 4");
+        assert_min_size(N);
+        buffer::assert_granular_size(N);
 
         let buffer = buffer::Buffer::new();
-        Self { buffer }
+        Self {
+            buffer,
+            recently_freed: cache::Cache::new(),
+            free_list: FreeList::new::<N>(),
+            reserved: 0,
+            dealloc_error_handler: None,
+            #[cfg(feature = "paranoid")]
+            overlap_handler: None,
+            split_threshold: DEFAULT_SPLIT_THRESHOLD,
+            strategy: Strategy::BestFit,
+            max_alloc: usize::MAX,
+            #[cfg(feature = "metrics")]
+            allocation_budget: u32::MAX,
+            frontier: None,
+            touched: HEADER_SIZE,
+            quarantine: None,
+            #[cfg(feature = "ring-log")]
+            log: RingLog::new(),
+            #[cfg(feature = "relocating")]
+            handles: HandleTable::new(),
+            total_allocations: 0,
+            total_frees: 0,
+            wasted_tail_bytes: 0,
+            max_single_alloc: 0,
+            rounding: 0,
+            peak_demand: 0,
+            #[cfg(any(test, feature = "metrics"))]
+            scan_steps: 0,
+        }
     }
 
-    /// Allocate a new memory block of size `n`.
+    /// Create a new [`RawAllocator`], whose first block is used.
     ///
-    /// This method is used for general allocation of multiple contiguous bytes.
-    /// It searches for the smallest possible free entry and mark it as "used".
-    /// As usual with [`RawAllocator`], this does not take alignment in account.
+    /// The first block has `prefix` bytes of memory and is never handed out
+    /// (unless it is freed). The remaining heap forms a single free block.
     ///
-    /// If the allocation fails, `None` will be returned.
-    pub fn alloc(&mut self, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
-        // round up `n` to next multiple of `size_of::<Entry>()`
-        let n = (n + HEADER_SIZE - 1) / HEADER_SIZE * HEADER_SIZE;
+    /// # Panics
+    /// This function panics under the same conditions as [`new()`](Self::new),
+    /// if `prefix` is not a multiple of the [`GRANULARITY`] or if there is no
+    /// room for the free block behind the prefix.
+    pub const fn with_prefix(prefix: usize) -> Self {
+        let mut this = Self::new();
+        this.buffer = buffer::Buffer::with_prefix(prefix);
+        let free = ValidatedOffset::behind_prefix(prefix);
+        this.free_list = FreeList::with_block(free, N - free.get() - HEADER_SIZE);
+        this.touched = free.get() + HEADER_SIZE;
+        this
+    }
 
-        let (offset, _) = self
-            .buffer
-            .entries()
-            .map(|offset| (offset, self.buffer[offset]))
-            .filter(|(_offset, entry)| entry.state() == State::Free)
-            .filter(|(_offset, entry)| entry.size() >= n)
-            .min_by_key(|(_offset, entry)| entry.size())?;
+    /// Create a new [`RawAllocator`] with the given blocks.
+    ///
+    /// The heap consists of the blocks described by the `(state, size)` pairs
+    /// in address order. Adjacent free blocks are not merged.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as [`new()`](Self::new),
+    /// if a size is not a multiple of the [`GRANULARITY`] or if the blocks
+    /// (including their headers) do not fill the heap exactly.
+    #[cfg(any(test, debug_assertions))]
+    pub fn from_layout(blocks: &[(State, usize)]) -> Self {
+        let total = blocks.iter().try_fold(0_usize, |total, (_state, size)| {
+            total.checked_add(HEADER_SIZE)?.checked_add(*size)
+        });
+        assert!(
+            blocks.iter().all(|(_state, size)| size % HEADER_SIZE == 0) && total == Some(N),
+            "invalid block layout: the sizes have to be multiples of the granularity filling the heap"
+        );
 
-        // if the found block is large enough, split it into a used and a free
-        self.buffer.mark_as_used(offset, n);
-        Some(self.buffer.memory_of_mut(offset))
+        let mut this = Self::new();
+        this.buffer.set_layout(blocks);
+        this.free_list.rebuild(&mut this.buffer);
+        // the links might have been written anywhere
+        this.touched = N;
+        this
     }
 
-    /// Free a pointer inside a used memory block.
+    /// Initialize a [`RawAllocator`] in place.
     ///
-    /// This method is used to release a memory block allocated with this raw
-    /// allocator. If a entry to the given pointer is found, the corresponding
-    /// memory block is marked as free. If no entry is found, than an error is
-    /// reported (as allocators are not allowed to unwind).
+    /// This is equivalent to writing [`RawAllocator::new()`] to `this`, but the
+    /// heap memory is not written except for the first header and its links
+    /// (see [`Buffer::init_at()`](buffer::Buffer::init_at)). This avoids a
+    /// temporary copy of the whole heap on the stack. Every field has to be
+    /// initialized exactly like in [`new()`](Self::new).
     ///
-    /// # Algorithm
-    /// Freeing a pointer is done in the following way: all the entries are
-    /// scanned linearly. The pointer is compared against each block. If the
-    /// pointer points to the memory of an entry, than that entry is selected.
-    /// If no such entry is found, than the user tried to free an allocation,
-    /// that was not allocated with this allocator (or the allocator messed up
-    /// internally). [`FreeError::AllocationNotFound`] is reported.
+    /// # Safety
+    /// The pointer `this` must be valid for writes and properly aligned.
     ///
-    /// The selected block is tested for its state. If it is marked as "used",
-    /// than everything is fine. If it is already marked as "free", than
-    /// [`FreeError::DoubleFreeDetected`] is returned. If the block following
-    /// the just freed up one is also free, the two blocks are concatenated to a
-    /// single one (to prevent fragmentation).
-    pub fn free(&mut self, ptr: *mut u8) -> Result<(), FreeError> {
-        let offset = self
-            .buffer
-            .entries()
-            .find(|offset| {
-                let size = self.buffer[*offset].size();
-                let memory = self.buffer.memory_of(*offset);
-                let ptr = ptr as *const _;
-                let start = memory.as_ptr();
-                let end = start.wrapping_add(size);
-
-                start <= ptr && ptr < end
-            })
-            .ok_or(FreeError::AllocationNotFound)?;
+    /// # Panics
+    /// This function panics under the same conditions as [`new()`](Self::new).
+    pub unsafe fn init_at(this: *mut Self) {
+        assert_min_size(N);
+        buffer::assert_granular_size(N);
 
-        let entry = self.buffer[offset];
-        if entry.state() == State::Free {
-            return Err(FreeError::DoubleFreeDetected);
+        // SAFETY: the allocator is valid for writes and properly aligned
+        // (safety contract of this function), so are all of its fields.
+        unsafe {
+            buffer::Buffer::init_at(ptr::addr_of_mut!((*this).buffer));
+            ptr::addr_of_mut!((*this).recently_freed).write(cache::Cache::new());
+            ptr::addr_of_mut!((*this).free_list).write(FreeList::new::<N>());
+            ptr::addr_of_mut!((*this).reserved).write(0);
+            ptr::addr_of_mut!((*this).dealloc_error_handler).write(None);
+            #[cfg(feature = "paranoid")]
+            ptr::addr_of_mut!((*this).overlap_handler).write(None);
+            ptr::addr_of_mut!((*this).split_threshold).write(DEFAULT_SPLIT_THRESHOLD);
+            ptr::addr_of_mut!((*this).strategy).write(Strategy::BestFit);
+            ptr::addr_of_mut!((*this).max_alloc).write(usize::MAX);
+            #[cfg(feature = "metrics")]
+            ptr::addr_of_mut!((*this).allocation_budget).write(u32::MAX);
+            ptr::addr_of_mut!((*this).frontier).write(None);
+            ptr::addr_of_mut!((*this).touched).write(HEADER_SIZE);
+            ptr::addr_of_mut!((*this).quarantine).write(None);
+            #[cfg(feature = "ring-log")]
+            ptr::addr_of_mut!((*this).log).write(RingLog::new());
+            #[cfg(feature = "relocating")]
+            ptr::addr_of_mut!((*this).handles).write(HandleTable::new());
+            ptr::addr_of_mut!((*this).total_allocations).write(0);
+            ptr::addr_of_mut!((*this).total_frees).write(0);
+            ptr::addr_of_mut!((*this).wasted_tail_bytes).write(0);
+            ptr::addr_of_mut!((*this).max_single_alloc).write(0);
+            ptr::addr_of_mut!((*this).rounding).write(0);
+            ptr::addr_of_mut!((*this).peak_demand).write(0);
+            #[cfg(any(test, feature = "metrics"))]
+            ptr::addr_of_mut!((*this).scan_steps).write(0);
         }
-        let additional_memory = self
-            .buffer
-            .following_free_entry(offset)
-            .map_or(0, |entry| entry.size() + HEADER_SIZE);
-        self.buffer[offset] = Entry::free(entry.size() + additional_memory);
-        Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::{Entry, FreeError, RawAllocator};
+    /// Allocate a new memory block of size `n`.
+    ///
+    /// This method is used for general allocation of multiple contiguous bytes.
+    /// It searches for a free entry according to the current [`Strategy`] (by
+    /// default the smallest possible one) and mark it as "used". As usual with
+    /// [`RawAllocator`], this does not take alignment in account. Only the free
+    /// blocks are visited by walking the free list (see [`FreeList`]).
+    ///
+    /// Before scanning the heap, the few most recently freed blocks are checked
+    /// first: if one of them has exactly the requested (rounded) size, it is
+    /// reused immediately. This makes repeated allocations and deallocations of
+    /// the same size cheap.
+    ///
+    /// The reserved memory at the end of the heap (see [`reserve()`]) is never
+    /// used by this function.
+    ///
+    /// If the allocation fails, `None` will be returned.
+    ///
+    /// [`reserve()`]: Self::reserve
+    pub fn alloc(&mut self, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        let offset = self.alloc_below(n, N - self.reserved);
+        self.allocated(n, offset)
+    }
 
-    /// Test, that the given allocator has exactly the given entries.
-    macro_rules! assert_allocations {
-        ($allocator:expr, $($entry:expr),*$(,)?) => {{
-            let mut iter = $allocator
-                .buffer
-                .entries()
-                .map(|offset| $allocator.buffer[offset]);
-            $(assert_eq!(iter.next(), Some($entry));)*
-            assert_eq!(iter.next(), None);
-        }};
+    /// Allocate a new memory block of size `n` containing aligned memory.
+    ///
+    /// The memory of the returned block contains an address aligned to `align`
+    /// (which has to be a power of two) with at least `n` bytes after it. In
+    /// contrast to requesting `n + align` bytes via [`alloc()`](Self::alloc),
+    /// the free blocks are checked individually: the first aligned address in
+    /// each free block is determined and the block is used, if the allocation
+    /// fits behind that address. The free block is chosen according to the
+    /// current [`Strategy`]. The gap in front of the aligned address is split
+    /// off into a separate free block, unless it is smaller than a header plus
+    /// the split threshold (it is kept as padding in the used block then).
+    /// Therefore an aligned allocation only takes the memory it needs.
+    ///
+    /// Before scanning the heap, the recently freed blocks are checked: the
+    /// most recently freed one, into which the allocation fits, is reused. For
+    /// alignments up to `4` this is the same as [`alloc()`](Self::alloc). The
+    /// reserved memory at the end of the heap is never used.
+    ///
+    /// If the allocation fails, `None` will be returned.
+    pub fn alloc_aligned(&mut self, n: usize, align: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        if align <= HEADER_SIZE {
+            return self.alloc(n);
+        }
+        let offset = self.alloc_aligned_below(n, align, N - self.reserved);
+        self.allocated(n, offset)
     }
 
-    #[test]
-    fn successful_single_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::free(20));
+    /// Determine where an allocation would be placed without performing it.
+    ///
+    /// This runs the same search as [`alloc_aligned()`](Self::alloc_aligned)
+    /// for `n` bytes aligned to `align`, but neither the blocks nor the cache
+    /// of recently freed blocks are modified. The offset of the memory from the
+    /// start of the heap is returned, i.e. the offset of the aligned address,
+    /// which the allocation would hand out right now. If the allocation would
+    /// fail, `None` is returned.
+    pub fn probe(&self, n: usize, align: usize) -> Option<usize> {
+        let n = self.new_block_size(n)?;
+        let limit = N - self.reserved;
+        let mut steps = 0;
+        if align <= HEADER_SIZE {
+            let (offset, _cached) = self.find_block(n, limit, &mut steps)?;
+            Some(offset.get() + HEADER_SIZE)
+        } else {
+            let (_offset, start, _cached) = self.find_aligned_block(n, align, limit, &mut steps)?;
+            Some(start)
+        }
     }
 
-    #[test]
-    fn unsuccessful_single_allocation() {
-        // the allocation is larger than the buffer itself
-        let mut allocator = RawAllocator::<32>::new();
-        assert!(allocator.alloc(36).is_none());
-        assert_allocations!(allocator, Entry::free(28));
+    /// Allocate a new memory block of size `n` referenced by a handle.
+    ///
+    /// The offset of the memory is stored in the handle table, which is kept up
+    /// to date by [`compact()`](Self::compact). The index of the entry in that
+    /// table is returned together with the address of the memory. If the
+    /// handle table is full or the allocation fails, `None` is returned.
+    #[cfg(feature = "relocating")]
+    pub fn alloc_handle(&mut self, n: usize) -> Option<(usize, *mut u8)> {
+        if self.handles.is_full() {
+            return None;
+        }
+        let base = self.buffer.as_ptr() as usize;
+        let ptr = self.alloc(n)?.as_mut_ptr().cast::<u8>();
+        let offset = NonZeroUsize::new(ptr as usize - base)?;
+        let index = self.handles.insert(offset)?;
+        Some((index, ptr))
     }
 
-    #[test]
-    fn successful_multiple_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(12).unwrap();
-        allocator.alloc(12).unwrap();
-        // allocator is now full
-        assert_allocations!(allocator, Entry::used(12), Entry::used(12));
+    /// Query the current address of the memory referenced by a handle.
+    ///
+    /// If there is no such handle, a null pointer is returned.
+    #[cfg(feature = "relocating")]
+    pub fn handle(&self, index: usize) -> *mut u8 {
+        self.handles.get(index).map_or(ptr::null_mut(), |offset| {
+            self.buffer.as_ptr().wrapping_add(offset.get()) as *mut u8
+        })
     }
 
-    #[test]
-    fn unsuccessful_multiple_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(12).unwrap();
-        // the second allocation is larger than the remaining space
-        assert!(allocator.alloc(13).is_none());
-        assert_allocations!(allocator, Entry::used(12), Entry::free(12));
+    /// Remove a handle from the handle table and return its current address.
+    ///
+    /// The memory is not freed. If there is no such handle, a null pointer is
+    /// returned.
+    #[cfg(feature = "relocating")]
+    pub fn take_handle(&mut self, index: usize) -> *mut u8 {
+        let ptr = self.handle(index);
+        self.handles.remove(index);
+        ptr
     }
 
-    macro_rules! address {
-        ($memory:expr) => {
-            $memory.as_mut_ptr().cast::<u8>()
-        };
+    /// Allocate a new memory block of size `n`, which may use reserved memory.
+    ///
+    /// This is the same as [`alloc()`](Self::alloc), but the reserved memory at
+    /// the end of the heap (see [`reserve()`](Self::reserve)) may be used too.
+    pub fn alloc_reserved(&mut self, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        let offset = self.alloc_below(n, N);
+        self.allocated(n, offset)
     }
 
-    #[test]
-    fn unsuccessful_allocation_due_to_fragmentation() {
-        // this test case shows, that the allocator is susceptible to memory
-        // fragmentation, which makes larger allocations impossible, if the
-        // heap is in a bad state.
-        let mut allocator = RawAllocator::<60>::new();
+    /// Allocate a new aligned memory block of size `n`, which may use reserved
+    /// memory.
+    ///
+    /// This is the same as [`alloc_aligned()`](Self::alloc_aligned), but the
+    /// reserved memory at the end of the heap may be used too.
+    pub fn alloc_aligned_reserved(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Option<&mut [MaybeUninit<u8>]> {
+        if align <= HEADER_SIZE {
+            return self.alloc_reserved(n);
+        }
+        let offset = self.alloc_aligned_below(n, align, N);
+        self.allocated(n, offset)
+    }
 
-        // build a fragmented heap
-        let ptr1 = address!(allocator.alloc(8).unwrap());
-        let _ptr2 = address!(allocator.alloc(8).unwrap());
-        let ptr3 = address!(allocator.alloc(8).unwrap());
-        let _ptr4 = address!(allocator.alloc(8).unwrap());
-        let ptr5 = address!(allocator.alloc(8).unwrap());
-        allocator.free(ptr1).unwrap();
-        allocator.free(ptr3).unwrap();
-        allocator.free(ptr5).unwrap();
-        assert_allocations!(
-            allocator,
-            Entry::free(8),
-            Entry::used(8),
-            Entry::free(8),
-            Entry::used(8),
-            Entry::free(8)
-        );
+    /// Allocate a new memory block of size `n` at the end of the heap.
+    ///
+    /// This is the counterpart to [`alloc()`](Self::alloc): instead of the
+    /// smallest fitting free block, the free block with the highest address is
+    /// used and the memory is taken from its end. Placing long-lived objects at
+    /// the end of the heap keeps them apart from the short-lived allocations at
+    /// the start, which reduces the fragmentation.
+    ///
+    /// The recently freed blocks are not considered and the reserved memory at
+    /// the end of the heap (see [`reserve()`](Self::reserve)) is never used.
+    ///
+    /// If the allocation fails, `None` will be returned.
+    pub fn alloc_from_top(&mut self, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        let offset = self.alloc_at_top(n);
+        self.allocated(n, offset)
+    }
 
-        // now, there are 24 free bytes (3x 8 bytes) and the headers, but the
-        // allocation of 10 bytes must fail, since there is no contiguous memory
-        // of that size
-        assert!(allocator.alloc(10).is_none());
+    /// Allocate a new aligned memory block of size `n` at the end of the heap.
+    ///
+    /// This is the same as [`alloc_from_top()`](Self::alloc_from_top), but the
+    /// memory of the returned block contains an address aligned to `align`
+    /// (which has to be a power of two) with at least `n` bytes after it. The
+    /// highest such address inside of each free block is determined, so only
+    /// the memory actually needed for the alignment is used, just like with
+    /// [`alloc_aligned()`](Self::alloc_aligned).
+    pub fn alloc_aligned_from_top(
+        &mut self,
+        n: usize,
+        align: usize,
+    ) -> Option<&mut [MaybeUninit<u8>]> {
+        if align <= HEADER_SIZE {
+            return self.alloc_from_top(n);
+        }
+        let offset = self.alloc_aligned_at_top(n, align);
+        self.allocated(n, offset)
     }
 
-    #[test]
-    fn simple_free() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::used(4));
+    /// Allocate a new memory block of size `n` at the end of the heap.
+    ///
+    /// See [`alloc_from_top()`](Self::alloc_from_top) for details. The offset
+    /// of the allocated block is returned.
+    fn alloc_at_top(&mut self, n: usize) -> Option<ValidatedOffset> {
+        let n = self.new_block_size(n)?;
+        let limit = N - self.reserved;
+        let end_of = |offset: ValidatedOffset, entry: Entry| {
+            limit.min(offset.get() + HEADER_SIZE + entry.size())
+        };
 
-        // now, that the heap is properly built up, there are two used entries.
-        // when the first one is freed up, there is no possibility for merging
-        // the free memory with the following one (as that one is used)
-        allocator.free(ptr).unwrap();
-        assert_allocations!(allocator, Entry::free(4), Entry::used(4));
+        let (offset, end) = self
+            .free_list
+            .blocks(&self.buffer, n < LINKS_SIZE)
+            .map(|offset| (offset, self.buffer[offset]))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .map(|(offset, entry)| (offset, end_of(offset, entry)))
+            .filter(|(offset, end)| offset.get() + HEADER_SIZE + n <= *end)
+            .max_by_key(|(offset, _end)| offset.get())?;
+
+        Some(self.take_block_before(offset, end, n))
     }
 
-    #[test]
-    fn double_free() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
+    /// Allocate an aligned block with `n` bytes at the end of the heap.
+    ///
+    /// See [`alloc_aligned_from_top()`](Self::alloc_aligned_from_top) for
+    /// details. The offset of the allocated block is returned.
+    fn alloc_aligned_at_top(&mut self, n: usize, align: usize) -> Option<ValidatedOffset> {
+        let n = self.new_block_size(n)?;
+        let limit = N - self.reserved;
+        let base = self.buffer.as_ptr() as usize;
+        // the offset of the last aligned address in the memory of the block at
+        // `offset`, behind which the allocation fits
+        let placement = |offset: ValidatedOffset, entry: Entry| {
+            let memory = offset.get() + HEADER_SIZE;
+            let end = limit.min(memory + entry.size());
+            let start = Self::align_down(base, end.checked_sub(n)?, align)?;
+            (start >= memory).then(|| start)
+        };
 
-        // try to free up the pointer twice. The first time has to succeed, but
-        // the second time has to result in a double-free-error.
-        allocator.free(ptr).unwrap();
-        assert_eq!(allocator.free(ptr), Err(FreeError::DoubleFreeDetected));
-        assert_allocations!(allocator, Entry::free(4), Entry::used(4));
+        let (offset, entry, start) = self
+            .free_list
+            .blocks(&self.buffer, n < LINKS_SIZE)
+            .map(|offset| (offset, self.buffer[offset]))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .filter_map(|(offset, entry)| Some((offset, entry, placement(offset, entry)?)))
+            .max_by_key(|(offset, _entry, _start)| offset.get())?;
+        Some(self.take_aligned(offset, entry, start, n, limit))
     }
 
-    #[test]
-    fn invalid_free() {
-        use core::ptr;
+    /// Allocate a new memory block of size `n`, whose memory starts at `offset`.
+    ///
+    /// The header of the block is placed directly before `offset`, so that the
+    /// memory of the block starts exactly at the given offset into the heap.
+    /// This requires the whole block (including its header) to lie within a
+    /// single free block, which is split on both sides as necessary. The offset
+    /// has to be a multiple of 4. The reserved memory at the end of the heap
+    /// (see [`reserve()`](Self::reserve)) is never used.
+    ///
+    /// If the memory at the offset is not free, `None` will be returned.
+    pub fn alloc_at(&mut self, offset: usize, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        let block = self.alloc_at_offset(offset, n);
+        self.allocated(n, block)
+    }
 
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
+    /// Allocate a new memory block of size `n`, whose memory starts at `offset`.
+    ///
+    /// See [`alloc_at()`](Self::alloc_at) for details. The offset of the
+    /// allocated block (i.e. of its header) is returned.
+    fn alloc_at_offset(&mut self, offset: usize, n: usize) -> Option<ValidatedOffset> {
+        let n = self.new_block_size(n)?;
+        if offset % HEADER_SIZE != 0 {
+            return None;
+        }
+        let header = offset.checked_sub(HEADER_SIZE)?;
+        let end = offset
+            .checked_add(n)
+            .filter(|&end| end <= N - self.reserved)?;
 
-        // try to free up a pointer, that was not allocated by this allocator.
-        // This invalid usage has to be detected.
-        let mut x = 0_u32;
-        let ptr = ptr::addr_of_mut!(x).cast();
-        assert_eq!(allocator.free(ptr), Err(FreeError::AllocationNotFound));
+        let block = self
+            .buffer
+            .entries()
+            .take_while(|block| block.get() <= header)
+            .last()?;
+        let entry = self.buffer[block];
+        let block_end = block.get() + HEADER_SIZE + entry.size();
+        if entry.state() != State::Free || end > block_end {
+            return None;
+        }
+        Some(self.take_block_before(block, end, n))
     }
 
-    #[test]
-    fn free_of_modified_pointer() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::used(4));
-
-        let ptr = ptr.wrapping_add(3);
-        // now there is a valid pointer, but this pointer was modified, e.g. to
-        // be aligned properly. As the `free()`-call should support any pointer
-        // into the memory block, this should succeed.
-        allocator.free(ptr).unwrap();
-        assert_allocations!(allocator, Entry::free(4), Entry::used(4));
+    /// Reserve memory at the end of the heap for [`alloc_reserved()`].
+    ///
+    /// After a successful call, the last `HEADER_SIZE + n` bytes (`n` rounded
+    /// up to a multiple of 4) of the heap are not used by [`alloc()`] anymore.
+    /// Therefore an allocation of `n` bytes via [`alloc_reserved()`] always
+    /// succeeds, as long as the reserved memory is not allocated already. This
+    /// replaces any previous reservation and a reservation of `0` bytes removes
+    /// the reservation.
+    ///
+    /// The reservation is only possible, if that memory is currently free. If
+    /// it is not, `false` is returned and the previous reservation is kept.
+    ///
+    /// [`alloc()`]: Self::alloc
+    /// [`alloc_reserved()`]: Self::alloc_reserved
+    pub fn reserve(&mut self, n: usize) -> bool {
+        if n == 0 {
+            self.reserved = 0;
+            return true;
+        }
+        let n = match Self::round_up(n) {
+            Some(n) => n,
+            None => return false,
+        };
+        let last_free = self
+            .buffer
+            .entries()
+            .last()
+            .map(|offset| self.buffer[offset])
+            .filter(|entry| entry.state() == State::Free);
+        match last_free {
+            Some(entry) if entry.size() >= n => {
+                self.reserved = HEADER_SIZE + n;
+                true
+            }
+            _ => false,
+        }
     }
 
-    #[test]
-    fn free_with_concatenation() {
-        let mut allocator = RawAllocator::<32>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        assert_allocations!(allocator, Entry::used(4), Entry::free(20));
+    /// Round up `n` to the next multiple of the [`HEADER_SIZE`].
+    ///
+    /// If that multiple is not representable, `None` is returned.
+    const fn round_up(n: usize) -> Option<usize> {
+        match n.checked_add(HEADER_SIZE - 1) {
+            Some(n) => Some(n / HEADER_SIZE * HEADER_SIZE),
+            None => None,
+        }
+    }
 
-        // now there is a used block followed by a free block. When the used
-        // block is freed up as well, this should lead to a single free block.
-        allocator.free(ptr).unwrap();
-        assert_allocations!(allocator, Entry::free(28));
+    /// Round up the `offset`, so that `base + offset` is aligned to `align`.
+    ///
+    /// The `align` has to be a power of two. If the resulting offset is not
+    /// representable, `None` is returned.
+    const fn align_up(base: usize, offset: usize, align: usize) -> Option<usize> {
+        let mismatch = base.wrapping_add(offset) & (align - 1);
+        if mismatch == 0 {
+            Some(offset)
+        } else {
+            offset.checked_add(align - mismatch)
+        }
     }
 
-    #[test]
-    fn free_at_end() {
-        let mut allocator = RawAllocator::<16>::new();
-        allocator.alloc(4).unwrap();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        assert_allocations!(allocator, Entry::used(4), Entry::used(4));
+    /// Round down the `offset` into the heap at address `base` to `align`.
+    ///
+    /// This is the counterpart of [`align_up()`](Self::align_up): the largest
+    /// offset not above `offset` is returned, whose address is aligned to
+    /// `align` (which has to be a power of two). If there is no such offset,
+    /// `None` is returned.
+    const fn align_down(base: usize, offset: usize, align: usize) -> Option<usize> {
+        offset.checked_sub(base.wrapping_add(offset) & (align - 1))
+    }
 
-        // now, that the heap is properly built up, there are two used entries.
-        // when the second one is freed up, there is no possibility for merging
-        // the free memory with the following one (as there is no following one)
-        allocator.free(ptr).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::free(4));
+    /// Round up the requested size `n` of an allocation.
+    ///
+    /// Sizes, which can never fit into the heap, are rejected by returning
+    /// `None`. This ensures, that adding offsets into the heap to the returned
+    /// size cannot overflow.
+    fn checked_size(n: usize) -> Option<usize> {
+        Self::round_up(n).filter(|&n| n < N)
     }
 
-    #[test]
-    fn free_impossible_defrag() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr1 = address!(allocator.alloc(4).unwrap());
-        let ptr2 = address!(allocator.alloc(4).unwrap());
-        allocator.free(ptr1).unwrap();
+    /// Round up the requested size `n` of an allocation, obeying the cap.
+    ///
+    /// This is the same as [`checked_size()`](Self::checked_size), but sizes
+    /// above the maximum size of a single allocation (see
+    /// [`set_max_alloc()`](Self::set_max_alloc)) are rejected as well.
+    fn capped_size(&self, n: usize) -> Option<usize> {
+        Self::checked_size(n).filter(|_| n <= self.max_alloc)
+    }
 
-        // now we have a free block, followed by a used block which in turn gets
-        // freed up. Therefore there are two contiguous free blocks, but those
-        // aren't concatenated, since the old free block is to the left (instead
-        // of to the right).
-        allocator.free(ptr2).unwrap();
+    /// Round up the requested size `n` of a new allocation, obeying the caps.
+    ///
+    /// This is the same as [`capped_size()`](Self::capped_size), but with the
+    /// `metrics` feature, the allocation is rejected as well, if the maximum
+    /// number of live allocations is reached (see
+    /// [`set_max_live_allocations()`](Self::set_max_live_allocations)).
+    fn new_block_size(&self, n: usize) -> Option<usize> {
+        #[cfg(feature = "metrics")]
+        if self.allocation_budget == 0 {
+            return None;
+        }
+        self.capped_size(n)
+    }
 
-        // therefore there must be two free blocks
-        assert_allocations!(allocator, Entry::free(4), Entry::free(4));
+    /// Allocate a new memory block of size `n` ending before offset `limit`.
+    ///
+    /// Only the memory before the offset `limit` is used for the allocation.
+    /// The free block, from which the memory is taken, might extend past that
+    /// limit though (in which case it is split).
+    fn alloc_below(&mut self, n: usize, limit: usize) -> Option<ValidatedOffset> {
+        let n = self.new_block_size(n)?;
+        let mut steps = 0;
+        let found = self.find_block(n, limit, &mut steps);
+        #[cfg(any(test, feature = "metrics"))]
+        {
+            self.scan_steps = self.scan_steps.saturating_add(steps);
+        }
+        let (offset, cached) = found?;
+        if cached {
+            self.recently_freed
+                .take_if(&self.buffer, |candidate, _entry| candidate == offset);
+            self.take_block(offset, n);
+            return Some(offset);
+        }
+        Some(self.alloc_in_block(offset, n, limit))
     }
 
-    #[test]
+    /// Find the free block for a new block of `n` bytes ending before `limit`.
+    ///
+    /// This is the search of [`alloc_below()`](Self::alloc_below) without
+    /// modifying the heap, so the size `n` has to be rounded up already. The
+    /// chosen block is returned together with whether it was found in the
+    /// cache of recently freed blocks (and has exactly `n` bytes therefore).
+    /// The number of visited free blocks is added to `steps`.
+    fn find_block(
+        &self,
+        n: usize,
+        limit: usize,
+        steps: &mut usize,
+    ) -> Option<(ValidatedOffset, bool)> {
+        let fits_below_limit = |offset: ValidatedOffset| offset.get() + HEADER_SIZE + n <= limit;
+
+        let cached = self.recently_freed.find(&self.buffer, n);
+        if let Some(offset) = cached.filter(|offset| fits_below_limit(*offset)) {
+            return Some((offset, true));
+        }
+
+        if self.strategy == Strategy::Frontier {
+            let frontier = self.frontier.filter(|offset| {
+                let entry = self.buffer[*offset];
+                entry.state() == State::Free && entry.size() >= n && fits_below_limit(*offset)
+            });
+            if let Some(offset) = frontier {
+                return Some((offset, false));
+            }
+        }
+
+        // the free list is not ordered, so ties are broken by the offsets
+        let candidates = self
+            .free_list
+            .blocks(&self.buffer, n < LINKS_SIZE)
+            .inspect(|_| *steps = steps.saturating_add(1))
+            .map(|offset| (offset, self.buffer[offset]))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .filter(|(_offset, entry)| entry.size() >= n)
+            .filter(|(offset, _entry)| fits_below_limit(*offset));
+        let (offset, _) = match self.strategy {
+            Strategy::BestFit => {
+                candidates.min_by_key(|(offset, entry)| (entry.size(), offset.get()))
+            }
+            Strategy::FirstFit => candidates.min_by_key(|(offset, _entry)| offset.get()),
+            Strategy::Frontier => {
+                candidates.min_by_key(|(offset, entry)| (Reverse(entry.size()), offset.get()))
+            }
+            Strategy::Custom(select) => {
+                let chosen =
+                    select(&mut candidates.map(|(offset, entry)| (offset.get(), entry.size())))?;
+                // only accept one of the offered blocks
+                self.free_list
+                    .blocks(&self.buffer, n < LINKS_SIZE)
+                    .find(|offset| offset.get() == chosen)
+                    .map(|offset| (offset, self.buffer[offset]))
+                    .filter(|(_offset, entry)| entry.state() == State::Free)
+                    .filter(|(_offset, entry)| entry.size() >= n)
+                    .filter(|(offset, _entry)| fits_below_limit(*offset))
+            }
+        }?;
+        Some((offset, false))
+    }
+
+    /// Allocate a block with `n` bytes at an address aligned to `align`.
+    ///
+    /// See [`alloc_aligned()`](Self::alloc_aligned) for details. Only the memory
+    /// before the offset `limit` is used for the allocation.
+    fn alloc_aligned_below(
+        &mut self,
+        n: usize,
+        align: usize,
+        limit: usize,
+    ) -> Option<ValidatedOffset> {
+        let n = self.new_block_size(n)?;
+        let mut steps = 0;
+        let found = self.find_aligned_block(n, align, limit, &mut steps);
+        #[cfg(any(test, feature = "metrics"))]
+        {
+            self.scan_steps = self.scan_steps.saturating_add(steps);
+        }
+        let (offset, start, cached) = found?;
+        if cached {
+            self.recently_freed
+                .take_if(&self.buffer, |candidate, _entry| candidate == offset);
+        }
+        let entry = self.buffer[offset];
+        let used = self.take_aligned(offset, entry, start, n, limit);
+        self.frontier = self.buffer.following_entry(used);
+        Some(used)
+    }
+
+    /// Find the free block for `n` bytes aligned to `align` before `limit`.
+    ///
+    /// This is the search of [`alloc_aligned_below()`] without modifying the
+    /// heap, so the size `n` has to be rounded up already. The chosen block is
+    /// returned together with the offset of the first suitably aligned address
+    /// in its memory and whether it was found in the cache of recently freed
+    /// blocks. The number of visited free blocks is added to `steps`.
+    ///
+    /// [`alloc_aligned_below()`]: Self::alloc_aligned_below
+    fn find_aligned_block(
+        &self,
+        n: usize,
+        align: usize,
+        limit: usize,
+        steps: &mut usize,
+    ) -> Option<(ValidatedOffset, usize, bool)> {
+        let base = self.buffer.as_ptr() as usize;
+        // the offset of the first aligned address in the memory of the block at
+        // `offset`, if the allocation fits behind it
+        let placement = |offset: ValidatedOffset, entry: Entry| {
+            let memory = offset.get() + HEADER_SIZE;
+            let start = Self::align_up(base, memory, align)?;
+            let end = start.checked_add(n)?;
+            (end <= limit.min(memory + entry.size())).then(|| start)
+        };
+
+        let cached = self.recently_freed.find_if(&self.buffer, |offset, entry| {
+            placement(offset, entry).is_some()
+        });
+        if let Some(offset) = cached {
+            let start = placement(offset, self.buffer[offset])?;
+            return Some((offset, start, true));
+        }
+
+        let candidates = self
+            .free_list
+            .blocks(&self.buffer, n < LINKS_SIZE)
+            .inspect(|_| *steps = steps.saturating_add(1))
+            .map(|offset| (offset, self.buffer[offset]))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .filter_map(|(offset, entry)| Some((offset, entry, placement(offset, entry)?)));
+        let (offset, _entry, start) = match self.strategy {
+            Strategy::BestFit => {
+                candidates.min_by_key(|(offset, entry, _start)| (entry.size(), offset.get()))
+            }
+            Strategy::FirstFit => candidates.min_by_key(|(offset, _entry, _start)| offset.get()),
+            Strategy::Frontier => candidates
+                .min_by_key(|(offset, entry, _start)| (Reverse(entry.size()), offset.get())),
+            Strategy::Custom(select) => {
+                let chosen = select(
+                    &mut candidates.map(|(offset, entry, _start)| (offset.get(), entry.size())),
+                )?;
+                // only accept one of the offered blocks
+                self.free_list
+                    .blocks(&self.buffer, n < LINKS_SIZE)
+                    .find(|offset| offset.get() == chosen)
+                    .map(|offset| (offset, self.buffer[offset]))
+                    .filter(|(_offset, entry)| entry.state() == State::Free)
+                    .and_then(|(offset, entry)| Some((offset, entry, placement(offset, entry)?)))
+            }
+        }?;
+        Some((offset, start, false))
+    }
+
+    /// Allocate `n` bytes at the offset `start` of the free block at `offset`.
+    ///
+    /// The `entry` is the header of that free block, whose memory has to
+    /// contain the `n` bytes at `start`. The memory in front of `start` and
+    /// behind the allocation is split off into free blocks. A small gap in
+    /// front of `start` is kept as padding, just like a small remainder after
+    /// the allocation is not split off (if the block ends before the offset
+    /// `limit`). The offset of the used block is returned.
+    fn take_aligned(
+        &mut self,
+        offset: ValidatedOffset,
+        entry: Entry,
+        start: usize,
+        n: usize,
+        limit: usize,
+    ) -> ValidatedOffset {
+        let gap = start - (offset.get() + HEADER_SIZE);
+        let header = if gap < HEADER_SIZE + self.split_threshold {
+            offset.get()
+        } else {
+            start - HEADER_SIZE
+        };
+        let block_end = offset.get() + HEADER_SIZE + entry.size();
+        let end = match (block_end - (start + n)).checked_sub(HEADER_SIZE) {
+            Some(remainder) if remainder < self.split_threshold && block_end <= limit => block_end,
+            _ => start + n,
+        };
+        self.wasted_tail_bytes = self.wasted_tail_bytes.saturating_add(end - (start + n));
+        self.take_block_before(offset, end, end - header - HEADER_SIZE)
+    }
+
+    /// Allocate `n` bytes from the start of the free block at `offset`.
+    ///
+    /// If the block is large enough, it is split into a used and a free one.
+    /// Tiny remainders are not split off but allocated as well. The following
+    /// block becomes the new frontier for the strategy [`Strategy::Frontier`].
+    fn alloc_in_block(
+        &mut self,
+        offset: ValidatedOffset,
+        n: usize,
+        limit: usize,
+    ) -> ValidatedOffset {
+        let size = self.block_size(offset, n, limit);
+        self.wasted_tail_bytes = self.wasted_tail_bytes.saturating_add(size - n);
+        self.take_block(offset, size);
+        self.frontier = self.buffer.following_entry(offset);
+        offset
+    }
+
+    /// Determine the size of the used block for `n` bytes in a free block.
+    ///
+    /// Usually the used block has exactly `n` bytes and the remaining memory is
+    /// split off into a new free block. If that new block would be smaller than
+    /// the split threshold, the whole free block is used instead (as long as
+    /// it ends before the offset `limit`).
+    fn block_size(&self, offset: ValidatedOffset, n: usize, limit: usize) -> usize {
+        let available = self.buffer[offset].size();
+        let whole_block_fits = offset.get() + HEADER_SIZE + available <= limit;
+        match (available - n).checked_sub(HEADER_SIZE) {
+            Some(remainder) if remainder < self.split_threshold && whole_block_fits => available,
+            _ => n,
+        }
+    }
+
+    /// Link the free block at `offset` into the free list.
+    ///
+    /// The links written into the memory of the block are recorded as touched,
+    /// so that [`reset_touched()`](Self::reset_touched) zeroes them.
+    fn link(&mut self, offset: ValidatedOffset) {
+        if self.free_list.insert(&mut self.buffer, offset) {
+            let end = offset.get() + HEADER_SIZE + LINKS_SIZE;
+            self.touched = self.touched.max(end);
+        }
+    }
+
+    /// Unlink the free block at `offset` from the free list.
+    ///
+    /// This has to be done before changing the header of the block.
+    fn unlink(&mut self, offset: ValidatedOffset) {
+        self.free_list.remove(&mut self.buffer, offset);
+    }
+
+    /// Allocate `size` bytes from the start of the free block at `offset`.
+    ///
+    /// This is [`Buffer::mark_as_used()`](buffer::Buffer::mark_as_used), which
+    /// keeps the free list up to date.
+    fn take_block(&mut self, offset: ValidatedOffset, size: usize) {
+        self.unlink(offset);
+        self.split_block(offset, size);
+    }
+
+    /// Allocate `size` bytes ending at `end` from the free block at `offset`.
+    ///
+    /// This is [`Buffer::mark_as_used_before()`], which keeps the free list up
+    /// to date. The offset of the used block is returned.
+    ///
+    /// [`Buffer::mark_as_used_before()`]: buffer::Buffer::mark_as_used_before
+    fn take_block_before(
+        &mut self,
+        offset: ValidatedOffset,
+        end: usize,
+        size: usize,
+    ) -> ValidatedOffset {
+        let block_end = offset.get() + HEADER_SIZE + self.buffer[offset].size();
+        self.unlink(offset);
+        let used = self.buffer.mark_as_used_before(offset, end, size);
+        if used != offset {
+            self.link(offset);
+        }
+        if end < block_end {
+            let following = self.buffer.following_entry(used);
+            self.link(following.expect("the remainder follows the used block"));
+        }
+        used
+    }
+
+    /// Shrink the unlinked block at `offset` to a used block of `size` bytes.
+    ///
+    /// The remaining memory (if any) is split off into a new free block, which
+    /// is linked into the free list.
+    fn split_block(&mut self, offset: ValidatedOffset, size: usize) {
+        let old_size = self.buffer[offset].size();
+        self.buffer.mark_as_used(offset, size);
+        if old_size > size {
+            let following = self.buffer.following_entry(offset);
+            self.link(following.expect("the remainder follows the used block"));
+        }
+    }
+
+    /// Merge the free block following the block at `offset` into that block.
+    ///
+    /// The following block (if any) is unlinked from the free list and the
+    /// size of the block at `offset` is increased accordingly, keeping its
+    /// state. The block at `offset` has to be unlinked as well, if it is free.
+    /// The new size of the block is returned. If the merged block would exceed
+    /// [`Entry::MAX_SIZE`], the blocks are left separate and the size of the
+    /// block at `offset` is returned unchanged.
+    fn absorb_following(&mut self, offset: ValidatedOffset) -> usize {
+        let entry = self.buffer[offset];
+        let following = self
+            .buffer
+            .following_entry(offset)
+            .filter(|following| self.buffer[*following].state() == State::Free);
+        let merged = following.and_then(|following| {
+            Self::merged_size(entry.size(), self.buffer[following].size())
+                .map(|size| (following, size))
+        });
+        let (following, size) = match merged {
+            Some(merged) => merged,
+            None => return entry.size(),
+        };
+        self.unlink(following);
+        self.buffer[offset] = Entry::new(entry.state(), size);
+        size
+    }
+
+    /// Compute the size of a block merged from two adjacent blocks.
+    ///
+    /// The merged block contains the memory of both blocks and the header of
+    /// the second one. If that size cannot be stored in an [`Entry`] (see
+    /// [`Entry::MAX_SIZE`]), `None` is returned, so that the blocks are never
+    /// merged into a block with a truncated size.
+    fn merged_size(left: usize, right: usize) -> Option<usize> {
+        left.checked_add(HEADER_SIZE)?
+            .checked_add(right)
+            .filter(|size| *size <= Entry::MAX_SIZE)
+    }
+
+    /// Record an allocation of `n` bytes and obtain the allocated memory.
+    ///
+    /// The `offset` is the allocated block or `None`, if the allocation failed.
+    /// A successful allocation is counted and it is recorded, that the block
+    /// might have been written to. Besides the block itself, the header
+    /// following it might have been written as well (when splitting a free
+    /// block), so it is included. With the `fill-on-alloc` feature, the memory
+    /// is filled with the [`FILL_PATTERN`].
+    #[cfg_attr(not(feature = "ring-log"), allow(unused_variables))]
+    fn allocated(
+        &mut self,
+        n: usize,
+        offset: Option<ValidatedOffset>,
+    ) -> Option<&mut [MaybeUninit<u8>]> {
+        #[cfg(feature = "ring-log")]
+        self.log
+            .record(Operation::Alloc, n, offset.map(ValidatedOffset::get));
+        if offset.is_none() {
+            self.count_demand(n);
+        }
+        let offset = offset?;
+        #[cfg(feature = "paranoid")]
+        if self.overlaps_used_block(offset) {
+            // the block is quarantined on purpose by leaving it marked as used:
+            // its memory overlaps a live allocation, so it can neither be
+            // handed out nor be returned to the free list
+            if let Some(handler) = self.overlap_handler {
+                handler(self.block_at(offset).0);
+            }
+            return None;
+        }
+        #[cfg(feature = "paranoid")]
+        debug_assert!(self.is_consistent(), "heap invariants violated");
+
+        self.total_allocations = self.total_allocations.saturating_add(1);
+        #[cfg(feature = "metrics")]
+        {
+            self.allocation_budget = self.allocation_budget.saturating_sub(1);
+        }
+        self.max_single_alloc = self.max_single_alloc.max(n);
+        self.count_rounding(n);
+        let end = offset.get() + HEADER_SIZE + self.buffer[offset].size();
+        self.touched = self.touched.max(N.min(end + HEADER_SIZE));
+        let memory = self.buffer.memory_of_mut(offset);
+        #[cfg(feature = "fill-on-alloc")]
+        memory.fill(MaybeUninit::new(FILL_PATTERN));
+        Some(memory)
+    }
+
+    /// Remember the heap size demanded by a failed allocation of `n` bytes.
+    ///
+    /// The demand is the memory of all the used blocks, the reserved memory and
+    /// the block, which could not be allocated, each including its header.
+    /// Sizes above the cap (see [`set_max_alloc()`](Self::set_max_alloc)) are
+    /// ignored, as they are rejected regardless of the heap size. The same
+    /// applies to the refusals due to the maximum number of live allocations.
+    /// This walks over all blocks, but only after an allocation failed.
+    fn count_demand(&mut self, n: usize) {
+        if n > self.max_alloc {
+            return;
+        }
+        #[cfg(feature = "metrics")]
+        if self.allocation_budget == 0 {
+            return;
+        }
+        let stats = self.stats();
+        let demand = Self::round_up(n)
+            .and_then(|n| n.checked_add(HEADER_SIZE))
+            .and_then(|block| block.checked_add(stats.used))
+            .and_then(|demand| demand.checked_add(stats.used_blocks * HEADER_SIZE))
+            .and_then(|demand| demand.checked_add(self.reserved))
+            .unwrap_or(usize::MAX);
+        self.peak_demand = self.peak_demand.max(demand);
+    }
+
+    /// Check, whether the just allocated block at `offset` overlaps another one.
+    ///
+    /// The block was found via the free list or the cache of recently freed
+    /// blocks, which might point anywhere, if they are corrupted (e.g. by a
+    /// write to freed memory). Therefore the chain of headers is walked from
+    /// the start of the heap and every other used block is compared with the
+    /// new one. This takes time linear to the number of blocks.
+    #[cfg(feature = "paranoid")]
+    fn overlaps_used_block(&self, offset: ValidatedOffset) -> bool {
+        let start = offset.get();
+        let end = start + HEADER_SIZE + self.buffer[offset].size();
+        self.buffer
+            .entries()
+            .filter(|other| *other != offset)
+            .any(|other| {
+                let entry = self.buffer[other];
+                let other_end = other.get() + HEADER_SIZE + entry.size();
+                entry.state() != State::Free && other.get() < end && start < other_end
+            })
+    }
+
+    /// Free all allocations and zero the memory, which was used so far.
+    ///
+    /// This resets the heap to a single free block, just like a freshly created
+    /// allocator. Only the memory up to the highest address ever allocated or
+    /// written by the free list (the "high-water mark") is overwritten with
+    /// zeros, as the memory after it was never handed out. This is faster than
+    /// zeroing the whole heap, if only a small part of the heap was used. The
+    /// settings (e.g. the reservation or the strategy) are kept.
+    pub fn reset_touched(&mut self) {
+        self.reset(self.touched, 0);
+    }
+
+    /// Free all allocations and fill the whole heap memory with `byte`.
+    ///
+    /// Afterwards the heap consists of a single free block, just like after
+    /// [`reset_touched()`](Self::reset_touched), but every byte except for the
+    /// first header and its free-list links contains `byte`. This allows tests
+    /// to detect the bytes written by the allocator.
+    #[cfg(any(test, debug_assertions))]
+    pub fn clear_to_pattern(&mut self, byte: u8) {
+        self.reset(N, byte);
+    }
+
+    /// Reset the heap to a single free block, filling the first `len` bytes.
+    fn reset(&mut self, len: usize, byte: u8) {
+        #[cfg(feature = "metrics")]
+        {
+            let stats = self.stats();
+            let freed =
+                u32::try_from(stats.used_blocks + stats.reserved_blocks).unwrap_or(u32::MAX);
+            self.allocation_budget = self.allocation_budget.saturating_add(freed);
+        }
+        self.buffer.reset(len, byte);
+        self.max_single_alloc = 0;
+        self.rounding = 0;
+        self.free_list.rebuild(&mut self.buffer);
+        self.recently_freed.clear();
+        self.frontier = None;
+        self.touched = HEADER_SIZE;
+        self.quarantine = None;
+        #[cfg(feature = "relocating")]
+        self.handles.clear();
+    }
+
+    /// Set the strategy for choosing a free block when scanning the heap.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    /// Query the strategy for choosing a free block when scanning the heap.
+    pub const fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    /// Set the minimum size of a free block remaining after splitting a block.
+    ///
+    /// When allocating from a free block, which is larger than requested, the
+    /// remaining memory is split off into a new free block. If that block would
+    /// have less than `threshold` bytes, the block is not split. Instead, the
+    /// whole block is allocated, which over-allocates slightly, but avoids tiny
+    /// free blocks, which are unlikely to be used but slow down the scans.
+    pub fn set_split_threshold(&mut self, threshold: usize) {
+        self.split_threshold = threshold;
+    }
+
+    /// Set the maximum size of a single allocation.
+    ///
+    /// Every allocation (and growing an allocation in place) requesting more
+    /// than `cap` bytes fails immediately without scanning the heap.
+    pub fn set_max_alloc(&mut self, cap: usize) {
+        self.max_alloc = cap;
+    }
+
+    /// Set the maximum number of live allocations.
+    ///
+    /// Every allocation fails immediately without scanning the heap, while
+    /// there are `cap` live allocations (or more). Freeing an allocation allows
+    /// a new one again. Every used (or reserved) block counts as a live
+    /// allocation, so this walks over all blocks once to count them.
+    #[cfg(feature = "metrics")]
+    pub fn set_max_live_allocations(&mut self, cap: usize) {
+        let stats = self.stats();
+        let live = stats.used_blocks + stats.reserved_blocks;
+        self.allocation_budget = u32::try_from(cap.saturating_sub(live)).unwrap_or(u32::MAX);
+    }
+
+    /// Free a pointer inside a used memory block.
+    ///
+    /// This method is used to release a memory block allocated with this raw
+    /// allocator. If a entry to the given pointer is found, the corresponding
+    /// memory block is marked as free. If no entry is found, than an error is
+    /// reported (as allocators are not allowed to unwind).
+    ///
+    /// # Algorithm
+    /// Freeing a pointer is done in the following way: all the entries are
+    /// scanned linearly. The pointer is compared against each block. If the
+    /// pointer points to the memory of an entry, than that entry is selected.
+    /// If no such entry is found, than the user tried to free an allocation,
+    /// that was not allocated with this allocator (or the allocator messed up
+    /// internally). [`FreeError::AllocationNotFound`] is reported.
+    ///
+    /// The selected block is tested for its state. If it is marked as "used",
+    /// than everything is fine. If it is already marked as "free", than
+    /// [`FreeError::DoubleFreeDetected`] is returned, while a reserved block is
+    /// reported as [`FreeError::ReservedBlock`]. If the block following
+    /// the just freed up one is also free, the two blocks are concatenated to a
+    /// single one (to prevent fragmentation). The freed block is linked into
+    /// the free list and remembered for fast reuse by [`alloc()`](Self::alloc).
+    ///
+    /// With the `zeroize-on-free` feature, the memory of the block is
+    /// overwritten with zeros before it is marked as free. Afterwards the
+    /// free-list links (the offsets of the neighbouring free blocks) are
+    /// written to the first 8 bytes of that memory, so only those stay in the
+    /// freed memory.
+    pub fn free(&mut self, ptr: *mut u8) -> Result<(), FreeError> {
+        let result = self.free_block(ptr);
+        self.freed(result)
+    }
+
+    /// Free the used memory block, whose memory starts at `offset`.
+    ///
+    /// This is the same as [`free()`](Self::free), but the block is not
+    /// searched by scanning the entries. Instead its header is read directly in
+    /// front of the memory. The offset has to be a multiple of 4 and the block
+    /// has to be inside of the heap, otherwise [`FreeError::AllocationNotFound`]
+    /// is returned. A free block is reported as [`FreeError::DoubleFreeDetected`]
+    /// and a reserved block as [`FreeError::ReservedBlock`].
+    ///
+    /// # Safety
+    /// The offset has to be the start of the memory of a block. This cannot be
+    /// checked without scanning the heap: any other offset might be mistaken
+    /// for a header, which corrupts the heap.
+    pub unsafe fn free_at(&mut self, offset: usize) -> Result<(), FreeError> {
+        let header = offset
+            .checked_sub(HEADER_SIZE)
+            // SAFETY: the caller guarantees, that this is a header
+            .and_then(|header| unsafe { self.buffer.header_at(header) });
+        let result = header
+            .ok_or(FreeError::AllocationNotFound)
+            .and_then(|header| self.free_entry(header));
+        self.freed(result)
+    }
+
+    /// Finish freeing a block, which resulted in `result`.
+    ///
+    /// The operation is recorded and, with the `paranoid` feature, the
+    /// invariants of the heap are checked.
+    #[cfg_attr(not(feature = "ring-log"), allow(clippy::needless_pass_by_ref_mut))]
+    #[cfg_attr(
+        not(any(feature = "ring-log", feature = "paranoid")),
+        allow(clippy::unused_self)
+    )]
+    fn freed(
+        &mut self,
+        result: Result<(ValidatedOffset, usize), FreeError>,
+    ) -> Result<(), FreeError> {
+        #[cfg(feature = "ring-log")]
+        self.log.record(
+            Operation::Free,
+            result.map_or(0, |(_offset, size)| size),
+            result.ok().map(|(offset, _size)| offset.get()),
+        );
+        #[cfg(feature = "paranoid")]
+        debug_assert!(self.is_consistent(), "heap invariants violated");
+        result.map(|_| ())
+    }
+
+    /// Check the invariants of the heap.
+    ///
+    /// The entries have to tile the whole buffer: every header is directly
+    /// followed by the memory of its block and the next header, while the last
+    /// block ends exactly at the end of the buffer. Furthermore all the block
+    /// sizes have to be multiples of 4. The free list has to contain exactly
+    /// the free blocks (see [`FreeList::is_consistent()`]). This walks over all
+    /// the entries, so it is only checked after every allocation and
+    /// deallocation in debug builds with the `paranoid` feature (and by the
+    /// fuzzing harness and [`recover()`](Self::recover)).
+    pub fn is_consistent(&self) -> bool {
+        let mut end = 0;
+        let aligned_sizes = self.buffer.entries().all(|offset| {
+            let size = self.buffer[offset].size();
+            end = offset.get() + HEADER_SIZE + size;
+            size % HEADER_SIZE == 0
+        });
+        aligned_sizes && end == N && self.free_list.is_consistent(&self.buffer)
+    }
+
+    /// Check the heap for corruption.
+    ///
+    /// This checks the invariants of the heap (see
+    /// [`is_consistent()`](Self::is_consistent)) and, with the `guard-block`
+    /// feature, that the guard block behind the heap is intact.
+    pub fn verify(&self) -> bool {
+        #[cfg(feature = "guard-block")]
+        if !self.buffer.is_guard_intact() {
+            return false;
+        }
+        self.is_consistent()
+    }
+
+    /// Try to recover from a corrupted chain of headers.
+    ///
+    /// The heap is rescanned from its start and the memory starting at the
+    /// first invalid header is quarantined as a single used block (see
+    /// [`Buffer::quarantine_invalid()`](buffer::Buffer::quarantine_invalid)).
+    /// As the recently freed blocks and the frontier might refer to that
+    /// memory, they are forgotten. The free list is rebuilt, even if all the
+    /// headers are valid. Afterwards the heap is consistent again.
+    pub fn recover(&mut self) -> RecoveryReport {
+        let corrupted_at = self.buffer.quarantine_invalid();
+        let quarantined = corrupted_at.map_or(0, |offset| N - offset);
+        // the links are stored in the free memory, which is prone to be
+        // corrupted as well, so they are rebuilt in any case
+        self.free_list.rebuild(&mut self.buffer);
+        if let Some(offset) = corrupted_at {
+            self.recently_freed.clear();
+            self.frontier = None;
+            self.touched = N;
+            self.quarantine = Some(offset);
+        }
+        debug_assert!(self.is_consistent(), "heap invariants violated");
+        RecoveryReport {
+            corrupted_at,
+            quarantined,
+        }
+    }
+
+    /// Forget the headers, which were merged into the block at `offset`.
+    ///
+    /// The block ends at the offset `end`. The recently freed blocks inside of
+    /// it are forgotten and a frontier inside of it is moved to the start of
+    /// the merged block, as this block contains the memory of the frontier.
+    fn forget_merged(&mut self, offset: ValidatedOffset, end: usize) {
+        let merged = offset.get() + 1..end;
+        self.recently_freed.forget(merged.start, merged.end);
+        if self
+            .frontier
+            .map_or(false, |frontier| merged.contains(&frontier.get()))
+        {
+            self.frontier = Some(offset);
+        }
+    }
+
+    /// Free the used memory block containing `ptr`.
+    ///
+    /// See [`free()`](Self::free) for details. The offset and the size of the
+    /// freed block (before merging it with the following block) are returned.
+    fn free_block(&mut self, ptr: *mut u8) -> Result<(ValidatedOffset, usize), FreeError> {
+        #[cfg(not(any(test, feature = "metrics")))]
+        let mut entries = self.buffer.entries();
+        #[cfg(any(test, feature = "metrics"))]
+        let mut entries = self
+            .buffer
+            .entries()
+            .inspect(|_| self.scan_steps = self.scan_steps.saturating_add(1));
+        let offset = entries
+            .find(|offset| self.buffer.memory_contains(*offset, ptr))
+            .ok_or(FreeError::AllocationNotFound)?;
+        self.free_entry(offset)
+    }
+
+    /// Free the used memory block at `offset`.
+    ///
+    /// See [`free_block()`](Self::free_block) for details.
+    fn free_entry(
+        &mut self,
+        offset: ValidatedOffset,
+    ) -> Result<(ValidatedOffset, usize), FreeError> {
+        let entry = self.buffer[offset];
+        match entry.state() {
+            State::Used => {}
+            State::Free => return Err(FreeError::DoubleFreeDetected),
+            State::Reserved => return Err(FreeError::ReservedBlock),
+        }
+        #[cfg(feature = "metrics")]
+        {
+            self.allocation_budget = self.allocation_budget.saturating_add(1);
+        }
+        if self.is_quarantined(offset) {
+            // the memory is leaked, as the block might be shared with other
+            // allocations
+            return Ok((offset, 0));
+        }
+        #[cfg(feature = "zeroize-on-free")]
+        self.buffer.memory_of_mut(offset).fill(MaybeUninit::new(0));
+        let size = self.absorb_following(offset);
+        self.buffer[offset] = Entry::free(size);
+        self.link(offset);
+        self.forget_merged(offset, offset.get() + HEADER_SIZE + size);
+        self.recently_freed.insert(offset);
+        self.total_frees = self.total_frees.saturating_add(1);
+        Ok((offset, entry.size()))
+    }
+
+    /// Merge all adjacent free blocks.
+    ///
+    /// Freeing a block only merges it with the following free block, so two
+    /// adjacent free blocks remain, if the block in front of the freed block
+    /// was free already. This walks over all entries and merges every run of
+    /// adjacent free blocks into a single free block.
+    pub fn coalesce_free_blocks(&mut self) {
+        let mut current = self.buffer.entries().next();
+        while let Some(offset) = current {
+            let entry = self.buffer[offset];
+            let mergeable = self
+                .buffer
+                .following_free_entry(offset)
+                .and_then(|following| Self::merged_size(entry.size(), following.size()))
+                .is_some();
+            if entry.state() == State::Free && mergeable {
+                // the current block might be merged with further blocks, so
+                // stay at the current block
+                self.unlink(offset);
+                let size = self.absorb_following(offset);
+                self.link(offset);
+                self.forget_merged(offset, offset.get() + HEADER_SIZE + size);
+                continue;
+            }
+            current = self.buffer.following_entry(offset);
+        }
+    }
+
+    /// Query the average number of scan steps per allocation or deallocation.
+    ///
+    /// A scan step is a free block visited by an allocation or an entry visited
+    /// by a deallocation while searching for the block containing the pointer.
+    /// The steps of all operations so far (including failed allocations) are
+    /// divided by the number of successful allocations and deallocations. If
+    /// there were no such operations yet, `0` is returned.
+    #[cfg(feature = "metrics")]
+    #[allow(clippy::cast_precision_loss)] // an approximation is sufficient
+    pub fn avg_scan_steps(&self) -> f32 {
+        let operations = self.total_allocations.saturating_add(self.total_frees);
+        if operations == 0 {
+            return 0.0;
+        }
+        self.scan_steps as f32 / operations as f32
+    }
+
+    /// Query the number of bytes wasted by not splitting off tiny remainders.
+    ///
+    /// If the remainder of a free block after an allocation is too small for a
+    /// new free block (see [`set_split_threshold()`](Self::set_split_threshold)),
+    /// the whole block is allocated. The additional bytes of all allocations so
+    /// far are counted, the counter saturates at `usize::MAX`.
+    pub const fn wasted_tail_bytes(&self) -> usize {
+        self.wasted_tail_bytes
+    }
+
+    /// Query the largest size of a single allocation so far.
+    ///
+    /// This is the largest size requested by a successful allocation since the
+    /// creation of the heap or its last reset (see
+    /// [`reset_touched()`](Self::reset_touched)).
+    pub const fn max_single_alloc(&self) -> usize {
+        self.max_single_alloc
+    }
+
+    /// Query the heap size, which would have avoided the failed allocations.
+    ///
+    /// This is the largest demand of a failed allocation since the creation of
+    /// the heap (see [`count_demand()`](Self::count_demand)), i.e. the heap
+    /// size required, if there was no fragmentation. If no allocation has
+    /// failed so far, `None` is returned.
+    pub const fn recommended_min_size(&self) -> Option<usize> {
+        match self.peak_demand {
+            0 => None,
+            demand => Some(demand),
+        }
+    }
+
+    /// Query the number of bytes lost to rounding up the live allocations.
+    ///
+    /// Every allocation of `n` bytes is rounded up to a multiple of 4. The
+    /// difference is counted for every successful allocation and has to be
+    /// removed via [`forget_rounding()`](Self::forget_rounding) again, when
+    /// the allocation is freed, as the requested size is not stored in the
+    /// heap.
+    pub const fn internal_fragmentation(&self) -> usize {
+        self.rounding
+    }
+
+    /// Count the rounding of an allocation of `n` bytes.
+    ///
+    /// This is done for every successful allocation automatically, but it is
+    /// necessary for allocations changing their size in place as well.
+    pub fn count_rounding(&mut self, n: usize) {
+        self.rounding = self.rounding.saturating_add(Self::rounding_of(n));
+    }
+
+    /// Stop counting the rounding of an allocation of `n` bytes.
+    ///
+    /// This is the counterpart of [`count_rounding()`](Self::count_rounding),
+    /// which is used, when the allocation is freed (or changes its size).
+    pub fn forget_rounding(&mut self, n: usize) {
+        self.rounding = self.rounding.saturating_sub(Self::rounding_of(n));
+    }
+
+    /// Compute the number of bytes, by which a size of `n` is rounded up.
+    const fn rounding_of(n: usize) -> usize {
+        n.wrapping_neg() & (HEADER_SIZE - 1)
+    }
+
+    /// Copy the most recent operations into `out` (oldest first).
+    ///
+    /// The number of copied operations is returned.
+    #[cfg(feature = "ring-log")]
+    pub fn recent_ops(&self, out: &mut [LogEntry]) -> usize {
+        self.log.copy_to(out)
+    }
+
+    /// Set the handler for errors detected while freeing memory.
+    ///
+    /// The handler is not called by [`free()`](Self::free) itself, as that
+    /// returns the error anyway. Instead, it is queried via
+    /// [`dealloc_error_handler()`](Self::dealloc_error_handler) by the callers,
+    /// which cannot report the error otherwise.
+    pub fn set_dealloc_error_handler(&mut self, handler: Option<fn(FreeError)>) {
+        self.dealloc_error_handler = handler;
+    }
+
+    /// Query the handler for errors detected while freeing memory (if any).
+    pub const fn dealloc_error_handler(&self) -> Option<fn(FreeError)> {
+        self.dealloc_error_handler
+    }
+
+    /// Set the handler for allocations refused due to an overlap.
+    ///
+    /// The handler is called with the refused block, whenever the check of
+    /// the `paranoid` feature finds a new allocation overlapping a used block.
+    #[cfg(feature = "paranoid")]
+    pub fn set_overlap_handler(&mut self, handler: Option<fn(BlockInfo)>) {
+        self.overlap_handler = handler;
+    }
+
+    /// Query the number of bytes usable from `ptr` up to the end of its block.
+    ///
+    /// If `ptr` does not point into a used block (or into the quarantined one,
+    /// which might be shared by several allocations), `None` is returned.
+    pub fn usable_size(&self, ptr: *const u8) -> Option<usize> {
+        let offset = self
+            .entry_containing(ptr)
+            .filter(|offset| self.buffer[*offset].state() == State::Used)
+            .filter(|offset| !self.is_quarantined(*offset))?;
+        let memory = self.buffer.memory_of(offset);
+        Some(memory.as_ptr() as usize + memory.len() - ptr as usize)
+    }
+
+    /// Check, whether the allocation containing `ptr` can grow in place.
+    ///
+    /// This checks, if the memory from `ptr` up to `ptr + new_size` fits into
+    /// the used block containing `ptr` and the free block following it (if
+    /// any). In that case, the allocation could be extended without moving the
+    /// data. If `ptr` does not point into a used block, `false` is returned.
+    pub fn can_grow_in_place(&self, ptr: *const u8, new_size: usize) -> bool {
+        let offset = match self.entry_containing(ptr) {
+            Some(offset)
+                if self.buffer[offset].state() == State::Used && !self.is_quarantined(offset) =>
+            {
+                offset
+            }
+            _ => return false,
+        };
+        let available = self.buffer[offset].size()
+            + self
+                .buffer
+                .following_free_entry(offset)
+                .map_or(0, |entry| entry.size() + HEADER_SIZE);
+        let start = self.buffer.memory_of(offset).as_ptr();
+        let used_before_ptr = ptr as usize - start as usize;
+        used_before_ptr
+            .checked_add(new_size)
+            .map_or(false, |required| required <= available)
+    }
+
+    /// Grow the allocation containing `ptr` in place.
+    ///
+    /// If the memory from `ptr` up to `ptr + new_size` fits into the used block
+    /// containing `ptr` and the free block following it (if any), the required
+    /// part of the free block is merged into the used block. The remainder of
+    /// the free block is split off again (unless it is smaller than the split
+    /// threshold). The reserved memory at the end of the heap is never used.
+    /// The number of bytes usable starting at `ptr` is returned.
+    ///
+    /// If the allocation cannot grow in place (or if `ptr` does not point into
+    /// a used block), nothing is changed and `None` is returned.
+    #[cfg(feature = "allocator-api")]
+    pub fn grow_in_place(&mut self, ptr: *const u8, new_size: usize) -> Option<usize> {
+        let offset = self
+            .entry_containing(ptr)
+            .filter(|offset| self.buffer[*offset].state() == State::Used)
+            .filter(|offset| !self.is_quarantined(*offset))?;
+        let size = self.buffer[offset].size();
+        let used_before_ptr = ptr as usize - self.buffer.memory_of(offset).as_ptr() as usize;
+        let required = self.capped_size(used_before_ptr.checked_add(new_size)?)?;
+        if required <= size {
+            return Some(size - used_before_ptr);
+        }
+
+        let limit = N - self.reserved;
+        let next = self.buffer.following_free_entry(offset)?;
+        let available = Self::merged_size(size, next.size())?;
+        if required > available || offset.get() + HEADER_SIZE + required > limit {
+            return None;
+        }
+        // treat both blocks as a single block, from which the memory is
+        // allocated as usual
+        self.absorb_following(offset);
+        self.forget_merged(offset, offset.get() + HEADER_SIZE + available);
+        let size = self.block_size(offset, required, limit);
+        self.split_block(offset, size);
+        let end = offset.get() + HEADER_SIZE + size;
+        self.touched = self.touched.max(N.min(end + HEADER_SIZE));
+        Some(size - used_before_ptr)
+    }
+
+    /// Shrink the allocation containing `ptr` in place.
+    ///
+    /// The used block containing `ptr` is reduced, so that the memory from
+    /// `ptr` up to `ptr + new_size` still fits. The memory after it is split
+    /// off into a free block, which is merged with the following free block
+    /// (if any). If the split off memory would be smaller than the split
+    /// threshold, the block is kept as is. The number of bytes usable starting
+    /// at `ptr` is returned.
+    ///
+    /// If the allocation would grow (or if `ptr` does not point into a used
+    /// block), nothing is changed and `None` is returned.
+    #[cfg(feature = "allocator-api")]
+    pub fn shrink_in_place(&mut self, ptr: *const u8, new_size: usize) -> Option<usize> {
+        let offset = self
+            .entry_containing(ptr)
+            .filter(|offset| self.buffer[*offset].state() == State::Used)
+            .filter(|offset| !self.is_quarantined(*offset))?;
+        let size = self.buffer[offset].size();
+        let used_before_ptr = ptr as usize - self.buffer.memory_of(offset).as_ptr() as usize;
+        let required = Self::checked_size(used_before_ptr.checked_add(new_size)?)?;
+        if required > size {
+            return None;
+        }
+
+        let following = self
+            .buffer
+            .following_free_entry(offset)
+            .filter(|entry| Self::merged_size(size, entry.size()).is_some())
+            .map_or(0, |entry| entry.size() + HEADER_SIZE);
+        match (size - required + following).checked_sub(HEADER_SIZE) {
+            Some(free) if following > 0 || free >= self.split_threshold => {
+                #[cfg(feature = "zeroize-on-free")]
+                self.buffer.memory_of_mut(offset)[required..].fill(MaybeUninit::new(0));
+                self.absorb_following(offset);
+                self.forget_merged(offset, offset.get() + HEADER_SIZE + size + following);
+                self.split_block(offset, required);
+                Some(required - used_before_ptr)
+            }
+            _ => Some(size - used_before_ptr),
+        }
+    }
+
+    /// Merge the two adjacent used blocks containing `a` and `b`.
+    ///
+    /// If the blocks are distinct and directly follow each other in the heap
+    /// (in any order), the header of the latter block is merged into the
+    /// memory of the former one, which now covers the memory of both. The one
+    /// of `a` and `b` pointing into the former block is returned. Otherwise
+    /// (or if a block is not used, quarantined or would exceed
+    /// [`Entry::MAX_SIZE`]) nothing is changed and `None` is returned. With
+    /// the `relocating` feature, the latter block must not be referenced by a
+    /// handle either, as the handle would point into the middle of a block.
+    pub fn try_merge(&mut self, a: *const u8, b: *const u8) -> Option<*const u8> {
+        let used = |offset: &ValidatedOffset| {
+            self.buffer[*offset].state() == State::Used && !self.is_quarantined(*offset)
+        };
+        let first = self.entry_containing(a).filter(used)?;
+        let second = self.entry_containing(b).filter(used)?;
+        let (former, latter, ptr) = if first.get() < second.get() {
+            (first, second, a)
+        } else {
+            (second, first, b)
+        };
+        if self.buffer.following_entry(former) != Some(latter) {
+            return None;
+        }
+        #[cfg(feature = "relocating")]
+        if NonZeroUsize::new(latter.get() + HEADER_SIZE)
+            .map_or(false, |offset| self.handles.contains(offset))
+        {
+            return None;
+        }
+
+        let size = Self::merged_size(self.buffer[former].size(), self.buffer[latter].size())?;
+        self.buffer[former] = Entry::used(size);
+        self.forget_merged(former, former.get() + HEADER_SIZE + size);
+        #[cfg(feature = "metrics")]
+        {
+            self.allocation_budget = self.allocation_budget.saturating_add(1);
+        }
+        Some(ptr)
+    }
+
+    /// Query the first entry of the heap.
+    pub fn first_entry(&self) -> Option<ValidatedOffset> {
+        self.buffer.entries().next()
+    }
+
+    /// Find the first free block starting at the entry at `offset`.
+    ///
+    /// The offset and the size of the free block are returned together with
+    /// the entry following it (if any), at which the search can continue.
+    pub fn next_free_block(
+        &self,
+        offset: ValidatedOffset,
+    ) -> Option<((usize, usize), Option<ValidatedOffset>)> {
+        let mut entries = self.buffer.entries_from(offset);
+        let offset = entries.find(|offset| self.buffer[*offset].state() == State::Free)?;
+        Some(((offset.get(), self.buffer[offset].size()), entries.next()))
+    }
+
+    /// Find the first run of adjacent free blocks starting at the entry at `offset`.
+    ///
+    /// A run consists of at least two free blocks, which would be merged by
+    /// [`coalesce_free_blocks()`](Self::coalesce_free_blocks). The offset of
+    /// its first block and the size of the merged block are returned together
+    /// with the entry following the run (if any), at which the search can
+    /// continue. Like there, a run ends before a block, which would exceed
+    /// [`Entry::MAX_SIZE`].
+    pub fn next_free_run(
+        &self,
+        offset: ValidatedOffset,
+    ) -> Option<((usize, usize), Option<ValidatedOffset>)> {
+        let mut current = Some(offset);
+        while let Some(offset) = current {
+            let mut size = self.buffer[offset].size();
+            let mut next = self.buffer.following_entry(offset);
+            if self.buffer[offset].state() == State::Free {
+                let mut merged = false;
+                while let Some(following) = next {
+                    let entry = self.buffer[following];
+                    match Self::merged_size(size, entry.size()) {
+                        Some(total) if entry.state() == State::Free => size = total,
+                        _ => break,
+                    }
+                    merged = true;
+                    next = self.buffer.following_entry(following);
+                }
+                if merged {
+                    return Some(((offset.get(), size), next));
+                }
+            }
+            current = next;
+        }
+        None
+    }
+
+    /// Query the metadata of the block, whose memory contains `ptr`.
+    ///
+    /// If the pointer does not point into the memory of any block (e.g. if it
+    /// points to a header or outside of the heap), `None` is returned.
+    pub fn block_of(&self, ptr: *const u8) -> Option<BlockInfo> {
+        let offset = self.entry_containing(ptr)?;
+        Some(self.block_at(offset).0)
+    }
+
+    /// Query the metadata of the block at `offset`.
+    ///
+    /// The entry following the block (if any) is returned as well, so that the
+    /// blocks can be walked without borrowing the allocator.
+    pub fn block_at(&self, offset: ValidatedOffset) -> (BlockInfo, Option<ValidatedOffset>) {
+        let entry = self.buffer[offset];
+        let info = BlockInfo {
+            offset: offset.get(),
+            size: entry.size(),
+            state: entry.state(),
+        };
+        (info, self.buffer.following_entry(offset))
+    }
+
+    /// Check whether both pointers point into the memory of the same block.
+    ///
+    /// The heap is scanned only once to find the block containing `a`, then
+    /// `b` is compared against the memory range of that block.
+    pub fn same_block(&self, a: *const u8, b: *const u8) -> bool {
+        self.entry_containing(a).map_or(false, |offset| {
+            let size = self.buffer[offset].size();
+            let start = self.buffer.memory_of(offset).as_ptr().cast::<u8>();
+            let end = start.wrapping_add(size);
+
+            start <= b && b < end
+        })
+    }
+
+    /// Check whether the block at `offset` was quarantined by [`recover()`].
+    ///
+    /// [`recover()`]: Self::recover
+    fn is_quarantined(&self, offset: ValidatedOffset) -> bool {
+        self.quarantine == Some(offset.get())
+    }
+
+    /// Change the state of the block containing `ptr` from `from` to `to`.
+    ///
+    /// This marks a used block as reserved (see [`State::Reserved`]) or commits
+    /// a reserved block. Both states have to be occupied ones, as free blocks
+    /// are linked into the free list. If `ptr` does not point into a block in
+    /// the state `from` (or into the quarantined block), `false` is returned
+    /// and nothing is changed.
+    pub fn set_state(&mut self, ptr: *const u8, from: State, to: State) -> bool {
+        debug_assert!(from != State::Free && to != State::Free);
+        let offset = match self.entry_containing(ptr) {
+            Some(offset) if self.buffer[offset].state() == from && !self.is_quarantined(offset) => {
+                offset
+            }
+            _ => return false,
+        };
+        self.buffer[offset] = Entry::new(to, self.buffer[offset].size());
+        true
+    }
+
+    /// Find the entry, whose memory contains the given pointer.
+    ///
+    /// All the entries are scanned linearly and the pointer is compared against
+    /// the memory of each block. The pointer may point anywhere into the memory
+    /// of the block, not necessarily to its start.
+    fn entry_containing(&self, ptr: *const u8) -> Option<ValidatedOffset> {
+        self.buffer
+            .entries()
+            .find(|offset| self.buffer.memory_contains(*offset, ptr))
+    }
+
+    /// Compact the heap by moving all used blocks to the start of the heap.
+    ///
+    /// Afterwards, all the free memory is contained in a single block at the
+    /// end of the heap. Every moved block is reported to the `relocate`
+    /// callback with the old and new address of its memory and its size.
+    #[cfg(feature = "relocating")]
+    pub fn compact(&mut self, mut relocate: impl FnMut(*mut u8, *mut u8, usize)) {
+        let base = self.buffer.as_ptr() as usize;
+        let handles = &mut self.handles;
+        let quarantine = &mut self.quarantine;
+        self.buffer.compact(|old, new, size| {
+            if let (Some(from), Some(to)) = (
+                NonZeroUsize::new(old as usize - base),
+                NonZeroUsize::new(new as usize - base),
+            ) {
+                handles.relocate(from, to);
+            }
+            if *quarantine == Some(old as usize - base - HEADER_SIZE) {
+                *quarantine = Some(new as usize - base - HEADER_SIZE);
+            }
+            relocate(old, new, size);
+        });
+        self.free_list.rebuild(&mut self.buffer);
+        self.recently_freed.clear();
+        self.frontier = None;
+        // the old copies of the moved blocks are now part of the free memory
+        #[cfg(feature = "zeroize-on-free")]
+        self.zero_free_memory();
+    }
+
+    /// Obtain a pointer to the start of the heap memory.
+    pub const fn as_ptr(&self) -> *const u8 {
+        self.buffer.as_ptr()
+    }
+
+    /// Write zeros to the memory of all free blocks.
+    ///
+    /// This does not change the logical state of the heap, as only memory not
+    /// belonging to any allocation is written. The links of the free list at
+    /// the start of the memory of the free blocks are kept. On an empty heap,
+    /// this touches the whole buffer except for the first header.
+    pub fn zero_free_memory(&mut self) {
+        let mut current = self.buffer.entries().next();
+        while let Some(offset) = current {
+            if self.buffer[offset].state() == State::Free {
+                let memory = self.buffer.memory_of_mut(offset);
+                let links = if memory.len() >= LINKS_SIZE {
+                    LINKS_SIZE
+                } else {
+                    0
+                };
+                memory[links..].fill(MaybeUninit::new(0));
+            }
+            current = self.buffer.following_entry(offset);
+        }
+    }
+
+    /// Collect the usage statistics of the heap.
+    ///
+    /// This walks over all entries once and sums up the sizes of the used,
+    /// reserved and free blocks.
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            size: N,
+            used: 0,
+            free: 0,
+            reserved: 0,
+            used_blocks: 0,
+            free_blocks: 0,
+            reserved_blocks: 0,
+            largest_free: 0,
+            total_allocations: self.total_allocations,
+            total_frees: self.total_frees,
+        };
+        for entry in self.buffer.entries().map(|offset| self.buffer[offset]) {
+            match entry.state() {
+                State::Used => {
+                    stats.used += entry.size();
+                    stats.used_blocks += 1;
+                }
+                State::Reserved => {
+                    stats.reserved += entry.size();
+                    stats.reserved_blocks += 1;
+                }
+                State::Free => {
+                    stats.free += entry.size();
+                    stats.free_blocks += 1;
+                    stats.largest_free = stats.largest_free.max(entry.size());
+                }
+            }
+        }
+        stats
+    }
+
+    /// Count the free blocks.
+    ///
+    /// This is the same as [`HeapStats::free_blocks`], but only tracks that
+    /// single number while walking over the entries.
+    pub fn free_block_count(&self) -> usize {
+        self.buffer
+            .entries()
+            .filter(|offset| self.buffer[*offset].state() == State::Free)
+            .count()
+    }
+
+    /// Query the number of bytes spent on the headers of all blocks.
+    ///
+    /// This walks over all entries and counts them.
+    pub fn overhead_bytes(&self) -> usize {
+        self.buffer.entries().count() * HEADER_SIZE
+    }
+
+    /// Query the size of the largest free block.
+    ///
+    /// This is the same as [`HeapStats::largest_free`], but only tracks that
+    /// single number while walking over the entries.
+    pub fn largest_free_block(&self) -> usize {
+        self.buffer
+            .entries()
+            .map(|offset| self.buffer[offset])
+            .filter(|entry| entry.state() == State::Free)
+            .map(Entry::size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Query the size of the largest allocation aligned to `align`, that would
+    /// currently succeed.
+    ///
+    /// This is the largest amount of memory behind the first aligned address in
+    /// any free block, as used by [`alloc_aligned()`](Self::alloc_aligned). The
+    /// `align` has to be a power of two. In contrast to
+    /// [`largest_free_block()`](Self::largest_free_block), the reserved memory
+    /// at the end of the heap is excluded, as it is not used by
+    /// [`alloc()`](Self::alloc).
+    pub fn largest_aligned_allocation(&self, align: usize) -> usize {
+        let base = self.buffer.as_ptr() as usize;
+        let limit = N - self.reserved;
+        self.buffer
+            .entries()
+            .map(|offset| (offset, self.buffer[offset]))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .filter_map(|(offset, entry)| {
+                let memory = offset.get() + HEADER_SIZE;
+                let start = Self::align_up(base, memory, align)?;
+                limit.min(memory + entry.size()).checked_sub(start)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "zeroize-on-free")]
+    use super::buffer::{Link, LINKS_SIZE};
+    use super::{
+        Entry, FreeError, RawAllocator, RecoveryReport, State, Strategy, GRANULARITY, HEADER_SIZE,
+    };
+    use core::mem::MaybeUninit;
+    #[cfg(feature = "guard-block")]
+    use core::ptr;
+
+    /// Test, that the given allocator has exactly the given entries.
+    macro_rules! assert_allocations {
+        ($allocator:expr, $($entry:expr),*$(,)?) => {{
+            let mut iter = $allocator
+                .buffer
+                .entries()
+                .map(|offset| $allocator.buffer[offset]);
+            $(assert_eq!(iter.next(), Some($entry));)*
+            assert_eq!(iter.next(), None);
+        }};
+    }
+
+    #[test]
+    fn successful_single_allocation() {
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.alloc(4).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(32 - 2 * HEADER_SIZE - GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn unsuccessful_single_allocation() {
+        // the allocation is larger than the buffer itself
+        let mut allocator = RawAllocator::<32>::new();
+        assert!(allocator.alloc(36).is_none());
+        assert_allocations!(allocator, Entry::free(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn allocation_near_overflow() {
+        // the sizes (and the computations with them) must not wrap around
+        let mut allocator = RawAllocator::<32>::new();
+        for n in [
+            usize::MAX,
+            usize::MAX - 3,
+            usize::MAX - 4,
+            usize::MAX / 2 + 1,
+        ] {
+            assert!(allocator.alloc(n).is_none());
+            assert!(allocator.alloc_reserved(n).is_none());
+            assert!(allocator.alloc_from_top(n).is_none());
+            assert!(!allocator.reserve(n));
+        }
+        assert_allocations!(allocator, Entry::free(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn successful_multiple_allocation() {
+        let mut allocator = RawAllocator::<32>::new();
+        let n = 16 - HEADER_SIZE;
+        allocator.alloc(n).unwrap();
+        allocator.alloc(n).unwrap();
+        // allocator is now full
+        assert_allocations!(allocator, Entry::used(n), Entry::used(n));
+    }
+
+    #[test]
+    fn unsuccessful_multiple_allocation() {
+        let mut allocator = RawAllocator::<32>::new();
+        let n = 16 - HEADER_SIZE;
+        allocator.alloc(n).unwrap();
+        // the second allocation is larger than the remaining space
+        assert!(allocator.alloc(n + 1).is_none());
+        assert_allocations!(allocator, Entry::used(n), Entry::free(n));
+    }
+
+    macro_rules! address {
+        ($memory:expr) => {
+            $memory.as_mut_ptr().cast::<u8>()
+        };
+    }
+
+    #[test]
+    fn allocation_of_the_whole_heap() {
+        // the largest possible allocation uses the whole buffer for a single
+        // used block without any (zero-sized) free block after it
+        let mut allocator = RawAllocator::<32>::new();
+        let n = 32 - HEADER_SIZE;
+        let ptr = address!(allocator.alloc(n).unwrap());
+        assert_allocations!(allocator, Entry::used(n));
+        assert!(allocator.alloc(0).is_none());
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(n));
+
+        // the same holds for the allocation at the end of the heap
+        let ptr = address!(allocator.alloc_from_top(n).unwrap());
+        assert_allocations!(allocator, Entry::used(n));
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(n));
+    }
+
+    #[test]
+    fn allocation_larger_than_the_whole_heap() {
+        let mut allocator = RawAllocator::<32>::new();
+        for n in [32 - HEADER_SIZE + 1, 31, 32] {
+            assert!(allocator.alloc(n).is_none());
+            assert!(allocator.alloc_reserved(n).is_none());
+            assert!(allocator.alloc_from_top(n).is_none());
+        }
+        assert_allocations!(allocator, Entry::free(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn ties_are_broken_by_the_address() {
+        // two blocks of 32 bytes, each followed by a small used block, and a
+        // remaining free block too small for the allocations below
+        const N: usize = 2 * (HEADER_SIZE + 32) + 3 * (HEADER_SIZE + GRANULARITY);
+        for strategy in [Strategy::BestFit, Strategy::FirstFit, Strategy::Frontier] {
+            let mut allocator = RawAllocator::<N>::new();
+            allocator.set_strategy(strategy);
+            let low = address!(allocator.alloc(32).unwrap());
+            allocator.alloc(4).unwrap();
+            let high = address!(allocator.alloc(32).unwrap());
+            allocator.alloc(4).unwrap();
+            // the higher block is freed last, so it is the most recently freed
+            // one. It is not reused, as the allocations do not fit exactly.
+            allocator.free(low).unwrap();
+            allocator.free(high).unwrap();
+
+            for _ in 0..3 {
+                let ptr = address!(allocator.alloc(12).unwrap());
+                assert_eq!(ptr, low);
+                allocator.free(ptr).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn best_fit_keeps_large_blocks_available() {
+        // a churny workload of small allocations with periodic attempts of a
+        // large allocation, which only succeeds with a large free block
+        let large_allocations = |strategy, seed: u64| {
+            let mut allocator = RawAllocator::<2048>::new();
+            allocator.set_strategy(strategy);
+            let mut rng = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+            let mut random = |n: u64| {
+                rng ^= rng << 13;
+                rng ^= rng >> 7;
+                rng ^= rng << 17;
+                usize::try_from(rng % n).unwrap()
+            };
+            let mut live = Vec::new();
+            let mut successes = 0;
+            for step in 0..1000 {
+                if live.len() < 40 && (live.is_empty() || random(3) != 0) {
+                    if let Some(memory) = allocator.alloc(random(60) + 4) {
+                        live.push(address!(memory));
+                    }
+                } else {
+                    let ptr = live.swap_remove(random(live.len() as u64));
+                    allocator.free(ptr).unwrap();
+                }
+                if step % 10 == 0 {
+                    if let Some(memory) = allocator.alloc(400) {
+                        let ptr = address!(memory);
+                        allocator.free(ptr).unwrap();
+                        successes += 1;
+                    }
+                }
+            }
+            successes
+        };
+
+        let seeds = 0..10;
+        let best_fit: usize = seeds
+            .clone()
+            .map(|seed| large_allocations(Strategy::BestFit, seed))
+            .sum();
+        let first_fit: usize = seeds
+            .map(|seed| large_allocations(Strategy::FirstFit, seed))
+            .sum();
+        assert!(best_fit > first_fit, "{} vs. {}", best_fit, first_fit);
+    }
+
+    #[test]
+    fn allocations_above_the_cap_fail_without_scanning() {
+        let mut allocator = RawAllocator::<256>::new();
+        allocator.alloc(8).unwrap();
+        allocator.set_max_alloc(16);
+        allocator.scan_steps = 0;
+
+        assert!(allocator.alloc(17).is_none());
+        assert!(allocator.alloc_aligned(17, 16).is_none());
+        assert!(allocator.alloc_from_top(17).is_none());
+        assert!(allocator.alloc_at(128, 17).is_none());
+        assert_eq!(allocator.scan_steps, 0);
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(256 - 2 * HEADER_SIZE - 8)
+        );
+
+        // the cap itself is allowed
+        assert!(allocator.alloc(16).is_some());
+        assert!(allocator.alloc(13).is_some());
+        let rest = 256 - 4 * HEADER_SIZE - 40;
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(16),
+            Entry::used(16),
+            Entry::free(rest)
+        );
+
+        allocator.set_max_alloc(usize::MAX);
+        assert!(allocator.alloc(rest).is_some());
+    }
+
+    #[test]
+    fn frontier_avoids_scans() {
+        let mut allocator = RawAllocator::<1024>::new();
+        allocator.set_strategy(Strategy::Frontier);
+        let ptrs: Vec<_> = (0..50)
+            .map(|_| address!(allocator.alloc(4).unwrap()) as usize)
+            .collect();
+
+        // only the first allocation scans the heap, the others are placed one
+        // after the other at the frontier
+        assert_eq!(allocator.scan_steps, 1);
+        assert!(ptrs
+            .windows(2)
+            .all(|pair| pair[1] == pair[0] + HEADER_SIZE + GRANULARITY));
+
+        // the same burst requires a full scan for every allocation, if the free
+        // list is not used
+        let mut best_fit = RawAllocator::<1024>::new();
+        best_fit.free_list.scan = true;
+        for _ in 0..50 {
+            best_fit.alloc(4).unwrap();
+        }
+        assert!(best_fit.scan_steps > 50 * 50 / 2);
+
+        // freeing still merges the blocks as usual
+        for &ptr in ptrs.iter().rev() {
+            allocator.free(ptr as *mut u8).unwrap();
+        }
+        assert_allocations!(allocator, Entry::free(1024 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn frontier_follows_merges() {
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.set_strategy(Strategy::Frontier);
+        let first = address!(allocator.alloc(8).unwrap());
+        let second = address!(allocator.alloc(8).unwrap());
+
+        // the freed block is merged with the frontier, so it is used next
+        allocator.free(second).unwrap();
+        let steps = allocator.scan_steps;
+        assert_eq!(address!(allocator.alloc(16).unwrap()), second);
+        assert_eq!(allocator.scan_steps, steps);
+
+        // an exhausted frontier falls back to the largest free block
+        let rest = 64 - 3 * HEADER_SIZE - 24;
+        allocator.free(first).unwrap();
+        allocator.alloc(rest).unwrap();
+        assert_eq!(address!(allocator.alloc(4).unwrap()), first);
+        assert!(allocator.scan_steps > steps);
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(16),
+            Entry::used(rest)
+        );
+    }
+
+    #[test]
+    fn unsuccessful_allocation_due_to_fragmentation() {
+        // this test case shows, that the allocator is susceptible to memory
+        // fragmentation, which makes larger allocations impossible, if the
+        // heap is in a bad state.
+        let mut allocator = RawAllocator::<{ 5 * (HEADER_SIZE + 8) }>::new();
+
+        // build a fragmented heap
+        let ptr1 = address!(allocator.alloc(8).unwrap());
+        let _ptr2 = address!(allocator.alloc(8).unwrap());
+        let ptr3 = address!(allocator.alloc(8).unwrap());
+        let _ptr4 = address!(allocator.alloc(8).unwrap());
+        let ptr5 = address!(allocator.alloc(8).unwrap());
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr3).unwrap();
+        allocator.free(ptr5).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(8),
+            Entry::used(8),
+            Entry::free(8),
+            Entry::used(8),
+            Entry::free(8)
+        );
+
+        // now, there are 24 free bytes (3x 8 bytes) and the headers, but the
+        // allocation of 10 bytes must fail, since there is no contiguous memory
+        // of that size
+        assert!(allocator.alloc(10).is_none());
+    }
+
+    #[test]
+    fn simple_free() {
+        let mut allocator = RawAllocator::<{ 2 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+
+        // now, that the heap is properly built up, there are two used entries.
+        // when the first one is freed up, there is no possibility for merging
+        // the free memory with the following one (as that one is used)
+        allocator.free(ptr).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn double_free() {
+        let mut allocator = RawAllocator::<{ 2 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+
+        // try to free up the pointer twice. The first time has to succeed, but
+        // the second time has to result in a double-free-error.
+        allocator.free(ptr).unwrap();
+        assert_eq!(allocator.free(ptr), Err(FreeError::DoubleFreeDetected));
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn invalid_free() {
+        use core::ptr;
+
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.alloc(4).unwrap();
+
+        // try to free up a pointer, that was not allocated by this allocator.
+        // This invalid usage has to be detected.
+        let mut x = 0_u32;
+        let ptr = ptr::addr_of_mut!(x).cast();
+        assert_eq!(allocator.free(ptr), Err(FreeError::AllocationNotFound));
+    }
+
+    #[test]
+    fn free_of_modified_pointer() {
+        let mut allocator = RawAllocator::<{ 2 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+
+        let ptr = ptr.wrapping_add(3);
+        // now there is a valid pointer, but this pointer was modified, e.g. to
+        // be aligned properly. As the `free()`-call should support any pointer
+        // into the memory block, this should succeed.
+        allocator.free(ptr).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn free_with_concatenation() {
+        let mut allocator = RawAllocator::<32>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(32 - 2 * HEADER_SIZE - GRANULARITY)
+        );
+
+        // now there is a used block followed by a free block. When the used
+        // block is freed up as well, this should lead to a single free block.
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn free_at_end() {
+        let mut allocator = RawAllocator::<{ 2 * (HEADER_SIZE + GRANULARITY) }>::new();
+        allocator.alloc(4).unwrap();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+
+        // now, that the heap is properly built up, there are two used entries.
+        // when the second one is freed up, there is no possibility for merging
+        // the free memory with the following one (as there is no following one)
+        allocator.free(ptr).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn free_impossible_defrag() {
+        let mut allocator = RawAllocator::<{ 2 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let ptr1 = address!(allocator.alloc(4).unwrap());
+        let ptr2 = address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr1).unwrap();
+
+        // now we have a free block, followed by a used block which in turn gets
+        // freed up. Therefore there are two contiguous free blocks, but those
+        // aren't concatenated, since the old free block is to the left (instead
+        // of to the right).
+        allocator.free(ptr2).unwrap();
+
+        // therefore there must be two free blocks
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::free(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn coalesce_free_blocks() {
+        const N: usize = 8 * (HEADER_SIZE + GRANULARITY);
+        // the size of two merged blocks of the smallest size
+        const MERGED: usize = HEADER_SIZE + 2 * GRANULARITY;
+        let mut allocator = RawAllocator::<N>::new();
+        let ptrs = [(); 6].map(|_| address!(allocator.alloc(4).unwrap()));
+        for &i in &[0, 1, 3, 4] {
+            allocator.free(ptrs[i]).unwrap();
+        }
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(GRANULARITY),
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(MERGED),
+        );
+
+        allocator.coalesce_free_blocks();
+        assert_allocations!(
+            allocator,
+            Entry::free(MERGED),
+            Entry::used(GRANULARITY),
+            Entry::free(MERGED),
+            Entry::used(GRANULARITY),
+            Entry::free(MERGED),
+        );
+
+        // the recently freed blocks are gone, so they are not reused
+        let ptr = address!(allocator.alloc(4).unwrap());
+        assert_eq!(ptr, ptrs[0]);
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(MERGED),
+            Entry::used(GRANULARITY),
+            Entry::free(MERGED),
+        );
+
+        allocator.free(ptrs[2]).unwrap();
+        allocator.free(ptrs[5]).unwrap();
+        allocator.free(ptr).unwrap();
+        allocator.coalesce_free_blocks();
+        assert_allocations!(allocator, Entry::free(N - HEADER_SIZE));
+    }
+
+    #[test]
+    fn merged_size_near_the_limit() {
+        type Allocator = RawAllocator<32>;
+        assert_eq!(Allocator::merged_size(8, 4), Some(12 + HEADER_SIZE));
+        assert_eq!(
+            Allocator::merged_size(Entry::MAX_SIZE - HEADER_SIZE - GRANULARITY, GRANULARITY),
+            Some(Entry::MAX_SIZE)
+        );
+        assert_eq!(
+            Allocator::merged_size(0, Entry::MAX_SIZE - HEADER_SIZE),
+            Some(Entry::MAX_SIZE)
+        );
+
+        // the merged size would not fit into the header, so it is refused
+        // instead of wrapping around
+        assert_eq!(
+            Allocator::merged_size(Entry::MAX_SIZE - HEADER_SIZE, GRANULARITY),
+            None
+        );
+        assert_eq!(Allocator::merged_size(Entry::MAX_SIZE, 0), None);
+        assert_eq!(Allocator::merged_size(1 << 30, 1 << 30), None);
+        assert_eq!(Allocator::merged_size(usize::MAX, 4), None);
+        assert_eq!(Allocator::merged_size(4, usize::MAX - 4), None);
+    }
+
+    #[test]
     fn alloc_impossible_splitting() {
+        // the middle block can hold two of the smallest blocks
+        const MIDDLE: usize = HEADER_SIZE + 2 * GRANULARITY;
+        let mut allocator = RawAllocator::<{ 4 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let _ptr1 = address!(allocator.alloc(4).unwrap());
+        let ptr2 = address!(allocator.alloc(MIDDLE).unwrap());
+        let _ptr3 = address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr2).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(MIDDLE),
+            Entry::used(GRANULARITY)
+        );
+
+        // new we've set up the heap such there is a free block of `MIDDLE` in
+        // the middle (and no free data at the end). If one acquires a block of
+        // size 4 everything should work fine and the free block should be split
+        // up.
+        let ptr4 = address!(allocator.alloc(4).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+        allocator.free(ptr4).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(MIDDLE),
+            Entry::used(GRANULARITY)
+        );
+
+        // now the previous state is restored. If there is an allocation for a
+        // size of `MIDDLE`, no splitting must be happening, since the block is
+        // only `MIDDLE` bytes of size, so splitting would tamper the following
+        // block.
+        let _ptr5 = address!(allocator.alloc(MIDDLE).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(MIDDLE),
+            Entry::used(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn reuse_recently_freed_block() {
+        let mut allocator = RawAllocator::<128>::new();
+        let ptr1 = address!(allocator.alloc(8).unwrap());
+        let _ptr2 = address!(allocator.alloc(4).unwrap());
+        let ptr3 = address!(allocator.alloc(8).unwrap());
+        let _ptr4 = address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr3).unwrap();
+
+        // there are two free blocks of size 8 now. A scan would choose the
+        // first one, but the most recently freed one is reused without any
+        // scanning instead.
+        let steps = allocator.scan_steps;
+        assert_eq!(address!(allocator.alloc(8).unwrap()), ptr3);
+        assert_eq!(allocator.scan_steps, steps);
+
+        // the other block is still cached as well
+        assert_eq!(address!(allocator.alloc(5).unwrap()), ptr1);
+        assert_eq!(allocator.scan_steps, steps);
+
+        // now there is no cached block anymore, so the heap is scanned
+        allocator.alloc(8).unwrap();
+        assert!(allocator.scan_steps > steps);
+    }
+
+    #[test]
+    fn recently_freed_blocks_of_other_size_are_not_reused() {
+        let mut allocator = RawAllocator::<64>::new();
+        let ptr = address!(allocator.alloc(16).unwrap());
+        allocator.alloc(4).unwrap();
+        allocator.free(ptr).unwrap();
+
+        // the freed block is larger than necessary, so a regular scan happens
+        let steps = allocator.scan_steps;
+        allocator.alloc(4).unwrap();
+        assert!(allocator.scan_steps > steps);
+    }
+
+    #[test]
+    fn recently_freed_blocks_are_forgotten_when_merged() {
+        const MERGED: usize = HEADER_SIZE + 2 * GRANULARITY;
+        let mut allocator = RawAllocator::<{ 4 * (HEADER_SIZE + GRANULARITY) }>::new();
+        let ptr1 = address!(allocator.alloc(4).unwrap());
+        let ptr2 = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+        allocator.free(ptr2).unwrap();
+        allocator.free(ptr1).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(MERGED),
+            Entry::used(GRANULARITY),
+            Entry::free(GRANULARITY)
+        );
+
+        // the block of `ptr2` does not exist anymore after merging, so it must
+        // not be handed out again. Instead the regular best fit is chosen.
+        assert_ne!(address!(allocator.alloc(4).unwrap()), ptr2);
+        assert_allocations!(
+            allocator,
+            Entry::free(MERGED),
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn reserved_memory() {
+        let mut allocator = RawAllocator::<64>::new();
+        assert!(allocator.reserve(7));
+        // the last header and 8 bytes must stay free, so the first allocation
+        // is split right before that memory
+        let n = 64 - 2 * HEADER_SIZE - 8;
+        assert!(allocator.alloc(n + 1).is_none());
+        allocator.alloc(n).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::free(8));
+        assert!(allocator.alloc(4).is_none());
+
+        // the reserved memory can be used by the special allocation only
+        let ptr = address!(allocator.alloc_reserved(8).unwrap());
+        assert_allocations!(allocator, Entry::used(n), Entry::used(8));
+        assert!(allocator.alloc_reserved(4).is_none());
+
+        // after freeing, the reserve is available again
+        allocator.free(ptr).unwrap();
+        assert!(allocator.alloc(4).is_none());
+        allocator.alloc_reserved(4).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::used(8));
+    }
+
+    #[test]
+    fn reserve_requires_free_memory() {
+        let mut allocator = RawAllocator::<32>::new();
+        let n = 16 - HEADER_SIZE;
+        allocator.alloc(n).unwrap();
+        assert!(!allocator.reserve(n + 1));
+        assert!(allocator.reserve(n));
+        assert!(!allocator.reserve(usize::MAX - 2));
+
+        // the reservation can be removed again
+        assert!(allocator.reserve(0));
+        allocator.alloc(n).unwrap();
+        assert!(!allocator.reserve(1));
+    }
+
+    #[test]
+    fn recently_freed_block_in_reserved_memory() {
+        let mut allocator = RawAllocator::<32>::new();
+        let n = 16 - HEADER_SIZE;
+        allocator.alloc(n).unwrap();
+        let ptr = address!(allocator.alloc(n).unwrap());
+        allocator.free(ptr).unwrap();
+        assert!(allocator.reserve(n));
+
+        // the freed block is cached, but it is part of the reserved memory
+        assert!(allocator.alloc(n).is_none());
+        assert_eq!(address!(allocator.alloc_reserved(n).unwrap()), ptr);
+    }
+
+    #[test]
+    fn zero_sized_block_at_the_end() {
+        // splitting the last block might leave just enough memory for a header
+        // at the end of the buffer. That zero-sized block must not get lost.
+        let mut allocator = RawAllocator::<{ 2 * HEADER_SIZE + 8 }>::new();
+        allocator.set_split_threshold(0);
+        let ptr = address!(allocator.alloc(8).unwrap());
+        assert_allocations!(allocator, Entry::used(8), Entry::free(0));
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(HEADER_SIZE + 8));
+    }
+
+    #[test]
+    #[cfg(feature = "zeroize-on-free")]
+    fn zeroize_on_free() {
+        let mut allocator = RawAllocator::<64>::new();
+        let memory = allocator.alloc(24).unwrap();
+        memory.fill(MaybeUninit::new(0xAA));
+        let ptr = address!(memory);
+        allocator.alloc(4).unwrap().fill(MaybeUninit::new(0xBB));
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(24),
+            Entry::used(GRANULARITY),
+            Entry::free(64 - 3 * HEADER_SIZE - 24 - GRANULARITY)
+        );
+        let mut entries = allocator.buffer.entries();
+        let is = |memory: &[MaybeUninit<u8>], value| {
+            memory
+                .iter()
+                .all(|byte| unsafe { byte.assume_init() } == value)
+        };
+        // the memory of the freed block only contains its free-list links
+        let freed = entries.next().unwrap();
+        assert!(is(&allocator.buffer.memory_of(freed)[LINKS_SIZE..], 0));
+        assert!(is(
+            allocator.buffer.memory_of(entries.next().unwrap()),
+            0xBB
+        ));
+        assert_eq!(allocator.buffer.link(freed, Link::Previous), None);
+        assert_eq!(allocator.buffer.link(freed, Link::Next), entries.next());
+    }
+
+    #[test]
+    fn block_of() {
+        let mut allocator = RawAllocator::<32>::new();
+        let first = address!(allocator.alloc(4).unwrap());
+        let second = address!(allocator.alloc(8).unwrap());
+        allocator.free(first).unwrap();
+
+        let offset = HEADER_SIZE + GRANULARITY;
+        let info = allocator.block_of(second).unwrap();
+        assert_eq!(
+            (info.offset, info.size, info.state),
+            (offset, 8, State::Used)
+        );
+        let info = allocator.block_of(second.wrapping_add(7)).unwrap();
+        assert_eq!(
+            (info.offset, info.size, info.state),
+            (offset, 8, State::Used)
+        );
+        let info = allocator.block_of(first).unwrap();
+        assert_eq!(
+            (info.offset, info.size, info.state),
+            (0, GRANULARITY, State::Free)
+        );
+
+        // headers and memory outside of the heap do not belong to any block
+        assert_eq!(allocator.block_of(second.wrapping_sub(1)), None);
+        assert_eq!(allocator.block_of(second.wrapping_add(8)), None);
+        assert_eq!(
+            allocator.block_of(allocator.as_ptr().wrapping_add(32)),
+            None
+        );
+    }
+
+    #[test]
+    fn split_threshold() {
+        // without a threshold, even blocks without any memory are split off
         let mut allocator = RawAllocator::<32>::new();
-        let _ptr1 = address!(allocator.alloc(4).unwrap());
-        let ptr2 = address!(allocator.alloc(12).unwrap());
-        let _ptr3 = address!(allocator.alloc(4).unwrap());
+        let n = 32 - 2 * HEADER_SIZE;
+        allocator.set_split_threshold(0);
+        allocator.alloc(n).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::free(0));
+        assert_eq!(allocator.free_block_count(), 1);
+
+        // by default, those useless blocks are avoided
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.alloc(n).unwrap();
+        assert_allocations!(allocator, Entry::used(32 - HEADER_SIZE));
+        assert_eq!(allocator.free_block_count(), 0);
+
+        // larger thresholds avoid larger fragments
+        let n = 32 - 2 * HEADER_SIZE - 8;
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.set_split_threshold(8);
+        allocator.alloc(n).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::free(8));
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.set_split_threshold(8);
+        allocator.alloc(n + GRANULARITY).unwrap();
+        assert_allocations!(allocator, Entry::used(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn split_threshold_respects_reserved_memory() {
+        const N: usize = 4 * (HEADER_SIZE + GRANULARITY);
+        const REST: usize = HEADER_SIZE + 2 * GRANULARITY;
+        let mut allocator = RawAllocator::<N>::new();
+        allocator.set_split_threshold(16);
+        assert!(allocator.reserve(4));
+        let n = N - 2 * HEADER_SIZE - REST;
+        allocator.alloc(n).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::free(REST));
+        allocator.alloc_reserved(4).unwrap();
+        assert_allocations!(allocator, Entry::used(n), Entry::used(REST));
+    }
+
+    #[test]
+    fn can_grow_in_place() {
+        const N: usize = 8 * (HEADER_SIZE + GRANULARITY);
+        const REST: usize = N - 5 * HEADER_SIZE - 3 * GRANULARITY - 8;
+        let mut allocator = RawAllocator::<N>::new();
+        let ptr1 = address!(allocator.alloc(4).unwrap());
+        let ptr2 = address!(allocator.alloc(8).unwrap());
+        let ptr3 = address!(allocator.alloc(4).unwrap());
+        let ptr4 = address!(allocator.alloc(4).unwrap());
         allocator.free(ptr2).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::free(12), Entry::used(4));
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(8),
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(REST)
+        );
 
-        // new we've set up the heap such there is a free block of 12 in the
-        // middle (and no free data at the end). If one acquires a block of size
-        // 4 everything should work fine and the free block should be split up.;
+        // the next block is free and large enough (the header can be used too)
+        let next = GRANULARITY + HEADER_SIZE + 8;
+        assert!(allocator.can_grow_in_place(ptr1, next));
+        assert!(allocator.can_grow_in_place(ptr1.wrapping_add(2), next - 2));
+        // the next block is free, but too small
+        assert!(!allocator.can_grow_in_place(ptr1, next + 1));
+        assert!(!allocator.can_grow_in_place(ptr1.wrapping_add(2), next - 1));
+        // the next block is used, so only the block itself is available
+        assert!(allocator.can_grow_in_place(ptr3, GRANULARITY));
+        assert!(!allocator.can_grow_in_place(ptr3, GRANULARITY + 1));
+        // the last used block is followed by the remaining free memory
+        let last = GRANULARITY + HEADER_SIZE + REST;
+        assert!(allocator.can_grow_in_place(ptr4, last));
+        assert!(!allocator.can_grow_in_place(ptr4, last + 1));
+        // free blocks and foreign pointers cannot grow
+        assert!(!allocator.can_grow_in_place(ptr2, 4));
+        assert!(!allocator.can_grow_in_place(&0_u8, 1));
+    }
+
+    #[test]
+    #[cfg(feature = "allocator-api")]
+    fn grow_in_place() {
+        const N: usize = 8 * (HEADER_SIZE + GRANULARITY);
+        const REST: usize = N - 5 * HEADER_SIZE - 3 * GRANULARITY - 8;
+        let mut allocator = RawAllocator::<N>::new();
+        let ptr1 = address!(allocator.alloc(4).unwrap());
+        let ptr2 = address!(allocator.alloc(8).unwrap());
+        let ptr3 = address!(allocator.alloc(4).unwrap());
         let ptr4 = address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr2).unwrap();
+
+        // the block itself is large enough, so nothing changes
+        assert_eq!(allocator.grow_in_place(ptr1, 3), Some(GRANULARITY));
+        // the following free block is too small or the next block is used
+        let next = GRANULARITY + HEADER_SIZE + 8;
+        assert_eq!(allocator.grow_in_place(ptr1, next + 1), None);
+        assert_eq!(allocator.grow_in_place(ptr3, GRANULARITY + 1), None);
+        // free blocks cannot grow
+        assert_eq!(allocator.grow_in_place(ptr2, 4), None);
         assert_allocations!(
             allocator,
-            Entry::used(4),
-            Entry::used(4),
-            Entry::free(4),
-            Entry::used(4)
+            Entry::used(GRANULARITY),
+            Entry::free(8),
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(REST)
         );
-        allocator.free(ptr4).unwrap();
-        assert_allocations!(allocator, Entry::used(4), Entry::free(12), Entry::used(4));
 
-        // now the previous state is restored. If there is an allocation for a
-        // size of 12, no splitting must be happening, since the block is only
-        // 12 bytes of size, so splitting would tamper the following block.
-        let _ptr5 = address!(allocator.alloc(12).unwrap());
-        assert_allocations!(allocator, Entry::used(4), Entry::used(12), Entry::used(4));
+        // the following free block is used exactly
+        assert_eq!(allocator.grow_in_place(ptr1, next), Some(next));
+        assert_allocations!(
+            allocator,
+            Entry::used(next),
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(REST)
+        );
+
+        // the remainder of the following block is split off again
+        let grown = 3 * GRANULARITY;
+        assert_eq!(
+            allocator.grow_in_place(ptr4.wrapping_add(2), grown - 2),
+            Some(grown - 2)
+        );
+        assert_allocations!(
+            allocator,
+            Entry::used(next),
+            Entry::used(GRANULARITY),
+            Entry::used(grown),
+            Entry::free(REST - 2 * GRANULARITY)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "allocator-api")]
+    fn grow_in_place_respects_reserved_memory() {
+        const N: usize = 4 * (HEADER_SIZE + GRANULARITY);
+        let mut allocator = RawAllocator::<N>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        assert!(allocator.reserve(8));
+        let n = N - 2 * HEADER_SIZE - 8;
+        assert_eq!(allocator.grow_in_place(ptr, n + 1), None);
+        assert_eq!(allocator.grow_in_place(ptr, n), Some(n));
+        assert_allocations!(allocator, Entry::used(n), Entry::free(8));
+    }
+
+    #[test]
+    #[cfg(feature = "allocator-api")]
+    fn shrink_in_place() {
+        let mut allocator = RawAllocator::<{ 3 * HEADER_SIZE + 64 }>::new();
+        let ptr1 = address!(allocator.alloc(24).unwrap());
+        let ptr2 = address!(allocator.alloc(24).unwrap());
+        assert_allocations!(allocator, Entry::used(24), Entry::used(24), Entry::free(16));
+
+        // growing and invalid pointers are rejected
+        assert_eq!(allocator.shrink_in_place(ptr1, 25), None);
+        assert_eq!(allocator.shrink_in_place(&0_u8, 1), None);
+        // a remainder smaller than the split threshold is kept
+        allocator.set_split_threshold(16);
+        assert_eq!(allocator.shrink_in_place(ptr1, 9), Some(24));
+        assert_eq!(allocator.shrink_in_place(ptr1.wrapping_add(4), 4), Some(20));
+        assert_allocations!(allocator, Entry::used(24), Entry::used(24), Entry::free(16));
+
+        // the remainder is split off into a new free block
+        allocator.set_split_threshold(super::DEFAULT_SPLIT_THRESHOLD);
+        assert_eq!(allocator.shrink_in_place(ptr1, 7), Some(8));
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(16 - HEADER_SIZE),
+            Entry::used(24),
+            Entry::free(16)
+        );
+        // the remainder is merged with the following free block
+        assert_eq!(allocator.shrink_in_place(ptr2, 1), Some(GRANULARITY));
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(16 - HEADER_SIZE),
+            Entry::used(GRANULARITY),
+            Entry::free(40 - GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn aligned_allocation_splits_off_the_gap() {
+        let mut allocator = RawAllocator::<256>::new();
+        let base = allocator.as_ptr() as usize;
+        let start = (base + HEADER_SIZE + 63) / 64 * 64 - base;
+        let memory = allocator.alloc_aligned(8, 64).unwrap();
+        let padding = start - (memory.as_ptr() as usize - base);
+        let gap = start - HEADER_SIZE;
+        let rest = 256 - 2 * HEADER_SIZE - 8 - gap;
+        if gap < 2 * HEADER_SIZE {
+            // a tiny gap is kept as padding in the used block
+            assert_eq!(padding, gap);
+            assert_allocations!(allocator, Entry::used(gap + 8), Entry::free(rest));
+        } else {
+            assert_eq!(padding, 0);
+            assert_allocations!(
+                allocator,
+                Entry::free(gap - HEADER_SIZE),
+                Entry::used(8),
+                Entry::free(rest)
+            );
+        }
+
+        // the largest aligned allocation starts at the next aligned address
+        let largest = allocator.largest_aligned_allocation(64);
+        assert_eq!(largest, 256 - (start + 64));
+        assert!(allocator.alloc_aligned(largest + 1, 64).is_none());
+        assert!(allocator.alloc_aligned(largest, 64).is_some());
+        assert!(allocator.is_consistent());
+    }
+
+    #[test]
+    fn largest_allocation() {
+        const N: usize = 16 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        assert_eq!(allocator.largest_aligned_allocation(4), N - HEADER_SIZE);
+        let ptr = address!(allocator.alloc(8).unwrap());
+        address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr).unwrap();
+        let largest = N - 3 * HEADER_SIZE - 8 - GRANULARITY;
+        assert_eq!(allocator.largest_aligned_allocation(4), largest);
+
+        // the reserved memory cannot be used by ordinary allocations
+        assert!(allocator.reserve(24));
+        let unreserved = largest - HEADER_SIZE - 24;
+        assert_eq!(allocator.largest_aligned_allocation(4), unreserved);
+        assert_eq!(allocator.largest_free_block(), largest);
+        assert!(allocator.alloc(unreserved + 1).is_none());
+        assert!(allocator.alloc(unreserved).is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "paranoid")]
+    fn overlapping_allocation_is_refused() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static OVERLAPS: AtomicUsize = AtomicUsize::new(0);
+        let mut allocator = RawAllocator::<64>::new();
+        // the forged block starts right behind the first header of the memory
+        const FORGED: usize = 2 * HEADER_SIZE;
+        allocator.set_overlap_handler(Some(|block| {
+            assert_eq!(
+                (block.offset, block.size, block.state),
+                (FORGED, 8, State::Used)
+            );
+            OVERLAPS.fetch_add(1, Ordering::SeqCst);
+        }));
+        let memory = allocator.alloc(24).unwrap();
+
+        // a stray write forges a free block inside of the used one, which is
+        // remembered as recently freed
+        memory[FORGED..FORGED + 8].fill(MaybeUninit::new(0));
+        unsafe {
+            memory[HEADER_SIZE..]
+                .as_mut_ptr()
+                .cast::<Entry>()
+                .write(Entry::free(8))
+        };
+        let forged = unsafe { allocator.buffer.header_at(FORGED) }.unwrap();
+        allocator.recently_freed.insert(forged);
+
+        assert!(allocator.alloc(8).is_none());
+        assert_eq!(OVERLAPS.load(Ordering::SeqCst), 1);
+
+        // the refused block is quarantined by leaving it marked as used
+        assert_eq!(allocator.buffer[forged].state(), State::Used);
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, feature = "paranoid"))]
+    #[should_panic(expected = "heap invariants violated")]
+    fn corrupted_heap_is_detected() {
+        let mut allocator = RawAllocator::<64>::new();
+        // the first block claims more memory than the heap contains
+        let first = allocator.buffer.entries().next().unwrap();
+        allocator.buffer[first] = Entry::free(64);
+        allocator.alloc(4);
+    }
+
+    #[test]
+    fn recover_from_corrupted_heap() {
+        let mut allocator = RawAllocator::<64>::new();
+        let first = address!(allocator.alloc(8).unwrap());
+        let second = address!(allocator.alloc(8).unwrap());
+        let third = address!(allocator.alloc(8).unwrap());
+        allocator.free(first).unwrap();
+        assert_eq!(
+            allocator.recover(),
+            RecoveryReport {
+                corrupted_at: None,
+                quarantined: 0
+            }
+        );
+
+        // an overflow of the second block overwrites the header of the third
+        let header = allocator.buffer.entries().nth(2).unwrap();
+        allocator.buffer[header] = Entry::used(100);
+        assert!(!allocator.is_consistent());
+        let corrupted_at = 2 * (HEADER_SIZE + 8);
+        assert_eq!(
+            allocator.recover(),
+            RecoveryReport {
+                corrupted_at: Some(corrupted_at),
+                quarantined: 64 - corrupted_at
+            }
+        );
+        assert!(allocator.is_consistent());
+        let quarantined = Entry::used(64 - corrupted_at - HEADER_SIZE);
+        assert_allocations!(allocator, Entry::free(8), Entry::used(8), quarantined);
+
+        // the quarantined block is never freed, but the other blocks are usable
+        allocator.free(third).unwrap();
+        assert_allocations!(allocator, Entry::free(8), Entry::used(8), quarantined);
+        assert!(allocator.alloc(12).is_none());
+        allocator.free(second).unwrap();
+        allocator.coalesce_free_blocks();
+        assert!(allocator.alloc(HEADER_SIZE + 16).is_some());
+        assert_allocations!(allocator, Entry::used(HEADER_SIZE + 16), quarantined);
+        assert_eq!(allocator.recover().corrupted_at, None);
+    }
+
+    #[test]
+    #[cfg(feature = "relocating")]
+    fn quarantine_survives_compaction() {
+        let mut allocator = RawAllocator::<64>::new();
+        let first = address!(allocator.alloc(8).unwrap());
+        allocator.alloc(8).unwrap();
+        let header = allocator.buffer.entries().nth(2).unwrap();
+        allocator.buffer[header] = Entry::free(100);
+        allocator.recover();
+        allocator.free(first).unwrap();
+
+        allocator.compact(|_, _, _| {});
+        let size = 64 - 3 * HEADER_SIZE - 16;
+        assert_allocations!(allocator, Entry::used(8), Entry::used(size), Entry::free(8));
+        let quarantined = allocator.buffer.entries().nth(1).unwrap();
+        let ptr = address!(allocator.buffer.memory_of_mut(quarantined));
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::used(8), Entry::used(size), Entry::free(8));
+    }
+
+    #[test]
+    fn recover_from_misaligned_size() {
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.alloc(8).unwrap();
+        let header = allocator.buffer.entries().nth(1).unwrap();
+        // a size of 6 would place the next header at an unaligned offset
+        allocator.buffer[header] = Entry::free(6);
+        let report = allocator.recover();
+        assert_eq!(report.corrupted_at, Some(HEADER_SIZE + 8));
+        assert_eq!(report.quarantined, 64 - HEADER_SIZE - 8);
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(64 - 2 * HEADER_SIZE - 8)
+        );
+        assert!(allocator.alloc(4).is_none());
+
+        // resetting the heap lifts the quarantine
+        allocator.reset_touched();
+        assert_allocations!(allocator, Entry::free(64 - HEADER_SIZE));
+    }
+
+    #[test]
+    fn verify_detects_corrupted_headers() {
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.alloc(8).unwrap();
+        assert!(allocator.verify());
+
+        let header = allocator.buffer.entries().nth(1).unwrap();
+        allocator.buffer[header] = Entry::free(100);
+        assert!(!allocator.verify());
+        allocator.recover();
+        assert!(allocator.verify());
+    }
+
+    #[test]
+    #[cfg(feature = "guard-block")]
+    fn guard_block_detects_overruns() {
+        let mut allocator = RawAllocator::<64>::new();
+        let first = address!(allocator.alloc(8).unwrap());
+        let last = allocator.alloc_from_top(8).unwrap();
+        last.fill(MaybeUninit::new(0xFF));
+        let last = address!(last);
+        allocator.free(first).unwrap();
+        let memory = allocator.alloc(40).unwrap();
+        memory.fill(MaybeUninit::new(0xFF));
+        let memory = address!(memory);
+        allocator.free(last).unwrap();
+        allocator.free(memory).unwrap();
+        assert!(allocator.verify());
+
+        // writing a single byte past the end of the last block is detected,
+        // although the blocks of the heap are still consistent
+        let guard = ptr::addr_of_mut!(allocator.buffer).cast::<u8>();
+        unsafe { guard.add(64).write(0) };
+        assert!(allocator.is_consistent());
+        assert!(!allocator.verify());
+    }
+
+    #[test]
+    fn alloc_at() {
+        const N: usize = 16 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        let offset = 2 * HEADER_SIZE + 8;
+        let ptr = address!(allocator.alloc_at(offset, 8).unwrap());
+        assert_eq!(ptr as usize - allocator.as_ptr() as usize, offset);
+        let rest = Entry::free(N - 3 * HEADER_SIZE - 16);
+        assert_allocations!(allocator, Entry::free(8), Entry::used(8), rest);
+
+        // the memory has to be free (including the header of the new block)
+        assert!(allocator.alloc_at(offset, 4).is_none());
+        assert!(allocator.alloc_at(offset + 8, 4).is_none());
+        assert!(allocator.alloc_at(HEADER_SIZE, HEADER_SIZE + 8).is_none());
+        // the offset has to be aligned and the block has to fit into the heap
+        assert!(allocator.alloc_at(30, 4).is_none());
+        assert!(allocator.alloc_at(0, 4).is_none());
+        assert!(allocator
+            .alloc_at(N - HEADER_SIZE, 2 * HEADER_SIZE)
+            .is_none());
+        assert_allocations!(allocator, Entry::free(8), Entry::used(8), rest);
+
+        // a block starting at the start of a free block is not split in front
+        address!(allocator.alloc_at(HEADER_SIZE, 8).unwrap());
+        address!(allocator.alloc_at(4 * HEADER_SIZE + 16, 4).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(8),
+            Entry::free(0),
+            Entry::used(GRANULARITY),
+            Entry::free(N - 5 * HEADER_SIZE - 16 - GRANULARITY)
+        );
+
+        // the reserved memory cannot be used
+        assert!(allocator.reserve(16));
+        let last = N - HEADER_SIZE - 16 - GRANULARITY;
+        assert!(allocator.alloc_at(last, GRANULARITY + 1).is_none());
+        assert!(allocator.alloc_at(last, GRANULARITY).is_some());
+    }
+
+    #[test]
+    fn zero_free_memory() {
+        let mut allocator = RawAllocator::<32>::new();
+        let memory = allocator.alloc(4).unwrap();
+        memory.fill(MaybeUninit::new(0xAA));
+        allocator.zero_free_memory();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(32 - 2 * HEADER_SIZE - GRANULARITY)
+        );
+
+        // the free memory is zeroed, but the used memory is not touched
+        let mut entries = allocator.buffer.entries();
+        let used = entries.next().unwrap();
+        let free = entries.next().unwrap();
+        let is = |offset, value| {
+            allocator
+                .buffer
+                .memory_of(offset)
+                .iter()
+                .all(|byte| unsafe { byte.assume_init() } == value)
+        };
+        assert!(is(used, 0xAA));
+        assert!(is(free, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "metrics")]
+    fn avg_scan_steps() {
+        let mut allocator = RawAllocator::<64>::new();
+        assert_eq!(allocator.avg_scan_steps(), 0.0);
+        // both allocations visit the single free block
+        allocator.alloc(8).unwrap();
+        let second = address!(allocator.alloc(8).unwrap());
+        assert_eq!(allocator.avg_scan_steps(), 1.0);
+
+        // the second block is found after visiting the first one, afterwards
+        // the merged free block is the only one visited
+        allocator.free(second).unwrap();
+        allocator.alloc(20).unwrap();
+        assert_eq!(allocator.scan_steps, 1 + 1 + 2 + 1);
+        assert_eq!(allocator.avg_scan_steps(), 5.0 / 4.0);
+
+        // a failed allocation takes steps as well, but is no operation
+        assert!(allocator.alloc(32).is_none());
+        assert_eq!(allocator.avg_scan_steps(), 6.0 / 4.0);
+    }
+
+    #[test]
+    fn wasted_tail_bytes() {
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.alloc(24).unwrap(); // the remainder is split off
+        assert_eq!(allocator.wasted_tail_bytes(), 0);
+        // the remainder of the smallest block size is not split
+        let rest = 64 - 2 * HEADER_SIZE - 24;
+        allocator.alloc(rest - GRANULARITY - 2).unwrap();
+        assert_allocations!(allocator, Entry::used(24), Entry::used(rest));
+        assert_eq!(allocator.wasted_tail_bytes(), GRANULARITY);
+
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.set_split_threshold(20);
+        allocator.alloc(40).unwrap(); // remainder of 16 bytes is not split
+        assert_allocations!(allocator, Entry::used(64 - HEADER_SIZE));
+        assert_eq!(allocator.wasted_tail_bytes(), 64 - HEADER_SIZE - 40);
+    }
+
+    #[test]
+    fn lifetime_counters() {
+        let mut allocator = RawAllocator::<64>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc_from_top(4).unwrap();
+        assert!(allocator.alloc(128).is_none());
+        allocator.free(ptr).unwrap();
+        assert!(allocator.free(ptr).is_err());
+        let stats = allocator.stats();
+        assert_eq!((stats.total_allocations, stats.total_frees), (2, 1));
+
+        // the counters saturate instead of wrapping around
+        allocator.total_allocations = usize::MAX - 1;
+        allocator.total_frees = usize::MAX;
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+        allocator.free(ptr).unwrap();
+        let stats = allocator.stats();
+        assert_eq!(stats.total_allocations, usize::MAX);
+        assert_eq!(stats.total_frees, usize::MAX);
+    }
+
+    #[test]
+    #[cfg(feature = "ring-log")]
+    fn log_of_failed_frees() {
+        use crate::{LogEntry, Operation};
+
+        let mut allocator = RawAllocator::<32>::new();
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.free(ptr).unwrap();
+        assert_eq!(allocator.free(ptr), Err(FreeError::DoubleFreeDetected));
+        let mut outside = 0_u8;
+        assert!(allocator.free(&mut outside).is_err());
+
+        let mut log = [LogEntry::default(); 4];
+        assert_eq!(allocator.recent_ops(&mut log), 4);
+        let entries = log.map(|entry| (entry.operation, entry.size, entry.offset));
+        assert_eq!(entries[1], (Operation::Free, GRANULARITY, Some(0)));
+        assert_eq!(entries[2], (Operation::Free, 0, None));
+        assert_eq!(entries[3], (Operation::Free, 0, None));
+    }
+
+    #[test]
+    fn reset_touched() {
+        let mut allocator = RawAllocator::<64>::new();
+        assert_eq!(allocator.touched, HEADER_SIZE);
+        allocator.alloc(4).unwrap().fill(MaybeUninit::new(0xAA));
+        let memory = allocator.alloc(8).unwrap();
+        memory.fill(MaybeUninit::new(0xAA));
+        let ptr = address!(memory);
+        allocator.free(ptr).unwrap();
+        // the two blocks and the header and links of the remaining free block
+        assert_eq!(
+            allocator.touched,
+            (HEADER_SIZE + GRANULARITY) + (HEADER_SIZE + 8) + HEADER_SIZE + 8
+        );
+
+        allocator.reset_touched();
+        assert_allocations!(allocator, Entry::free(64 - HEADER_SIZE));
+        assert_eq!(allocator.touched, HEADER_SIZE);
+        let memory = allocator
+            .buffer
+            .memory_of(allocator.buffer.entries().next().unwrap());
+        assert!(memory[..20]
+            .iter()
+            .all(|byte| unsafe { byte.assume_init() } == 0));
+        // the recently freed block was forgotten
+        assert!(allocator.alloc(8).is_some());
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(64 - 2 * HEADER_SIZE - 8)
+        );
+    }
+
+    #[test]
+    fn touched_memory_of_allocations_at_the_end() {
+        let mut allocator = RawAllocator::<64>::new();
+        allocator.alloc_from_top(8).unwrap();
+        assert_eq!(allocator.touched, 64);
+        allocator.reset_touched();
+        assert_allocations!(allocator, Entry::free(64 - HEADER_SIZE));
+    }
+
+    #[test]
+    #[cfg(feature = "relocating")]
+    fn compact() {
+        const N: usize = 16 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        let base = allocator.as_ptr() as usize;
+        let a = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(8).unwrap().fill(MaybeUninit::new(0xBB));
+        let c = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(8).unwrap().fill(MaybeUninit::new(0xDD));
+        allocator.free(a).unwrap();
+        allocator.free(c).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(8),
+            Entry::free(GRANULARITY),
+            Entry::used(8),
+            Entry::free(N - 5 * HEADER_SIZE - 2 * GRANULARITY - 16),
+        );
+
+        let mut moves = Vec::new();
+        allocator.compact(|old, new, size| {
+            moves.push((old as usize - base, new as usize - base, size));
+        });
+        let (h, g) = (HEADER_SIZE, GRANULARITY);
+        assert_eq!(
+            moves,
+            [(2 * h + g, h, 8), (4 * h + 2 * g + 8, 2 * h + 8, 8)]
+        );
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(8),
+            Entry::free(N - 3 * HEADER_SIZE - 16)
+        );
+
+        // the contents are moved along with the blocks
+        let mut entries = allocator.buffer.entries();
+        for value in [0xBB, 0xDD] {
+            let offset = entries.next().unwrap();
+            let memory = allocator.buffer.memory_of(offset);
+            assert!(memory
+                .iter()
+                .all(|byte| unsafe { byte.assume_init() } == value));
+        }
+
+        // the recently freed blocks are gone, so they must not be reused
+        let ptr = address!(allocator.alloc(4).unwrap());
+        assert_eq!(ptr as usize - base, 3 * HEADER_SIZE + 16);
+    }
+
+    #[test]
+    #[cfg(feature = "relocating")]
+    fn compact_without_free_memory() {
+        let mut allocator = RawAllocator::<32>::new();
+        allocator.alloc(32 - HEADER_SIZE).unwrap();
+        allocator.compact(|_, _, _| panic!("nothing to move"));
+        assert_allocations!(allocator, Entry::used(32 - HEADER_SIZE));
+    }
+
+    #[test]
+    #[cfg(feature = "relocating")]
+    fn blocks_referenced_by_handles_are_not_merged_away() {
+        const N: usize = 8 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        let (_, first) = allocator.alloc_handle(4).unwrap();
+        let (_, second) = allocator.alloc_handle(4).unwrap();
+        assert_eq!(allocator.try_merge(first, second), None);
+        assert_eq!(allocator.try_merge(second, first), None);
+
+        // the block referenced by the handle is kept as the start of the block
+        let (index, _) = allocator.alloc_handle(4).unwrap();
+        let third = allocator.take_handle(index);
+        assert_eq!(
+            allocator.try_merge(second, third),
+            Some(second as *const u8)
+        );
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(HEADER_SIZE + 2 * GRANULARITY),
+            Entry::free(N - 4 * HEADER_SIZE - 3 * GRANULARITY)
+        );
+    }
+
+    #[test]
+    fn stats() {
+        const N: usize = 8 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.free), (0, N - HEADER_SIZE));
+        assert_eq!((stats.used_blocks, stats.free_blocks), (0, 1));
+        assert_eq!(stats.largest_free, N - HEADER_SIZE);
+
+        let ptr = address!(allocator.alloc(4).unwrap());
+        allocator.alloc(4).unwrap();
+        allocator.free(ptr).unwrap();
+        let rest = N - 3 * HEADER_SIZE - 2 * GRANULARITY;
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(rest)
+        );
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.free), (GRANULARITY, GRANULARITY + rest));
+        assert_eq!((stats.used_blocks, stats.free_blocks), (1, 2));
+        assert_eq!(stats.largest_free, rest);
+    }
+
+    #[test]
+    fn allocation_from_top() {
+        const N: usize = 16 * HEADER_SIZE;
+        // the top block can hold the header and memory of the smallest block
+        const TOP: usize = 2 * GRANULARITY;
+        const MIDDLE: usize = N - 3 * HEADER_SIZE - GRANULARITY - TOP;
+        let mut allocator = RawAllocator::<N>::new();
+        let top = address!(allocator.alloc_from_top(TOP).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::free(N - 2 * HEADER_SIZE - TOP),
+            Entry::used(TOP)
+        );
+        let bottom = address!(allocator.alloc(4).unwrap());
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::free(MIDDLE),
+            Entry::used(TOP)
+        );
+
+        // the top allocation fills the remaining free block exactly
+        allocator.alloc_from_top(MIDDLE).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(GRANULARITY),
+            Entry::used(MIDDLE),
+            Entry::used(TOP)
+        );
+        assert!(allocator.alloc_from_top(4).is_none());
+
+        allocator.free(top).unwrap();
+        allocator.free(bottom).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(MIDDLE),
+            Entry::free(TOP)
+        );
+        allocator.alloc_from_top(4).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::used(MIDDLE),
+            Entry::free(0),
+            Entry::used(GRANULARITY),
+        );
+    }
+
+    #[test]
+    fn allocation_from_top_respects_reserved_memory() {
+        let mut allocator = RawAllocator::<64>::new();
+        assert!(allocator.reserve(8));
+        allocator.alloc_from_top(4).unwrap();
+        let front = 64 - 3 * HEADER_SIZE - 8 - GRANULARITY;
+        assert_allocations!(
+            allocator,
+            Entry::free(front),
+            Entry::used(GRANULARITY),
+            Entry::free(8)
+        );
+        assert!(allocator.alloc_from_top(front + 1).is_none());
+        assert!(allocator.alloc_reserved(8).is_some());
+    }
+
+    #[test]
+    fn free_block_count() {
+        const N: usize = 16 * HEADER_SIZE;
+        let mut allocator = RawAllocator::<N>::new();
+        assert_eq!(allocator.free_block_count(), 1);
+
+        let ptrs = [(); 5].map(|_| address!(allocator.alloc(4).unwrap()));
+        assert_eq!(allocator.free_block_count(), 1);
+        allocator.free(ptrs[0]).unwrap();
+        allocator.free(ptrs[2]).unwrap();
+        assert_eq!(allocator.free_block_count(), 3);
+
+        // the freed block is merged with the following free block
+        allocator.free(ptrs[1]).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(GRANULARITY),
+            Entry::free(HEADER_SIZE + 2 * GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::used(GRANULARITY),
+            Entry::free(N - 6 * HEADER_SIZE - 5 * GRANULARITY),
+        );
+        assert_eq!(allocator.free_block_count(), 3);
+        allocator.free(ptrs[4]).unwrap();
+        assert_eq!(allocator.free_block_count(), 3);
+    }
+
+    #[test]
+    fn largest_free_block() {
+        const N: usize = 8 * HEADER_SIZE;
+        const REST: usize = N - 3 * HEADER_SIZE - 8 - GRANULARITY;
+        let mut allocator = RawAllocator::<N>::new();
+        assert_eq!(allocator.largest_free_block(), N - HEADER_SIZE);
+
+        // fragmented heap
+        let ptr = address!(allocator.alloc(8).unwrap());
+        allocator.alloc(4).unwrap();
+        allocator.free(ptr).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(8),
+            Entry::used(GRANULARITY),
+            Entry::free(REST)
+        );
+        assert_eq!(allocator.largest_free_block(), REST);
+        allocator.alloc(8).unwrap();
+        assert_eq!(allocator.largest_free_block(), REST);
+
+        // full heap
+        allocator.alloc(REST).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::used(GRANULARITY),
+            Entry::used(REST)
+        );
+        assert_eq!(allocator.largest_free_block(), 0);
     }
 
     #[test]
@@ -0,0 +1,248 @@
+//! Randomized tests comparing the allocator against a simple reference model.
+//!
+//! Random sequences of allocations (from both ends of the heap) and
+//! deallocations are generated and applied to an [`Allocator`]. After each step, the following invariants are checked:
+//! - every returned pointer lies completely inside the heap buffer
+//! - every returned pointer fulfills the requested alignment
+//! - no two live allocations overlap (checked against a model tracking the
+//!   memory intervals of the live allocations)
+//! - the entries in the buffer tile the whole buffer exactly
+//!
+//! Every other sequence uses the first-fit strategy instead of best-fit.
+//!
+//! Furthermore, random sequences are applied to a raw allocator walking the
+//! free list and to one scanning all the entries instead. Both have to choose
+//! exactly the same blocks.
+//!
+//! The operations are generated by a tiny pseudo random number generator with
+//! fixed seeds, so the tests are fully deterministic. There is no automatic
+//! shrinking of failing cases: instead the failing seed and step are reported
+//! in the assertion message. A failure can be reproduced by running only that
+//! seed, and a smaller case can be searched manually by reducing the number of
+//! steps (the sequence for a given seed is always the same prefix).
+use super::{buffer::HEADER_SIZE, RawAllocator};
+use crate::{Allocator, Strategy};
+use core::alloc::{GlobalAlloc, Layout};
+
+/// The heap size used for the tests.
+const HEAP_SIZE: usize = 512;
+/// The number of random sequences to test (fewer under `miri` for speed).
+const SEEDS: u64 = if cfg!(miri) { 4 } else { 200 };
+/// The number of operations per random sequence.
+const STEPS: usize = if cfg!(miri) { 50 } else { 300 };
+
+/// A minimal `xorshift64` pseudo random number generator.
+struct Rng(u64);
+impl Rng {
+    /// Create a generator from a seed (which may be zero).
+    const fn new(seed: u64) -> Self {
+        Self(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Generate a random number in the range `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        #[allow(clippy::cast_possible_truncation)] // result is less than `n`
+        let value = (self.0 % n as u64) as usize;
+        value
+    }
+}
+
+/// Check, that the entries of the raw allocator tile the buffer exactly.
+fn assert_tiling<const N: usize>(raw: &RawAllocator<N>, seed: u64, step: usize) {
+    let mut expected_offset = 0;
+    for offset in raw.buffer.entries() {
+        assert_eq!(
+            offset.get(),
+            expected_offset,
+            "seed {}, step {}",
+            seed,
+            step
+        );
+        expected_offset += HEADER_SIZE + raw.buffer[offset].size();
+    }
+    assert_eq!(expected_offset, N, "seed {}, step {}", seed, step);
+}
+
+/// Run a single random sequence of operations.
+fn run(seed: u64) {
+    let allocator = Allocator::<HEAP_SIZE>::new();
+    match seed % 3 {
+        1 => allocator.set_strategy(Strategy::FirstFit),
+        2 => allocator.set_strategy(Strategy::Frontier),
+        _ => {}
+    }
+    let base = {
+        let raw = allocator.raw.lock();
+        let first = raw.buffer.entries().next().unwrap();
+        raw.buffer.memory_of(first).as_ptr() as usize - HEADER_SIZE
+    };
+
+    let mut rng = Rng::new(seed);
+    // the model: the address and the layout of every live allocation
+    let mut live: Vec<(usize, Layout)> = Vec::new();
+    for step in 0..STEPS {
+        if live.is_empty() || rng.below(3) != 0 {
+            let size = rng.below(64) + 1;
+            let align = 1 << rng.below(7);
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = if rng.below(4) == 0 {
+                unsafe { allocator.alloc_from_top(layout) }
+            } else {
+                unsafe { allocator.alloc(layout) }
+            };
+            if !ptr.is_null() {
+                let start = ptr as usize;
+                let end = start + size;
+                assert!(start >= base + HEADER_SIZE, "seed {}, step {}", seed, step);
+                assert!(end <= base + HEAP_SIZE, "seed {}, step {}", seed, step);
+                assert_eq!(start % align, 0, "seed {}, step {}", seed, step);
+                for &(other, other_layout) in &live {
+                    let other_end = other + other_layout.size();
+                    let disjoint = end <= other || other_end <= start;
+                    assert!(disjoint, "seed {}, step {}", seed, step);
+                }
+                live.push((start, layout));
+            }
+        } else {
+            let (ptr, layout) = live.swap_remove(rng.below(live.len()));
+            unsafe { allocator.dealloc(ptr as *mut u8, layout) };
+        }
+
+        let raw = allocator.raw.lock();
+        assert_tiling(&raw, seed, step);
+        assert_eq!(
+            raw.stats().used_blocks,
+            live.len(),
+            "seed {}, step {}",
+            seed,
+            step
+        );
+    }
+}
+
+#[test]
+fn random_operations() {
+    crate::tests::return_null_on_oom();
+    for seed in 0..SEEDS {
+        run(seed);
+    }
+}
+
+/// Query the offsets and entries of all blocks of the raw allocator.
+fn blocks<const N: usize>(raw: &RawAllocator<N>) -> Vec<(usize, super::Entry)> {
+    raw.buffer
+        .entries()
+        .map(|offset| (offset.get(), raw.buffer[offset]))
+        .collect()
+}
+
+/// Run a random sequence against an allocator using the free list and one
+/// scanning all the entries instead, which have to behave identically.
+fn run_against_scanning(seed: u64) {
+    /// An allocator aligned, so that both heaps have the same alignment.
+    #[repr(align(64))]
+    struct Aligned(RawAllocator<HEAP_SIZE>);
+
+    let (mut linked, mut scanning) = (Aligned(RawAllocator::new()), Aligned(RawAllocator::new()));
+    let (linked, scanning) = (&mut linked.0, &mut scanning.0);
+    scanning.free_list.scan = true;
+    let strategy = match seed % 3 {
+        1 => Strategy::FirstFit,
+        2 => Strategy::Frontier,
+        _ => Strategy::BestFit,
+    };
+    // without a split threshold, blocks too small for the links are created
+    let threshold = if seed % 2 == 0 { 0 } else { HEADER_SIZE };
+    for raw in [&mut *linked, &mut *scanning] {
+        raw.set_strategy(strategy);
+        raw.set_split_threshold(threshold);
+    }
+
+    let mut rng = Rng::new(seed);
+    // the offsets of the live allocations (identical for both allocators)
+    let mut live: Vec<usize> = Vec::new();
+    for step in 0..STEPS {
+        let operation = rng.below(8);
+        if live.is_empty() || operation < 5 {
+            let size = rng.below(32) + 1;
+            let align = 1 << rng.below(5);
+            let kind = rng.below(3);
+            let alloc = |raw: &mut RawAllocator<HEAP_SIZE>| {
+                let base = raw.as_ptr() as usize;
+                let memory = match kind {
+                    0 => raw.alloc(size),
+                    1 => raw.alloc_aligned(size, align),
+                    _ => raw.alloc_from_top(size),
+                };
+                memory.map(|memory| memory.as_ptr() as usize - base)
+            };
+            let offset = alloc(linked);
+            assert_eq!(offset, alloc(scanning), "seed {}, step {}", seed, step);
+            live.extend(offset);
+        } else if operation < 7 {
+            let offset = live.swap_remove(rng.below(live.len()));
+            for raw in [&mut *linked, &mut *scanning] {
+                let ptr = raw.as_ptr().wrapping_add(offset) as *mut u8;
+                raw.free(ptr).unwrap();
+            }
+        } else {
+            linked.coalesce_free_blocks();
+            scanning.coalesce_free_blocks();
+        }
+
+        assert!(linked.is_consistent(), "seed {}, step {}", seed, step);
+        assert_eq!(
+            blocks(linked),
+            blocks(scanning),
+            "seed {}, step {}",
+            seed,
+            step
+        );
+    }
+}
+
+#[test]
+fn free_list_matches_scanning() {
+    for seed in 0..SEEDS {
+        run_against_scanning(seed);
+    }
+}
+
+#[test]
+fn free_list_reduces_scan_steps() {
+    let mut linked = RawAllocator::<4096>::new();
+    let mut scanning = RawAllocator::<4096>::new();
+    scanning.free_list.scan = true;
+    for raw in [&mut linked, &mut scanning] {
+        // many live allocations with a few holes in between
+        let ptrs: Vec<_> = (0..200)
+            .map(|_| raw.alloc(8).unwrap().as_mut_ptr().cast::<u8>())
+            .collect();
+        for &ptr in ptrs.iter().step_by(10) {
+            raw.free(ptr).unwrap();
+        }
+        raw.scan_steps = 0;
+        // only the free block at the end of the heap fits
+        for _ in 0..10 {
+            raw.alloc(16).unwrap();
+        }
+    }
+    assert_eq!(blocks(&linked), blocks(&scanning));
+    // only the 20 holes and the last block are visited instead of all blocks
+    assert_eq!(linked.scan_steps, 10 * 21);
+    assert!(scanning.scan_steps > 10 * 200);
+}
+
+#[test]
+fn rng_is_deterministic() {
+    let mut a = Rng::new(42);
+    let mut b = Rng::new(42);
+    for _ in 0..100 {
+        let value = a.below(10);
+        assert_eq!(value, b.below(10));
+        assert!(value < 10);
+    }
+}
@@ -0,0 +1,50 @@
+//! This module provides the [`Reservation`] of a block, which is not yet
+//! committed.
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+/// A reserved block, which is either committed or aborted later on.
+///
+/// A reservation is obtained via [`Allocator::reserve_block()`]. Its memory is
+/// already carved out of the heap, so it can be filled via
+/// [`as_ptr()`](Self::as_ptr) before it is handed out, e.g. to a consumer. The
+/// reservation is then finalized via [`Allocator::commit()`], which turns it
+/// into an ordinary allocation, or rolled back via [`Allocator::abort()`],
+/// which returns the memory to the heap.
+///
+/// The reservation is pending until either of those is called. It must be
+/// finalized by the allocator, which created it. Dropping a reservation leaks
+/// its memory.
+///
+/// [`Allocator::reserve_block()`]: crate::Allocator::reserve_block
+/// [`Allocator::commit()`]: crate::Allocator::commit
+/// [`Allocator::abort()`]: crate::Allocator::abort
+#[derive(Debug, PartialEq, Eq)]
+#[must_use = "the reservation has to be committed or aborted, otherwise its memory is leaked"]
+pub struct Reservation {
+    /// The memory of the reserved block.
+    pub(crate) ptr: NonNull<u8>,
+    /// The layout, which was used to reserve the memory.
+    pub(crate) layout: Layout,
+}
+// SAFETY: the reservation owns its memory exclusively, just like a `Box`, so
+// it can be finalized on another thread.
+unsafe impl Send for Reservation {}
+// SAFETY: the reservation only hands out raw pointers to its memory.
+unsafe impl Sync for Reservation {}
+impl Reservation {
+    /// Query the address of the reserved memory.
+    ///
+    /// The memory is valid for writes of the size of the reserved layout and
+    /// it is aligned accordingly. It is uninitialized.
+    #[must_use]
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Query the layout, which was used to reserve the memory.
+    #[must_use]
+    pub const fn layout(&self) -> Layout {
+        self.layout
+    }
+}
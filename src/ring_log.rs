@@ -0,0 +1,125 @@
+//! This module provides the [`LogEntry`] of the log of recent operations.
+//!
+//! With the `ring-log` feature, the allocator records its most recent
+//! operations in a small ring buffer, which acts like a black-box recorder for
+//! debugging in the field.
+
+/// The number of operations kept in the log of recent operations.
+pub const RING_LOG_LEN: usize = 16;
+
+/// The kind of an operation recorded in a [`LogEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// An allocation of memory.
+    Alloc,
+    /// A deallocation of memory.
+    Free,
+}
+
+/// A single operation recorded in the log of recent operations.
+///
+/// This type is obtained via
+/// [`Allocator::recent_ops()`](crate::Allocator::recent_ops).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LogEntry {
+    /// The kind of the operation.
+    pub operation: Operation,
+    /// The size of the operation in bytes.
+    ///
+    /// For an allocation, this is the size requested from the heap. For a
+    /// deallocation, this is the size of the freed block (or `0`, if the
+    /// deallocation failed).
+    pub size: usize,
+    /// The offset of the block from the start of the heap.
+    ///
+    /// This is `None`, if the operation failed, i.e. if an allocation returned
+    /// a null pointer or if a deallocation detected an error.
+    pub offset: Option<usize>,
+}
+impl LogEntry {
+    /// A placeholder for the unused entries of the log.
+    const EMPTY: Self = Self {
+        operation: Operation::Alloc,
+        size: 0,
+        offset: None,
+    };
+}
+impl Default for LogEntry {
+    /// Create a placeholder entry, e.g. for the buffer passed to
+    /// [`Allocator::recent_ops()`](crate::Allocator::recent_ops).
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+/// A ring buffer of the most recent operations.
+pub struct RingLog {
+    /// The recorded entries, the oldest one is overwritten first.
+    entries: [LogEntry; RING_LOG_LEN],
+    /// The index of the entry to overwrite next.
+    next: usize,
+    /// The number of recorded entries (at most [`RING_LOG_LEN`]).
+    len: usize,
+}
+impl RingLog {
+    /// Create a new, empty log.
+    pub const fn new() -> Self {
+        Self {
+            entries: [LogEntry::EMPTY; RING_LOG_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    /// Record an operation, overwriting the oldest one if the log is full.
+    pub fn record(&mut self, operation: Operation, size: usize, offset: Option<usize>) {
+        self.entries[self.next] = LogEntry {
+            operation,
+            size,
+            offset,
+        };
+        self.next = (self.next + 1) % RING_LOG_LEN;
+        self.len = (self.len + 1).min(RING_LOG_LEN);
+    }
+
+    /// Copy the most recent entries into `out` (oldest first).
+    ///
+    /// At most `out.len()` entries are copied. The number of copied entries is
+    /// returned.
+    pub fn copy_to(&self, out: &mut [LogEntry]) -> usize {
+        let count = self.len.min(out.len());
+        let first = self.next + RING_LOG_LEN - count;
+        for (i, slot) in out.iter_mut().take(count).enumerate() {
+            *slot = self.entries[(first + i) % RING_LOG_LEN];
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogEntry, Operation, RingLog, RING_LOG_LEN};
+
+    #[test]
+    fn wrap_around() {
+        let mut log = RingLog::new();
+        let mut out = [LogEntry::EMPTY; RING_LOG_LEN + 1];
+        assert_eq!(log.copy_to(&mut out), 0);
+
+        for size in 0..RING_LOG_LEN + 3 {
+            log.record(Operation::Alloc, size, Some(4 * size));
+        }
+        assert_eq!(log.copy_to(&mut out), RING_LOG_LEN);
+        for (i, entry) in out[..RING_LOG_LEN].iter().enumerate() {
+            assert_eq!(entry.size, i + 3);
+            assert_eq!(entry.offset, Some(4 * (i + 3)));
+        }
+
+        // only the most recent entries are copied into a short slice
+        assert_eq!(log.copy_to(&mut out[..2]), 2);
+        assert_eq!(out[0].size, RING_LOG_LEN + 1);
+        assert_eq!(out[1].size, RING_LOG_LEN + 2);
+    }
+}
@@ -0,0 +1,32 @@
+//! This module provides the [`allocator_in_section!`] macro.
+
+/// Define a static [`Allocator`](crate::Allocator) in a specific linker section.
+///
+/// Some microcontrollers have special memory regions (e.g. the _core coupled
+/// memory_ of STM32 devices), which are well suited for the heap. This macro
+/// defines a `static` allocator with the given name and heap size and places
+/// it into the given linker section via the `#[link_section]`-attribute. Any
+/// further attributes (e.g. `#[global_allocator]`) and the visibility are
+/// applied to the static as well:
+/// ```
+/// emballoc::allocator_in_section!(
+///     /// The heap in the core coupled memory.
+///     pub ALLOCATOR, 4096, ".ccmram"
+/// );
+/// ```
+/// Note, that the whole allocator is placed into that section. This is the
+/// heap memory itself and a few bytes of bookkeeping (e.g. the lock).
+///
+/// __Be careful__: the allocator has to be initialized, like any other static
+/// variable in the `.data`-section. Some linker scripts do not initialize
+/// custom sections (e.g. sections marked as `NOLOAD`), which results in a
+/// corrupted heap. Make sure, that the startup code copies the initial values
+/// of the section from the flash memory.
+#[macro_export]
+macro_rules! allocator_in_section {
+    ($(#[$attr:meta])* $vis:vis $name:ident, $size:expr, $section:literal $(,)?) => {
+        $(#[$attr])*
+        #[link_section = $section]
+        $vis static $name: $crate::Allocator<$size> = $crate::Allocator::new();
+    };
+}
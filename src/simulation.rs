@@ -0,0 +1,164 @@
+//! This module provides the [`simulate()`]-function for sizing the heap.
+extern crate alloc;
+
+use crate::raw_allocator::{RawAllocator, HEADER_SIZE};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// A single operation of a simulated workload.
+///
+/// See [`simulate()`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Allocate a block of the given size in bytes.
+    Alloc(usize),
+    /// Free the block allocated by the operation with the given index.
+    Free(usize),
+}
+
+/// The result of a simulated workload.
+///
+/// All the values are the maximum over the whole workload. See [`simulate()`]
+/// for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SimResult {
+    /// The peak number of bytes used by live allocations including headers.
+    pub peak_used: usize,
+    /// The peak offset after the end of a used block in the heap.
+    ///
+    /// This is the part of the heap, which was actually used, including the
+    /// holes between the allocations caused by fragmentation. A heap of this
+    /// size would have been sufficient for the workload.
+    pub peak_extent: usize,
+    /// The peak number of free bytes outside of the largest free block.
+    ///
+    /// Those bytes are free, but cannot be used for an allocation of the size
+    /// of the whole free memory. A value of `0` means, that there was no
+    /// fragmentation at all.
+    pub peak_fragmentation: usize,
+    /// The number of allocations, which failed due to insufficient memory.
+    pub failed_allocations: usize,
+}
+
+/// Simulate an allocation pattern on a heap of `N` bytes.
+///
+/// Sizing the heap is often guesswork, as the fragmentation depends on the
+/// order of the allocations and deallocations. This function runs the given
+/// `operations` against a throwaway allocator with the same algorithm as the
+/// [`Allocator`](crate::Allocator) and measures the peak heap usage, so that
+/// the heap size can be chosen based on measurements instead.
+///
+/// An [`Op::Free`] refers to the index of the [`Op::Alloc`] in `operations`,
+/// whose block should be freed. Freeing a failed allocation does nothing.
+/// Note, that the alignment of the allocations is not simulated.
+///
+/// # Panics
+/// This function panics, if an [`Op::Free`] does not refer to a previous
+/// [`Op::Alloc`] or if that block was freed already. Furthermore it panics, if
+/// `N` is an invalid heap size (see [`Allocator::new()`]).
+///
+/// # Example
+/// ```
+/// use emballoc::{simulate, Allocator, Op};
+///
+/// let result = simulate::<4096>(&[Op::Alloc(96), Op::Alloc(24), Op::Free(0)]);
+/// let header = Allocator::<4096>::GRANULARITY;
+/// assert_eq!(result.peak_used, 96 + 24 + 2 * header);
+/// assert_eq!(result.failed_allocations, 0);
+/// ```
+///
+/// [`Allocator::new()`]: crate::Allocator::new
+pub fn simulate<const N: usize>(operations: &[Op]) -> SimResult {
+    let mut allocator = Box::new(RawAllocator::<N>::new());
+    let start = allocator.as_ptr() as usize;
+    let mut blocks = Vec::with_capacity(operations.len());
+    let mut result = SimResult {
+        peak_used: 0,
+        peak_extent: 0,
+        peak_fragmentation: 0,
+        failed_allocations: 0,
+    };
+
+    for operation in operations {
+        let block = match *operation {
+            Op::Alloc(size) => {
+                let memory = allocator.alloc(size);
+                if memory.is_none() {
+                    result.failed_allocations += 1;
+                }
+                memory.map(|memory| {
+                    let end = memory.as_ptr() as usize - start + memory.len();
+                    result.peak_extent = result.peak_extent.max(end);
+                    memory.as_mut_ptr().cast::<u8>()
+                })
+            }
+            Op::Free(allocation) => {
+                let ptr = blocks
+                    .get_mut(allocation)
+                    .and_then(Option::take)
+                    .expect("`Op::Free` does not refer to a previous `Op::Alloc`");
+                if let Some(ptr) = ptr {
+                    allocator
+                        .free(ptr)
+                        .expect("the simulated allocation can be freed");
+                }
+                None
+            }
+        };
+        // failed allocations are remembered as well, so that they can be freed
+        blocks.push(match operation {
+            Op::Alloc(_) => Some(block),
+            Op::Free(_) => None,
+        });
+
+        let stats = allocator.stats();
+        let used = stats.used + stats.used_blocks * HEADER_SIZE;
+        result.peak_used = result.peak_used.max(used);
+        let fragmentation = stats.free - stats.largest_free;
+        result.peak_fragmentation = result.peak_fragmentation.max(fragmentation);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{simulate, Op};
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn known_workload() {
+        let result = simulate::<64>(&[
+            Op::Alloc(8),  // 0..12
+            Op::Alloc(16), // 12..32
+            Op::Alloc(3),  // 32..40
+            Op::Free(1),   // free blocks of 16 and 20 bytes
+            Op::Alloc(8),  // 12..24, free blocks of 4 and 20 bytes
+        ]);
+        assert_eq!(result.peak_used, (4 + 8) + (4 + 16) + (4 + 4));
+        assert_eq!(result.peak_extent, 40);
+        assert_eq!(result.peak_fragmentation, 16);
+        assert_eq!(result.failed_allocations, 0);
+    }
+
+    #[test]
+    #[cfg(not(feature = "granularity-8"))]
+    fn failed_allocations() {
+        let result = simulate::<32>(&[Op::Alloc(20), Op::Alloc(20), Op::Free(1), Op::Free(0)]);
+        assert_eq!(result.failed_allocations, 1);
+        assert_eq!(result.peak_used, 24);
+        assert_eq!(result.peak_extent, 24);
+    }
+
+    #[test]
+    #[should_panic(expected = "`Op::Free` does not refer to a previous `Op::Alloc`")]
+    fn double_free() {
+        simulate::<32>(&[Op::Alloc(4), Op::Free(0), Op::Free(0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "`Op::Free` does not refer to a previous `Op::Alloc`")]
+    fn free_of_an_unknown_allocation() {
+        simulate::<32>(&[Op::Free(1), Op::Alloc(4)]);
+    }
+}
@@ -0,0 +1,259 @@
+//! This module provides the diagnostic types describing the state of the heap.
+use crate::lock::MutexGuard;
+use crate::raw_allocator::{RawAllocator, ValidatedOffset};
+use crate::{RawLock, State};
+
+use core::fmt::{self, Display, Formatter};
+
+/// A snapshot of the heap usage of an [`Allocator`](crate::Allocator).
+///
+/// This type is obtained via [`Allocator::stats()`](crate::Allocator::stats)
+/// and describes the state of the heap at the time of the call. The numbers
+/// are obtained by walking over all blocks in the heap, except for the lifetime
+/// counters [`total_allocations`](Self::total_allocations) and
+/// [`total_frees`](Self::total_frees).
+///
+/// Note, that the sizes reported here are the sizes of the _payloads_, i.e.
+/// the memory after each block header. The headers themselves are neither
+/// counted as used nor as free memory. Every header is as large as the
+/// [`GRANULARITY`](crate::GenericAllocator::GRANULARITY), so the following
+/// holds:
+/// ```
+/// # let allocator = emballoc::Allocator::<64>::new();
+/// let stats = allocator.stats();
+/// let blocks = stats.used_blocks + stats.free_blocks + stats.reserved_blocks;
+/// let headers = blocks * emballoc::Allocator::<64>::GRANULARITY;
+/// assert_eq!(stats.used + stats.free + stats.reserved + headers, stats.size);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct HeapStats {
+    /// The total size of the heap, i.e. `N`.
+    pub size: usize,
+    /// The number of bytes occupied by allocations.
+    pub used: usize,
+    /// The number of bytes available for allocations.
+    ///
+    /// This memory might be fragmented, so it is not guaranteed, that a
+    /// single allocation of that size succeeds. Refer to
+    /// [`largest_free`](Self::largest_free) for that.
+    pub free: usize,
+    /// The number of bytes occupied by pending reservations.
+    ///
+    /// These blocks were reserved via
+    /// [`Allocator::reserve_block()`](crate::Allocator::reserve_block), but are
+    /// neither committed nor aborted yet. They are neither used nor free.
+    pub reserved: usize,
+    /// The number of used blocks, i.e. the number of live allocations.
+    pub used_blocks: usize,
+    /// The number of free blocks.
+    pub free_blocks: usize,
+    /// The number of reserved blocks, i.e. the number of pending reservations.
+    pub reserved_blocks: usize,
+    /// The size of the largest free block.
+    ///
+    /// This is the upper limit of a single allocation, that might succeed.
+    pub largest_free: usize,
+    /// The number of successful allocations since the creation of the heap.
+    ///
+    /// This counter saturates at `usize::MAX` instead of wrapping around. If
+    /// it reached that value, the counter is unreliable.
+    pub total_allocations: usize,
+    /// The number of successful deallocations since the creation of the heap.
+    ///
+    /// This counter saturates at `usize::MAX` instead of wrapping around. If
+    /// it reached that value, the counter is unreliable.
+    pub total_frees: usize,
+}
+impl Display for HeapStats {
+    /// Format the statistics as a compact, human-readable single line.
+    ///
+    /// This is intended to be printed e.g. over a serial line and does not
+    /// require any allocations itself:
+    /// ```text
+    /// heap: 123/4096 bytes used, 5 blocks, largest free 800
+    /// ```
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "heap: {}/{} bytes used, {} blocks, largest free {}",
+            self.used, self.size, self.used_blocks, self.largest_free
+        )
+    }
+}
+
+/// The metadata of a single block in the heap.
+///
+/// This type is obtained via [`Allocator::block_of()`](crate::Allocator::block_of)
+/// and describes the block at the time of the call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct BlockInfo {
+    /// The offset of the block (i.e. of its header) from the start of the heap.
+    pub offset: usize,
+    /// The size of the memory of the block (excluding the header).
+    ///
+    /// This is the requested size rounded up to a multiple of the granularity
+    /// (see [`Allocator::GRANULARITY`](crate::Allocator::GRANULARITY)), but
+    /// might be larger, e.g. due to the additional memory for alignments above
+    /// the granularity.
+    pub size: usize,
+    /// Whether the block is currently used, reserved or free.
+    pub state: State,
+}
+
+/// The result of an attempt to recover from a corrupted heap.
+///
+/// This type is obtained via
+/// [`Allocator::try_recover()`](crate::Allocator::try_recover).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecoveryReport {
+    /// The offset of the first invalid header from the start of the heap.
+    ///
+    /// If the heap was not corrupted, this is `None`.
+    pub corrupted_at: Option<usize>,
+    /// The number of bytes, which were quarantined (including the header).
+    ///
+    /// This memory is marked as used and thus never handed out again.
+    pub quarantined: usize,
+}
+
+/// An iterator over the free blocks of the heap.
+///
+/// This type is obtained via
+/// [`Allocator::free_blocks()`](crate::Allocator::free_blocks) and yields the
+/// offset (of the header) and the size of every free block in address order.
+/// The allocator stays locked until the iterator is dropped, so the free blocks
+/// cannot change in the meantime.
+pub struct FreeBlocks<'allocator, L: RawLock, const N: usize> {
+    /// The locked raw allocator.
+    raw: MutexGuard<'allocator, L, RawAllocator<N>>,
+    /// The entry, at which the search for the next free block continues.
+    next: Option<ValidatedOffset>,
+}
+impl<'allocator, L: RawLock, const N: usize> FreeBlocks<'allocator, L, N> {
+    /// Iterate over the free blocks of the locked raw allocator.
+    pub(crate) fn new(raw: MutexGuard<'allocator, L, RawAllocator<N>>) -> Self {
+        let next = raw.first_entry();
+        Self { raw, next }
+    }
+}
+impl<L: RawLock, const N: usize> Iterator for FreeBlocks<'_, L, N> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (block, next) = self.raw.next_free_block(self.next?)?;
+        self.next = next;
+        Some(block)
+    }
+}
+impl<L: RawLock, const N: usize> fmt::Debug for FreeBlocks<'_, L, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("FreeBlocks")
+            .field("next", &self.next.map(ValidatedOffset::get))
+            .finish()
+    }
+}
+
+/// An iterator over the runs of adjacent free blocks of the heap.
+///
+/// This type is obtained via
+/// [`Allocator::coalescible_pairs()`](crate::Allocator::coalescible_pairs) and
+/// yields the offset (of the header of the first block) and the size of the
+/// merged block for every run of at least two adjacent free blocks in address
+/// order. The allocator stays locked until the iterator is dropped, so the
+/// blocks cannot change in the meantime.
+pub struct CoalesciblePairs<'allocator, L: RawLock, const N: usize> {
+    /// The locked raw allocator.
+    raw: MutexGuard<'allocator, L, RawAllocator<N>>,
+    /// The entry, at which the search for the next run continues.
+    next: Option<ValidatedOffset>,
+}
+impl<'allocator, L: RawLock, const N: usize> CoalesciblePairs<'allocator, L, N> {
+    /// Iterate over the runs of free blocks of the locked raw allocator.
+    pub(crate) fn new(raw: MutexGuard<'allocator, L, RawAllocator<N>>) -> Self {
+        let next = raw.first_entry();
+        Self { raw, next }
+    }
+}
+impl<L: RawLock, const N: usize> Iterator for CoalesciblePairs<'_, L, N> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (run, next) = self.raw.next_free_run(self.next?)?;
+        self.next = next;
+        Some(run)
+    }
+}
+impl<L: RawLock, const N: usize> fmt::Debug for CoalesciblePairs<'_, L, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("CoalesciblePairs")
+            .field("next", &self.next.map(ValidatedOffset::get))
+            .finish()
+    }
+}
+
+/// An iterator over all the blocks of the heap.
+///
+/// This type is obtained via
+/// [`Allocator::iter_blocks()`](crate::Allocator::iter_blocks) and yields the
+/// metadata of every block (used or free) in address order. The allocator
+/// stays locked until the iterator is dropped, so the blocks cannot change in
+/// the meantime.
+pub struct Blocks<'allocator, L: RawLock, const N: usize> {
+    /// The locked raw allocator.
+    raw: MutexGuard<'allocator, L, RawAllocator<N>>,
+    /// The entry of the next block.
+    next: Option<ValidatedOffset>,
+}
+impl<'allocator, L: RawLock, const N: usize> Blocks<'allocator, L, N> {
+    /// Iterate over the blocks of the locked raw allocator.
+    pub(crate) fn new(raw: MutexGuard<'allocator, L, RawAllocator<N>>) -> Self {
+        let next = raw.first_entry();
+        Self { raw, next }
+    }
+}
+impl<L: RawLock, const N: usize> Iterator for Blocks<'_, L, N> {
+    type Item = BlockInfo;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (block, next) = self.raw.block_at(self.next?);
+        self.next = next;
+        Some(block)
+    }
+}
+impl<L: RawLock, const N: usize> fmt::Debug for Blocks<'_, L, N> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Blocks")
+            .field("next", &self.next.map(ValidatedOffset::get))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeapStats;
+
+    #[test]
+    fn display() {
+        let stats = HeapStats {
+            size: 4096,
+            used: 123,
+            free: 3873,
+            reserved: 0,
+            used_blocks: 5,
+            free_blocks: 5,
+            reserved_blocks: 0,
+            largest_free: 800,
+            total_allocations: 12,
+            total_frees: 7,
+        };
+        assert_eq!(
+            format!("{}", stats),
+            "heap: 123/4096 bytes used, 5 blocks, largest free 800"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! This module provides the [`Strategy`] for choosing a free block.
+use core::fmt;
+
+/// A user-supplied policy for choosing a free block, see [`Strategy::Custom`].
+///
+/// The function receives the free blocks, into which the allocation fits, as
+/// pairs of the offset (of the header, see [`BlockInfo::offset`]) and the size
+/// of the block, and returns the offset of the chosen block. If no block is
+/// chosen (`None` or an offset, which was not offered), the allocation fails.
+///
+/// [`BlockInfo::offset`]: crate::BlockInfo::offset
+pub type Selector = fn(&mut dyn Iterator<Item = (usize, usize)>) -> Option<usize>;
+
+/// The strategy for choosing the free block for an allocation.
+///
+/// Usually there are multiple free blocks, which are large enough for an
+/// allocation. The strategy decides, which one of them is used. It can be
+/// changed at runtime via [`Allocator::set_strategy()`].
+///
+/// Regardless of the strategy, a recently freed block of exactly the required
+/// size is always reused first without scanning the heap (the most recently
+/// freed one, if there are multiple). Otherwise ties are broken by the address:
+/// if multiple free blocks fit equally well, the one with the lowest address is
+/// chosen. Therefore the placement is deterministic for a given sequence of
+/// allocations and deallocations. Other policies can be plugged in via
+/// [`Strategy::Custom`].
+///
+/// [`Allocator::set_strategy()`]: crate::Allocator::set_strategy
+#[derive(Clone, Copy)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Use the smallest free block, that is large enough.
+    ///
+    /// If there are multiple smallest blocks, the first one is used, i.e. this
+    /// is an address-ordered best-fit. This requires visiting all the free
+    /// blocks, but it keeps large free blocks available for large allocations and
+    /// therefore tends to reduce the fragmentation. Preferring the lower
+    /// addresses among the best fitting blocks fills the holes at the start of
+    /// the heap first, so that the large free block at the end of the heap is
+    /// only split, if no other block fits. This is the default strategy.
+    BestFit,
+    /// Use the first free block, that is large enough.
+    ///
+    /// The suitable block with the lowest address is used. This tends to
+    /// split large blocks, but keeps the allocations at the start of the heap.
+    FirstFit,
+    /// Advance into the free block following the previous allocation.
+    ///
+    /// This hybridizes a bump allocator and the free-list: the free block
+    /// after the previous allocation (the "frontier") is used without scanning
+    /// the heap, as long as it is large enough. Only if the frontier is
+    /// exhausted, the heap is scanned and the largest free block becomes the
+    /// new frontier. This is fast for bursts of allocations, which are freed
+    /// rarely (e.g. during the startup), as the allocations are placed one
+    /// after the other without leaving any free fragments in between.
+    Frontier,
+    /// Let a user-supplied [`Selector`] choose among the fitting free blocks.
+    ///
+    /// The allocator enumerates the free blocks, which are large enough (and
+    /// contain a suitably aligned address for over-aligned allocations), and
+    /// the selector picks one of them. The blocks are offered in an unspecified
+    /// but deterministic order, so the selector has to order them itself. The
+    /// selector is called with the allocator locked, so it must not allocate
+    /// (or deallocate) memory with that allocator. It should be deterministic
+    /// to keep the placement reproducible.
+    ///
+    /// # Example
+    /// A worst-fit policy using the largest fitting block:
+    /// ```
+    /// use emballoc::Strategy;
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+    /// ALLOCATOR.set_strategy(Strategy::Custom(|blocks| {
+    ///     blocks.max_by_key(|&(_offset, size)| size).map(|(offset, _size)| offset)
+    /// }));
+    /// ```
+    Custom(Selector),
+}
+// `Debug` and `PartialEq` are implemented manually, as older compilers do not
+// provide them for function pointers taking references.
+impl fmt::Debug for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::BestFit => f.write_str("BestFit"),
+            Self::FirstFit => f.write_str("FirstFit"),
+            Self::Frontier => f.write_str("Frontier"),
+            Self::Custom(select) => write!(f, "Custom({:p})", *select),
+        }
+    }
+}
+impl PartialEq for Strategy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::BestFit, Self::BestFit)
+            | (Self::FirstFit, Self::FirstFit)
+            | (Self::Frontier, Self::Frontier) => true,
+            // the selectors are compared by their addresses
+            (Self::Custom(a), Self::Custom(b)) => *a as usize == *b as usize,
+            _ => false,
+        }
+    }
+}
+impl Eq for Strategy {}
+impl Default for Strategy {
+    fn default() -> Self {
+        Self::BestFit
+    }
+}
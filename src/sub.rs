@@ -0,0 +1,205 @@
+//! This module provides the [`SubAllocator`] for partitioning the heap.
+use crate::{GenericAllocator, RawLock};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+
+/// A fixed part of the heap of a parent allocator, which is managed separately.
+///
+/// If several subsystems share a single heap, one of them might use up all the
+/// memory and starve the others. A sub-allocator prevents this: it owns a block
+/// of `M` bytes of heap space carved out of the parent heap and manages the
+/// free memory of that block independently of the parent and all the other
+/// sub-allocators. Therefore each subsystem has its own memory limit and the
+/// fragmentation caused by one subsystem does not affect the others.
+///
+/// A sub-allocator is created via [`GenericAllocator::sub_allocator()`]. It
+/// dereferences to a [`GenericAllocator`] with a heap of `M` bytes, so all the
+/// methods (e.g. [`stats()`](GenericAllocator::stats)) are available. It
+/// implements [`GlobalAlloc`] as well. The memory has to be freed via the same
+/// sub-allocator, which allocated it.
+///
+/// When the sub-allocator is dropped, its block is returned to the parent. All
+/// the memory allocated from the sub-allocator must not be used afterwards.
+///
+/// # Example
+/// ```
+/// use core::alloc::{GlobalAlloc, Layout};
+///
+/// static ALLOCATOR: emballoc::Allocator<4096> = emballoc::Allocator::new();
+///
+/// let network = ALLOCATOR.sub_allocator::<1024>("network").unwrap();
+/// let storage = ALLOCATOR.sub_allocator::<512>("storage").unwrap();
+///
+/// // the network stack cannot use more than its own heap
+/// let layout = Layout::new::<[u8; 1000]>();
+/// let packet = unsafe { network.alloc(layout) };
+/// assert!(!packet.is_null());
+/// # #[cfg(not(feature = "abort-on-oom"))]
+/// assert!(unsafe { network.alloc(layout) }.is_null());
+///
+/// // but the storage still has all of its memory available (except for the
+/// // header of its single free block)
+/// let header = emballoc::Allocator::<512>::GRANULARITY;
+/// assert_eq!(storage.available_bytes(), 512 - header);
+/// # unsafe { network.dealloc(packet, layout) };
+/// ```
+pub struct SubAllocator<'parent, L: RawLock, const N: usize, const M: usize> {
+    /// The allocator, whose heap contains the sub-allocator.
+    parent: &'parent GenericAllocator<L, N>,
+    /// The allocator of the sub-heap, which is stored in a block of the parent.
+    heap: NonNull<GenericAllocator<L, M>>,
+    /// The name of the sub-allocator (for diagnostic purposes only).
+    name: &'static str,
+}
+// SAFETY: the sub-allocator is a shared reference to both the parent and its
+// own allocator, which is sound to share if both of them can be shared.
+unsafe impl<L: RawLock, const N: usize, const M: usize> Sync for SubAllocator<'_, L, N, M>
+where
+    GenericAllocator<L, N>: Sync,
+    GenericAllocator<L, M>: Sync,
+{
+}
+// SAFETY: the sub-allocator is a shared reference to both the parent and its
+// own allocator. Its own allocator is dropped, when the sub-allocator is
+// dropped, therefore that one has to be `Send` in addition.
+unsafe impl<L: RawLock, const N: usize, const M: usize> Send for SubAllocator<'_, L, N, M>
+where
+    GenericAllocator<L, N>: Sync,
+    GenericAllocator<L, M>: Send + Sync,
+{
+}
+impl<'parent, L: RawLock, const N: usize, const M: usize> SubAllocator<'parent, L, N, M> {
+    /// The layout of the block of the parent containing the sub-allocator.
+    const LAYOUT: Layout = Layout::new::<GenericAllocator<L, M>>();
+
+    /// Carve a sub-allocator with `M` bytes of heap space out of `parent`.
+    ///
+    /// If the parent has not enough memory available, `None` is returned.
+    ///
+    /// # Panics
+    /// This function panics, if `M` is less than `8` or not divisible by `4`
+    /// (`16` and `8` with the `granularity-8` feature).
+    pub(crate) fn new(
+        parent: &'parent GenericAllocator<L, N>,
+        name: &'static str,
+        lock: L,
+    ) -> Option<Self> {
+        let granularity = GenericAllocator::<L, M>::GRANULARITY;
+        assert!(
+            M >= 2 * granularity && M % granularity == 0,
+            "invalid heap size: M has to be at least {} and divisible by {}",
+            2 * granularity,
+            granularity
+        );
+        // SAFETY: the layout has a non-zero size, as it contains the heap.
+        let (heap, _size) = unsafe { parent.alloc_with_size(Self::LAYOUT) }?;
+        let heap = heap.cast::<GenericAllocator<L, M>>();
+        // SAFETY: the block was just allocated with the layout of the allocator,
+        // so it is valid for writes and properly aligned. The size `M` is valid,
+        // so this does not panic.
+        unsafe { GenericAllocator::init_at(heap.as_ptr(), lock) };
+        Some(Self { parent, heap, name })
+    }
+
+    /// Query the name of the sub-allocator.
+    ///
+    /// The name is the one passed to [`GenericAllocator::sub_allocator()`].
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+impl<L: RawLock, const N: usize, const M: usize> Deref for SubAllocator<'_, L, N, M> {
+    type Target = GenericAllocator<L, M>;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: the allocator was initialized in `new()` and is alive until
+        // the sub-allocator is dropped. It is only accessed via shared
+        // references, as the allocator synchronizes internally.
+        unsafe { self.heap.as_ref() }
+    }
+}
+impl<L: RawLock, const N: usize, const M: usize> Drop for SubAllocator<'_, L, N, M> {
+    fn drop(&mut self) {
+        // SAFETY: the allocator was initialized in `new()` and is not used
+        // anymore. Its block was allocated from the parent with this layout.
+        unsafe {
+            ptr::drop_in_place(self.heap.as_ptr());
+            self.parent.dealloc(self.heap.as_ptr().cast(), Self::LAYOUT);
+        }
+    }
+}
+impl<L: RawLock, const N: usize, const M: usize> fmt::Debug for SubAllocator<'_, L, N, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SubAllocator")
+            .field("name", &self.name)
+            .field("heap", &**self)
+            .finish()
+    }
+}
+// SAFETY: the allocations are forwarded to the own allocator of the sub-heap.
+unsafe impl<L: RawLock, const N: usize, const M: usize> GlobalAlloc for SubAllocator<'_, L, N, M> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: the safety contract is the same as for this function.
+        unsafe { (**self).alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: the safety contract is the same as for this function.
+        unsafe { (**self).dealloc(ptr, layout) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Allocator;
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::mem;
+
+    #[test]
+    fn independent_heaps() {
+        crate::tests::return_null_on_oom();
+        let parent = Allocator::<4096>::new();
+        let first = parent.sub_allocator::<64>("first").unwrap();
+        let second = parent.sub_allocator::<64>("second").unwrap();
+        assert_eq!(first.name(), "first");
+        assert_eq!(parent.stats().used_blocks, 2);
+
+        // exhausting the first sub-allocator does not affect the second one
+        let layout = Layout::from_size_align(first.capacity(), 1).unwrap();
+        let ptr = unsafe { first.alloc(layout) };
+        assert!(!ptr.is_null());
+        assert!(unsafe { first.alloc(Layout::new::<u8>()) }.is_null());
+        let other = unsafe { second.alloc(layout) };
+        assert!(!other.is_null());
+
+        // the frees are routed to the sub-allocator of the memory
+        unsafe { first.dealloc(ptr, layout) };
+        assert_eq!(first.stats().used_blocks, 0);
+        assert_eq!(second.stats().used_blocks, 1);
+        unsafe { second.dealloc(other, layout) };
+        assert_eq!(second.stats().used_blocks, 0);
+
+        // the memory of the sub-allocators is returned to the parent
+        drop(first);
+        drop(second);
+        assert_eq!(parent.stats().used_blocks, 0);
+    }
+
+    #[test]
+    fn insufficient_parent_memory() {
+        let parent = Allocator::<2048>::new();
+        assert!(parent.sub_allocator::<2048>("too large").is_none());
+        assert_eq!(parent.stats().used_blocks, 0);
+
+        // the sub-allocator occupies its heap and the bookkeeping
+        let sub = parent.sub_allocator::<64>("small").unwrap();
+        let occupied = parent.capacity() - parent.available_bytes();
+        assert!(occupied >= mem::size_of::<Allocator<64>>());
+        drop(sub);
+        assert_eq!(parent.available_bytes(), parent.capacity());
+    }
+}
@@ -0,0 +1,20 @@
+//! A global `defmt` logger for the integration tests and doctests.
+//!
+//! With the `defmt` feature the allocator logs via `defmt`, so every binary
+//! using it has to provide the global logger. The integration tests and the
+//! doctests of this crate are such binaries, so the crate enables the internal
+//! `test-logger` feature for them via a dev-dependency on itself. The unit
+//! tests provide their own logger counting the logged frames instead.
+
+#[defmt::global_logger]
+struct Logger;
+// SAFETY: the logger discards all frames, so there is no state, which could be
+// accessed concurrently.
+unsafe impl defmt::Logger for Logger {
+    fn acquire() {}
+    unsafe fn flush() {}
+    unsafe fn release() {}
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("{=u8}", 0);
@@ -0,0 +1,174 @@
+//! This module provides the tracking of live allocations for leak diagnosis.
+//!
+//! With the `backtrace` feature, a backtrace is captured for every allocation
+//! and kept until the allocation is freed. Allocations made via
+//! `Allocator::alloc_tracked()` additionally record the source location of the
+//! call. Without that feature, the tracking does nothing and takes no memory.
+#[cfg(feature = "backtrace")]
+pub use enabled::{LiveAllocation, Tracker};
+
+#[cfg(feature = "backtrace")]
+#[allow(clippy::incompatible_msrv)] // the feature requires a newer compiler
+mod enabled {
+    use core::panic::Location;
+    use std::backtrace::Backtrace;
+    use std::cell::Cell;
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::vec::Vec;
+
+    /// A live allocation together with the backtrace of its allocation.
+    ///
+    /// This type is obtained via
+    /// [`Allocator::live_allocations()`](crate::Allocator::live_allocations).
+    #[derive(Debug, Clone)]
+    #[non_exhaustive]
+    pub struct LiveAllocation {
+        /// The pointer returned by the allocation.
+        pub ptr: *mut u8,
+        /// The backtrace captured when the memory was allocated.
+        pub backtrace: Arc<Backtrace>,
+        /// The source location of the allocation.
+        ///
+        /// This is only recorded for allocations made via
+        /// [`Allocator::alloc_tracked()`](crate::Allocator::alloc_tracked) and
+        /// `None` otherwise.
+        pub location: Option<&'static Location<'static>>,
+    }
+
+    /// A tracked allocation.
+    struct Tracked {
+        /// The address of the allocation.
+        ///
+        /// This is stored as an integer, so that the tracker is `Sync`.
+        addr: usize,
+        /// The backtrace captured when the memory was allocated.
+        backtrace: Arc<Backtrace>,
+        /// The source location of the allocation (if known).
+        location: Option<&'static Location<'static>>,
+    }
+
+    std::thread_local! {
+        /// Whether the current thread is updating the tracked allocations.
+        ///
+        /// Capturing a backtrace and storing it allocates memory itself. If the
+        /// tracked allocator is the global allocator, this would recurse into
+        /// the tracking, so nested allocations are not tracked.
+        static BUSY: Cell<bool> = Cell::new(false);
+    }
+
+    /// The tracked live allocations of an allocator.
+    pub struct Tracker(Mutex<Vec<Tracked>>);
+    impl Tracker {
+        /// Create a new tracker without any tracked allocations.
+        pub const fn new() -> Self {
+            Self(Mutex::new(Vec::new()))
+        }
+
+        /// Run `f`, unless this is a nested call from within another `f`.
+        fn guarded<R>(f: impl FnOnce() -> R) -> Option<R> {
+            if BUSY.with(|busy| busy.replace(true)) {
+                return None;
+            }
+            let result = f();
+            BUSY.with(|busy| busy.set(false));
+            Some(result)
+        }
+
+        /// Run `f` on the tracked allocations, unless this is a nested call.
+        fn with<R>(&self, f: impl FnOnce(&mut Vec<Tracked>) -> R) -> Option<R> {
+            Self::guarded(|| f(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner)))
+        }
+
+        /// Track the allocation at `ptr` (if it is not null).
+        pub fn insert(&self, ptr: *mut u8) {
+            self.insert_at(ptr, None);
+        }
+
+        /// Track the allocation at `ptr` made at the given source `location`.
+        ///
+        /// A null pointer is not tracked.
+        pub fn insert_at(&self, ptr: *mut u8, location: Option<&'static Location<'static>>) {
+            if !ptr.is_null() {
+                Self::guarded(|| {
+                    let backtrace = Arc::new(Backtrace::force_capture());
+                    let mut live = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+                    live.push(Tracked {
+                        addr: ptr as usize,
+                        backtrace,
+                        location,
+                    });
+                });
+            }
+        }
+
+        /// Stop tracking the allocation at `ptr`.
+        pub fn remove(&self, ptr: *mut u8) {
+            let removed = self.with(|live| {
+                let index = live.iter().position(|tracked| tracked.addr == ptr as usize);
+                index.map(|index| live.swap_remove(index))
+            });
+            // the backtrace is freed here, after the tracker is unlocked
+            drop(removed);
+        }
+
+        /// Update the tracked allocation, which moved from `old` to `new`.
+        #[cfg(feature = "relocating")]
+        pub fn relocate(&self, old: *mut u8, new: *mut u8) {
+            self.with(|live| {
+                for tracked in live
+                    .iter_mut()
+                    .filter(|tracked| tracked.addr == old as usize)
+                {
+                    tracked.addr = new as usize;
+                }
+            });
+        }
+
+        /// Stop tracking all allocations.
+        pub fn clear(&self) {
+            let removed = self.with(core::mem::take);
+            drop(removed);
+        }
+
+        /// Obtain a copy of all the tracked allocations.
+        pub fn snapshot(&self) -> Vec<LiveAllocation> {
+            self.with(|live| {
+                live.iter()
+                    .map(|tracked| LiveAllocation {
+                        ptr: tracked.addr as *mut u8,
+                        backtrace: Arc::clone(&tracked.backtrace),
+                        location: tracked.location,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+        }
+    }
+}
+
+/// The disabled tracking of live allocations.
+///
+/// All the operations do nothing.
+#[cfg(not(feature = "backtrace"))]
+pub struct Tracker;
+#[cfg(not(feature = "backtrace"))]
+#[allow(clippy::unused_self)]
+impl Tracker {
+    /// Create a new tracker, which does nothing.
+    pub const fn new() -> Self {
+        Self
+    }
+
+    /// Track the allocation at `ptr` (does nothing).
+    pub const fn insert(&self, _ptr: *mut u8) {}
+
+    /// Stop tracking the allocation at `ptr` (does nothing).
+    pub const fn remove(&self, _ptr: *mut u8) {}
+
+    /// Update the tracked allocation, which moved (does nothing).
+    #[cfg(feature = "relocating")]
+    pub const fn relocate(&self, _old: *mut u8, _new: *mut u8) {}
+
+    /// Stop tracking all allocations (does nothing).
+    pub const fn clear(&self) {}
+}
@@ -1,9 +1,20 @@
 #[test]
 fn is_usable_in_const_contexts() {
+    #[allow(clippy::declare_interior_mutable_const)] // this is the point of the test
     const _ALLOCATOR1: emballoc::Allocator<32> = emballoc::Allocator::new();
     static _ALLOCATOR2: emballoc::Allocator<32> = emballoc::Allocator::new();
 }
 
+#[test]
+fn checked_construction_of_valid_sizes() {
+    #[cfg(not(feature = "granularity-8"))]
+    static _ALLOCATOR1: emballoc::Allocator<8> = emballoc::Allocator::new_const_checked();
+    #[cfg(feature = "granularity-8")]
+    static _ALLOCATOR1: emballoc::Allocator<16> = emballoc::Allocator::new_const_checked();
+    static _ALLOCATOR2: emballoc::Allocator<4096> = emballoc::Allocator::new_const_checked();
+    let _allocator = emballoc::Allocator::<64>::new_const_checked();
+}
+
 #[test]
 fn supports_global_alloc() {
     fn assert<T: core::alloc::GlobalAlloc>(_: T) {}
@@ -18,6 +29,121 @@ fn min_heap_size_of_at_least_8() {
 
 #[test]
 #[should_panic(expected = "divisible by 4")]
+#[cfg(not(feature = "granularity-8"))]
 fn heap_size_must_be_a_multiple_of_4() {
     let _allocator = emballoc::Allocator::<31>::new(); // panic here
 }
+
+#[test]
+#[should_panic(expected = "divisible by 8")]
+#[cfg(feature = "granularity-8")]
+fn heap_size_must_be_a_multiple_of_8() {
+    let _allocator = emballoc::Allocator::<28>::new(); // panic here
+}
+
+/// Run the test `name` in a child process, which is expected to abort.
+///
+/// Aborting cannot be observed within the test process itself, therefore the
+/// test binary runs the single test again with `EMBALLOC_CHILD` set. Tests use
+/// [`in_child()`] to run the aborting code only in that child process. The
+/// output of the aborted child is returned.
+#[cfg(any(feature = "abort-on-oom", feature = "panic-on-dealloc-error"))]
+fn aborting_child(name: &str) -> String {
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args([name, "--exact", "--nocapture", "--test-threads=1"])
+        .env("EMBALLOC_CHILD", "1")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    assert!(
+        !output.status.success(),
+        "the child did not abort: {}",
+        stderr
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        assert_eq!(output.status.signal(), Some(6), "no SIGABRT: {}", stderr);
+    }
+    stderr
+}
+
+/// Query, whether this is the child process of [`aborting_child()`].
+#[cfg(any(feature = "abort-on-oom", feature = "panic-on-dealloc-error"))]
+fn in_child() -> bool {
+    std::env::var_os("EMBALLOC_CHILD").is_some()
+}
+
+#[test]
+#[cfg(feature = "panic-on-dealloc-error")]
+fn abort_on_double_free() {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    if in_child() {
+        let allocator = emballoc::Allocator::<128>::new();
+        let layout = Layout::new::<u32>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        unreachable!("the double free did not abort");
+    }
+    let stderr = aborting_child("abort_on_double_free");
+    assert!(stderr.contains("invalid deallocation: double free detected"));
+}
+
+#[test]
+#[cfg(feature = "abort-on-oom")]
+fn abort_on_out_of_memory() {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    if in_child() {
+        let allocator = emballoc::Allocator::<64>::new();
+        let ptr = unsafe { allocator.alloc(Layout::new::<u64>()) };
+        assert!(!ptr.is_null());
+        unsafe { allocator.alloc(Layout::from_size_align(64, 4).unwrap()) };
+        unreachable!("the failed allocation did not abort");
+    }
+    let stderr = aborting_child("abort_on_out_of_memory");
+    // the `u64` needs additional memory for its alignment unless the blocks
+    // are rounded to 8 bytes
+    let used = if emballoc::Allocator::<64>::GRANULARITY == 8 {
+        8
+    } else {
+        12
+    };
+    assert!(stderr.contains(&format!(
+        "out of memory: failed to allocate 64 bytes aligned to 4 (HeapStats {{ size: 64, used: {},",
+        used
+    )));
+}
+
+#[test]
+#[cfg(feature = "abort-on-oom")]
+fn failures_reported_otherwise_do_not_abort() {
+    use core::alloc::Layout;
+
+    let allocator = emballoc::Allocator::<64>::new();
+    let layout = Layout::from_size_align(64, 4).unwrap();
+    assert!(unsafe { allocator.alloc_with_size(layout) }.is_none());
+    assert!(allocator.reserve_block(layout).is_none());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn allocator_in_section() {
+    emballoc::allocator_in_section!(ALLOCATOR, 64, "emballoc_heap");
+
+    // the linker provides symbols for the start and end of the section
+    extern "C" {
+        static __start_emballoc_heap: u8;
+        static __stop_emballoc_heap: u8;
+    }
+    #[allow(unused_unsafe)] // older compilers require `unsafe` here
+    let start = unsafe { core::ptr::addr_of!(__start_emballoc_heap) } as usize;
+    #[allow(unused_unsafe)] // older compilers require `unsafe` here
+    let end = unsafe { core::ptr::addr_of!(__stop_emballoc_heap) } as usize;
+    let allocator = core::ptr::addr_of!(ALLOCATOR) as usize;
+    assert!(start <= allocator);
+    assert!(allocator + core::mem::size_of_val(&ALLOCATOR) <= end);
+    assert_eq!(ALLOCATOR.stats().free, ALLOCATOR.capacity());
+}